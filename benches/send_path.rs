@@ -0,0 +1,218 @@
+//! Publishes an issue to a million confirmed subscribers and measures the send path: enqueueing the deliveries and
+//! the throughput of the delivery worker against a mock email provider. One subscriber in a thousand has an invalid
+//! email, the worker must skip them without stopping.
+//!
+//! It needs the Postgres of the configuration, where it creates and drops a database of its own:
+//!
+//! ```sh
+//! cargo bench --bench send_path
+//! # Smaller or bigger runs, or a sample of the sends of a big audience
+//! BENCH_SUBSCRIBERS=100000 cargo bench --bench send_path
+//! BENCH_DELIVERIES=10000 cargo bench --bench send_path
+//! ```
+//!
+//! Every delivery is sent unless `BENCH_DELIVERIES` limits the sends that are measured.
+use secrecy::Secret;
+use sqlx::{migrate, Connection, Executor, PgConnection, PgPool, Row};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use uuid::Uuid;
+use wiremock::matchers::any;
+use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+use email_newsletter::config::{get_configuration, DatabaseSettings};
+use email_newsletter::domain::subscriber_email::SubscriberEmail;
+use email_newsletter::email_client::EmailClient;
+use email_newsletter::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+use email_newsletter::routes::{prepare_newsletter, publish_newsletter, NewNewsletter};
+use email_newsletter::startup::get_connection_db_pool;
+
+const DEFAULT_SUBSCRIBERS: i64 = 1_000_000;
+
+fn main() {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(run());
+}
+
+async fn run() {
+    let subscribers = env_or_default("BENCH_SUBSCRIBERS", DEFAULT_SUBSCRIBERS);
+    let deliveries = env_or_default("BENCH_DELIVERIES", subscribers).min(subscribers);
+    let mut config = get_configuration().expect("Missing configuration file.");
+    let maintenance_db = config.database.clone();
+    let db_pool = create_db(&mut config.database).await;
+
+    let started_at = Instant::now();
+
+    seed_subscribers(&db_pool, subscribers).await;
+    report("Seeded subscribers", subscribers, started_at);
+
+    let started_at = Instant::now();
+    let newsletter: NewNewsletter = serde_json::from_value(serde_json::json!({
+      "title": "Benchmark issue",
+      "content": {
+        "markdown": "Hi {{ subscriber.name }}, read [the latest post](https://blog.example.com/latest).\n\n[Unsubscribe]({{ unsubscribe_url }})"
+      }
+    }))
+    .unwrap();
+    let prepared_newsletter = prepare_newsletter(&db_pool, &newsletter).await.unwrap();
+    let mut transaction = db_pool.begin().await.unwrap();
+
+    publish_newsletter(&mut transaction, &newsletter, prepared_newsletter)
+        .await
+        .unwrap();
+    transaction.commit().await.unwrap();
+    report("Enqueued deliveries", subscribers, started_at);
+
+    // Counting the sends at the provider keeps the loop off the million rows of the queue
+    let sent_emails = Arc::new(AtomicI64::new(0));
+    let email_server = MockServer::builder()
+        .disable_request_recording()
+        .start()
+        .await;
+
+    Mock::given(any())
+        .respond_with({
+            let sent_emails = sent_emails.clone();
+
+            move |_: &Request| {
+                sent_emails.fetch_add(1, Ordering::Relaxed);
+                ResponseTemplate::new(200)
+            }
+        })
+        .mount(&email_server)
+        .await;
+
+    let email_client = EmailClient::new(
+        email_server.uri(),
+        SubscriberEmail::parse(String::from("newsletter@example.com")).unwrap(),
+        Secret::new(String::from("benchmark-key")),
+        None,
+    );
+    let base_url = config.get_app_base_url();
+    let hmac_secret = config.get_hmac_secret();
+    let started_at = Instant::now();
+
+    while sent_emails.load(Ordering::Relaxed) < deliveries {
+        if let ExecutionOutcome::EmptyQueue =
+            try_execute_task(&db_pool, &email_client, &base_url, &hmac_secret)
+                .await
+                .unwrap()
+        {
+            break;
+        }
+    }
+
+    let processed = count_deliveries(&db_pool, "status <> 'pending'").await;
+
+    report("Processed deliveries", processed, started_at);
+    println!(
+        "Sent: {}, skipped invalid emails: {}",
+        count_deliveries(&db_pool, "status = 'sent'").await,
+        count_deliveries(&db_pool, "status = 'skipped'").await
+    );
+
+    if let Some(peak_memory) = peak_memory() {
+        println!("Peak memory: {}", peak_memory);
+    }
+
+    db_pool.close().await;
+    drop_db(&maintenance_db, &config.database.name).await;
+}
+
+fn env_or_default(name: &str, default: i64) -> i64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+fn report(step: &str, count: i64, started_at: Instant) {
+    let elapsed = started_at.elapsed();
+
+    println!(
+        "{}: {} in {:.2?} ({:.0}/s)",
+        step,
+        count,
+        elapsed,
+        count as f64 / elapsed.as_secs_f64()
+    );
+}
+
+async fn create_db(db_config: &mut DatabaseSettings) -> PgPool {
+    let db_name = format!("bench_{}", Uuid::new_v4().to_string().replace('-', "_"));
+    let mut connection = PgConnection::connect_with(&db_config.get_db_options())
+        .await
+        .expect("Failed to connect to Postgres.");
+
+    connection
+        .execute(&*format!(r#"CREATE DATABASE "{}";"#, db_name))
+        .await
+        .expect("Failed to create database.");
+    connection.close().await.unwrap();
+    db_config.set_name(db_name);
+
+    let db_pool = get_connection_db_pool(db_config);
+
+    migrate!("./migrations")
+        .run(&db_pool)
+        .await
+        .expect("Failed to run migrations.");
+
+    db_pool
+}
+
+async fn drop_db(maintenance_db: &DatabaseSettings, db_name: &str) {
+    let mut connection = PgConnection::connect_with(&maintenance_db.get_db_options())
+        .await
+        .expect("Failed to connect to Postgres.");
+
+    // The sessions of the closed pool can still be ending on the server
+    connection
+        .execute(&*format!(r#"DROP DATABASE "{}" WITH (FORCE);"#, db_name))
+        .await
+        .expect("Failed to drop database.");
+}
+
+/// Confirmed subscribers, one in a thousand with an invalid email
+async fn seed_subscribers(db_pool: &PgPool, subscribers: i64) {
+    sqlx::query(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        SELECT
+            gen_random_uuid(),
+            CASE WHEN n % 1000 = 0 THEN 'invalid-' || n ELSE 'subscriber' || n || '@example.com' END,
+            'Subscriber ' || n,
+            now(),
+            'confirmed'
+        FROM generate_series(1, $1) AS n
+        "#,
+    )
+    .bind(subscribers)
+    .execute(db_pool)
+    .await
+    .expect("Failed to seed the subscribers.");
+}
+
+async fn count_deliveries(db_pool: &PgPool, condition: &str) -> i64 {
+    sqlx::query(&format!(
+        "SELECT count(*) FROM issue_deliveries WHERE {}",
+        condition
+    ))
+    .fetch_one(db_pool)
+    .await
+    .unwrap()
+    .get(0)
+}
+
+/// Highest resident memory of the process, only available on Linux
+fn peak_memory() -> Option<String> {
+    std::fs::read_to_string("/proc/self/status")
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))
+        .map(|value| value.trim().to_owned())
+}
@@ -0,0 +1,7 @@
+ALTER TABLE subscriptions ADD COLUMN attributes JSONB NOT NULL DEFAULT '{}'::jsonb;
+
+CREATE TABLE subscriber_attribute_definitions(
+  key TEXT NOT NULL PRIMARY KEY,
+  value_type TEXT NOT NULL,
+  created_at timestamptz NOT NULL
+);
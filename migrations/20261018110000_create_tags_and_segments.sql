@@ -0,0 +1,15 @@
+CREATE TABLE subscriber_tags(
+  subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+  tag TEXT NOT NULL,
+  created_at timestamptz NOT NULL,
+  PRIMARY KEY (subscriber_id, tag)
+);
+
+CREATE INDEX subscriber_tags_tag_idx ON subscriber_tags (tag);
+
+CREATE TABLE segments(
+  id uuid NOT NULL PRIMARY KEY,
+  name TEXT NOT NULL UNIQUE,
+  filter TEXT NOT NULL,
+  created_at timestamptz NOT NULL
+);
@@ -0,0 +1,19 @@
+CREATE TABLE newsletter_issues(
+  id uuid NOT NULL PRIMARY KEY,
+  title TEXT NOT NULL,
+  html_content TEXT NOT NULL,
+  created_at timestamptz NOT NULL
+);
+
+-- One row per recipient of an issue. Rows are kept after being delivered so they can be used for reporting.
+CREATE TABLE issue_deliveries(
+  id uuid NOT NULL PRIMARY KEY,
+  newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (id) ON DELETE CASCADE,
+  subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+  status TEXT NOT NULL,
+  created_at timestamptz NOT NULL,
+  sent_at timestamptz NULL,
+  UNIQUE (newsletter_issue_id, subscriber_id)
+);
+
+CREATE INDEX issue_deliveries_pending_idx ON issue_deliveries (created_at) WHERE status = 'pending';
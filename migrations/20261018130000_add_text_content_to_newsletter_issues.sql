@@ -0,0 +1,2 @@
+-- Issues created before this migration have no plain-text part, it is generated from the HTML when delivered
+ALTER TABLE newsletter_issues ADD COLUMN text_content TEXT NULL;
@@ -0,0 +1,7 @@
+CREATE TABLE layouts(
+  id uuid NOT NULL PRIMARY KEY,
+  name TEXT NOT NULL UNIQUE,
+  html TEXT NOT NULL,
+  created_at timestamptz NOT NULL,
+  updated_at timestamptz NOT NULL
+);
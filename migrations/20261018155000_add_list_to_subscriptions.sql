@@ -0,0 +1,2 @@
+-- List the subscriber signed up to, NULL for those who did not choose one
+ALTER TABLE subscriptions ADD COLUMN list TEXT NULL;
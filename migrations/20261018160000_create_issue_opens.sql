@@ -0,0 +1,12 @@
+-- Issues created before this migration were sent without a tracking pixel
+ALTER TABLE newsletter_issues ADD COLUMN track_opens BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- One row per time the tracking pixel of a delivery was loaded
+CREATE TABLE issue_opens(
+  id uuid NOT NULL PRIMARY KEY,
+  issue_delivery_id uuid NOT NULL REFERENCES issue_deliveries (id) ON DELETE CASCADE,
+  opened_at timestamptz NOT NULL,
+  user_agent TEXT NULL
+);
+
+CREATE INDEX issue_opens_issue_delivery_id_idx ON issue_opens (issue_delivery_id);
@@ -0,0 +1,13 @@
+-- Issues created before this migration were sent without tracked links
+ALTER TABLE newsletter_issues ADD COLUMN track_clicks BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- One row per time a subscriber followed a tracked link of a delivery
+CREATE TABLE issue_clicks(
+  id uuid NOT NULL PRIMARY KEY,
+  issue_delivery_id uuid NOT NULL REFERENCES issue_deliveries (id) ON DELETE CASCADE,
+  url TEXT NOT NULL,
+  clicked_at timestamptz NOT NULL,
+  user_agent TEXT NULL
+);
+
+CREATE INDEX issue_clicks_issue_delivery_id_idx ON issue_clicks (issue_delivery_id);
@@ -0,0 +1,11 @@
+-- Events of a delivery after it was sent, reported by the email provider or the unsubscribe link
+CREATE TABLE issue_delivery_events(
+  id uuid NOT NULL PRIMARY KEY,
+  issue_delivery_id uuid NOT NULL REFERENCES issue_deliveries (id) ON DELETE CASCADE,
+  event TEXT NOT NULL,
+  occurred_at timestamptz NOT NULL,
+  -- The provider can report the same event more than once
+  provider_event_id TEXT NULL UNIQUE
+);
+
+CREATE INDEX issue_delivery_events_issue_delivery_id_idx ON issue_delivery_events (issue_delivery_id);
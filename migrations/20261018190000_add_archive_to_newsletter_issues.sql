@@ -0,0 +1,13 @@
+-- Issues are published in the web archive and the feeds under their slug, unless they are private
+ALTER TABLE newsletter_issues ADD COLUMN slug TEXT NULL UNIQUE;
+ALTER TABLE newsletter_issues ADD COLUMN is_private BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Issues created before this migration get a slug from their title and id
+UPDATE newsletter_issues
+SET slug = concat_ws(
+  '-',
+  nullif(trim(BOTH '-' FROM regexp_replace(lower(title), '[^a-z0-9]+', '-', 'g')), ''),
+  left(id::text, 8)
+);
+
+ALTER TABLE newsletter_issues ALTER COLUMN slug SET NOT NULL;
@@ -0,0 +1,25 @@
+-- Issues scheduled for a future time. The issue is stored right away and its deliveries are enqueued by the
+-- scheduler at send time, so the audience includes the subscribers confirmed in the meantime.
+CREATE TABLE scheduled_issues(
+  newsletter_issue_id uuid NOT NULL PRIMARY KEY REFERENCES newsletter_issues (id) ON DELETE CASCADE,
+  send_at timestamptz NOT NULL,
+  segment TEXT NULL,
+  exclude_segments TEXT[] NOT NULL,
+  status TEXT NOT NULL,
+  enqueued_at timestamptz NULL
+);
+
+CREATE INDEX scheduled_issues_send_at_idx ON scheduled_issues (send_at) WHERE status = 'scheduled';
+
+-- Newsletters published every time their cron expression fires, in the local time of the timezone
+CREATE TABLE recurring_newsletters(
+  id uuid NOT NULL PRIMARY KEY,
+  cron TEXT NOT NULL,
+  timezone TEXT NOT NULL,
+  newsletter jsonb NOT NULL,
+  next_run_at timestamptz NOT NULL,
+  last_run_at timestamptz NULL,
+  created_at timestamptz NOT NULL
+);
+
+CREATE INDEX recurring_newsletters_next_run_at_idx ON recurring_newsletters (next_run_at);
@@ -0,0 +1,5 @@
+-- IANA timezone of the subscriber, chosen by them or inferred from their locale
+ALTER TABLE subscriptions ADD COLUMN timezone TEXT NULL;
+
+-- Deliveries of issues sent at a local time wait in the queue until this time
+ALTER TABLE issue_deliveries ADD COLUMN send_at timestamptz NULL;
@@ -0,0 +1,3 @@
+-- Workers claim a batch of pending deliveries until this time and send them without holding a lock. Deliveries of a
+-- worker that stopped before completing them are claimed again once it ends.
+ALTER TABLE issue_deliveries ADD COLUMN claimed_until timestamptz NULL;
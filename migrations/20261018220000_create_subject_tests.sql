@@ -0,0 +1,23 @@
+-- Subject lines tested on random samples of the audience of an issue. Once the test window ends, the subject with
+-- the best rate is sent to the rest of the audience and the results of every variant are kept.
+CREATE TABLE subject_tests(
+  newsletter_issue_id uuid NOT NULL PRIMARY KEY REFERENCES newsletter_issues (id) ON DELETE CASCADE,
+  subjects TEXT[] NOT NULL,
+  sample_percent SMALLINT NOT NULL,
+  window_hours INTEGER NOT NULL,
+  metric TEXT NOT NULL,
+  segment TEXT NULL,
+  exclude_segments TEXT[] NOT NULL,
+  -- Scheduled, running, paused with the delivery of the issue, completed, cancelled or failed
+  status TEXT NOT NULL,
+  -- Set when the samples are enqueued, scheduled issues start the test at their send time
+  ends_at timestamptz NULL,
+  winner SMALLINT NULL,
+  results jsonb NULL,
+  completed_at timestamptz NULL
+);
+
+CREATE INDEX subject_tests_ends_at_idx ON subject_tests (ends_at) WHERE status = 'running';
+
+-- Position of the subject sent to the subscriber in the subjects of the test, the title is used when missing
+ALTER TABLE issue_deliveries ADD COLUMN subject_variant SMALLINT NULL;
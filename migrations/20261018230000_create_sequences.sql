@@ -0,0 +1,27 @@
+-- Emails sent to every subscriber after they confirm, e.g. a welcome email right away and a survey ten days later
+CREATE TABLE sequences(
+  id uuid NOT NULL PRIMARY KEY,
+  name TEXT NOT NULL UNIQUE,
+  created_at timestamptz NOT NULL
+);
+
+-- The email of each step is a private issue, delivered the number of days after the confirmation
+CREATE TABLE sequence_steps(
+  sequence_id uuid NOT NULL REFERENCES sequences (id) ON DELETE CASCADE,
+  position SMALLINT NOT NULL,
+  delay_days INTEGER NOT NULL,
+  newsletter_issue_id uuid NOT NULL UNIQUE REFERENCES newsletter_issues (id) ON DELETE CASCADE,
+  PRIMARY KEY (sequence_id, position)
+);
+
+-- Subscribers going through a sequence. Their steps are enqueued as deliveries when they are enrolled, so the
+-- progress is read from the deliveries.
+CREATE TABLE sequence_enrolments(
+  sequence_id uuid NOT NULL REFERENCES sequences (id) ON DELETE CASCADE,
+  subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+  enrolled_at timestamptz NOT NULL,
+  stopped_at timestamptz NULL,
+  PRIMARY KEY (sequence_id, subscriber_id)
+);
+
+CREATE INDEX sequence_enrolments_subscriber_id_idx ON sequence_enrolments (subscriber_id);
@@ -0,0 +1,16 @@
+-- Subscribers who do not confirm get one reminder, and their row is deleted or anonymised after a retention period
+ALTER TABLE subscriptions ADD COLUMN confirmation_reminder_sent_at timestamptz NULL;
+ALTER TABLE subscriptions ADD COLUMN anonymised_at timestamptz NULL;
+
+CREATE INDEX subscriptions_pending_confirmation_idx ON subscriptions (subscribed_at)
+WHERE status = 'pending_confirmation' AND anonymised_at IS NULL;
+
+-- Confirmation tokens sent to each subscriber, so they can all be revoked from Redis when the pending subscription is
+-- cleaned up. Postgres keeps the list because appending to it in Redis is not atomic with GET and SET.
+CREATE TABLE subscription_tokens(
+  subscription_token TEXT NOT NULL PRIMARY KEY,
+  subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+  created_at timestamptz NOT NULL
+);
+
+CREATE INDEX subscription_tokens_subscriber_id_idx ON subscription_tokens (subscriber_id);
@@ -0,0 +1,23 @@
+-- Confirmed subscribers who stop opening and clicking are marked as inactive until they re-engage or are unsubscribed
+ALTER TABLE subscriptions ADD COLUMN inactive_since timestamptz NULL;
+-- Inactivity is only measured from the last time the subscriber re-engaged
+ALTER TABLE subscriptions ADD COLUMN reactivated_at timestamptz NULL;
+-- Set once the re-engagement email of an inactive subscriber was sent, the grace period starts then. Inactive
+-- subscribers without it are retried in the next runs of the sunset policy.
+ALTER TABLE subscriptions ADD COLUMN re_engagement_sent_at timestamptz NULL;
+
+CREATE INDEX subscriptions_inactive_since_idx ON subscriptions (inactive_since) WHERE inactive_since IS NOT NULL;
+
+-- One row per action taken on the status of a subscriber by the sunset policy. Actions of a run share their time,
+-- the serial id keeps the order in which they were taken.
+CREATE TABLE subscriber_status_history(
+  id BIGSERIAL PRIMARY KEY,
+  subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+  action TEXT NOT NULL,
+  occurred_at timestamptz NOT NULL
+);
+
+CREATE INDEX subscriber_status_history_subscriber_id_idx ON subscriber_status_history (subscriber_id);
+
+-- The sunset policy looks up the sends and the engagement of each subscriber
+CREATE INDEX issue_deliveries_subscriber_id_idx ON issue_deliveries (subscriber_id);
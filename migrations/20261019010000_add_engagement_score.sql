@@ -0,0 +1,10 @@
+-- Engagement score of the subscriber as of engagement_scored_at, the time decay since then is applied when it is
+-- next updated. Subscribers who were never scored have no engagement_scored_at.
+ALTER TABLE subscriptions ADD COLUMN engagement_score DOUBLE PRECISION NOT NULL DEFAULT 0;
+ALTER TABLE subscriptions ADD COLUMN engagement_scored_at timestamptz NULL;
+
+-- Deliveries of the same issue are sent to the most engaged subscribers first
+ALTER TABLE issue_deliveries ADD COLUMN priority DOUBLE PRECISION NOT NULL DEFAULT 0;
+
+DROP INDEX issue_deliveries_pending_idx;
+CREATE INDEX issue_deliveries_pending_idx ON issue_deliveries (created_at, priority DESC) WHERE status = 'pending';
@@ -1,12 +1,19 @@
 use config::{Config, ConfigError, File};
 use secrecy::{ExposeSecret, Secret};
-use serde_aux::field_attributes::deserialize_number_from_string;
+use serde_aux::field_attributes::{
+    deserialize_number_from_string, deserialize_option_number_from_string,
+};
 use sqlx::{
     postgres::{PgConnectOptions, PgSslMode},
     ConnectOptions,
 };
+use std::collections::HashMap;
+use std::num::NonZeroU32;
 
+use crate::domain::engagement_score::EngagementScoring;
+use crate::domain::sendgrid_webhook_key::SendgridWebhookKey;
 use crate::domain::subscriber_email::SubscriberEmail;
+use crate::domain::subscriber_locale::SubscriberLocale;
 
 #[derive(Debug)]
 pub enum Environment {
@@ -20,6 +27,11 @@ pub struct Settings {
     pub database: DatabaseSettings,
     pub email_client: EmailClientSettings,
     pub redis: RedisSettings,
+    pub email_templates: EmailTemplatesSettings,
+    pub subscription_confirmation: SubscriptionConfirmationSettings,
+    pub archive: ArchiveSettings,
+    pub sunset_policy: SunsetPolicySettings,
+    pub engagement_score: EngagementScoreSettings,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -28,6 +40,8 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub host: String,
     pub base_url: String,
+    // Key used to sign the tracked links, so they cannot be used to redirect to arbitrary URLs
+    pub hmac_secret: Secret<String>,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -35,6 +49,24 @@ pub struct EmailClientSettings {
     pub base_url: String,
     pub sender_email: String,
     pub api_key: Secret<String>,
+    // Emails are sent as fast as the provider accepts them when missing
+    pub rate_limit: Option<RateLimitSettings>,
+    // Public key of the signed event webhook, events that are not signed with it are rejected
+    pub webhook_verification_key: Secret<String>,
+}
+
+/// Emails sent per second by every instance of the application together, so the provider does not throttle us
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct RateLimitSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub messages_per_second: u32,
+    /// Limit for the recipients of each domain, e.g. `gmail.com`
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub messages_per_second_per_domain: u32,
+    /// Sends the emails without the limit while Redis cannot be reached. By default the sends wait until Redis is
+    /// back, so the provider never gets more than the limit.
+    #[serde(default)]
+    pub fail_open: bool,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -49,6 +81,89 @@ pub struct DatabaseSettings {
     pub require_ssl: bool,
 }
 
+#[derive(serde::Deserialize, Clone)]
+pub struct EmailTemplatesSettings {
+    // Directory with a folder of translations per email, e.g. templates/confirmation_email/en.toml
+    pub path: String,
+    pub default_locale: String,
+}
+
+/// The confirmation link shows a page with the result, unless a redirect URL is set for that result. Subscribers can
+/// sign up to one of the `lists`, whose redirect URLs take precedence over the ones set here.
+#[derive(serde::Deserialize, Clone)]
+pub struct SubscriptionConfirmationSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub token_validity_hours: u64,
+    /// Hours after subscribing at which one reminder is sent to those who did not confirm. No reminder is sent when
+    /// missing.
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub reminder_after_hours: Option<u64>,
+    /// Days after subscribing at which the subscriptions that were never confirmed are cleaned up
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub pending_retention_days: u64,
+    pub pending_cleanup: PendingCleanup,
+    #[serde(flatten)]
+    pub redirects: ConfirmationRedirects,
+    /// Lists by name, e.g. `[subscription_confirmation.lists.blog]`. The configuration lowercases the names.
+    #[serde(default)]
+    pub lists: HashMap<String, ConfirmationRedirects>,
+}
+
+/// Where the confirmation link sends the subscriber for each result, the page is shown for those without one
+#[derive(serde::Deserialize, Clone, Default)]
+pub struct ConfirmationRedirects {
+    pub success_redirect_url: Option<String>,
+    pub already_confirmed_redirect_url: Option<String>,
+    pub expired_redirect_url: Option<String>,
+    pub error_redirect_url: Option<String>,
+}
+
+/// What happens to the subscriptions never confirmed once their retention period ends
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PendingCleanup {
+    Delete,
+    /// The row is kept for the statistics, without the personal data of the subscriber
+    Anonymise,
+}
+
+/// Confirmed subscribers with no opens or clicks in the last sends, or in the last months, are marked as inactive and
+/// sent a re-engagement email. They are unsubscribed if they do not open, click or follow its link within the grace
+/// period. The policy is disabled when both thresholds are missing, operators opt in by setting one of them. The
+/// thresholds are at least 1, a zero would mark every subscriber as inactive.
+#[derive(serde::Deserialize, Clone)]
+pub struct SunsetPolicySettings {
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub inactive_after_sends: Option<NonZeroU32>,
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub inactive_after_months: Option<NonZeroU32>,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub unsubscribe_after_days: u64,
+}
+
+/// Weights of the events in the engagement score of the subscribers. Bounces should have a negative weight.
+#[derive(serde::Deserialize, Clone)]
+pub struct EngagementScoreSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub open_weight: f64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub click_weight: f64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub bounce_weight: f64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub subscription_age_weight: f64,
+    /// Days after which the weight of an event is halved
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub half_life_days: u64,
+}
+
+/// Name of the newsletter in the web archive and the feeds
+#[derive(serde::Deserialize, Clone)]
+pub struct ArchiveSettings {
+    pub title: String,
+    pub description: String,
+}
+
 #[derive(serde::Deserialize, Clone)]
 pub struct RedisSettings {
     pub port: u16,
@@ -68,26 +183,46 @@ impl Settings {
         self.application.get_base_url()
     }
 
+    pub fn get_hmac_secret(&self) -> Secret<String> {
+        self.application.hmac_secret.clone()
+    }
+
     pub fn get_db_options(&self) -> PgConnectOptions {
         self.database.get_db_options()
     }
 
     pub fn get_email_client_sender(&self) -> Result<SubscriberEmail, String> {
-        return self.email_client.get_sender_email();
+        self.email_client.get_sender_email()
     }
 
     pub fn get_email_client_base_url(&self) -> String {
-        return self.email_client.get_base_url();
+        self.email_client.get_base_url()
     }
 
     pub fn get_email_client_api(&self) -> Secret<String> {
-        return self.email_client.get_api_key();
+        self.email_client.get_api_key()
     }
 
     pub fn set_email_client_base_url(&mut self, new_base_url: String) {
         self.email_client.base_url = new_base_url
     }
 
+    pub fn get_email_client_rate_limit(&self) -> Option<RateLimitSettings> {
+        self.email_client.rate_limit.clone()
+    }
+
+    pub fn set_email_client_rate_limit(&mut self, rate_limit: Option<RateLimitSettings>) {
+        self.email_client.rate_limit = rate_limit
+    }
+
+    pub fn get_email_client_webhook_key(&self) -> Result<SendgridWebhookKey, String> {
+        self.email_client.get_webhook_key()
+    }
+
+    pub fn set_email_client_webhook_verification_key(&mut self, webhook_verification_key: String) {
+        self.email_client.webhook_verification_key = Secret::new(webhook_verification_key)
+    }
+
     pub fn get_db_name(&self) -> String {
         self.database.get_name()
     }
@@ -107,6 +242,38 @@ impl Settings {
     pub fn get_redis_address(&self) -> String {
         self.redis.get_address()
     }
+
+    pub fn get_email_templates_path(&self) -> std::path::PathBuf {
+        self.email_templates.get_path()
+    }
+
+    pub fn get_default_locale(&self) -> Result<SubscriberLocale, String> {
+        self.email_templates.get_default_locale()
+    }
+
+    pub fn get_subscription_confirmation(&self) -> SubscriptionConfirmationSettings {
+        self.subscription_confirmation.clone()
+    }
+
+    pub fn set_pending_cleanup(&mut self, pending_cleanup: PendingCleanup) {
+        self.subscription_confirmation.pending_cleanup = pending_cleanup
+    }
+
+    pub fn get_archive(&self) -> ArchiveSettings {
+        self.archive.clone()
+    }
+
+    pub fn get_sunset_policy(&self) -> SunsetPolicySettings {
+        self.sunset_policy.clone()
+    }
+
+    pub fn set_sunset_policy(&mut self, sunset_policy: SunsetPolicySettings) {
+        self.sunset_policy = sunset_policy
+    }
+
+    pub fn get_engagement_scoring(&self) -> EngagementScoring {
+        self.engagement_score.get_scoring()
+    }
 }
 
 impl DatabaseSettings {
@@ -119,7 +286,7 @@ impl DatabaseSettings {
 
         let mut db_options = PgConnectOptions::new()
             .host(&self.host)
-            .password(&self.password.expose_secret())
+            .password(self.password.expose_secret())
             .username(&self.username)
             .port(self.port)
             .database(&self.name)
@@ -173,6 +340,10 @@ impl EmailClientSettings {
         SubscriberEmail::parse(self.sender_email.clone())
     }
 
+    pub fn get_webhook_key(&self) -> Result<SendgridWebhookKey, String> {
+        SendgridWebhookKey::parse(self.webhook_verification_key.expose_secret())
+    }
+
     pub fn get_base_url(&self) -> String {
         self.base_url.clone()
     }
@@ -186,6 +357,82 @@ impl EmailClientSettings {
     }
 }
 
+impl EmailTemplatesSettings {
+    pub fn get_path(&self) -> std::path::PathBuf {
+        std::path::PathBuf::from(&self.path)
+    }
+
+    pub fn get_default_locale(&self) -> Result<SubscriberLocale, String> {
+        SubscriberLocale::parse(self.default_locale.clone())
+    }
+}
+
+impl SubscriptionConfirmationSettings {
+    pub fn has_list(&self, list: &str) -> bool {
+        self.lists.contains_key(list)
+    }
+
+    /// Redirect URLs of the list, completed with the ones shared by every list
+    pub fn get_redirects(&self, list: Option<&str>) -> ConfirmationRedirects {
+        let shared = &self.redirects;
+        let list = list
+            .and_then(|list| self.lists.get(list))
+            .cloned()
+            .unwrap_or_default();
+
+        ConfirmationRedirects {
+            success_redirect_url: list
+                .success_redirect_url
+                .or_else(|| shared.success_redirect_url.clone()),
+            already_confirmed_redirect_url: list
+                .already_confirmed_redirect_url
+                .or_else(|| shared.already_confirmed_redirect_url.clone()),
+            expired_redirect_url: list
+                .expired_redirect_url
+                .or_else(|| shared.expired_redirect_url.clone()),
+            error_redirect_url: list
+                .error_redirect_url
+                .or_else(|| shared.error_redirect_url.clone()),
+        }
+    }
+
+    pub fn get_token_validity(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.token_validity_hours as i64)
+    }
+
+    pub fn get_reminder_delay(&self) -> Option<chrono::Duration> {
+        self.reminder_after_hours
+            .map(|hours| chrono::Duration::hours(hours as i64))
+    }
+
+    pub fn get_pending_retention(&self) -> chrono::Duration {
+        chrono::Duration::days(self.pending_retention_days as i64)
+    }
+}
+
+impl SunsetPolicySettings {
+    pub fn is_enabled(&self) -> bool {
+        self.inactive_after_sends.is_some() || self.inactive_after_months.is_some()
+    }
+
+    pub fn get_grace_period(&self) -> chrono::Duration {
+        chrono::Duration::days(self.unsubscribe_after_days as i64)
+    }
+}
+
+impl EngagementScoreSettings {
+    pub fn get_scoring(&self) -> EngagementScoring {
+        EngagementScoring {
+            open_weight: self.open_weight,
+            click_weight: self.click_weight,
+            bounce_weight: self.bounce_weight,
+            subscription_age_weight: self.subscription_age_weight,
+            // A half-life of zero would divide by zero
+            half_life: chrono::Duration::days(self.half_life_days.max(1) as i64),
+        }
+    }
+}
+
 impl RedisSettings {
     pub fn get_address(&self) -> String {
         format!("redis://{}:{}", self.host, self.port)
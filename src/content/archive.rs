@@ -0,0 +1,231 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::content::html::escape_text;
+
+/// Published issue, rendered without subscriber values, as it is shown in the web archive and the feeds
+pub struct ArchiveEntry {
+    pub id: Uuid,
+    pub title: String,
+    /// Archive page of the issue
+    pub url: String,
+    pub html: String,
+    pub published_at: DateTime<Utc>,
+}
+
+/// Description of the newsletter in the archive and the feeds
+pub struct ArchiveChannel<'a> {
+    pub title: &'a str,
+    pub description: &'a str,
+    pub base_url: &'a str,
+}
+
+/// Archive page of an issue. Slugs only have alphanumeric characters and dashes, so only non-ASCII characters need
+/// to be percent-encoded.
+pub fn archive_url(base_url: &str, slug: &str) -> String {
+    let path: String = slug
+        .bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric() || byte == b'-' {
+                char::from(byte).to_string()
+            } else {
+                format!("%{:02X}", byte)
+            }
+        })
+        .collect();
+
+    format!("{}/archive/{}", base_url, path)
+}
+
+/// Adds a "View in browser" link at the beginning of the body
+pub fn add_view_in_browser_link(html: &str, url: &str) -> String {
+    let link = format!(
+        r#"<p style="margin: 0; padding: 8px; font-size: 12px; text-align: center;"><a href="{}" style="color: #71717a;">View in browser</a></p>"#,
+        escape_text(url)
+    );
+    let body_start = html
+        .to_ascii_lowercase()
+        .find("<body")
+        .and_then(|position| html[position..].find('>').map(|end| position + end + 1));
+
+    match body_start {
+        Some(position) => format!("{}{}{}", &html[..position], link, &html[position..]),
+        None => format!("{}{}", link, html),
+    }
+}
+
+/// List of the published issues, newest first
+pub fn render_archive_index(channel: &ArchiveChannel, entries: &[ArchiveEntry]) -> String {
+    let items: String = entries
+        .iter()
+        .map(|entry| {
+            format!(
+                r#"<li style="margin: 0 0 12px;"><a href="{}" style="color: #2563eb;">{}</a> <time datetime="{}" style="color: #71717a;">{}</time></li>"#,
+                escape_text(&entry.url),
+                escape_text(&entry.title),
+                entry.published_at.to_rfc3339(),
+                entry.published_at.format("%B %-d, %Y")
+            )
+        })
+        .collect();
+    let items = if items.is_empty() {
+        String::from("<p>No issues have been published yet.</p>")
+    } else {
+        format!(
+            r#"<ul style="padding: 0; list-style: none;">{}</ul>"#,
+            items
+        )
+    };
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+<link rel="alternate" type="application/rss+xml" title="{title}" href="{base_url}/feed.xml">
+<link rel="alternate" type="application/atom+xml" title="{title}" href="{base_url}/feed.atom">
+</head>
+<body style="margin: 0; padding: 48px 16px; font-family: Helvetica, Arial, sans-serif; color: #18181b;">
+<main style="max-width: 600px; margin: 0 auto;">
+<h1 style="margin: 0 0 8px; font-size: 28px;">{title}</h1>
+<p style="margin: 0 0 32px; color: #52525b;">{description}</p>
+{items}
+</main>
+</body>
+</html>"#,
+        title = escape_text(channel.title),
+        description = escape_text(channel.description),
+        base_url = escape_text(channel.base_url),
+        items = items
+    )
+}
+
+/// RSS 2.0 feed of the published issues
+pub fn render_rss_feed(channel: &ArchiveChannel, entries: &[ArchiveEntry]) -> String {
+    let items: String = entries
+        .iter()
+        .map(|entry| {
+            format!(
+                "<item><title>{}</title><link>{}</link><guid isPermaLink=\"false\">urn:uuid:{}</guid><pubDate>{}</pubDate><description>{}</description></item>",
+                escape_text(&entry.title),
+                escape_text(&entry.url),
+                entry.id,
+                entry.published_at.to_rfc2822(),
+                escape_text(&entry.html)
+            )
+        })
+        .collect();
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\"><channel><title>{}</title><link>{}/archive</link><description>{}</description><atom:link href=\"{}/feed.xml\" rel=\"self\" type=\"application/rss+xml\"/>{}</channel></rss>",
+        escape_text(channel.title),
+        escape_text(channel.base_url),
+        escape_text(channel.description),
+        escape_text(channel.base_url),
+        items
+    )
+}
+
+/// Atom feed of the published issues
+pub fn render_atom_feed(channel: &ArchiveChannel, entries: &[ArchiveEntry]) -> String {
+    // Atom requires the date of the last change, which is the newest issue since issues are not edited
+    let updated = entries
+        .iter()
+        .map(|entry| entry.published_at)
+        .max()
+        .unwrap_or_else(Utc::now);
+    let entries: String = entries
+        .iter()
+        .map(|entry| {
+            format!(
+                "<entry><title>{}</title><link href=\"{}\"/><id>urn:uuid:{}</id><updated>{}</updated><content type=\"html\">{}</content></entry>",
+                escape_text(&entry.title),
+                escape_text(&entry.url),
+                entry.id,
+                entry.published_at.to_rfc3339(),
+                escape_text(&entry.html)
+            )
+        })
+        .collect();
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\"><title>{}</title><subtitle>{}</subtitle><link href=\"{}/archive\"/><link href=\"{}/feed.atom\" rel=\"self\"/><id>{}/archive</id><updated>{}</updated>{}</feed>",
+        escape_text(channel.title),
+        escape_text(channel.description),
+        escape_text(channel.base_url),
+        escape_text(channel.base_url),
+        escape_text(channel.base_url),
+        updated.to_rfc3339(),
+        entries
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn entries() -> Vec<ArchiveEntry> {
+        vec![ArchiveEntry {
+            id: Uuid::nil(),
+            title: String::from("Tom & Jerry"),
+            url: archive_url("http://localhost", "tom-jerry"),
+            html: String::from("<p>Hi</p>"),
+            published_at: Utc.with_ymd_and_hms(2026, 10, 18, 9, 30, 0).unwrap(),
+        }]
+    }
+
+    fn channel() -> ArchiveChannel<'static> {
+        ArchiveChannel {
+            title: "Newsletter",
+            description: "Past issues",
+            base_url: "http://localhost",
+        }
+    }
+
+    #[test]
+    fn archive_urls_are_percent_encoded() {
+        assert_eq!(
+            archive_url("http://localhost", "ñandú-news"),
+            "http://localhost/archive/%C3%B1and%C3%BA-news"
+        );
+    }
+
+    #[test]
+    fn view_in_browser_link_is_added_at_the_beginning_of_the_body() {
+        let html = add_view_in_browser_link(
+            r#"<html><body class="a"><p>Hi</p></body></html>"#,
+            "/archive/a",
+        );
+
+        assert!(html.starts_with(r#"<html><body class="a"><p style="#));
+        assert!(html.contains(r#"<a href="/archive/a""#));
+        assert!(html.ends_with("<p>Hi</p></body></html>"));
+        assert!(add_view_in_browser_link("<p>Hi</p>", "/archive/a").ends_with("</p><p>Hi</p>"));
+    }
+
+    #[test]
+    fn rss_feed_has_escaped_entries() {
+        let feed = render_rss_feed(&channel(), &entries());
+
+        assert!(feed.contains("<title>Tom &amp; Jerry</title>"));
+        assert!(feed.contains("<link>http://localhost/archive/tom-jerry</link>"));
+        assert!(feed.contains("<pubDate>Sun, 18 Oct 2026 09:30:00 +0000</pubDate>"));
+        assert!(feed.contains("<description>&lt;p&gt;Hi&lt;/p&gt;</description>"));
+    }
+
+    #[test]
+    fn atom_feed_is_updated_with_the_newest_entry() {
+        let feed = render_atom_feed(&channel(), &entries());
+
+        assert!(
+            feed.contains("<feed xmlns=\"http://www.w3.org/2005/Atom\"><title>Newsletter</title>")
+        );
+        assert!(feed.contains(
+            "<id>http://localhost/archive</id><updated>2026-10-18T09:30:00+00:00</updated>"
+        ));
+        assert!(feed.contains("<id>urn:uuid:00000000-0000-0000-0000-000000000000</id>"));
+    }
+}
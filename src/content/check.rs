@@ -0,0 +1,379 @@
+use crate::content::html::{serialize, tokenize, HtmlToken, StartTag, VOID_ELEMENTS};
+
+/// Gmail clips the messages whose HTML is bigger than ~102KB, hiding the rest of the content behind a link
+pub const GMAIL_CLIPPING_THRESHOLD: usize = 102 * 1024;
+
+// Elements removed together with their content, since email clients block them or they are a security risk. `<base>`
+// and `<link>` change where the links of the email go or load external resources.
+const REMOVED_ELEMENTS: [&str; 11] = [
+    "script", "form", "iframe", "object", "embed", "button", "input", "select", "textarea", "base",
+    "link",
+];
+// Attributes whose value is followed or loaded as a URL
+const URL_ATTRIBUTES: [&str; 14] = [
+    "action",
+    "background",
+    "cite",
+    "data",
+    "dynsrc",
+    "formaction",
+    "href",
+    "longdesc",
+    "lowsrc",
+    "poster",
+    "src",
+    "srcset",
+    "usemap",
+    "xlink:href",
+];
+// Any other scheme, like `javascript:`, `vbscript:` or `data:`, may run scripts
+const ALLOWED_URL_SCHEMES: [&str; 3] = ["http", "https", "mailto"];
+// Elements whose closing tag can be omitted
+const OPTIONAL_END_TAG_ELEMENTS: [&str; 17] = [
+    "html", "head", "body", "p", "li", "dt", "dd", "tr", "td", "th", "thead", "tbody", "tfoot",
+    "caption", "colgroup", "option", "optgroup",
+];
+
+/// Outcome of checking the HTML of an email before sending it. Errors block the sending, warnings are only
+/// reported.
+#[derive(Debug, serde::Serialize)]
+pub struct ContentCheck {
+    /// Sanitised HTML, without scripts, forms or event handlers
+    #[serde(skip)]
+    pub html: String,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl ContentCheck {
+    pub fn has_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
+}
+
+/// Sanitises the HTML and looks for structure errors and common email pitfalls.
+pub fn check_html(html: &str) -> ContentCheck {
+    let mut errors = vec![];
+    let mut warnings = vec![];
+    let (tokens, is_sanitised) = sanitise(tokenize(html), &mut warnings);
+    let mut open_elements: Vec<&str> = vec![];
+
+    for token in tokens.iter() {
+        match token {
+            HtmlToken::StartTag(tag) => {
+                if tag.name == "img" && tag.get_attribute("alt").is_none() {
+                    warnings.push(format!(
+                        "Image {} has no alt text.",
+                        tag.get_attribute("src").unwrap_or("without src")
+                    ));
+                }
+
+                if !tag.is_void() {
+                    open_elements.push(&tag.name);
+                }
+            }
+            HtmlToken::EndTag(name) if name.is_empty() => {
+                errors.push(String::from("Closing tag </> has no element name."));
+            }
+            HtmlToken::EndTag(name) if VOID_ELEMENTS.contains(&name.as_str()) => {
+                errors.push(format!("Element <{}> cannot have a closing tag.", name));
+            }
+            HtmlToken::EndTag(name) => {
+                match open_elements.iter().rposition(|element| element == name) {
+                    Some(position) => {
+                        errors.extend(unclosed_elements(&open_elements[position + 1..]));
+                        open_elements.truncate(position);
+                    }
+                    None => errors.push(format!("Closing tag </{}> has no opening tag.", name)),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    errors.extend(unclosed_elements(&open_elements));
+
+    let html = if is_sanitised {
+        serialize(&tokens)
+    } else {
+        String::from(html)
+    };
+
+    if html.len() > GMAIL_CLIPPING_THRESHOLD {
+        warnings.push(format!(
+            "The HTML is {}KB, Gmail clips messages bigger than 102KB.",
+            html.len() / 1024
+        ));
+    }
+
+    if !html.contains("unsubscribe_url") {
+        warnings.push(String::from(
+            "There is no unsubscribe link, add one with {{ unsubscribe_url }}.",
+        ));
+    }
+
+    ContentCheck {
+        html,
+        errors,
+        warnings,
+    }
+}
+
+fn unclosed_elements(elements: &[&str]) -> Vec<String> {
+    elements
+        .iter()
+        .filter(|element| !OPTIONAL_END_TAG_ELEMENTS.contains(element))
+        .map(|element| format!("Element <{}> is not closed.", element))
+        .collect()
+}
+
+/// Removes dangerous elements and attributes. Returns whether anything was removed.
+fn sanitise<'a>(
+    tokens: Vec<HtmlToken<'a>>,
+    warnings: &mut Vec<String>,
+) -> (Vec<HtmlToken<'a>>, bool) {
+    let mut sanitised = Vec::with_capacity(tokens.len());
+    let mut is_sanitised = false;
+    // Name and nesting depth of the element being removed
+    let mut removed_element: Option<(String, usize)> = None;
+
+    for token in tokens {
+        if let Some((name, depth)) = removed_element.as_mut() {
+            match &token {
+                HtmlToken::StartTag(tag) if tag.name == *name && !tag.is_void() => *depth += 1,
+                HtmlToken::EndTag(end_name) if end_name == name => *depth -= 1,
+                _ => {}
+            }
+
+            if *depth == 0 {
+                removed_element = None;
+            }
+
+            continue;
+        }
+
+        match token {
+            HtmlToken::StartTag(tag) if is_removed_element(&tag) => {
+                warnings.push(format!("Element <{}> was removed.", tag.name));
+                is_sanitised = true;
+
+                if !tag.is_void() {
+                    removed_element = Some((tag.name, 1));
+                }
+            }
+            HtmlToken::EndTag(name) if REMOVED_ELEMENTS.contains(&name.as_str()) => {
+                is_sanitised = true;
+            }
+            HtmlToken::StartTag(mut tag) => {
+                let attributes = tag.attributes.len();
+
+                tag.attributes.retain(is_safe_attribute);
+
+                if tag.attributes.len() < attributes {
+                    warnings.push(format!("Scripts were removed from element <{}>.", tag.name));
+                    is_sanitised = true;
+                }
+
+                sanitised.push(HtmlToken::StartTag(tag));
+            }
+            token => sanitised.push(token),
+        }
+    }
+
+    (sanitised, is_sanitised)
+}
+
+// A refresh `<meta>` redirects the email to any URL once it is opened
+fn is_removed_element(tag: &StartTag) -> bool {
+    let is_refresh = tag.name == "meta"
+        && tag
+            .get_attribute("http-equiv")
+            .map(|http_equiv| http_equiv.trim().eq_ignore_ascii_case("refresh"))
+            .unwrap_or(false);
+
+    is_refresh || REMOVED_ELEMENTS.contains(&tag.name.as_str())
+}
+
+// Event handlers and URLs with a scheme that is not allowed run scripts
+fn is_safe_attribute((name, value): &(String, String)) -> bool {
+    if name.starts_with("on") {
+        return false;
+    }
+
+    match name.as_str() {
+        // Comma-separated candidates, each one a URL followed by its size. Sizes are safe relative URLs, so every
+        // part is checked, however the candidates are split.
+        "srcset" => value
+            .split(|char: char| char == ',' || char.is_whitespace())
+            .all(is_safe_url),
+        name if URL_ATTRIBUTES.contains(&name) => is_safe_url(value),
+        _ => true,
+    }
+}
+
+/// Values are decoded by the tokenizer. Browsers ignore whitespace and control characters in the scheme and decode
+/// entities that the tokenizer does not (e.g. `&colon;` or `&#106` without `;`), so those are removed first and a
+/// remaining `&` before the path makes the URL unsafe.
+fn is_safe_url(url: &str) -> bool {
+    let url: String = url
+        .chars()
+        .filter(|char| !char.is_whitespace() && !char.is_control())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    let path_start = url.find(['/', '?', '#']).unwrap_or(url.len());
+
+    match url[..path_start].split_once(':') {
+        Some((scheme, _)) if is_url_scheme(scheme) => ALLOWED_URL_SCHEMES.contains(&scheme),
+        // Relative URLs, like `/archive` or `{{ unsubscribe_url }}`
+        _ => !url[..path_start].contains('&'),
+    }
+}
+
+fn is_url_scheme(scheme: &str) -> bool {
+    scheme.starts_with(|char: char| char.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || matches!(char, '+' | '-' | '.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check_html;
+
+    const UNSUBSCRIBE_LINK: &str = r#"<a href="{{ unsubscribe_url }}">Unsubscribe</a>"#;
+
+    #[test]
+    fn valid_html_has_no_errors_nor_warnings() {
+        let html = format!(
+            r#"<html><body><p>Hi<br>there<p><img src="logo.png" alt="">{}</body></html>"#,
+            UNSUBSCRIBE_LINK
+        );
+        let check = check_html(&html);
+
+        assert!(check.errors.is_empty(), "{:?}", check.errors);
+        assert!(check.warnings.is_empty(), "{:?}", check.warnings);
+        assert_eq!(check.html, html);
+    }
+
+    #[test]
+    fn broken_structure_is_an_error() {
+        let check = check_html("<div><h1>Title</><span>Text</div></section>");
+
+        assert_eq!(
+            check.errors,
+            vec![
+                "Closing tag </> has no element name.",
+                "Element <h1> is not closed.",
+                "Element <span> is not closed.",
+                "Closing tag </section> has no opening tag.",
+            ]
+        );
+    }
+
+    #[test]
+    fn scripts_and_forms_are_removed() {
+        let check = check_html(&format!(
+            r#"<p onclick="steal()">Hi</p><script>alert(1)</script><form><input name="a"><form></form></form><a href=" JavaScript:alert(1)">Link</a>{}"#,
+            UNSUBSCRIBE_LINK
+        ));
+
+        assert!(check.errors.is_empty());
+        assert_eq!(
+            check.html,
+            format!("<p>Hi</p><a>Link</a>{}", UNSUBSCRIBE_LINK)
+        );
+        assert_eq!(check.warnings.len(), 4);
+    }
+
+    #[test]
+    fn script_urls_are_removed_however_they_are_written() {
+        let urls = [
+            "java\tscript:alert(1)",
+            "java&#x09;script:alert(1)",
+            "\u{1}javascript:alert(1)",
+            "&#106;avascript:alert(1)",
+            "&#106avascript:alert(1)",
+            "javascript&colon;alert(1)",
+            "JAVASCRIPT:alert(1)",
+            "vbscript:msgbox(1)",
+            "data:text/html;base64,PHNjcmlwdD5hbGVydCgxKTwvc2NyaXB0Pg==",
+        ];
+
+        for url in urls {
+            let check = check_html(&format!(
+                r#"<a href="{}">Link</a><img src="{}" srcset="logo.png 1x, {} 2x" alt="">{}"#,
+                url, url, url, UNSUBSCRIBE_LINK
+            ));
+
+            assert_eq!(
+                check.html,
+                format!(r#"<a>Link</a><img alt>{}"#, UNSUBSCRIBE_LINK),
+                "The URL {:?} was not removed.",
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn web_mailto_and_relative_urls_are_kept() {
+        let html = format!(
+            r#"<a href="https://blog.test.com/post?a=1&amp;b=2">Blog</a><a href="HTTP://test.com">Site</a><a href="mailto:frank@test.com">Mail</a><a href="/archive#top">Archive</a><img src="logo.png" alt="Logo: the brand">{}"#,
+            UNSUBSCRIBE_LINK
+        );
+        let check = check_html(&html);
+
+        assert!(check.warnings.is_empty(), "{:?}", check.warnings);
+        assert_eq!(check.html, html);
+    }
+
+    #[test]
+    fn refresh_base_and_link_elements_are_removed() {
+        let check = check_html(&format!(
+            r#"<head><meta charset="utf-8"><meta http-equiv=" Refresh " content="0;url=https://evil.test.com"><base href="https://evil.test.com/"><link rel="stylesheet" href="https://evil.test.com/style.css"></head><p>Hi</p>{}"#,
+            UNSUBSCRIBE_LINK
+        ));
+
+        assert_eq!(
+            check.html,
+            format!(
+                r#"<head><meta charset="utf-8"></head><p>Hi</p>{}"#,
+                UNSUBSCRIBE_LINK
+            )
+        );
+        assert_eq!(
+            check.warnings,
+            vec![
+                "Element <meta> was removed.",
+                "Element <base> was removed.",
+                "Element <link> was removed.",
+            ]
+        );
+    }
+
+    #[test]
+    fn images_without_alt_text_are_reported() {
+        let check = check_html(&format!(r#"<img src="logo.png">{}"#, UNSUBSCRIBE_LINK));
+
+        assert_eq!(check.warnings, vec!["Image logo.png has no alt text."]);
+    }
+
+    #[test]
+    fn missing_unsubscribe_link_is_reported() {
+        let check = check_html("<p>Hi</p>");
+
+        assert_eq!(check.warnings.len(), 1);
+        assert!(check.warnings[0].contains("unsubscribe"));
+    }
+
+    #[test]
+    fn html_bigger_than_gmail_clipping_threshold_is_reported() {
+        let check = check_html(&format!(
+            "<p>{}</p>{}",
+            "a".repeat(110 * 1024),
+            UNSUBSCRIBE_LINK
+        ));
+
+        assert_eq!(check.warnings.len(), 1);
+        assert!(check.warnings[0].contains("Gmail clips"));
+    }
+}
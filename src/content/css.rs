@@ -0,0 +1,356 @@
+use crate::content::html::{tokenize, HtmlToken, StartTag};
+
+/// Moves the rules of `<style>` blocks into the `style` attribute of the elements they match, since Gmail and
+/// Outlook ignore or partially support style sheets. Rules applied in order of specificity and the styles already
+/// in the elements take precedence, as in browsers.
+///
+/// Only type, class and id selectors, optionally combined with descendant selectors (e.g. `.footer p`), can be
+/// inlined. The rest of the rules (e.g. media queries or `:hover`) are kept in the `<style>` block.
+pub fn inline_css(html: &str) -> String {
+    let tokens = tokenize(html);
+    let mut rules = vec![];
+    let mut remaining_css = String::new();
+    let mut in_style = false;
+
+    for token in tokens.iter() {
+        match token {
+            HtmlToken::StartTag(tag) if tag.name == "style" => in_style = true,
+            HtmlToken::EndTag(name) if name == "style" => in_style = false,
+            HtmlToken::Text(css) if in_style => {
+                let (inlinable_rules, not_inlinable_css) = parse_css(css, rules.len());
+
+                rules.extend(inlinable_rules);
+                remaining_css.push_str(&not_inlinable_css);
+            }
+            _ => {}
+        }
+    }
+
+    if rules.is_empty() {
+        return String::from(html);
+    }
+
+    let mut output = String::with_capacity(html.len());
+    let mut open_elements: Vec<StartTag> = vec![];
+    let mut in_style = false;
+    let mut is_style_written = false;
+
+    for token in tokens {
+        match token {
+            HtmlToken::StartTag(tag) if tag.name == "style" => {
+                // Rules that cannot be inlined are gathered in the first <style> block
+                if !is_style_written && !remaining_css.is_empty() {
+                    output.push_str(&format!("{}{}</style>", tag, remaining_css));
+                    is_style_written = true;
+                }
+
+                in_style = !tag.is_void();
+            }
+            HtmlToken::EndTag(name) if name == "style" => in_style = false,
+            _ if in_style => {}
+            HtmlToken::StartTag(mut tag) => {
+                let mut matching_rules: Vec<&CssRule> = rules
+                    .iter()
+                    .filter(|rule| rule.selector.matches(&tag, &open_elements))
+                    .collect();
+
+                matching_rules.sort_by_key(|rule| (rule.selector.specificity(), rule.order));
+
+                if !matching_rules.is_empty() {
+                    let declarations: Vec<&str> = matching_rules
+                        .iter()
+                        .map(|rule| rule.declarations.as_str())
+                        .collect();
+
+                    tag.add_style(&declarations.join(" "));
+                }
+
+                output.push_str(&tag.to_string());
+
+                if !tag.is_void() {
+                    open_elements.push(tag);
+                }
+            }
+            HtmlToken::EndTag(name) => {
+                if let Some(position) = open_elements.iter().rposition(|tag| tag.name == name) {
+                    open_elements.truncate(position);
+                }
+
+                output.push_str(&format!("</{}>", name));
+            }
+            token => output.push_str(&token.to_string()),
+        }
+    }
+
+    output
+}
+
+struct CssRule {
+    selector: Selector,
+    declarations: String,
+    order: usize,
+}
+
+/// Selector made of compound selectors separated by the descendant combinator. The last one is the subject.
+struct Selector(Vec<CompoundSelector>);
+
+#[derive(Default)]
+struct CompoundSelector {
+    name: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+}
+
+impl Selector {
+    fn parse(selector: &str) -> Option<Selector> {
+        let compounds = selector
+            .split_whitespace()
+            .map(CompoundSelector::parse)
+            .collect::<Option<Vec<CompoundSelector>>>()?;
+
+        if compounds.is_empty() {
+            return None;
+        }
+
+        Some(Self(compounds))
+    }
+
+    fn matches(&self, element: &StartTag, ancestors: &[StartTag]) -> bool {
+        let Some((subject, rest)) = self.0.split_last() else {
+            return false;
+        };
+
+        if !subject.matches(element) {
+            return false;
+        }
+
+        // Every remaining compound has to match an ancestor, from the closest to the root
+        let mut ancestors = ancestors.iter().rev();
+
+        rest.iter()
+            .rev()
+            .all(|compound| ancestors.any(|ancestor| compound.matches(ancestor)))
+    }
+
+    fn specificity(&self) -> (usize, usize, usize) {
+        self.0
+            .iter()
+            .fold((0, 0, 0), |(ids, classes, names), compound| {
+                (
+                    ids + usize::from(compound.id.is_some()),
+                    classes + compound.classes.len(),
+                    names + usize::from(compound.name.is_some()),
+                )
+            })
+    }
+}
+
+impl CompoundSelector {
+    fn parse(compound: &str) -> Option<CompoundSelector> {
+        let mut selector = CompoundSelector::default();
+        let mut rest = compound;
+
+        if let Some(universal) = rest.strip_prefix('*') {
+            rest = universal;
+        } else {
+            let end = rest.find(['.', '#']).unwrap_or(rest.len());
+
+            if end > 0 {
+                selector.name = Some(parse_identifier(&rest[..end])?.to_lowercase());
+            }
+
+            rest = &rest[end..];
+        }
+
+        while let Some(prefix) = rest.chars().next() {
+            let end = rest[1..].find(['.', '#']).map_or(rest.len(), |end| end + 1);
+            let identifier = String::from(parse_identifier(&rest[1..end])?);
+
+            match prefix {
+                '.' => selector.classes.push(identifier),
+                '#' => selector.id = Some(identifier),
+                _ => return None,
+            }
+
+            rest = &rest[end..];
+        }
+
+        Some(selector)
+    }
+
+    fn matches(&self, element: &StartTag) -> bool {
+        let name_matches = self.name.as_ref().is_none_or(|name| *name == element.name);
+        let id_matches = self
+            .id
+            .as_ref()
+            .is_none_or(|id| element.get_attribute("id") == Some(id.as_str()));
+
+        name_matches && id_matches && self.classes.iter().all(|class| element.has_class(class))
+    }
+}
+
+// Pseudo-classes, attribute selectors and other combinators are not supported
+fn parse_identifier(identifier: &str) -> Option<&str> {
+    let is_valid = !identifier.is_empty()
+        && identifier
+            .chars()
+            .all(|char| char.is_alphanumeric() || char == '-' || char == '_');
+
+    is_valid.then_some(identifier)
+}
+
+/// Splits the style sheet into the rules that can be inlined and the CSS that has to stay in a `<style>` block
+fn parse_css(css: &str, first_order: usize) -> (Vec<CssRule>, String) {
+    let css = remove_comments(css);
+    let mut rules = vec![];
+    let mut remaining_css = String::new();
+    let mut rest = css.as_str();
+
+    while let Some(open) = rest.find('{') {
+        let prelude = rest[..open].trim();
+        let Some(length) = find_block_end(&rest[open..]) else {
+            remaining_css.push_str(rest);
+            rest = "";
+            break;
+        };
+        let block = &rest[open..open + length];
+        let declarations = block[1..block.len() - 1].trim();
+        let selectors: Option<Vec<Selector>> = if prelude.starts_with('@') {
+            None
+        } else {
+            prelude.split(',').map(Selector::parse).collect()
+        };
+
+        match selectors {
+            Some(selectors) if !declarations.contains('{') => {
+                let declarations = if declarations.ends_with(';') || declarations.is_empty() {
+                    String::from(declarations)
+                } else {
+                    format!("{};", declarations)
+                };
+
+                for selector in selectors {
+                    rules.push(CssRule {
+                        selector,
+                        declarations: declarations.clone(),
+                        order: first_order + rules.len(),
+                    });
+                }
+            }
+            _ => remaining_css.push_str(&format!("{} {}\n", prelude, block)),
+        }
+
+        rest = &rest[open + length..];
+    }
+
+    // Statements without block, such as @import
+    let rest = rest.trim();
+
+    if !rest.is_empty() {
+        remaining_css.push_str(rest);
+    }
+
+    (rules, remaining_css)
+}
+
+fn remove_comments(css: &str) -> String {
+    let mut output = String::with_capacity(css.len());
+    let mut rest = css;
+
+    while let Some(start) = rest.find("/*") {
+        output.push_str(&rest[..start]);
+        rest = rest[start + 2..]
+            .find("*/")
+            .map_or("", |end| &rest[start + 2 + end + 2..]);
+    }
+
+    output.push_str(rest);
+
+    output
+}
+
+// Returns the length of the block, including its nested blocks (e.g. the rules of a media query)
+fn find_block_end(block: &str) -> Option<usize> {
+    let mut depth = 0;
+
+    for (index, char) in block.char_indices() {
+        match char {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+
+                if depth == 0 {
+                    return Some(index + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::inline_css;
+
+    #[test]
+    fn rules_are_inlined_by_specificity() {
+        let html = inline_css(
+            r#"<style>
+              /* Brand colors */
+              #intro { color: blue }
+              p.lead, .other { color: green; }
+              p { color: red; margin: 0; }
+            </style><p id="intro" class="lead" style="font-size: 18px;">Hi</p>"#,
+        );
+
+        assert_eq!(
+            html,
+            r#"<p id="intro" class="lead" style="color: red; margin: 0; color: green; color: blue; font-size: 18px;">Hi</p>"#
+        );
+    }
+
+    #[test]
+    fn descendant_selectors_are_inlined() {
+        let html = inline_css(
+            r#"<style>.footer a { color: gray; }</style><a href="/">Home</a><div class="footer"><p><a href="/u">Unsubscribe</a></p></div>"#,
+        );
+
+        assert_eq!(
+            html,
+            r#"<a href="/">Home</a><div class="footer"><p><a href="/u" style="color: gray;">Unsubscribe</a></p></div>"#
+        );
+    }
+
+    #[test]
+    fn rules_that_cannot_be_inlined_are_kept() {
+        let html = inline_css(
+            r#"<head><style>a:hover { color: red; } p { margin: 0; } @media (max-width: 600px) { p { margin: 8px; } }</style></head><p>Hi</p>"#,
+        );
+
+        assert_eq!(
+            html,
+            "<head><style>a:hover { color: red; }\n@media (max-width: 600px) { p { margin: 8px; } }\n</style></head><p style=\"margin: 0;\">Hi</p>"
+        );
+    }
+
+    #[test]
+    fn html_without_style_blocks_is_not_changed() {
+        let html = r#"<p class='intro'>Hi <br>{{ subscriber.name }}</p>"#;
+
+        assert_eq!(inline_css(html), html);
+    }
+
+    #[test]
+    fn merge_tags_in_elements_are_kept() {
+        let html = inline_css(
+            r#"<style>td { padding: 0; }</style><td {% if attr.vip %}class="vip"{% endif %}>{{ subscriber.name }}</td>"#,
+        );
+
+        assert_eq!(
+            html,
+            r#"<td {% if attr.vip %}class="vip" {% endif %} style="padding: 0;">{{ subscriber.name }}</td>"#
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use minijinja::{AutoEscape, Environment, Value};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::content::html_to_text::html_to_text;
+use crate::domain::subscriber_locale::SubscriberLocale;
+
+/// Transactional email (e.g. the confirmation email) translated to several languages. Each translation is a TOML
+/// file named after its locale, e.g. `confirmation_email/es.toml`:
+///
+/// ```toml
+/// subject = "Bienvenido a nuestra newsletter"
+/// html = """<p>Hola {{ subscriber.name }}, <a href="{{ confirmation_link }}">confirma tu email</a>.</p>"""
+/// # Optional, it is generated from the HTML when missing
+/// text = """Hola {{ subscriber.name }}, confirma tu email: {{ confirmation_link }}"""
+/// ```
+#[derive(Debug)]
+pub struct LocalizedEmailTemplates {
+    default_locale: SubscriberLocale,
+    templates: HashMap<String, EmailTemplate>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct EmailTemplate {
+    subject: String,
+    html: String,
+    text: Option<String>,
+}
+
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+impl LocalizedEmailTemplates {
+    /// Loads every translation in the directory. Templates using variables other than `variables` are rejected, as
+    /// well as a directory without a translation for the default locale.
+    pub fn load(
+        directory: &Path,
+        default_locale: SubscriberLocale,
+        variables: &[&str],
+    ) -> Result<LocalizedEmailTemplates, String> {
+        let entries = std::fs::read_dir(directory)
+            .map_err(|err| format!("Failed to read {}: {}", directory.display(), err))?;
+        let mut templates = HashMap::new();
+
+        for entry in entries {
+            let path = entry
+                .map_err(|err| format!("Failed to read {}: {}", directory.display(), err))?
+                .path();
+
+            if path.extension().and_then(|extension| extension.to_str()) != Some("toml") {
+                continue;
+            }
+
+            let locale = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .map(String::from)
+                .ok_or_else(|| format!("{} is not a valid template name", path.display()))
+                .and_then(SubscriberLocale::parse)?;
+            let template: EmailTemplate = config::Config::builder()
+                .add_source(config::File::from(path.as_path()))
+                .build()
+                .and_then(|template| template.try_deserialize())
+                .map_err(|err| format!("Failed to load {}: {}", path.display(), err))?;
+
+            template
+                .validate(variables)
+                .map_err(|err| format!("Invalid template {}: {}", path.display(), err))?;
+            templates.insert(String::from(locale.as_ref()), template);
+        }
+
+        if !templates.contains_key(default_locale.as_ref()) {
+            return Err(format!(
+                "There is no template for the default locale {} in {}",
+                default_locale.as_ref(),
+                directory.display()
+            ));
+        }
+
+        Ok(Self {
+            default_locale,
+            templates,
+        })
+    }
+
+    /// Returns the first locale, in order of preference, that has a translation, or the default locale
+    pub fn negotiate(&self, preferred_locales: &[SubscriberLocale]) -> SubscriberLocale {
+        preferred_locales
+            .iter()
+            .find_map(|locale| self.find_locale(locale))
+            .map(|locale| SubscriberLocale::parse(String::from(locale)).unwrap())
+            .unwrap_or_else(|| self.default_locale.clone())
+    }
+
+    /// Renders the translation for the locale, falling back to its language and then to the default locale
+    pub fn render(
+        &self,
+        locale: &SubscriberLocale,
+        context: Value,
+    ) -> Result<RenderedEmail, String> {
+        let template = self
+            .find_locale(locale)
+            .or_else(|| self.find_locale(&self.default_locale))
+            .and_then(|locale| self.templates.get(locale))
+            .ok_or_else(|| format!("There is no template for locale {}", locale.as_ref()))?;
+        let html = render(&template.html, &context, AutoEscape::Html)?;
+        let text = match &template.text {
+            Some(text) => render(text, &context, AutoEscape::None)?,
+            None => html_to_text(&html),
+        };
+
+        Ok(RenderedEmail {
+            subject: render(&template.subject, &context, AutoEscape::None)?,
+            html,
+            text,
+        })
+    }
+
+    fn find_locale(&self, locale: &SubscriberLocale) -> Option<&str> {
+        [locale.as_ref(), locale.language()]
+            .into_iter()
+            .find_map(|key| self.templates.get_key_value(key))
+            .map(|(key, _)| key.as_str())
+    }
+}
+
+impl EmailTemplate {
+    fn validate(&self, variables: &[&str]) -> Result<(), String> {
+        let environment = Environment::new();
+        let sources = [Some(&self.subject), Some(&self.html), self.text.as_ref()];
+
+        for source in sources.into_iter().flatten() {
+            let template = environment
+                .template_from_str(source)
+                .map_err(|err| err.to_string())?;
+            let mut unknown_variables: Vec<String> = template
+                .undeclared_variables(true)
+                .into_iter()
+                .filter(|variable| !variables.contains(&variable.as_str()))
+                .collect();
+
+            if !unknown_variables.is_empty() {
+                unknown_variables.sort();
+
+                return Err(format!(
+                    "Unknown variables: {}",
+                    unknown_variables.join(", ")
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn render(source: &str, context: &Value, auto_escape: AutoEscape) -> Result<String, String> {
+    let mut environment = Environment::new();
+
+    environment.set_auto_escape_callback(move |_| auto_escape);
+    environment
+        .render_str(source, context)
+        .map_err(|err| format!("Failed to render template: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LocalizedEmailTemplates;
+    use crate::domain::subscriber_locale::SubscriberLocale;
+    use claim::{assert_err, assert_ok};
+    use minijinja::{context, Value};
+    use std::path::PathBuf;
+
+    const VARIABLES: [&str; 2] = ["subscriber.name", "link"];
+
+    fn locale(locale: &str) -> SubscriberLocale {
+        SubscriberLocale::parse(String::from(locale)).unwrap()
+    }
+
+    fn templates_directory(templates: &[(&str, &str)]) -> PathBuf {
+        let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+
+        std::fs::create_dir(&directory).unwrap();
+
+        for (name, content) in templates {
+            std::fs::write(directory.join(name), content).unwrap();
+        }
+
+        directory
+    }
+
+    #[test]
+    fn templates_are_rendered_in_the_closest_locale() {
+        let directory = templates_directory(&[
+            (
+                "en.toml",
+                r#"
+                subject = "Welcome {{ subscriber.name }}"
+                html = "<a href='{{ link }}'>Confirm</a>"
+                "#,
+            ),
+            (
+                "es.toml",
+                r#"
+                subject = "Bienvenido {{ subscriber.name }}"
+                html = "<p>Hola</p>"
+                text = "Hola {{ subscriber.name }}"
+                "#,
+            ),
+        ]);
+        let templates = assert_ok!(LocalizedEmailTemplates::load(
+            &directory,
+            locale("en"),
+            &VARIABLES
+        ));
+        let context = context! { subscriber => context! { name => "Tom & Jerry" }, link => Value::from_safe_string(String::from("/confirm")) };
+
+        let email = assert_ok!(templates.render(&locale("es-MX"), context.clone()));
+
+        assert_eq!(email.subject, "Bienvenido Tom & Jerry");
+        assert_eq!(email.text, "Hola Tom & Jerry");
+
+        let email = assert_ok!(templates.render(&locale("fr"), context));
+
+        assert_eq!(email.subject, "Welcome Tom & Jerry");
+        assert_eq!(email.html, "<a href='/confirm'>Confirm</a>");
+        assert_eq!(email.text, "Confirm [1]\n\n[1] /confirm");
+    }
+
+    #[test]
+    fn negotiated_locale_has_a_translation() {
+        let directory = templates_directory(&[
+            ("en.toml", "subject = \"Welcome\"\nhtml = \"<p>Hi</p>\""),
+            (
+                "es.toml",
+                "subject = \"Bienvenido\"\nhtml = \"<p>Hola</p>\"",
+            ),
+        ]);
+        let templates = assert_ok!(LocalizedEmailTemplates::load(
+            &directory,
+            locale("en"),
+            &VARIABLES
+        ));
+
+        assert_eq!(
+            templates.negotiate(&[locale("fr"), locale("es-ES"), locale("en")]),
+            locale("es")
+        );
+        assert_eq!(templates.negotiate(&[locale("fr")]), locale("en"));
+    }
+
+    #[test]
+    fn templates_with_unknown_variables_are_rejected() {
+        let directory = templates_directory(&[(
+            "en.toml",
+            "subject = \"Welcome\"\nhtml = \"<p>{{ subscriber.email }}</p>\"",
+        )]);
+
+        assert_err!(LocalizedEmailTemplates::load(
+            &directory,
+            locale("en"),
+            &VARIABLES
+        ));
+    }
+
+    #[test]
+    fn templates_without_default_locale_are_rejected() {
+        let directory =
+            templates_directory(&[("es.toml", "subject = \"Hola\"\nhtml = \"<p>Hola</p>\"")]);
+
+        assert_err!(LocalizedEmailTemplates::load(
+            &directory,
+            locale("en"),
+            &VARIABLES
+        ));
+    }
+}
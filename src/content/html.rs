@@ -0,0 +1,414 @@
+/// Minimal HTML tokenizer used to analyse and transform email content. It is not a full HTML5 parser: it only splits
+/// the document into tags and text, which is enough for the markup we send by email.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HtmlToken<'a> {
+    StartTag(StartTag),
+    EndTag(String),
+    Text(&'a str),
+    Comment(&'a str),
+    Doctype(&'a str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StartTag {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub self_closing: bool,
+}
+
+/// Elements that never have content nor a closing tag
+pub const VOID_ELEMENTS: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+// Elements whose content is not HTML and has to be read verbatim until the closing tag
+const RAW_TEXT_ELEMENTS: [&str; 2] = ["script", "style"];
+
+impl StartTag {
+    pub fn get_attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attribute_name, _)| attribute_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn is_void(&self) -> bool {
+        self.self_closing || VOID_ELEMENTS.contains(&self.name.as_str())
+    }
+
+    pub fn has_class(&self, class: &str) -> bool {
+        self.get_attribute("class")
+            .map(|classes| classes.split_whitespace().any(|name| name == class))
+            .unwrap_or(false)
+    }
+
+    /// Adds the declarations before the existing inline style, so the ones already in the element take precedence
+    pub fn add_style(&mut self, declarations: &str) {
+        match self
+            .attributes
+            .iter_mut()
+            .find(|(attribute_name, _)| attribute_name == "style")
+        {
+            Some((_, style)) if !style.trim().is_empty() => {
+                *style = format!("{} {}", declarations, style)
+            }
+            Some((_, style)) => *style = String::from(declarations),
+            None => self
+                .attributes
+                .push((String::from("style"), String::from(declarations))),
+        }
+    }
+}
+
+impl std::fmt::Display for StartTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<{}", self.name)?;
+
+        for (name, value) in &self.attributes {
+            if value.is_empty() {
+                write!(f, " {}", name)?;
+            } else {
+                write!(f, " {}=\"{}\"", name, escape_attribute(value))?;
+            }
+        }
+
+        if self.self_closing {
+            write!(f, " />")
+        } else {
+            write!(f, ">")
+        }
+    }
+}
+
+impl std::fmt::Display for HtmlToken<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::StartTag(tag) => tag.fmt(f),
+            Self::EndTag(name) => write!(f, "</{}>", name),
+            Self::Text(text) => write!(f, "{}", text),
+            Self::Comment(comment) => write!(f, "<!--{}-->", comment),
+            Self::Doctype(doctype) => write!(f, "<!{}>", doctype),
+        }
+    }
+}
+
+pub fn tokenize(html: &str) -> Vec<HtmlToken<'_>> {
+    let mut tokens = vec![];
+    let mut position = 0;
+
+    while position < html.len() {
+        let rest = &html[position..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            let end = comment.find("-->").unwrap_or(comment.len());
+
+            tokens.push(HtmlToken::Comment(&comment[..end]));
+            position += 4 + (end + 3).min(comment.len());
+        } else if let Some(doctype) = rest.strip_prefix("<!") {
+            let end = doctype.find('>').unwrap_or(doctype.len());
+
+            tokens.push(HtmlToken::Doctype(&doctype[..end]));
+            position += 2 + (end + 1).min(doctype.len());
+        } else if let Some(end_tag) = rest.strip_prefix("</") {
+            let end = end_tag.find('>').unwrap_or(end_tag.len());
+            let name = end_tag[..end]
+                .split_whitespace()
+                .next()
+                .unwrap_or("")
+                .to_lowercase();
+
+            tokens.push(HtmlToken::EndTag(name));
+            position += 2 + (end + 1).min(end_tag.len());
+        } else if rest.starts_with('<')
+            && rest[1..].starts_with(|char: char| char.is_ascii_alphabetic())
+        {
+            let (start_tag, length) = parse_start_tag(rest);
+            let is_raw_text = RAW_TEXT_ELEMENTS.contains(&start_tag.name.as_str());
+            let name = start_tag.name.clone();
+
+            tokens.push(HtmlToken::StartTag(start_tag));
+            position += length;
+
+            if is_raw_text {
+                let closing_tag = format!("</{}", name);
+                let content = &html[position..];
+                let end = content
+                    .to_ascii_lowercase()
+                    .find(&closing_tag)
+                    .unwrap_or(content.len());
+
+                if end > 0 {
+                    tokens.push(HtmlToken::Text(&content[..end]));
+                }
+
+                position += end;
+            }
+        } else {
+            // A '<' that does not start a tag is just text
+            let end = rest
+                .char_indices()
+                .skip(1)
+                .find(|(_, char)| *char == '<')
+                .map(|(end, _)| end)
+                .unwrap_or(rest.len());
+
+            tokens.push(HtmlToken::Text(&rest[..end]));
+            position += end;
+        }
+    }
+
+    tokens
+}
+
+fn parse_start_tag(html: &str) -> (StartTag, usize) {
+    let chars: Vec<(usize, char)> = html.char_indices().collect();
+    let mut index = 1;
+    let mut name = String::new();
+    let mut attributes = vec![];
+    let mut self_closing = false;
+
+    while index < chars.len() && is_name_char(chars[index].1) {
+        name.push(chars[index].1.to_ascii_lowercase());
+        index += 1;
+    }
+
+    loop {
+        while index < chars.len() && chars[index].1.is_whitespace() {
+            index += 1;
+        }
+
+        if index >= chars.len() {
+            break;
+        }
+
+        match chars[index].1 {
+            '>' => {
+                index += 1;
+                break;
+            }
+            '/' => {
+                self_closing = true;
+                index += 1;
+            }
+            _ => {
+                let mut attribute_name = String::new();
+
+                while index < chars.len()
+                    && !chars[index].1.is_whitespace()
+                    && !['=', '>', '/'].contains(&chars[index].1)
+                {
+                    attribute_name.push(chars[index].1.to_ascii_lowercase());
+                    index += 1;
+                }
+
+                let mut value = String::new();
+
+                if index < chars.len() && chars[index].1 == '=' {
+                    index += 1;
+
+                    match chars.get(index).map(|(_, char)| *char) {
+                        Some(quote) if quote == '"' || quote == '\'' => {
+                            index += 1;
+
+                            while index < chars.len() && chars[index].1 != quote {
+                                value.push(chars[index].1);
+                                index += 1;
+                            }
+
+                            index += 1;
+                        }
+                        _ => {
+                            while index < chars.len()
+                                && !chars[index].1.is_whitespace()
+                                && chars[index].1 != '>'
+                            {
+                                value.push(chars[index].1);
+                                index += 1;
+                            }
+                        }
+                    }
+                }
+
+                if !attribute_name.is_empty() {
+                    self_closing = false;
+                    attributes.push((attribute_name, decode_entities(&value)));
+                }
+            }
+        }
+    }
+
+    let length = chars
+        .get(index)
+        .map(|(offset, _)| *offset)
+        .unwrap_or(html.len());
+
+    (
+        StartTag {
+            name,
+            attributes,
+            self_closing,
+        },
+        length,
+    )
+}
+
+fn is_name_char(char: char) -> bool {
+    char.is_ascii_alphanumeric() || char == '-' || char == ':'
+}
+
+/// Writes the tokens back as HTML
+pub fn serialize(tokens: &[HtmlToken<'_>]) -> String {
+    tokens.iter().map(HtmlToken::to_string).collect()
+}
+
+/// Escapes text to be placed in HTML or XML content and attributes
+pub fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Merge tags in attributes, e.g. `{% if a > b %}`, are kept readable by escaping only what ends the value
+fn escape_attribute(value: &str) -> String {
+    value.replace('&', "&amp;").replace('"', "&quot;")
+}
+
+/// Decodes the character references that are common in email content
+pub fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity = rest[1..]
+            .find(';')
+            .filter(|end| *end <= 10)
+            .map(|end| &rest[1..end + 1]);
+        let char = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        });
+
+        match (entity, char) {
+            (Some(entity), Some(char)) => {
+                decoded.push(char);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    decoded.push_str(rest);
+
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start_tag(name: &str, attributes: Vec<(&str, &str)>) -> HtmlToken<'static> {
+        HtmlToken::StartTag(StartTag {
+            name: String::from(name),
+            attributes: attributes
+                .into_iter()
+                .map(|(name, value)| (String::from(name), String::from(value)))
+                .collect(),
+            self_closing: false,
+        })
+    }
+
+    #[test]
+    fn tags_and_text_are_tokenized() {
+        let tokens = tokenize(r#"<p class="intro">Hi <a href='https://test.com'>there</a></p>"#);
+
+        assert_eq!(
+            tokens,
+            vec![
+                start_tag("p", vec![("class", "intro")]),
+                HtmlToken::Text("Hi "),
+                start_tag("a", vec![("href", "https://test.com")]),
+                HtmlToken::Text("there"),
+                HtmlToken::EndTag(String::from("a")),
+                HtmlToken::EndTag(String::from("p")),
+            ]
+        );
+    }
+
+    #[test]
+    fn script_content_is_read_as_text() {
+        let tokens = tokenize("<script>if (a < b) {}</script>");
+
+        assert_eq!(tokens[1], HtmlToken::Text("if (a < b) {}"));
+        assert_eq!(tokens[2], HtmlToken::EndTag(String::from("script")));
+    }
+
+    #[test]
+    fn comments_and_doctype_are_tokenized() {
+        let tokens = tokenize("<!DOCTYPE html><!-- note --><br/>");
+
+        assert_eq!(tokens[0], HtmlToken::Doctype("DOCTYPE html"));
+        assert_eq!(tokens[1], HtmlToken::Comment(" note "));
+        assert!(matches!(&tokens[2], HtmlToken::StartTag(tag) if tag.self_closing));
+    }
+
+    #[test]
+    fn non_ascii_text_is_tokenized() {
+        let tokens = tokenize("¡Hola! <b>café</b> 1 < 2");
+
+        assert_eq!(tokens[0], HtmlToken::Text("¡Hola! "));
+        assert_eq!(tokens[2], HtmlToken::Text("café"));
+        assert_eq!(tokens[4], HtmlToken::Text(" 1 "));
+        assert_eq!(tokens[5], HtmlToken::Text("< 2"));
+    }
+
+    #[test]
+    fn tokens_are_serialized_back_to_html() {
+        let mut tokens =
+            tokenize(r#"<!DOCTYPE html><p style="color: red;">Tom &amp; Jerry<br/></p>"#);
+
+        if let HtmlToken::StartTag(tag) = &mut tokens[1] {
+            tag.add_style("margin: 0;");
+            tag.attributes
+                .push((String::from("title"), String::from("\"Quoted\"")));
+        }
+
+        assert_eq!(
+            serialize(&tokens),
+            r#"<!DOCTYPE html><p style="margin: 0; color: red;" title="&quot;Quoted&quot;">Tom &amp; Jerry<br /></p>"#
+        );
+    }
+
+    #[test]
+    fn entities_are_decoded() {
+        assert_eq!(
+            decode_entities("Tom &amp; Jerry &lt;3 &#39;&#x41;&#66;&apos; & more"),
+            "Tom & Jerry <3 'AB' & more"
+        );
+    }
+
+    #[test]
+    fn text_is_escaped() {
+        assert_eq!(
+            escape_text(r#"Tom & "Jerry" <3"#),
+            "Tom &amp; &quot;Jerry&quot; &lt;3"
+        );
+    }
+}
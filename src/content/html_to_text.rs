@@ -0,0 +1,287 @@
+use crate::content::html::{decode_entities, tokenize, HtmlToken};
+
+// Elements rendered as a paragraph, separated from their siblings by a blank line
+const PARAGRAPH_ELEMENTS: [&str; 13] = [
+    "p",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "ul",
+    "ol",
+    "table",
+    "blockquote",
+    "pre",
+    "hr",
+];
+// Elements rendered in their own line
+const LINE_ELEMENTS: [&str; 10] = [
+    "div", "li", "tr", "section", "article", "header", "footer", "main", "nav", "dl",
+];
+// Elements whose content is not shown to readers
+const HIDDEN_ELEMENTS: [&str; 4] = ["head", "style", "script", "title"];
+
+/// Generates a readable plain-text version of an HTML email. Links are replaced by numbered footnotes, e.g.
+/// `Read our blog [1]`, which are listed at the end of the text.
+pub fn html_to_text(html: &str) -> String {
+    let mut writer = TextWriter::default();
+    let mut footnotes: Vec<String> = vec![];
+    let mut links: Vec<Option<String>> = vec![];
+    let mut lists: Vec<Option<usize>> = vec![];
+    let mut hidden_depth = 0;
+    let mut pre_depth = 0;
+
+    for token in tokenize(html) {
+        match token {
+            HtmlToken::StartTag(tag) if HIDDEN_ELEMENTS.contains(&tag.name.as_str()) => {
+                if !tag.is_void() {
+                    hidden_depth += 1;
+                }
+            }
+            HtmlToken::EndTag(name) if HIDDEN_ELEMENTS.contains(&name.as_str()) => {
+                hidden_depth = usize::max(hidden_depth, 1) - 1;
+            }
+            _ if hidden_depth > 0 => {}
+            HtmlToken::StartTag(tag) => {
+                let name = tag.name.as_str();
+
+                match name {
+                    "br" => writer.line_break(),
+                    "hr" => {
+                        writer.block_break(2);
+                        writer.push_raw("----------");
+                        writer.block_break(2);
+                    }
+                    "ul" => lists.push(None),
+                    "ol" => lists.push(Some(0)),
+                    "li" => {
+                        writer.block_break(1);
+
+                        let indentation = "  ".repeat(lists.len().saturating_sub(1));
+                        let bullet = match lists.last_mut() {
+                            Some(Some(number)) => {
+                                *number += 1;
+                                format!("{}. ", number)
+                            }
+                            _ => String::from("- "),
+                        };
+
+                        writer.push_raw(&format!("{}{}", indentation, bullet));
+                    }
+                    "a" => links.push(tag.get_attribute("href").map(String::from)),
+                    "img" => {
+                        if let Some(alt) = tag.get_attribute("alt").filter(|alt| !alt.is_empty()) {
+                            writer.push_text(&format!("[{}]", alt));
+                        }
+                    }
+                    "pre" => pre_depth += 1,
+                    "sup" => writer.push_raw("^"),
+                    "td" | "th" => writer.push_text(" "),
+                    _ => {}
+                }
+
+                if PARAGRAPH_ELEMENTS.contains(&name) && name != "hr" {
+                    writer.block_break(2);
+                } else if LINE_ELEMENTS.contains(&name) && name != "li" {
+                    writer.block_break(1);
+                }
+            }
+            HtmlToken::EndTag(name) => {
+                match name.as_str() {
+                    "ul" | "ol" => {
+                        lists.pop();
+                    }
+                    "a" => {
+                        if let Some(Some(href)) = links.pop() {
+                            if let Some(footnote) = get_footnote(&href, &writer) {
+                                footnotes.push(footnote);
+                                writer.push_text(&format!(" [{}]", footnotes.len()));
+                            }
+                        }
+                    }
+                    "pre" => pre_depth = usize::max(pre_depth, 1) - 1,
+                    _ => {}
+                }
+
+                if PARAGRAPH_ELEMENTS.contains(&name.as_str()) {
+                    writer.block_break(2);
+                } else if LINE_ELEMENTS.contains(&name.as_str()) {
+                    writer.block_break(1);
+                }
+            }
+            HtmlToken::Text(text) if pre_depth > 0 => writer.push_raw(&decode_entities(text)),
+            HtmlToken::Text(text) => writer.push_text(&decode_entities(text)),
+            HtmlToken::Comment(_) | HtmlToken::Doctype(_) => {}
+        }
+    }
+
+    let mut text = writer.finish();
+
+    if !footnotes.is_empty() {
+        text.push_str("\n\n");
+        text.push_str(
+            &footnotes
+                .iter()
+                .enumerate()
+                .map(|(index, url)| format!("[{}] {}", index + 1, url))
+                .collect::<Vec<String>>()
+                .join("\n"),
+        );
+    }
+
+    text
+}
+
+// Links pointing to the same page or whose text is already the URL do not need a footnote
+fn get_footnote(href: &str, writer: &TextWriter) -> Option<String> {
+    let href = href.trim();
+
+    if href.is_empty() || href.starts_with('#') || writer.output.trim_end().ends_with(href) {
+        return None;
+    }
+
+    Some(String::from(href.strip_prefix("mailto:").unwrap_or(href)))
+}
+
+#[derive(Default)]
+struct TextWriter {
+    output: String,
+    pending_breaks: usize,
+    pending_space: bool,
+}
+
+impl TextWriter {
+    /// Adds text collapsing whitespace, as browsers do
+    fn push_text(&mut self, text: &str) {
+        if text.starts_with(char::is_whitespace) {
+            self.pending_space = true;
+        }
+
+        for (index, word) in text.split_whitespace().enumerate() {
+            if index > 0 {
+                self.pending_space = true;
+            }
+
+            self.push_word(word);
+        }
+
+        if text.ends_with(char::is_whitespace) {
+            self.pending_space = true;
+        }
+    }
+
+    fn push_word(&mut self, word: &str) {
+        if self.output.is_empty() {
+            self.pending_breaks = 0;
+        } else if self.pending_breaks > 0 {
+            self.output.push_str(&"\n".repeat(self.pending_breaks));
+            self.pending_breaks = 0;
+        } else if self.pending_space && !self.output.ends_with(char::is_whitespace) {
+            self.output.push(' ');
+        }
+
+        self.pending_space = false;
+        self.output.push_str(word);
+    }
+
+    /// Adds text preserving its whitespace
+    fn push_raw(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+
+        if !self.output.is_empty() && self.pending_breaks > 0 {
+            self.output.push_str(&"\n".repeat(self.pending_breaks));
+        }
+
+        self.pending_breaks = 0;
+        self.pending_space = false;
+        self.output.push_str(text);
+    }
+
+    fn line_break(&mut self) {
+        self.output.push('\n');
+        self.pending_space = false;
+    }
+
+    fn block_break(&mut self, breaks: usize) {
+        let trailing_breaks = self.output.len() - self.output.trim_end_matches('\n').len();
+
+        self.pending_breaks =
+            usize::max(self.pending_breaks, breaks.saturating_sub(trailing_breaks));
+        self.pending_space = false;
+    }
+
+    fn finish(self) -> String {
+        self.output
+            .lines()
+            .map(str::trim_end)
+            .collect::<Vec<&str>>()
+            .join("\n")
+            .trim()
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::html_to_text;
+
+    #[test]
+    fn paragraphs_are_separated_by_a_blank_line() {
+        let text =
+            html_to_text("<h1>Title</h1><p>First   paragraph\n with spaces.</p><p>Second</p>");
+
+        assert_eq!(text, "Title\n\nFirst paragraph with spaces.\n\nSecond");
+    }
+
+    #[test]
+    fn links_become_footnotes() {
+        let text = html_to_text(
+            r#"<p>Read <a href="https://blog.test.com/post">our post</a> or <a href="https://test.com">https://test.com</a>.</p>"#,
+        );
+
+        assert_eq!(
+            text,
+            "Read our post [1] or https://test.com.\n\n[1] https://blog.test.com/post"
+        );
+    }
+
+    #[test]
+    fn lists_are_rendered_with_bullets() {
+        let text =
+            html_to_text("<ul><li>One</li><li>Two</li></ul><ol><li>First</li><li>Second</li></ol>");
+
+        assert_eq!(text, "- One\n- Two\n\n1. First\n2. Second");
+    }
+
+    #[test]
+    fn hidden_content_is_removed() {
+        let text = html_to_text(
+            "<html><head><title>Issue</title><style>p { color: red; }</style></head><body><p>Hi &amp; welcome</p></body></html>",
+        );
+
+        assert_eq!(text, "Hi & welcome");
+    }
+
+    #[test]
+    fn line_breaks_and_images_are_kept() {
+        let text = html_to_text(r#"<p>Line one<br>Line two <img src="a.png" alt="Logo"></p>"#);
+
+        assert_eq!(text, "Line one\nLine two [Logo]");
+    }
+
+    #[test]
+    fn merge_tags_are_kept() {
+        let text = html_to_text(
+            r#"<p>Hi {{ subscriber.name }}</p><a href="{{ unsubscribe_url }}">Unsubscribe</a>"#,
+        );
+
+        assert_eq!(
+            text,
+            "Hi {{ subscriber.name }}\n\nUnsubscribe [1]\n\n[1] {{ unsubscribe_url }}"
+        );
+    }
+}
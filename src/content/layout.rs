@@ -0,0 +1,121 @@
+use crate::content::template::MergeTemplate;
+
+/// Reusable HTML shared by several newsletter issues, usually the header and footer. The `{{ content }}` slot marks
+/// where the content of each issue goes. The rest of the layout can use the same merge tags as the issues, e.g.
+///
+/// ```text
+/// <html>
+///   <body>
+///     <img src="https://example.com/logo.png" alt="Logo">
+///     {{ content }}
+///     <a href="{{ unsubscribe_url }}">Unsubscribe</a>
+///   </body>
+/// </html>
+/// ```
+#[derive(Debug)]
+pub struct LayoutTemplate(String);
+
+impl LayoutTemplate {
+    pub fn parse(source: String) -> Result<LayoutTemplate, String> {
+        let slots = find_content_slots(&source);
+
+        if slots.len() != 1 {
+            return Err(format!(
+                "A layout must have exactly one {{{{ content }}}} slot, but it has {}.",
+                slots.len()
+            ));
+        }
+
+        let layout = Self(source);
+
+        MergeTemplate::parse(layout.wrap(""))?;
+
+        Ok(layout)
+    }
+
+    /// Returns the layout with the content in its slot
+    pub fn wrap(&self, content: &str) -> String {
+        match find_content_slots(&self.0).first() {
+            Some((start, end)) => format!("{}{}{}", &self.0[..*start], content, &self.0[*end..]),
+            None => String::from(content),
+        }
+    }
+}
+
+impl AsRef<str> for LayoutTemplate {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for LayoutTemplate {
+    // Used for layouts coming from the database, which were validated when they were saved
+    fn from(source: String) -> Self {
+        Self(source)
+    }
+}
+
+impl serde::Serialize for LayoutTemplate {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+// Returns the start and end offsets of every `{{ content }}` tag, whatever its spacing
+fn find_content_slots(source: &str) -> Vec<(usize, usize)> {
+    let mut slots = vec![];
+    let mut offset = 0;
+
+    while let Some(start) = source[offset..].find("{{").map(|start| offset + start) {
+        let Some(end) = source[start..].find("}}").map(|end| start + end + 2) else {
+            break;
+        };
+
+        if source[start + 2..end - 2].trim() == "content" {
+            slots.push((start, end));
+        }
+
+        offset = end;
+    }
+
+    slots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LayoutTemplate;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn layout_without_content_slot_is_rejected() {
+        assert_err!(LayoutTemplate::parse(String::from(
+            "<body>{{ subscriber.name }}</body>"
+        )));
+    }
+
+    #[test]
+    fn layout_with_several_content_slots_is_rejected() {
+        assert_err!(LayoutTemplate::parse(String::from(
+            "<body>{{ content }}{{content}}</body>"
+        )));
+    }
+
+    #[test]
+    fn layout_with_invalid_merge_tags_is_rejected() {
+        assert_err!(LayoutTemplate::parse(String::from(
+            "<body>{{ content }}{{ unknown }}</body>"
+        )));
+    }
+
+    #[test]
+    fn content_is_placed_in_the_slot() {
+        let layout = assert_ok!(LayoutTemplate::parse(String::from(
+            r#"<body>{{content}}<a href="{{ unsubscribe_url }}">Unsubscribe</a></body>"#
+        )));
+
+        assert_eq!(
+            layout.wrap("<p>Issue</p>"),
+            r#"<body><p>Issue</p><a href="{{ unsubscribe_url }}">Unsubscribe</a></body>"#
+        );
+    }
+}
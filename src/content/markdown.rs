@@ -0,0 +1,290 @@
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag};
+use std::collections::HashMap;
+
+use crate::content::html::{serialize, tokenize, HtmlToken};
+
+// Merge tags are swapped by placeholders while parsing so Markdown does not mangle them, e.g. inside link URLs
+const MERGE_TAG_DELIMITERS: [(&str, &str); 3] = [("{{", "}}"), ("{%", "%}"), ("{#", "#}")];
+const MERGE_TAG_PLACEHOLDER: &str = "MERGETAGPLACEHOLDER";
+
+// Email clients drop <style> blocks often, so every element is styled inline
+const INLINE_STYLES: [(&str, &str); 16] = [
+    ("h1", "margin: 0 0 16px; font-size: 28px; line-height: 1.25;"),
+    ("h2", "margin: 24px 0 12px; font-size: 22px; line-height: 1.3;"),
+    ("h3", "margin: 20px 0 8px; font-size: 18px; line-height: 1.4;"),
+    ("p", "margin: 0 0 16px;"),
+    ("a", "color: #2563eb; text-decoration: underline;"),
+    ("img", "display: block; max-width: 100%; height: auto; border: 0;"),
+    ("ul", "margin: 0 0 16px; padding-left: 24px;"),
+    ("ol", "margin: 0 0 16px; padding-left: 24px;"),
+    ("li", "margin: 0 0 4px;"),
+    (
+        "blockquote",
+        "margin: 0 0 16px; padding: 0 16px; border-left: 4px solid #d4d4d8; color: #52525b;",
+    ),
+    (
+        "pre",
+        "margin: 0 0 16px; padding: 12px 16px; background-color: #f4f4f5; border-radius: 4px; overflow-x: auto; white-space: pre;",
+    ),
+    (
+        "code",
+        "font-family: Menlo, Consolas, 'Courier New', monospace; font-size: 14px;",
+    ),
+    ("table", "margin: 0 0 16px; border-collapse: collapse;"),
+    ("th", "padding: 6px 12px; border: 1px solid #d4d4d8; text-align: left;"),
+    ("td", "padding: 6px 12px; border: 1px solid #d4d4d8;"),
+    ("hr", "margin: 24px 0; border: 0; border-top: 1px solid #e4e4e7;"),
+];
+const FOOTNOTES_STYLE: &str = "font-size: 14px; color: #52525b;";
+
+/// Renders Markdown to an HTML fragment styled for email clients. Besides CommonMark, it supports tables,
+/// strikethrough and footnotes, which are listed at the end of the content.
+pub fn markdown_to_html(markdown: &str) -> String {
+    let (markdown, merge_tags) = protect_merge_tags(markdown);
+    let options =
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_FOOTNOTES;
+    let events = move_footnotes_to_the_end(Parser::new_ext(&markdown, options));
+    let mut html = String::new();
+
+    html::push_html(&mut html, events.into_iter());
+
+    restore_merge_tags(add_inline_styles(&html), &merge_tags)
+}
+
+/// Wraps the content in a table based layout, the most reliable way of centering content across email clients
+pub fn wrap_in_email_layout(content: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+</head>
+<body style="margin: 0; padding: 0; background-color: #f4f4f5;">
+<table role="presentation" width="100%" cellpadding="0" cellspacing="0" border="0" style="background-color: #f4f4f5;">
+<tr>
+<td align="center" style="padding: 24px 12px;">
+<table role="presentation" width="600" cellpadding="0" cellspacing="0" border="0" style="width: 100%; max-width: 600px; background-color: #ffffff;">
+<tr>
+<td style="padding: 32px; font-family: Arial, Helvetica, sans-serif; font-size: 16px; line-height: 1.5; color: #27272a;">
+{}
+</td>
+</tr>
+</table>
+</td>
+</tr>
+</table>
+</body>
+</html>"#,
+        content
+    )
+}
+
+fn protect_merge_tags(markdown: &str) -> (String, Vec<String>) {
+    let mut protected = String::with_capacity(markdown.len());
+    let mut merge_tags = vec![];
+    let mut rest = markdown;
+
+    while let Some((start, (_, closing))) = MERGE_TAG_DELIMITERS
+        .iter()
+        .filter_map(|delimiters| rest.find(delimiters.0).map(|start| (start, delimiters)))
+        .min_by_key(|(start, _)| *start)
+    {
+        let Some(end) = rest[start + 2..]
+            .find(closing)
+            .map(|end| start + 2 + end + 2)
+        else {
+            break;
+        };
+
+        protected.push_str(&rest[..start]);
+        protected.push_str(&placeholder(merge_tags.len()));
+        merge_tags.push(String::from(&rest[start..end]));
+        rest = &rest[end..];
+    }
+
+    protected.push_str(rest);
+
+    (protected, merge_tags)
+}
+
+fn restore_merge_tags(html: String, merge_tags: &[String]) -> String {
+    merge_tags
+        .iter()
+        .enumerate()
+        .fold(html, |html, (index, merge_tag)| {
+            html.replace(&placeholder(index), merge_tag)
+        })
+}
+
+// The trailing letter keeps the placeholder of the first tag from matching the start of the eleventh
+fn placeholder(index: usize) -> String {
+    format!("{}{}X", MERGE_TAG_PLACEHOLDER, index)
+}
+
+/// Numbers the footnotes in order of reference and renders their definitions as a list after the content
+fn move_footnotes_to_the_end<'a>(parser: Parser<'a, '_>) -> Vec<Event<'a>> {
+    let mut events = vec![];
+    let mut numbers: HashMap<CowStr<'a>, usize> = HashMap::new();
+    let mut definitions: Vec<(CowStr<'a>, Vec<Event<'a>>)> = vec![];
+    let mut current_definition: Option<(CowStr<'a>, Vec<Event<'a>>)> = None;
+
+    for event in parser {
+        match event {
+            Event::Start(Tag::FootnoteDefinition(name)) => {
+                current_definition = Some((name, vec![]));
+            }
+            Event::End(Tag::FootnoteDefinition(_)) => {
+                definitions.extend(current_definition.take());
+            }
+            event => {
+                let event = match event {
+                    Event::FootnoteReference(name) => {
+                        let next_number = numbers.len() + 1;
+                        let number = *numbers.entry(name).or_insert(next_number);
+
+                        Event::Html(CowStr::from(format!(
+                            r##"<sup class="footnote-reference"><a href="#footnote-{0}">{0}</a></sup>"##,
+                            number
+                        )))
+                    }
+                    event => event,
+                };
+
+                match current_definition.as_mut() {
+                    Some((_, definition_events)) => definition_events.push(event),
+                    None => events.push(event),
+                }
+            }
+        }
+    }
+
+    if definitions.is_empty() {
+        return events;
+    }
+
+    // Definitions that are never referenced are kept after the referenced ones
+    let mut definitions: Vec<(usize, Vec<Event<'a>>)> = definitions
+        .into_iter()
+        .map(|(name, definition_events)| {
+            let next_number = numbers.len() + 1;
+
+            (
+                *numbers.entry(name).or_insert(next_number),
+                definition_events,
+            )
+        })
+        .collect();
+
+    definitions.sort_by_key(|(number, _)| *number);
+    events.push(Event::Html(CowStr::from(
+        r#"<hr class="footnotes-separator"><ol class="footnotes">"#,
+    )));
+
+    for (number, definition_events) in definitions {
+        events.push(Event::Html(CowStr::from(format!(
+            r#"<li id="footnote-{}" value="{}">"#,
+            number, number
+        ))));
+        events.extend(unwrap_single_paragraph(definition_events));
+        events.push(Event::Html(CowStr::from("</li>")));
+    }
+
+    events.push(Event::Html(CowStr::from("</ol>")));
+
+    events
+}
+
+// Most footnotes are a single sentence, which reads better inline with its number
+fn unwrap_single_paragraph(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    let paragraphs = events
+        .iter()
+        .filter(|event| matches!(event, Event::Start(Tag::Paragraph)))
+        .count();
+
+    match (events.first(), events.last()) {
+        (Some(Event::Start(Tag::Paragraph)), Some(Event::End(Tag::Paragraph)))
+            if paragraphs == 1 =>
+        {
+            let length = events.len();
+
+            events.into_iter().skip(1).take(length - 2).collect()
+        }
+        _ => events,
+    }
+}
+
+fn add_inline_styles(html: &str) -> String {
+    let mut tokens = tokenize(html);
+
+    for token in tokens.iter_mut() {
+        if let HtmlToken::StartTag(tag) = token {
+            if let Some((_, style)) = INLINE_STYLES.iter().find(|(name, _)| *name == tag.name) {
+                tag.add_style(style);
+            }
+
+            if tag.has_class("footnotes") {
+                tag.add_style(FOOTNOTES_STYLE);
+            }
+        }
+    }
+
+    serialize(&tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::markdown_to_html;
+    use crate::content::html_to_text::html_to_text;
+
+    #[test]
+    fn elements_are_styled_inline() {
+        let html = markdown_to_html("# Title\n\nSome *text*.");
+
+        assert!(html.starts_with(r#"<h1 style="margin: 0 0 16px;"#));
+        assert!(html.contains(r#"<p style="margin: 0 0 16px;">Some <em>text</em>.</p>"#));
+    }
+
+    #[test]
+    fn code_blocks_are_rendered() {
+        let html = markdown_to_html("```rust\nlet a = 1 < 2;\n```");
+
+        assert!(html.contains("<pre style="));
+        assert!(html.contains(r#"class="language-rust""#));
+        assert!(html.contains("let a = 1 &lt; 2;\n</code></pre>"));
+        assert_eq!(html_to_text(&html), "let a = 1 < 2;");
+    }
+
+    #[test]
+    fn images_are_rendered() {
+        let html = markdown_to_html("![Our logo](https://test.com/logo.png)");
+
+        assert!(html.contains(
+            r#"<img src="https://test.com/logo.png" alt="Our logo" style="display: block;"#
+        ));
+    }
+
+    #[test]
+    fn footnotes_are_numbered_and_listed_at_the_end() {
+        let html = markdown_to_html(
+            "[^unused]: Never referenced.\n\n[^source]: The source.\n\nA claim[^source] and another[^note].\n\n[^note]: A note.",
+        );
+
+        assert_eq!(
+            html_to_text(&html),
+            "A claim^1 and another^2.\n\n----------\n\n1. The source.\n2. A note.\n3. Never referenced."
+        );
+    }
+
+    #[test]
+    fn merge_tags_are_kept() {
+        let html = markdown_to_html(
+            "Hi {{ subscriber.name }}, {% if attr.plan %}*thanks*{% endif %}\n\n[Unsubscribe]({{ unsubscribe_url }})",
+        );
+
+        assert!(
+            html.contains("Hi {{ subscriber.name }}, {% if attr.plan %}<em>thanks</em>{% endif %}")
+        );
+        assert!(html.contains(r#"<a href="{{ unsubscribe_url }}" style="#));
+    }
+}
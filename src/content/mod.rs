@@ -0,0 +1,10 @@
+pub mod archive;
+pub mod check;
+pub mod css;
+pub mod email_template;
+pub mod html;
+pub mod html_to_text;
+pub mod layout;
+pub mod markdown;
+pub mod template;
+pub mod tracking;
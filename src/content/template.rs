@@ -0,0 +1,242 @@
+use minijinja::{context, AutoEscape, Environment, Value};
+
+use crate::domain::subscriber_attributes::AttributesMap;
+
+// Merge tags are limited to these variables. Any other variable is considered a typo and rejected when the issue
+// is created, instead of silently rendering an empty string to every subscriber.
+const SUBSCRIBER_FIELDS: [&str; 2] = ["subscriber.name", "subscriber.email"];
+const URL_VARIABLES: [&str; 3] = ["unsubscribe_url", "preferences_url", "view_in_browser_url"];
+const ATTRIBUTES_VARIABLE: &str = "attr";
+
+/// Newsletter content with merge tags, rendered once per recipient. For example:
+///
+/// ```text
+/// <p>Hi {{ subscriber.name }}!</p>
+/// {% if attr.company %}<p>How is everything at {{ attr.company }}?</p>{% endif %}
+/// <p>Your plan: {{ attr.plan | default("free") }}</p>
+/// <a href="{{ unsubscribe_url }}">Unsubscribe</a> <a href="{{ preferences_url }}">Preferences</a>
+/// <a href="{{ view_in_browser_url }}">View in browser</a>
+/// ```
+#[derive(Debug, Clone)]
+pub struct MergeTemplate(String);
+
+/// Values available to merge tags when rendering the template for a subscriber.
+pub struct MergeContext<'a> {
+    pub subscriber_name: &'a str,
+    pub subscriber_email: &'a str,
+    pub attributes: &'a AttributesMap,
+    pub unsubscribe_url: String,
+    /// Page where the subscriber chooses their timezone
+    pub preferences_url: String,
+    /// Archive page of the issue
+    pub view_in_browser_url: String,
+}
+
+#[derive(serde::Serialize)]
+struct SubscriberContext<'a> {
+    name: &'a str,
+    email: &'a str,
+}
+
+impl MergeTemplate {
+    pub fn parse(source: String) -> Result<MergeTemplate, String> {
+        let environment = Environment::new();
+        let template = environment
+            .template_from_str(&source)
+            .map_err(|err| format!("Invalid template: {}", err))?;
+        let mut unknown_variables: Vec<String> = template
+            .undeclared_variables(true)
+            .into_iter()
+            .filter(|variable| !is_merge_variable(variable))
+            .collect();
+
+        if !unknown_variables.is_empty() {
+            unknown_variables.sort();
+
+            return Err(format!(
+                "Unknown merge tags: {}",
+                unknown_variables.join(", ")
+            ));
+        }
+
+        let template = Self(source);
+
+        // Some errors (e.g. unknown filters) are only detected when rendering
+        template.render(&MergeContext {
+            subscriber_name: "Subscriber",
+            subscriber_email: "subscriber@example.com",
+            attributes: &AttributesMap::new(),
+            unsubscribe_url: String::from("https://example.com/unsubscribe"),
+            preferences_url: String::from("https://example.com/preferences"),
+            view_in_browser_url: String::from("https://example.com/archive/issue"),
+        })?;
+
+        Ok(template)
+    }
+
+    /// Renders the template escaping any subscriber value, so they cannot inject HTML in the email.
+    pub fn render(&self, merge_context: &MergeContext) -> Result<String, String> {
+        self.render_with_escape(merge_context, AutoEscape::Html)
+    }
+
+    /// Renders the template for the plain-text part of an email, where values must not be escaped.
+    pub fn render_text(&self, merge_context: &MergeContext) -> Result<String, String> {
+        self.render_with_escape(merge_context, AutoEscape::None)
+    }
+
+    fn render_with_escape(
+        &self,
+        merge_context: &MergeContext,
+        auto_escape: AutoEscape,
+    ) -> Result<String, String> {
+        let mut environment = Environment::new();
+
+        environment.set_auto_escape_callback(move |_| auto_escape);
+        environment
+            .render_str(
+                &self.0,
+                context! {
+                    subscriber => SubscriberContext {
+                        name: merge_context.subscriber_name,
+                        email: merge_context.subscriber_email,
+                    },
+                    attr => merge_context.attributes,
+                    unsubscribe_url => Value::from_safe_string(merge_context.unsubscribe_url.clone()),
+                    preferences_url => Value::from_safe_string(merge_context.preferences_url.clone()),
+                    view_in_browser_url => Value::from_safe_string(merge_context.view_in_browser_url.clone()),
+                },
+            )
+            .map_err(|err| format!("Failed to render template: {}", err))
+    }
+}
+
+impl AsRef<str> for MergeTemplate {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for MergeTemplate {
+    // Used for templates coming from the database, which were validated when the issue was created
+    fn from(source: String) -> Self {
+        Self(source)
+    }
+}
+
+impl serde::Serialize for MergeTemplate {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+fn is_merge_variable(variable: &str) -> bool {
+    SUBSCRIBER_FIELDS.contains(&variable)
+        || URL_VARIABLES.contains(&variable)
+        || variable == "subscriber"
+        || variable == ATTRIBUTES_VARIABLE
+        || variable.starts_with(&format!("{}.", ATTRIBUTES_VARIABLE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claim::{assert_err, assert_ok};
+
+    fn render(source: &str, attributes: serde_json::Value) -> String {
+        let attributes = attributes.as_object().unwrap().clone();
+
+        MergeTemplate::parse(String::from(source))
+            .unwrap()
+            .render(&MergeContext {
+                subscriber_name: "Frank",
+                subscriber_email: "frank@test.com",
+                attributes: &attributes,
+                unsubscribe_url: String::from("http://localhost/unsubscribe?token=abc"),
+                preferences_url: String::from("http://localhost/preferences?token=abc"),
+                view_in_browser_url: String::from("http://localhost/archive/issue"),
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn template_with_invalid_syntax_is_rejected() {
+        assert_err!(MergeTemplate::parse(String::from(
+            "<p>{% if attr.company %}Hi</p>"
+        )));
+    }
+
+    #[test]
+    fn template_with_unknown_variables_is_rejected() {
+        assert_err!(MergeTemplate::parse(String::from(
+            "<p>Hi {{ subscriber.nmae }}</p>"
+        )));
+    }
+
+    #[test]
+    fn template_with_unknown_filters_is_rejected() {
+        assert_err!(MergeTemplate::parse(String::from(
+            "<p>Hi {{ subscriber.name | shout }}</p>"
+        )));
+    }
+
+    #[test]
+    fn template_without_merge_tags_is_accepted() {
+        assert_ok!(MergeTemplate::parse(String::from("<p>Hi!</p>")));
+    }
+
+    #[test]
+    fn merge_tags_are_replaced_with_subscriber_values() {
+        let html = render(
+            r#"<p>Hi {{ subscriber.name }} from {{ attr.company }}</p><a href="{{ unsubscribe_url }}">Unsubscribe</a>"#,
+            serde_json::json!({ "company": "ACME" }),
+        );
+
+        assert_eq!(
+            html,
+            r#"<p>Hi Frank from ACME</p><a href="http://localhost/unsubscribe?token=abc">Unsubscribe</a>"#
+        );
+    }
+
+    #[test]
+    fn conditional_blocks_and_fallbacks_are_supported() {
+        let source = r#"{% if attr.company %}{{ attr.company }}{% else %}no company{% endif %}, {{ attr.plan | default("free") }}"#;
+
+        assert_eq!(render(source, serde_json::json!({})), "no company, free");
+        assert_eq!(
+            render(
+                source,
+                serde_json::json!({ "company": "ACME", "plan": "pro" })
+            ),
+            "ACME, pro"
+        );
+    }
+
+    #[test]
+    fn subscriber_values_are_escaped() {
+        let html = render(
+            "<p>{{ attr.company }}</p>",
+            serde_json::json!({ "company": "<script>alert(1)</script>" }),
+        );
+
+        assert!(!html.contains("<script>"));
+    }
+
+    #[test]
+    fn subscriber_values_are_not_escaped_in_text() {
+        let text = MergeTemplate::parse(String::from("Hi {{ attr.company }}"))
+            .unwrap()
+            .render_text(&MergeContext {
+                subscriber_name: "Frank",
+                subscriber_email: "frank@test.com",
+                attributes: serde_json::json!({ "company": "Tom & Jerry" })
+                    .as_object()
+                    .unwrap(),
+                unsubscribe_url: String::from("http://localhost/unsubscribe?token=abc"),
+                preferences_url: String::from("http://localhost/preferences?token=abc"),
+                view_in_browser_url: String::from("http://localhost/archive/issue"),
+            })
+            .unwrap();
+
+        assert_eq!(text, "Hi Tom & Jerry");
+    }
+}
@@ -0,0 +1,348 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use linkify::{LinkFinder, LinkKind};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::content::html::{serialize, tokenize, HtmlToken};
+
+/// Adds an invisible 1x1 image to the end of the body, which email clients load when the email is opened.
+pub fn add_open_tracking_pixel(html: &str, pixel_url: &str) -> String {
+    let pixel = format!(
+        r#"<img src="{}" width="1" height="1" alt="" style="display: block; width: 1px; height: 1px; border: 0;">"#,
+        pixel_url
+    );
+
+    match html.to_ascii_lowercase().rfind("</body>") {
+        Some(position) => format!("{}{}{}", &html[..position], pixel, &html[position..]),
+        None => format!("{}{}", html, pixel),
+    }
+}
+
+/// Only web links are tracked, `mailto:`, `tel:` or anchor links are left as they are
+pub fn is_trackable_link(url: &str) -> bool {
+    let url = url.trim_start();
+
+    ["http://", "https://"].iter().any(|scheme| {
+        url.get(..scheme.len())
+            .map(|prefix| prefix.eq_ignore_ascii_case(scheme))
+            .unwrap_or(false)
+    })
+}
+
+/// Replaces the `href` of every link with the URL returned by `rewrite`. Links for which it returns `None` are kept.
+pub fn rewrite_html_links(html: &str, mut rewrite: impl FnMut(&str) -> Option<String>) -> String {
+    let mut tokens = tokenize(html);
+    let mut is_rewritten = false;
+
+    for token in tokens.iter_mut() {
+        let HtmlToken::StartTag(tag) = token else {
+            continue;
+        };
+
+        if tag.name != "a" {
+            continue;
+        }
+
+        if let Some((_, href)) = tag.attributes.iter_mut().find(|(name, _)| name == "href") {
+            if let Some(url) = rewrite(href) {
+                *href = url;
+                is_rewritten = true;
+            }
+        }
+    }
+
+    if is_rewritten {
+        serialize(&tokens)
+    } else {
+        String::from(html)
+    }
+}
+
+/// Replaces every URL found in the plain text with the URL returned by `rewrite`. Links for which it returns `None`
+/// are kept.
+pub fn rewrite_text_links(text: &str, mut rewrite: impl FnMut(&str) -> Option<String>) -> String {
+    let mut finder = LinkFinder::new();
+
+    finder.kinds(&[LinkKind::Url]);
+    finder
+        .spans(text)
+        .map(|span| match span.kind() {
+            Some(LinkKind::Url) => {
+                rewrite(span.as_str()).unwrap_or_else(|| String::from(span.as_str()))
+            }
+            _ => String::from(span.as_str()),
+        })
+        .collect()
+}
+
+/// Token of a tracked link. It contains the delivery and the destination URL, signed so the redirect endpoint
+/// cannot be used to send people to URLs that were not in a newsletter.
+pub fn sign_click_token(hmac_secret: &Secret<String>, delivery_id: &Uuid, url: &str) -> String {
+    sign_payload(
+        hmac_secret,
+        [delivery_id.as_bytes().as_slice(), url.as_bytes()].concat(),
+    )
+}
+
+/// Returns the delivery and the destination URL of a tracked link, if its signature is valid
+pub fn verify_click_token(
+    hmac_secret: &Secret<String>,
+    token: &str,
+) -> Result<(Uuid, String), String> {
+    let payload = verify_payload(hmac_secret, token)?;
+
+    if payload.len() <= 16 {
+        return Err(String::from("The token has no URL"));
+    }
+
+    let (delivery_id, url) = payload.split_at(16);
+    let delivery_id = Uuid::from_slice(delivery_id).map_err(|err| err.to_string())?;
+    let url = String::from_utf8(url.to_vec()).map_err(|err| err.to_string())?;
+
+    Ok((delivery_id, url))
+}
+
+/// Token of the link of the re-engagement email, which keeps an inactive subscriber subscribed. The payload has a
+/// prefix so a click token can never be taken for it.
+pub fn sign_re_engagement_token(hmac_secret: &Secret<String>, subscriber_id: &Uuid) -> String {
+    sign_payload(
+        hmac_secret,
+        [RE_ENGAGEMENT_PREFIX, subscriber_id.as_bytes().as_slice()].concat(),
+    )
+}
+
+/// Returns the subscriber of a re-engagement link, if its signature is valid
+pub fn verify_re_engagement_token(
+    hmac_secret: &Secret<String>,
+    token: &str,
+) -> Result<Uuid, String> {
+    let payload = verify_payload(hmac_secret, token)?;
+    let subscriber_id = payload
+        .strip_prefix(RE_ENGAGEMENT_PREFIX)
+        .ok_or_else(|| String::from("The token is not a re-engagement token"))?;
+
+    Uuid::from_slice(subscriber_id).map_err(|err| err.to_string())
+}
+
+const RE_ENGAGEMENT_PREFIX: &[u8] = b"re-engagement:";
+
+/// Token of the unsubscribe link of a delivery. The delivery id alone is not a secret, it is also in the tracking
+/// pixel and in the events of the email provider.
+pub fn sign_unsubscribe_token(hmac_secret: &Secret<String>, delivery_id: &Uuid) -> String {
+    sign_payload(
+        hmac_secret,
+        [UNSUBSCRIBE_PREFIX, delivery_id.as_bytes().as_slice()].concat(),
+    )
+}
+
+/// Returns the delivery of an unsubscribe link, if its signature is valid
+pub fn verify_unsubscribe_token(hmac_secret: &Secret<String>, token: &str) -> Result<Uuid, String> {
+    let payload = verify_payload(hmac_secret, token)?;
+    let delivery_id = payload
+        .strip_prefix(UNSUBSCRIBE_PREFIX)
+        .ok_or_else(|| String::from("The token is not an unsubscribe token"))?;
+
+    Uuid::from_slice(delivery_id).map_err(|err| err.to_string())
+}
+
+const UNSUBSCRIBE_PREFIX: &[u8] = b"unsubscribe:";
+
+/// Token of the preferences link of a delivery, which lets the subscriber change their preferences without an account
+pub fn sign_preferences_token(hmac_secret: &Secret<String>, subscriber_id: &Uuid) -> String {
+    sign_payload(
+        hmac_secret,
+        [PREFERENCES_PREFIX, subscriber_id.as_bytes().as_slice()].concat(),
+    )
+}
+
+/// Returns the subscriber of a preferences link, if its signature is valid
+pub fn verify_preferences_token(hmac_secret: &Secret<String>, token: &str) -> Result<Uuid, String> {
+    let payload = verify_payload(hmac_secret, token)?;
+    let subscriber_id = payload
+        .strip_prefix(PREFERENCES_PREFIX)
+        .ok_or_else(|| String::from("The token is not a preferences token"))?;
+
+    Uuid::from_slice(subscriber_id).map_err(|err| err.to_string())
+}
+
+const PREFERENCES_PREFIX: &[u8] = b"preferences:";
+
+fn sign_payload(hmac_secret: &Secret<String>, payload: Vec<u8>) -> String {
+    let signature = new_mac(hmac_secret).chain_update(&payload).finalize();
+
+    format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(payload),
+        URL_SAFE_NO_PAD.encode(signature.into_bytes())
+    )
+}
+
+fn verify_payload(hmac_secret: &Secret<String>, token: &str) -> Result<Vec<u8>, String> {
+    let (payload, signature) = token
+        .split_once('.')
+        .ok_or_else(|| String::from("The token has no signature"))?;
+    let payload = URL_SAFE_NO_PAD
+        .decode(payload)
+        .map_err(|_| String::from("The token is not valid base64"))?;
+    let signature = URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(|_| String::from("The signature is not valid base64"))?;
+
+    new_mac(hmac_secret)
+        .chain_update(&payload)
+        .verify_slice(&signature)
+        .map_err(|_| String::from("The signature is not valid"))?;
+
+    Ok(payload)
+}
+
+fn new_mac(hmac_secret: &Secret<String>) -> Hmac<Sha256> {
+    Hmac::<Sha256>::new_from_slice(hmac_secret.expose_secret().as_bytes())
+        .expect("HMAC accepts keys of any size")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claim::{assert_err, assert_ok};
+
+    fn secret() -> Secret<String> {
+        Secret::new(String::from("secret"))
+    }
+
+    #[test]
+    fn pixel_is_added_at_the_end_of_the_body() {
+        let html = add_open_tracking_pixel("<html><BODY><p>Hi</p></BODY></html>", "/t/o/1.gif");
+
+        assert!(html.starts_with(r#"<html><BODY><p>Hi</p><img src="/t/o/1.gif""#));
+        assert!(html.ends_with("></BODY></html>"));
+    }
+
+    #[test]
+    fn pixel_is_appended_to_fragments() {
+        let html = add_open_tracking_pixel("<p>Hi</p>", "/t/o/1.gif");
+
+        assert!(html.starts_with(r#"<p>Hi</p><img src="/t/o/1.gif""#));
+    }
+
+    #[test]
+    fn only_web_links_are_trackable() {
+        assert!(is_trackable_link("https://test.com"));
+        assert!(is_trackable_link(" HTTP://test.com"));
+        assert!(!is_trackable_link("mailto:frank@test.com"));
+        assert!(!is_trackable_link("#top"));
+        assert!(!is_trackable_link("javascript:alert(1)"));
+    }
+
+    #[test]
+    fn html_links_are_rewritten() {
+        let html = rewrite_html_links(
+            r#"<a href="https://test.com/?a=1&amp;b=2">Test</a> <a href="mailto:a@test.com">Mail</a> <img src="https://test.com/a.png">"#,
+            |url| is_trackable_link(url).then(|| format!("/t/c/{}", url.len())),
+        );
+
+        assert_eq!(
+            html,
+            r#"<a href="/t/c/25">Test</a> <a href="mailto:a@test.com">Mail</a> <img src="https://test.com/a.png">"#
+        );
+    }
+
+    #[test]
+    fn text_links_are_rewritten() {
+        let text = rewrite_text_links(
+            "Read it [1]\n\n[1] https://test.com/a, or write to frank@test.com",
+            |url| (url != "https://test.com/b").then(|| String::from("https://t.com/c")),
+        );
+
+        assert_eq!(
+            text,
+            "Read it [1]\n\n[1] https://t.com/c, or write to frank@test.com"
+        );
+    }
+
+    #[test]
+    fn click_tokens_are_verified() {
+        let delivery_id = Uuid::new_v4();
+        let token = sign_click_token(&secret(), &delivery_id, "https://test.com/a?b=c");
+
+        assert_eq!(
+            assert_ok!(verify_click_token(&secret(), &token)),
+            (delivery_id, String::from("https://test.com/a?b=c"))
+        );
+    }
+
+    #[test]
+    fn tampered_click_tokens_are_rejected() {
+        let delivery_id = Uuid::new_v4();
+        let token = sign_click_token(&secret(), &delivery_id, "https://test.com");
+        let (_, signature) = token.split_once('.').unwrap();
+        let forged_payload = URL_SAFE_NO_PAD
+            .encode([delivery_id.as_bytes().as_slice(), b"https://evil.com"].concat());
+
+        assert_err!(verify_click_token(
+            &secret(),
+            &format!("{}.{}", forged_payload, signature)
+        ));
+        assert_err!(verify_click_token(
+            &Secret::new(String::from("other secret")),
+            &token
+        ));
+        assert_err!(verify_click_token(&secret(), "not-a-token"));
+    }
+
+    #[test]
+    fn re_engagement_tokens_are_verified() {
+        let subscriber_id = Uuid::new_v4();
+        let token = sign_re_engagement_token(&secret(), &subscriber_id);
+
+        assert_eq!(
+            assert_ok!(verify_re_engagement_token(&secret(), &token)),
+            subscriber_id
+        );
+    }
+
+    #[test]
+    fn click_tokens_are_not_re_engagement_tokens() {
+        let token = sign_click_token(&secret(), &Uuid::new_v4(), "https://test.com");
+
+        assert_err!(verify_re_engagement_token(&secret(), &token));
+    }
+
+    #[test]
+    fn unsubscribe_tokens_are_verified() {
+        let delivery_id = Uuid::new_v4();
+        let token = sign_unsubscribe_token(&secret(), &delivery_id);
+
+        assert_eq!(
+            assert_ok!(verify_unsubscribe_token(&secret(), &token)),
+            delivery_id
+        );
+        assert_err!(verify_unsubscribe_token(
+            &secret(),
+            &delivery_id.to_string()
+        ));
+        assert_err!(verify_unsubscribe_token(
+            &secret(),
+            &sign_re_engagement_token(&secret(), &delivery_id)
+        ));
+    }
+
+    #[test]
+    fn preferences_tokens_are_verified() {
+        let subscriber_id = Uuid::new_v4();
+        let token = sign_preferences_token(&secret(), &subscriber_id);
+
+        assert_eq!(
+            assert_ok!(verify_preferences_token(&secret(), &token)),
+            subscriber_id
+        );
+        assert_err!(verify_preferences_token(
+            &secret(),
+            &sign_re_engagement_token(&secret(), &subscriber_id)
+        ));
+        assert_err!(verify_preferences_token(&secret(), "preview"));
+    }
+}
@@ -0,0 +1,119 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use std::str::FromStr;
+
+/// Recurring schedule of a newsletter, written as a five-field cron expression (minute, hour, day of month, month
+/// and day of week) evaluated in the local time of a timezone, e.g. `0 8 * * MON` in `Europe/Madrid`.
+#[derive(Debug, Clone)]
+pub struct CronSchedule {
+    expression: String,
+    timezone: Tz,
+    schedule: cron::Schedule,
+}
+
+impl CronSchedule {
+    /// Days of the week must be written by name, e.g. `MON-FRI`: cron implementations disagree on whether `1` is
+    /// Sunday or Monday.
+    pub fn parse(expression: String, timezone: &str) -> Result<CronSchedule, String> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+
+        if fields.len() != 5 {
+            return Err(format!(
+                "{} is not a valid cron expression, it must have five fields",
+                expression
+            ));
+        }
+
+        if fields[4].chars().any(|char| char.is_ascii_digit()) {
+            return Err(format!(
+                "{} is not a valid cron expression, use names for the days of the week (e.g. MON-FRI)",
+                expression
+            ));
+        }
+
+        // The `cron` crate expects the seconds as the first field
+        let schedule = cron::Schedule::from_str(&format!("0 {}", fields.join(" ")))
+            .map_err(|err| format!("{} is not a valid cron expression: {}", expression, err))?;
+        let timezone =
+            Tz::from_str(timezone).map_err(|_| format!("{} is not a valid timezone", timezone))?;
+
+        Ok(Self {
+            expression: fields.join(" "),
+            timezone,
+            schedule,
+        })
+    }
+
+    pub fn timezone(&self) -> &str {
+        self.timezone.name()
+    }
+
+    /// Returns the first time the schedule fires after the given one, if any
+    pub fn next_run_after(&self, after: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.schedule
+            .after(&after.with_timezone(&self.timezone))
+            .next()
+            .map(|next_run| next_run.with_timezone(&Utc))
+    }
+}
+
+impl AsRef<str> for CronSchedule {
+    fn as_ref(&self) -> &str {
+        &self.expression
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CronSchedule;
+    use chrono::{DateTime, Utc};
+    use claim::{assert_err, assert_ok};
+
+    fn utc(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn schedules_fire_in_the_local_time_of_the_timezone() {
+        let schedule = assert_ok!(CronSchedule::parse(
+            String::from("0 8 * * MON"),
+            "Europe/Madrid"
+        ));
+
+        // Madrid is UTC+2 in summer and UTC+1 in winter
+        assert_eq!(
+            schedule.next_run_after(&utc("2026-10-18T12:00:00Z")),
+            Some(utc("2026-10-19T06:00:00Z"))
+        );
+        assert_eq!(
+            schedule.next_run_after(&utc("2026-10-26T06:00:00Z")),
+            Some(utc("2026-10-26T07:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn next_run_is_strictly_after_the_given_time() {
+        let schedule = assert_ok!(CronSchedule::parse(String::from("30 * * * *"), "UTC"));
+
+        assert_eq!(
+            schedule.next_run_after(&utc("2026-10-18T12:30:00Z")),
+            Some(utc("2026-10-18T13:30:00Z"))
+        );
+    }
+
+    #[test]
+    fn invalid_schedules_are_rejected() {
+        for (expression, timezone) in [
+            ("0 8 * * MON", "Europe/Atlantis"),
+            ("0 8 * *", "UTC"),
+            ("0 0 8 * * MON", "UTC"),
+            ("0 25 * * *", "UTC"),
+            ("0 8 * * 1", "UTC"),
+            ("every monday", "UTC"),
+        ] {
+            assert_err!(CronSchedule::parse(String::from(expression), timezone));
+        }
+    }
+}
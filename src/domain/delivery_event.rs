@@ -0,0 +1,47 @@
+/// Something that happened to a delivery after it was sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryEvent {
+    /// The recipient's server accepted the email
+    Delivered,
+    /// The email could not be delivered
+    Bounced,
+    /// The recipient marked the email as spam
+    Complained,
+    Unsubscribed,
+}
+
+impl DeliveryEvent {
+    pub fn parse(event: String) -> Result<DeliveryEvent, String> {
+        match event.as_str() {
+            "delivered" => Ok(DeliveryEvent::Delivered),
+            "bounced" => Ok(DeliveryEvent::Bounced),
+            "complained" => Ok(DeliveryEvent::Complained),
+            "unsubscribed" => Ok(DeliveryEvent::Unsubscribed),
+            _ => Err(format!("{} is not a valid delivery event", event)),
+        }
+    }
+
+    /// Maps the type of a Sendgrid event. Events that are not tracked (e.g. `processed`, or the opens and clicks we
+    /// track ourselves) return `None`.
+    pub fn from_sendgrid(event: &str) -> Option<DeliveryEvent> {
+        match event {
+            "delivered" => Some(DeliveryEvent::Delivered),
+            "bounce" | "dropped" => Some(DeliveryEvent::Bounced),
+            "spamreport" => Some(DeliveryEvent::Complained),
+            "unsubscribe" | "group_unsubscribe" => Some(DeliveryEvent::Unsubscribed),
+            _ => None,
+        }
+    }
+}
+
+impl AsRef<str> for DeliveryEvent {
+    fn as_ref(&self) -> &str {
+        match self {
+            DeliveryEvent::Delivered => "delivered",
+            DeliveryEvent::Bounced => "bounced",
+            DeliveryEvent::Complained => "complained",
+            DeliveryEvent::Unsubscribed => "unsubscribed",
+        }
+    }
+}
@@ -0,0 +1,40 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    Pending,
+    Sent,
+    Failed,
+    /// The subscriber was no longer confirmed when the delivery was processed, or their email is not valid
+    Skipped,
+    /// Held back by an admin until the delivery of the issue is resumed
+    Paused,
+    /// Stopped by an admin before being sent
+    Cancelled,
+}
+
+impl DeliveryStatus {
+    pub fn parse(status: String) -> Result<DeliveryStatus, String> {
+        match status.as_str() {
+            "pending" => Ok(DeliveryStatus::Pending),
+            "sent" => Ok(DeliveryStatus::Sent),
+            "failed" => Ok(DeliveryStatus::Failed),
+            "skipped" => Ok(DeliveryStatus::Skipped),
+            "paused" => Ok(DeliveryStatus::Paused),
+            "cancelled" => Ok(DeliveryStatus::Cancelled),
+            _ => Err(format!("{} is not a valid delivery status", status)),
+        }
+    }
+}
+
+impl AsRef<str> for DeliveryStatus {
+    fn as_ref(&self) -> &str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Sent => "sent",
+            DeliveryStatus::Failed => "failed",
+            DeliveryStatus::Skipped => "skipped",
+            DeliveryStatus::Paused => "paused",
+            DeliveryStatus::Cancelled => "cancelled",
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use chrono::{DateTime, Duration, Utc};
+
+/// Tracking event that changes the engagement score of a subscriber
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EngagementEvent {
+    Open,
+    Click,
+    Bounce,
+}
+
+/// How engaged a subscriber is. Every open, click and bounce adds its weight to the score, and that weight halves
+/// every half-life so recent events count more. Subscribers also earn up to the subscription age weight as they stay
+/// subscribed, at the same pace, so new subscribers start lower than the ones who have been reading for a while.
+///
+/// Only the score and the time it was computed are stored, it is brought up to date when an event arrives or when
+/// it is refreshed.
+#[derive(Debug, Clone)]
+pub struct EngagementScoring {
+    pub open_weight: f64,
+    pub click_weight: f64,
+    pub bounce_weight: f64,
+    pub subscription_age_weight: f64,
+    pub half_life: Duration,
+}
+
+impl EngagementScoring {
+    /// Score at `now` of a subscriber whose score was `score` at `scored_at`. Subscribers who were never scored only
+    /// have the score of their subscription age.
+    pub fn decay(
+        &self,
+        score: f64,
+        scored_at: Option<DateTime<Utc>>,
+        subscribed_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> f64 {
+        let events_score = match scored_at {
+            Some(scored_at) => {
+                (score - self.age_score(subscribed_at, scored_at))
+                    * self.decay_factor(now - scored_at)
+            }
+            None => 0.0,
+        };
+
+        events_score + self.age_score(subscribed_at, now)
+    }
+
+    /// Score at `now` after an event that happened at `now`
+    pub fn add_event(
+        &self,
+        score: f64,
+        scored_at: Option<DateTime<Utc>>,
+        subscribed_at: DateTime<Utc>,
+        event: EngagementEvent,
+        now: DateTime<Utc>,
+    ) -> f64 {
+        self.decay(score, scored_at, subscribed_at, now) + self.weight(event)
+    }
+
+    pub fn weight(&self, event: EngagementEvent) -> f64 {
+        match event {
+            EngagementEvent::Open => self.open_weight,
+            EngagementEvent::Click => self.click_weight,
+            EngagementEvent::Bounce => self.bounce_weight,
+        }
+    }
+
+    fn age_score(&self, subscribed_at: DateTime<Utc>, at: DateTime<Utc>) -> f64 {
+        self.subscription_age_weight * (1.0 - self.decay_factor(at - subscribed_at))
+    }
+
+    fn decay_factor(&self, elapsed: Duration) -> f64 {
+        // Clocks of different instances may disagree a little, time going backwards must not grow the score
+        let elapsed = elapsed.num_seconds().max(0) as f64;
+
+        0.5_f64.powf(elapsed / self.half_life.num_seconds() as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scoring() -> EngagementScoring {
+        EngagementScoring {
+            open_weight: 1.0,
+            click_weight: 3.0,
+            bounce_weight: -5.0,
+            subscription_age_weight: 0.0,
+            half_life: Duration::days(30),
+        }
+    }
+
+    fn assert_close(left: f64, right: f64) {
+        assert!((left - right).abs() < 1e-9, "{} is not {}", left, right);
+    }
+
+    #[test]
+    fn events_add_their_weight() {
+        let now = Utc::now();
+        let score = scoring().add_event(0.0, None, now, EngagementEvent::Click, now);
+        let score = scoring().add_event(score, Some(now), now, EngagementEvent::Open, now);
+
+        assert_close(score, 4.0);
+        assert_close(
+            scoring().add_event(score, Some(now), now, EngagementEvent::Bounce, now),
+            -1.0,
+        );
+    }
+
+    #[test]
+    fn events_halve_every_half_life() {
+        let subscribed_at = Utc::now();
+
+        assert_close(
+            scoring().decay(
+                4.0,
+                Some(subscribed_at),
+                subscribed_at,
+                subscribed_at + Duration::days(60),
+            ),
+            1.0,
+        );
+    }
+
+    #[test]
+    fn decay_is_the_same_in_one_step_or_several() {
+        let scoring = EngagementScoring {
+            subscription_age_weight: 2.0,
+            ..scoring()
+        };
+        let subscribed_at = Utc::now();
+        let scored_at = subscribed_at + Duration::days(10);
+        let score = scoring.add_event(0.0, None, subscribed_at, EngagementEvent::Click, scored_at);
+        let middle = scored_at + Duration::days(17);
+        let now = scored_at + Duration::days(45);
+
+        assert_close(
+            scoring.decay(
+                scoring.decay(score, Some(scored_at), subscribed_at, middle),
+                Some(middle),
+                subscribed_at,
+                now,
+            ),
+            scoring.decay(score, Some(scored_at), subscribed_at, now),
+        );
+    }
+
+    #[test]
+    fn subscription_age_grows_up_to_its_weight() {
+        let scoring = EngagementScoring {
+            subscription_age_weight: 2.0,
+            ..scoring()
+        };
+        let subscribed_at = Utc::now();
+
+        assert_close(scoring.decay(0.0, None, subscribed_at, subscribed_at), 0.0);
+        assert_close(
+            scoring.decay(0.0, None, subscribed_at, subscribed_at + Duration::days(30)),
+            1.0,
+        );
+        assert_close(
+            scoring.decay(
+                0.0,
+                None,
+                subscribed_at,
+                subscribed_at + Duration::days(3000),
+            ),
+            2.0,
+        );
+    }
+
+    #[test]
+    fn time_going_backwards_does_not_grow_the_score() {
+        let now = Utc::now();
+
+        assert_close(
+            scoring().decay(2.0, Some(now), now, now - Duration::days(1)),
+            2.0,
+        );
+    }
+}
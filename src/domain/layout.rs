@@ -0,0 +1,58 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::content::layout::LayoutTemplate;
+
+const MAX_NAME_LENGTH: usize = 100;
+
+/// Stored HTML layout that newsletter issues can be rendered with.
+#[derive(Debug, serde::Serialize)]
+pub struct Layout {
+    pub id: uuid::Uuid,
+    pub name: LayoutName,
+    pub html: LayoutTemplate,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct LayoutName(String);
+
+impl LayoutName {
+    pub fn parse(name: String) -> Result<LayoutName, String> {
+        let is_empty_or_whitespace = name.trim().is_empty();
+        let is_too_long = name.graphemes(true).count() > MAX_NAME_LENGTH;
+
+        if is_empty_or_whitespace || is_too_long {
+            return Err(format!("{} is not a valid layout name", name));
+        }
+
+        Ok(Self(name))
+    }
+}
+
+impl AsRef<str> for LayoutName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LayoutName;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn empty_name_is_rejected() {
+        assert_err!(LayoutName::parse(String::from(" ")));
+    }
+
+    #[test]
+    fn name_greater_than_100_chars_is_rejected() {
+        assert_err!(LayoutName::parse("a".repeat(101)));
+    }
+
+    #[test]
+    fn name_valid() {
+        assert_ok!(LayoutName::parse(String::from("Weekly digest")));
+    }
+}
@@ -1,5 +1,22 @@
+pub mod cron_schedule;
+pub mod delivery_event;
+pub mod delivery_status;
+pub mod engagement_score;
+pub mod layout;
 pub mod new_subscriber;
+pub mod newsletter_issue;
+pub mod segment;
+pub mod segment_filter;
+pub mod sendgrid_webhook_key;
+pub mod sequence;
+pub mod status_history;
+pub mod subject_test;
 pub mod subscriber;
+pub mod subscriber_attributes;
 pub mod subscriber_email;
+pub mod subscriber_locale;
 pub mod subscriber_name;
 pub mod subscriber_status;
+pub mod subscriber_tag;
+pub mod subscriber_timezone;
+pub mod subscription_token;
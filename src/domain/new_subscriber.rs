@@ -1,18 +1,33 @@
 use actix_web::web;
 use serde::Deserialize;
 
+use crate::domain::subscriber_attributes::SubscriberAttributes;
 use crate::domain::subscriber_email::SubscriberEmail;
+use crate::domain::subscriber_locale::SubscriberLocale;
 use crate::domain::subscriber_name::SubscriberName;
+use crate::domain::subscriber_timezone::SubscriberTimezone;
 
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
+    pub attributes: SubscriberAttributes,
+    /// Locale chosen in the subscription form. The `Accept-Language` header is used when missing.
+    pub locale: Option<SubscriberLocale>,
+    /// Timezone chosen in the subscription form. It is inferred from the locale when missing.
+    pub timezone: Option<SubscriberTimezone>,
+    /// One of the lists of the configuration, checked by the handler
+    pub list: Option<String>,
 }
 
 #[derive(Deserialize)]
 pub struct NewSubscriberBody {
     pub name: String,
     pub email: String,
+    #[serde(default)]
+    pub attributes: serde_json::Map<String, serde_json::Value>,
+    pub locale: Option<String>,
+    pub timezone: Option<String>,
+    pub list: Option<String>,
 }
 
 impl TryFrom<web::Json<NewSubscriberBody>> for NewSubscriber {
@@ -21,7 +36,25 @@ impl TryFrom<web::Json<NewSubscriberBody>> for NewSubscriber {
     fn try_from(body: web::Json<NewSubscriberBody>) -> Result<Self, Self::Error> {
         let name = SubscriberName::parse(body.name.clone())?;
         let email = SubscriberEmail::parse(body.email.clone())?;
+        let attributes = SubscriberAttributes::parse(body.attributes.clone())?;
+        let locale = body
+            .locale
+            .clone()
+            .map(SubscriberLocale::parse)
+            .transpose()?;
+        let timezone = body
+            .timezone
+            .clone()
+            .map(SubscriberTimezone::parse)
+            .transpose()?;
 
-        Ok(NewSubscriber { email, name })
+        Ok(NewSubscriber {
+            email,
+            name,
+            attributes,
+            locale,
+            timezone,
+            list: body.list.clone(),
+        })
     }
 }
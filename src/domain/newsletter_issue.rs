@@ -0,0 +1,47 @@
+use crate::content::template::MergeTemplate;
+
+#[derive(Debug, serde::Serialize)]
+pub struct NewsletterIssue {
+    pub id: uuid::Uuid,
+    pub title: String,
+    /// Path of the issue in the web archive, e.g. `/archive/weekly-digest-1`
+    pub slug: String,
+    pub html_content: MergeTemplate,
+    pub text_content: MergeTemplate,
+    /// Whether the emails include a pixel that records when they are opened
+    pub track_opens: bool,
+    /// Whether the links of the emails redirect through a tracked URL that records the clicks
+    pub track_clicks: bool,
+    /// Private issues are not published in the web archive nor the feeds
+    pub is_private: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Turns the title into a readable URL path segment, e.g. `Weekly digest #1` into `weekly-digest-1`
+pub fn slugify(title: &str) -> String {
+    let slug = title
+        .to_lowercase()
+        .split(|char: char| !char.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-");
+
+    if slug.is_empty() {
+        String::from("issue")
+    } else {
+        slug
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::slugify;
+
+    #[test]
+    fn titles_are_slugified() {
+        assert_eq!(slugify("Weekly digest #1"), "weekly-digest-1");
+        assert_eq!(slugify("  What's new?  "), "what-s-new");
+        assert_eq!(slugify("Ñandú news"), "ñandú-news");
+        assert_eq!(slugify("¿?"), "issue");
+    }
+}
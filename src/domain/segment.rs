@@ -0,0 +1,57 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::domain::segment_filter::SegmentFilter;
+
+const MAX_NAME_LENGTH: usize = 100;
+
+/// Saved group of subscribers, defined by a filter expression that is evaluated every time the segment is used.
+#[derive(Debug, serde::Serialize)]
+pub struct Segment {
+    pub id: uuid::Uuid,
+    pub name: SegmentName,
+    pub filter: SegmentFilter,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SegmentName(String);
+
+impl SegmentName {
+    pub fn parse(name: String) -> Result<SegmentName, String> {
+        let is_empty_or_whitespace = name.trim().is_empty();
+        let is_too_long = name.graphemes(true).count() > MAX_NAME_LENGTH;
+
+        if is_empty_or_whitespace || is_too_long {
+            return Err(format!("{} is not a valid segment name", name));
+        }
+
+        Ok(Self(name))
+    }
+}
+
+impl AsRef<str> for SegmentName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SegmentName;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn empty_name_is_rejected() {
+        assert_err!(SegmentName::parse(String::from(" ")));
+    }
+
+    #[test]
+    fn name_greater_than_100_chars_is_rejected() {
+        assert_err!(SegmentName::parse("a".repeat(101)));
+    }
+
+    #[test]
+    fn name_valid() {
+        assert_ok!(SegmentName::parse(String::from("Beta testers")));
+    }
+}
@@ -0,0 +1,515 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::{types::Json, Postgres, QueryBuilder};
+
+use crate::domain::subscriber_attributes::AttributeKey;
+use crate::domain::subscriber_status::SubscriberStatus;
+use crate::domain::subscriber_tag::SubscriberTag;
+
+/// Filter expression used by segments to select subscribers, e.g.
+/// `tag:beta AND subscribed_at > 2026-01-01 AND attr.country = "ES"`.
+///
+/// Supported conditions are `tag:<tag>`, `inactive` (marked by the sunset policy), `subscribed_at`,
+/// `engagement_score`, `status`, `email`, `name` and `attr.<key>` comparisons, which can be combined with `AND`, `OR`,
+/// `NOT` and parentheses.
+#[derive(Debug, Clone)]
+pub struct SegmentFilter {
+    source: String,
+    expression: Expression,
+}
+
+#[derive(Debug, Clone)]
+enum Expression {
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    Condition(Condition),
+}
+
+#[derive(Debug, Clone)]
+enum Condition {
+    Tag(SubscriberTag),
+    Inactive,
+    SubscribedAt(Operator, DateTime<Utc>),
+    EngagementScore(Operator, f64),
+    Status(Operator, String),
+    Email(Operator, String),
+    Name(Operator, String),
+    Attribute(AttributeKey, Operator, serde_json::Value),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterOrEqual,
+    Lower,
+    LowerOrEqual,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    LeftParen,
+    RightParen,
+    And,
+    Or,
+    Not,
+    Operator(Operator),
+    Word(String),
+    Text(String),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl SegmentFilter {
+    pub fn parse(filter: String) -> Result<SegmentFilter, String> {
+        let tokens = tokenize(&filter)?;
+
+        if tokens.is_empty() {
+            return Err(String::from("Segment filter cannot be empty"));
+        }
+
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+        };
+        let expression = parser.parse_or()?;
+
+        if let Some(token) = parser.peek() {
+            return Err(format!("Unexpected token {:?} in segment filter", token));
+        }
+
+        Ok(Self {
+            source: filter,
+            expression,
+        })
+    }
+
+    /// Appends the filter to the query as a boolean SQL expression over the `subscriptions` table. Values are always
+    /// added as bind parameters, never interpolated into the SQL.
+    pub fn push_sql(&self, query: &mut QueryBuilder<'_, Postgres>) {
+        self.expression.push_sql(query);
+    }
+}
+
+impl AsRef<str> for SegmentFilter {
+    fn as_ref(&self) -> &str {
+        &self.source
+    }
+}
+
+impl serde::Serialize for SegmentFilter {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl Expression {
+    // Every expression compiles to a non-null boolean, so NOT behaves as users expect for missing attributes
+    fn push_sql(&self, query: &mut QueryBuilder<'_, Postgres>) {
+        match self {
+            Expression::And(left, right) => {
+                query.push("(");
+                left.push_sql(query);
+                query.push(" AND ");
+                right.push_sql(query);
+                query.push(")");
+            }
+            Expression::Or(left, right) => {
+                query.push("(");
+                left.push_sql(query);
+                query.push(" OR ");
+                right.push_sql(query);
+                query.push(")");
+            }
+            Expression::Not(expression) => {
+                query.push("(NOT ");
+                expression.push_sql(query);
+                query.push(")");
+            }
+            Expression::Condition(condition) => condition.push_sql(query),
+        }
+    }
+}
+
+impl Condition {
+    fn push_sql(&self, query: &mut QueryBuilder<'_, Postgres>) {
+        match self {
+            Condition::Tag(tag) => {
+                query.push(
+                    "EXISTS (SELECT 1 FROM subscriber_tags \
+                    WHERE subscriber_tags.subscriber_id = subscriptions.id AND subscriber_tags.tag = ",
+                );
+                query.push_bind(tag.as_ref().to_string());
+                query.push(")");
+            }
+            Condition::Inactive => {
+                query.push("(subscriptions.inactive_since IS NOT NULL)");
+            }
+            Condition::SubscribedAt(operator, date) => {
+                query.push(format!(
+                    "(subscriptions.subscribed_at {} ",
+                    operator.as_sql()
+                ));
+                query.push_bind(*date);
+                query.push(")");
+            }
+            Condition::EngagementScore(operator, score) => {
+                query.push(format!(
+                    "(subscriptions.engagement_score {} ",
+                    operator.as_sql()
+                ));
+                query.push_bind(*score);
+                query.push(")");
+            }
+            Condition::Status(operator, status) => {
+                query.push(format!("(subscriptions.status {} ", operator.as_sql()));
+                query.push_bind(status.clone());
+                query.push(")");
+            }
+            Condition::Email(operator, email) => {
+                query.push(format!("(subscriptions.email {} ", operator.as_sql()));
+                query.push_bind(email.clone());
+                query.push(")");
+            }
+            Condition::Name(operator, name) => {
+                query.push(format!("(subscriptions.name {} ", operator.as_sql()));
+                query.push_bind(name.clone());
+                query.push(")");
+            }
+            Condition::Attribute(key, operator, value) => {
+                // Comparing JSONB values keeps numbers, booleans and ISO dates ordered by their own type
+                query.push("COALESCE(subscriptions.attributes -> ");
+                query.push_bind(key.as_ref().to_string());
+                query.push(format!(" {} ", operator.as_sql()));
+                query.push_bind(Json(value.clone()));
+                query.push(", FALSE)");
+            }
+        }
+    }
+}
+
+impl Operator {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            Operator::Equal => "=",
+            Operator::NotEqual => "<>",
+            Operator::Greater => ">",
+            Operator::GreaterOrEqual => ">=",
+            Operator::Lower => "<",
+            Operator::LowerOrEqual => "<=",
+        }
+    }
+
+    fn is_equality(&self) -> bool {
+        matches!(self, Operator::Equal | Operator::NotEqual)
+    }
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+
+        self.position += 1;
+
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<Expression, String> {
+        let mut expression = self.parse_and()?;
+
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            expression = Expression::Or(Box::new(expression), Box::new(self.parse_and()?));
+        }
+
+        Ok(expression)
+    }
+
+    fn parse_and(&mut self) -> Result<Expression, String> {
+        let mut expression = self.parse_unary()?;
+
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            expression = Expression::And(Box::new(expression), Box::new(self.parse_unary()?));
+        }
+
+        Ok(expression)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, String> {
+        match self.next() {
+            Some(Token::Not) => Ok(Expression::Not(Box::new(self.parse_unary()?))),
+            Some(Token::LeftParen) => {
+                let expression = self.parse_or()?;
+
+                match self.next() {
+                    Some(Token::RightParen) => Ok(expression),
+                    _ => Err(String::from(
+                        "Missing closing parenthesis in segment filter",
+                    )),
+                }
+            }
+            Some(Token::Word(word)) => self.parse_condition(word).map(Expression::Condition),
+            Some(token) => Err(format!("Unexpected token {:?} in segment filter", token)),
+            None => Err(String::from("Unexpected end of segment filter")),
+        }
+    }
+
+    fn parse_condition(&mut self, field: String) -> Result<Condition, String> {
+        if let Some(tag) = field.strip_prefix("tag:") {
+            return SubscriberTag::parse(tag.to_string()).map(Condition::Tag);
+        }
+
+        if field == "inactive" {
+            return Ok(Condition::Inactive);
+        }
+
+        let operator = match self.next() {
+            Some(Token::Operator(operator)) => operator,
+            _ => return Err(format!("Missing comparison operator after {}", field)),
+        };
+        let value = match self.next() {
+            Some(Token::Word(word)) => parse_bare_value(word),
+            Some(Token::Text(text)) => serde_json::Value::String(text),
+            _ => return Err(format!("Missing value to compare {} with", field)),
+        };
+
+        match field.as_str() {
+            "subscribed_at" => {
+                let date = value
+                    .as_str()
+                    .and_then(parse_date)
+                    .ok_or_else(|| String::from("subscribed_at must be compared with a date"))?;
+
+                Ok(Condition::SubscribedAt(operator, date))
+            }
+            "engagement_score" => {
+                let score = value.as_f64().ok_or_else(|| {
+                    String::from("engagement_score must be compared with a number")
+                })?;
+
+                Ok(Condition::EngagementScore(operator, score))
+            }
+            "status" | "email" | "name" if !operator.is_equality() => {
+                Err(format!("{} can only be compared with = or !=", field))
+            }
+            "status" => {
+                let status = SubscriberStatus::parse(text_value(&field, value)?)?;
+
+                Ok(Condition::Status(operator, status.as_ref().to_string()))
+            }
+            "email" => Ok(Condition::Email(operator, text_value(&field, value)?)),
+            "name" => Ok(Condition::Name(operator, text_value(&field, value)?)),
+            _ => match field.strip_prefix("attr.") {
+                Some(key) => Ok(Condition::Attribute(
+                    AttributeKey::parse(key.to_string())?,
+                    operator,
+                    value,
+                )),
+                None => Err(format!("{} is not a valid segment filter field", field)),
+            },
+        }
+    }
+}
+
+fn tokenize(filter: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = filter.chars().peekable();
+
+    while let Some(char) = chars.next() {
+        match char {
+            char if char.is_whitespace() => {}
+            '(' => tokens.push(Token::LeftParen),
+            ')' => tokens.push(Token::RightParen),
+            '=' => tokens.push(Token::Operator(Operator::Equal)),
+            '!' if chars.next_if_eq(&'=').is_some() => {
+                tokens.push(Token::Operator(Operator::NotEqual))
+            }
+            '>' if chars.next_if_eq(&'=').is_some() => {
+                tokens.push(Token::Operator(Operator::GreaterOrEqual))
+            }
+            '>' => tokens.push(Token::Operator(Operator::Greater)),
+            '<' if chars.next_if_eq(&'=').is_some() => {
+                tokens.push(Token::Operator(Operator::LowerOrEqual))
+            }
+            '<' => tokens.push(Token::Operator(Operator::Lower)),
+            '"' => {
+                let mut text = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(escaped) => text.push(escaped),
+                            None => {
+                                return Err(String::from("Unterminated text in segment filter"))
+                            }
+                        },
+                        Some(char) => text.push(char),
+                        None => return Err(String::from("Unterminated text in segment filter")),
+                    }
+                }
+
+                tokens.push(Token::Text(text));
+            }
+            char if is_word_char(char) => {
+                let mut word = String::from(char);
+
+                while let Some(char) = chars.next_if(|char| is_word_char(*char)) {
+                    word.push(char);
+                }
+
+                tokens.push(match word.to_uppercase().as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Word(word),
+                });
+            }
+            char => return Err(format!("Unexpected character {} in segment filter", char)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn is_word_char(char: char) -> bool {
+    char.is_alphanumeric() || ['_', '-', '.', ':', '+', '@'].contains(&char)
+}
+
+fn parse_bare_value(word: String) -> serde_json::Value {
+    match word.as_str() {
+        "true" => serde_json::Value::Bool(true),
+        "false" => serde_json::Value::Bool(false),
+        _ => serde_json::from_str::<serde_json::Number>(&word)
+            .map(serde_json::Value::Number)
+            .unwrap_or(serde_json::Value::String(word)),
+    }
+}
+
+fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(date_time) = DateTime::parse_from_rfc3339(value) {
+        return Some(date_time.with_timezone(&Utc));
+    }
+
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|date_time| DateTime::<Utc>::from_utc(date_time, Utc))
+}
+
+fn text_value(field: &str, value: serde_json::Value) -> Result<String, String> {
+    match value {
+        serde_json::Value::String(text) => Ok(text),
+        _ => Err(format!("{} must be compared with a text value", field)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claim::{assert_err, assert_ok};
+
+    fn to_sql(filter: &str) -> String {
+        let filter = SegmentFilter::parse(String::from(filter)).unwrap();
+        let mut query = QueryBuilder::new("");
+
+        filter.push_sql(&mut query);
+
+        query.sql().to_string()
+    }
+
+    #[test]
+    fn empty_filter_is_rejected() {
+        assert_err!(SegmentFilter::parse(String::from("  ")));
+    }
+
+    #[test]
+    fn unknown_field_is_rejected() {
+        assert_err!(SegmentFilter::parse(String::from("country = \"ES\"")));
+    }
+
+    #[test]
+    fn unbalanced_parenthesis_are_rejected() {
+        assert_err!(SegmentFilter::parse(String::from("(tag:beta OR tag:alpha")));
+    }
+
+    #[test]
+    fn invalid_dates_are_rejected() {
+        assert_err!(SegmentFilter::parse(String::from(
+            "subscribed_at > yesterday"
+        )));
+    }
+
+    #[test]
+    fn engagement_score_must_be_compared_with_a_number() {
+        assert_err!(SegmentFilter::parse(String::from(
+            "engagement_score > high"
+        )));
+        assert_eq!(
+            to_sql("engagement_score >= 2.5"),
+            "(subscriptions.engagement_score >= $1)"
+        );
+    }
+
+    #[test]
+    fn invalid_status_is_rejected() {
+        assert_err!(SegmentFilter::parse(String::from("status = active")));
+    }
+
+    #[test]
+    fn ordering_comparison_on_text_fields_is_rejected() {
+        assert_err!(SegmentFilter::parse(String::from("email > \"a@test.com\"")));
+    }
+
+    #[test]
+    fn keywords_are_case_insensitive() {
+        assert_ok!(SegmentFilter::parse(String::from(
+            "tag:beta and not (tag:alpha or status = confirmed)"
+        )));
+    }
+
+    #[test]
+    fn and_has_higher_precedence_than_or() {
+        assert_eq!(
+            to_sql("tag:a OR tag:b AND tag:c"),
+            to_sql("tag:a OR (tag:b AND tag:c)")
+        );
+    }
+
+    #[test]
+    fn filter_compiles_to_parameterized_sql() {
+        let sql = to_sql("tag:beta AND subscribed_at > 2026-01-01 AND attr.country = \"ES\"");
+
+        assert_eq!(
+            sql,
+            "((EXISTS (SELECT 1 FROM subscriber_tags \
+            WHERE subscriber_tags.subscriber_id = subscriptions.id AND subscriber_tags.tag = $1) \
+            AND (subscriptions.subscribed_at > $2)) \
+            AND COALESCE(subscriptions.attributes -> $3 = $4, FALSE))"
+        );
+    }
+
+    #[test]
+    fn inactive_subscribers_can_be_selected() {
+        assert_eq!(
+            to_sql("inactive OR NOT inactive"),
+            "((subscriptions.inactive_since IS NOT NULL) OR (NOT (subscriptions.inactive_since IS NOT NULL)))"
+        );
+    }
+
+    #[test]
+    fn values_are_never_interpolated() {
+        let sql = to_sql("name = \"Robert'); DROP TABLE subscriptions;--\"");
+
+        assert_eq!(sql, "(subscriptions.name = $1)");
+    }
+}
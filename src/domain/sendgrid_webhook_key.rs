@@ -0,0 +1,96 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_ASN1};
+
+/// DER header of a P-256 public key (SubjectPublicKeyInfo), followed by the uncompressed point of the key
+const P256_PUBLIC_KEY_PREFIX: [u8; 26] = [
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08, 0x2a,
+    0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+];
+
+/// Public key of the signed event webhook of Sendgrid, as shown in its settings (base64 DER). Sendgrid signs the
+/// timestamp of the request followed by its body with ECDSA (P-256, SHA-256).
+#[derive(Debug, Clone)]
+pub struct SendgridWebhookKey(Vec<u8>);
+
+impl SendgridWebhookKey {
+    pub fn parse(public_key: &str) -> Result<Self, String> {
+        let public_key = STANDARD
+            .decode(public_key.trim())
+            .map_err(|_| String::from("The webhook verification key is not valid base64"))?;
+
+        match public_key.strip_prefix(P256_PUBLIC_KEY_PREFIX.as_slice()) {
+            Some(point) if point.len() == 65 => Ok(Self(point.to_vec())),
+            _ => Err(String::from(
+                "The webhook verification key is not a P-256 public key",
+            )),
+        }
+    }
+
+    /// Checks the base64 signature of the `X-Twilio-Email-Event-Webhook-Signature` header against the
+    /// `X-Twilio-Email-Event-Webhook-Timestamp` header and the raw body
+    pub fn verify(&self, timestamp: &str, body: &[u8], signature: &str) -> Result<(), String> {
+        let signature = STANDARD
+            .decode(signature)
+            .map_err(|_| String::from("The signature is not valid base64"))?;
+        let payload = [timestamp.as_bytes(), body].concat();
+
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, &self.0)
+            .verify(&payload, &signature)
+            .map_err(|_| String::from("The signature is not valid"))
+    }
+
+    /// Key in the format of the Sendgrid settings, from the uncompressed point of a P-256 public key
+    pub fn encode(point: &[u8]) -> String {
+        STANDARD.encode([P256_PUBLIC_KEY_PREFIX.as_slice(), point].concat())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok};
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+
+    use super::*;
+
+    fn key_pair() -> EcdsaKeyPair {
+        let pkcs8 =
+            EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &SystemRandom::new())
+                .unwrap();
+
+        EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref()).unwrap()
+    }
+
+    fn sign(key_pair: &EcdsaKeyPair, payload: &[u8]) -> String {
+        STANDARD.encode(key_pair.sign(&SystemRandom::new(), payload).unwrap())
+    }
+
+    #[test]
+    fn keys_from_the_sendgrid_settings_are_parsed() {
+        assert_ok!(SendgridWebhookKey::parse(
+            "MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAE36WDIUCLRyyLlpnl0YJwWiI8E1dQ4k767oTTyITzSc5USl1KASlb65RBBr9ocdojQsvf7dwdJDZ979kgD1SYZQ=="
+        ));
+        assert_err!(SendgridWebhookKey::parse("not a key"));
+        assert_err!(SendgridWebhookKey::parse(&STANDARD.encode([0; 91])));
+    }
+
+    #[test]
+    fn signatures_of_the_timestamp_and_body_are_verified() {
+        let key_pair = key_pair();
+        let key =
+            SendgridWebhookKey::parse(&SendgridWebhookKey::encode(key_pair.public_key().as_ref()))
+                .unwrap();
+        let signature = sign(&key_pair, b"1760000000[]");
+
+        assert_ok!(key.verify("1760000000", b"[]", &signature));
+        assert_err!(key.verify("1760000001", b"[]", &signature));
+        assert_err!(key.verify("1760000000", b"[{}]", &signature));
+        assert_err!(key.verify(
+            "1760000000",
+            b"[]",
+            &sign(&self::key_pair(), b"1760000000[]")
+        ));
+        assert_err!(key.verify("1760000000", b"[]", "not base64"));
+    }
+}
@@ -0,0 +1,100 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+const MAX_NAME_LENGTH: usize = 100;
+const MAX_STEPS: usize = 20;
+/// Steps are sent at most a year after the confirmation
+const MAX_DELAY_DAYS: u32 = 365;
+
+/// Emails sent to every subscriber after they confirm their subscription, each step a number of days after the
+/// confirmation
+#[derive(Debug, serde::Serialize)]
+pub struct Sequence {
+    pub id: uuid::Uuid,
+    pub name: SequenceName,
+    pub steps: Vec<SequenceStep>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SequenceStep {
+    pub delay_days: u32,
+    /// Private issue sent by the step
+    pub newsletter_issue_id: uuid::Uuid,
+    pub title: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SequenceName(String);
+
+impl SequenceName {
+    pub fn parse(name: String) -> Result<SequenceName, String> {
+        let is_empty_or_whitespace = name.trim().is_empty();
+        let is_too_long = name.graphemes(true).count() > MAX_NAME_LENGTH;
+
+        if is_empty_or_whitespace || is_too_long {
+            return Err(format!("{} is not a valid sequence name", name));
+        }
+
+        Ok(Self(name))
+    }
+}
+
+impl AsRef<str> for SequenceName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Steps are listed in the order they are sent, so their delays cannot decrease
+pub fn validate_step_delays(delays: &[u32]) -> Result<(), String> {
+    if delays.is_empty() || delays.len() > MAX_STEPS {
+        return Err(format!(
+            "A sequence must have between 1 and {} steps.",
+            MAX_STEPS
+        ));
+    }
+
+    if delays.iter().any(|delay| *delay > MAX_DELAY_DAYS) {
+        return Err(format!(
+            "Steps must be sent at most {} days after the confirmation.",
+            MAX_DELAY_DAYS
+        ));
+    }
+
+    if delays.windows(2).any(|delays| delays[0] > delays[1]) {
+        return Err(String::from(
+            "Steps must be listed in the order they are sent.",
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate_step_delays, SequenceName};
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn empty_name_is_rejected() {
+        assert_err!(SequenceName::parse(String::from(" ")));
+    }
+
+    #[test]
+    fn name_valid() {
+        assert_ok!(SequenceName::parse(String::from("Welcome")));
+    }
+
+    #[test]
+    fn steps_are_sent_in_order() {
+        assert_ok!(validate_step_delays(&[0, 3, 3, 10]));
+        assert_err!(validate_step_delays(&[0, 10, 3]));
+    }
+
+    #[test]
+    fn sequences_without_steps_or_with_distant_ones_are_rejected() {
+        assert_err!(validate_step_delays(&[]));
+        assert_err!(validate_step_delays(&[0; 21]));
+        assert_err!(validate_step_delays(&[0, 366]));
+    }
+}
@@ -0,0 +1,43 @@
+use chrono::{DateTime, Utc};
+
+/// Action of the sunset policy on the status of a subscriber
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusHistoryAction {
+    /// No opens or clicks in the configured number of sends or months
+    MarkedInactive,
+    ReEngagementSent,
+    /// Opened, clicked or followed the link of the re-engagement email while inactive
+    Reactivated,
+    /// Did not re-engage before the grace period ended
+    Unsubscribed,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct StatusHistoryEntry {
+    pub action: StatusHistoryAction,
+    pub occurred_at: DateTime<Utc>,
+}
+
+impl StatusHistoryAction {
+    pub fn parse(action: String) -> Result<StatusHistoryAction, String> {
+        match action.as_str() {
+            "marked_inactive" => Ok(StatusHistoryAction::MarkedInactive),
+            "re_engagement_sent" => Ok(StatusHistoryAction::ReEngagementSent),
+            "reactivated" => Ok(StatusHistoryAction::Reactivated),
+            "unsubscribed" => Ok(StatusHistoryAction::Unsubscribed),
+            _ => Err(format!("{} is not a valid status history action", action)),
+        }
+    }
+}
+
+impl AsRef<str> for StatusHistoryAction {
+    fn as_ref(&self) -> &str {
+        match self {
+            StatusHistoryAction::MarkedInactive => "marked_inactive",
+            StatusHistoryAction::ReEngagementSent => "re_engagement_sent",
+            StatusHistoryAction::Reactivated => "reactivated",
+            StatusHistoryAction::Unsubscribed => "unsubscribed",
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use serde::{Deserialize, Serialize};
+
+/// Subject lines of an issue tested on random samples of its audience, e.g. two subjects sent to 10% of the
+/// subscribers each. When the window ends, the subject with the highest rate of the metric is sent to the rest.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SubjectTest {
+    pub subjects: Vec<String>,
+    /// Share of the audience each subject is sent to
+    pub sample_percent: u8,
+    /// Hours the samples have to open or click before the winner is picked
+    pub window_hours: u32,
+    #[serde(default)]
+    pub metric: SubjectTestMetric,
+}
+
+/// Longest test window, a week
+const MAX_WINDOW_HOURS: u32 = 7 * 24;
+
+impl SubjectTest {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.subjects.len() < 2 {
+            return Err(String::from("A subject test needs at least two subjects."));
+        }
+
+        if self
+            .subjects
+            .iter()
+            .any(|subject| subject.trim().is_empty())
+        {
+            return Err(String::from("The subjects of a test cannot be empty."));
+        }
+
+        if self.sample_percent == 0 || self.subjects.len() * self.sample_percent as usize >= 100 {
+            return Err(String::from(
+                "The samples of a subject test must leave part of the audience for the winner.",
+            ));
+        }
+
+        if self.window_hours == 0 || self.window_hours > MAX_WINDOW_HOURS {
+            return Err(format!(
+                "The window of a subject test is between 1 and {} hours.",
+                MAX_WINDOW_HOURS
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Subscribers each subject is sent to. It is rounded up, so small audiences still test every subject.
+    pub fn sample_size(&self, audience: i64) -> i64 {
+        (audience * self.sample_percent as i64 + 99) / 100
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubjectTestMetric {
+    /// Unique opens per sent email
+    #[default]
+    Opens,
+    /// Unique clicks per sent email
+    Clicks,
+}
+
+impl SubjectTestMetric {
+    pub fn parse(metric: String) -> Result<SubjectTestMetric, String> {
+        match metric.as_str() {
+            "opens" => Ok(SubjectTestMetric::Opens),
+            "clicks" => Ok(SubjectTestMetric::Clicks),
+            _ => Err(format!("{} is not a valid subject test metric", metric)),
+        }
+    }
+}
+
+impl AsRef<str> for SubjectTestMetric {
+    fn as_ref(&self) -> &str {
+        match self {
+            SubjectTestMetric::Opens => "opens",
+            SubjectTestMetric::Clicks => "clicks",
+        }
+    }
+}
+
+/// Outcome of a subject on its sample
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SubjectVariantResult {
+    pub subject: String,
+    pub sent: i64,
+    pub opened: i64,
+    pub clicked: i64,
+    pub open_rate: f64,
+    pub click_rate: f64,
+}
+
+/// Returns the position of the subject with the highest rate of the metric. Ties go to the first subject.
+pub fn pick_winner(results: &[SubjectVariantResult], metric: SubjectTestMetric) -> usize {
+    let rate = |result: &SubjectVariantResult| match metric {
+        SubjectTestMetric::Opens => result.open_rate,
+        SubjectTestMetric::Clicks => result.click_rate,
+    };
+
+    results
+        .iter()
+        .enumerate()
+        .fold(0, |winner, (position, result)| {
+            if rate(result) > rate(&results[winner]) {
+                position
+            } else {
+                winner
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pick_winner, SubjectTest, SubjectTestMetric, SubjectVariantResult};
+    use claim::{assert_err, assert_ok};
+
+    fn subject_test(subjects: &[&str], sample_percent: u8, window_hours: u32) -> SubjectTest {
+        SubjectTest {
+            subjects: subjects
+                .iter()
+                .map(|subject| String::from(*subject))
+                .collect(),
+            sample_percent,
+            window_hours,
+            metric: SubjectTestMetric::Opens,
+        }
+    }
+
+    fn result(open_rate: f64, click_rate: f64) -> SubjectVariantResult {
+        SubjectVariantResult {
+            subject: String::from("Subject"),
+            sent: 10,
+            opened: (open_rate * 10.0) as i64,
+            clicked: (click_rate * 10.0) as i64,
+            open_rate,
+            click_rate,
+        }
+    }
+
+    #[test]
+    fn valid_subject_tests_are_accepted() {
+        assert_ok!(subject_test(&["A", "B"], 10, 4).validate());
+        assert_ok!(subject_test(&["A", "B", "C"], 33, 168).validate());
+    }
+
+    #[test]
+    fn invalid_subject_tests_are_rejected() {
+        assert_err!(subject_test(&["A"], 10, 4).validate());
+        assert_err!(subject_test(&["A", " "], 10, 4).validate());
+        assert_err!(subject_test(&["A", "B"], 0, 4).validate());
+        assert_err!(subject_test(&["A", "B"], 50, 4).validate());
+        assert_err!(subject_test(&["A", "B"], 10, 0).validate());
+        assert_err!(subject_test(&["A", "B"], 10, 169).validate());
+    }
+
+    #[test]
+    fn sample_size_is_rounded_up() {
+        let subject_test = subject_test(&["A", "B"], 10, 4);
+
+        assert_eq!(subject_test.sample_size(1000), 100);
+        assert_eq!(subject_test.sample_size(15), 2);
+        assert_eq!(subject_test.sample_size(0), 0);
+    }
+
+    #[test]
+    fn winner_has_the_highest_rate_of_the_metric() {
+        let results = [result(0.2, 0.1), result(0.5, 0.0), result(0.3, 0.3)];
+
+        assert_eq!(pick_winner(&results, SubjectTestMetric::Opens), 1);
+        assert_eq!(pick_winner(&results, SubjectTestMetric::Clicks), 2);
+    }
+
+    #[test]
+    fn ties_go_to_the_first_subject() {
+        let results = [result(0.0, 0.0), result(0.0, 0.0)];
+
+        assert_eq!(pick_winner(&results, SubjectTestMetric::Opens), 0);
+    }
+}
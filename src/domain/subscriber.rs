@@ -1,12 +1,22 @@
+use crate::domain::subscriber_attributes::SubscriberAttributes;
 use crate::domain::subscriber_email::SubscriberEmail;
+use crate::domain::subscriber_locale::SubscriberLocale;
 use crate::domain::subscriber_name::SubscriberName;
 use crate::domain::subscriber_status::SubscriberStatus;
+use crate::domain::subscriber_timezone::SubscriberTimezone;
 
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct Subscriber {
     pub id: uuid::Uuid,
     pub email: SubscriberEmail,
     pub name: SubscriberName,
     pub status: SubscriberStatus,
     pub subscribed_at: chrono::DateTime<chrono::Utc>,
+    pub attributes: SubscriberAttributes,
+    pub locale: SubscriberLocale,
+    pub timezone: Option<SubscriberTimezone>,
+    /// Refreshed when tracking events arrive and at least daily, see `EngagementScoring`
+    pub engagement_score: f64,
+    /// List the subscriber signed up to, which sets where the confirmation link redirects
+    pub list: Option<String>,
 }
@@ -0,0 +1,216 @@
+use serde_json::{Map, Value};
+
+const MAX_KEY_LENGTH: usize = 64;
+
+pub type AttributesMap = Map<String, Value>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttributeType {
+    Text,
+    Number,
+    Boolean,
+    Date,
+}
+
+/// Entry of the attributes schema registry. Only keys defined in the registry can be stored on a subscriber.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct AttributeDefinition {
+    pub key: AttributeKey,
+    #[serde(rename = "type")]
+    pub value_type: AttributeType,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct AttributeKey(String);
+
+/// Custom per-subscriber fields (company, country, plan...), stored as JSONB in the subscriptions table.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct SubscriberAttributes(AttributesMap);
+
+impl AttributeType {
+    pub fn parse(value_type: String) -> Result<AttributeType, String> {
+        match value_type.as_str() {
+            "text" => Ok(AttributeType::Text),
+            "number" => Ok(AttributeType::Number),
+            "boolean" => Ok(AttributeType::Boolean),
+            "date" => Ok(AttributeType::Date),
+            _ => Err(format!("{} is not a valid attribute type", value_type)),
+        }
+    }
+
+    pub fn accepts(&self, value: &Value) -> bool {
+        match (self, value) {
+            (AttributeType::Text, Value::String(_)) => true,
+            (AttributeType::Number, Value::Number(_)) => true,
+            (AttributeType::Boolean, Value::Bool(_)) => true,
+            // Dates are stored as ISO 8601 strings (YYYY-MM-DD) so they can be compared as text
+            (AttributeType::Date, Value::String(date)) => {
+                chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok()
+            }
+            _ => false,
+        }
+    }
+}
+
+impl AsRef<str> for AttributeType {
+    fn as_ref(&self) -> &str {
+        match self {
+            AttributeType::Text => "text",
+            AttributeType::Number => "number",
+            AttributeType::Boolean => "boolean",
+            AttributeType::Date => "date",
+        }
+    }
+}
+
+impl AttributeKey {
+    pub fn parse(key: String) -> Result<AttributeKey, String> {
+        let starts_with_letter = key
+            .chars()
+            .next()
+            .map(|char| char.is_ascii_lowercase())
+            .unwrap_or(false);
+        let has_valid_chars = key
+            .chars()
+            .all(|char| char.is_ascii_lowercase() || char.is_ascii_digit() || char == '_');
+
+        if !starts_with_letter || !has_valid_chars || key.len() > MAX_KEY_LENGTH {
+            return Err(format!("{} is not a valid attribute key", key));
+        }
+
+        Ok(Self(key))
+    }
+}
+
+impl AsRef<str> for AttributeKey {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl SubscriberAttributes {
+    /// Validates that the attributes have a valid shape. Use `validate` to check them against the schema registry.
+    pub fn parse(attributes: AttributesMap) -> Result<SubscriberAttributes, String> {
+        for (key, value) in attributes.iter() {
+            AttributeKey::parse(key.clone())?;
+
+            if value.is_array() || value.is_object() || value.is_null() {
+                return Err(format!(
+                    "{} attribute must be a text, number or boolean",
+                    key
+                ));
+            }
+        }
+
+        Ok(Self(attributes))
+    }
+
+    pub fn validate(&self, definitions: &[AttributeDefinition]) -> Result<(), String> {
+        for (key, value) in self.0.iter() {
+            let definition = definitions
+                .iter()
+                .find(|definition| definition.key.as_ref() == key)
+                .ok_or_else(|| format!("{} is not a registered attribute", key))?;
+
+            if !definition.value_type.accepts(value) {
+                return Err(format!(
+                    "{} attribute must be of type {}",
+                    key,
+                    definition.value_type.as_ref()
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.0.get(key)
+    }
+
+    pub fn as_map(&self) -> &AttributesMap {
+        &self.0
+    }
+}
+
+impl From<AttributesMap> for SubscriberAttributes {
+    // Used for values coming from the database, which were already validated when stored
+    fn from(attributes: AttributesMap) -> Self {
+        Self(attributes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claim::{assert_err, assert_ok};
+    use serde_json::json;
+
+    fn definitions() -> Vec<AttributeDefinition> {
+        vec![
+            AttributeDefinition {
+                key: AttributeKey::parse(String::from("company")).unwrap(),
+                value_type: AttributeType::Text,
+                created_at: chrono::Utc::now(),
+            },
+            AttributeDefinition {
+                key: AttributeKey::parse(String::from("signup_date")).unwrap(),
+                value_type: AttributeType::Date,
+                created_at: chrono::Utc::now(),
+            },
+        ]
+    }
+
+    fn attributes(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn key_with_uppercase_chars_is_rejected() {
+        assert_err!(AttributeKey::parse(String::from("Company")));
+    }
+
+    #[test]
+    fn key_starting_with_a_digit_is_rejected() {
+        assert_err!(AttributeKey::parse(String::from("1company")));
+    }
+
+    #[test]
+    fn key_with_snake_case_is_accepted() {
+        assert_ok!(AttributeKey::parse(String::from("signup_source")));
+    }
+
+    #[test]
+    fn nested_values_are_rejected() {
+        assert_err!(SubscriberAttributes::parse(attributes(
+            json!({ "company": { "name": "ACME" } })
+        )));
+    }
+
+    #[test]
+    fn unregistered_attributes_are_rejected() {
+        let attributes = SubscriberAttributes::parse(attributes(json!({ "plan": "pro" }))).unwrap();
+
+        assert_err!(attributes.validate(&definitions()));
+    }
+
+    #[test]
+    fn attributes_with_wrong_type_are_rejected() {
+        let attributes =
+            SubscriberAttributes::parse(attributes(json!({ "signup_date": "yesterday" }))).unwrap();
+
+        assert_err!(attributes.validate(&definitions()));
+    }
+
+    #[test]
+    fn registered_attributes_are_accepted() {
+        let attributes = SubscriberAttributes::parse(attributes(
+            json!({ "company": "ACME", "signup_date": "2026-01-01" }),
+        ))
+        .unwrap();
+
+        assert_ok!(attributes.validate(&definitions()));
+    }
+}
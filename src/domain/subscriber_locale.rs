@@ -0,0 +1,109 @@
+/// Language tag of the subscriber (e.g. `es` or `pt-BR`), used to choose the language of their emails.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct SubscriberLocale(String);
+
+impl SubscriberLocale {
+    /// Accepts a language code with optional subtags and normalises its case, e.g. `pt-br` becomes `pt-BR`.
+    pub fn parse(locale: String) -> Result<SubscriberLocale, String> {
+        let subtags: Vec<&str> = locale.trim().split(['-', '_']).collect();
+        let is_valid_language = (2..=3).contains(&subtags[0].len())
+            && subtags[0].chars().all(|char| char.is_ascii_alphabetic());
+        let are_valid_subtags = subtags[1..].iter().all(|subtag| {
+            (2..=8).contains(&subtag.len())
+                && subtag.chars().all(|char| char.is_ascii_alphanumeric())
+        });
+
+        if !is_valid_language || !are_valid_subtags {
+            return Err(format!("{} is not a valid locale", locale));
+        }
+
+        let normalised: Vec<String> = subtags
+            .iter()
+            .enumerate()
+            .map(|(index, subtag)| match (index, subtag.len()) {
+                (0, _) => subtag.to_ascii_lowercase(),
+                (_, 2) => subtag.to_ascii_uppercase(),
+                _ => subtag.to_ascii_lowercase(),
+            })
+            .collect();
+
+        Ok(Self(normalised.join("-")))
+    }
+
+    /// Primary language of the locale, e.g. `pt` for `pt-BR`
+    pub fn language(&self) -> &str {
+        self.0.split('-').next().unwrap_or(&self.0)
+    }
+
+    /// Country or region of the locale, e.g. `BR` for `pt-BR`
+    pub fn region(&self) -> Option<&str> {
+        self.0.split('-').skip(1).find(|subtag| {
+            subtag.len() == 2 && subtag.chars().all(|char| char.is_ascii_uppercase())
+        })
+    }
+
+    /// Returns the locales of an `Accept-Language` header sorted by preference. Invalid or wildcard entries are
+    /// ignored.
+    pub fn parse_accept_language(header: &str) -> Vec<SubscriberLocale> {
+        let mut locales: Vec<(SubscriberLocale, f32)> = header
+            .split(',')
+            .filter_map(|entry| {
+                let mut parts = entry.split(';');
+                let locale = SubscriberLocale::parse(String::from(parts.next()?)).ok()?;
+                let quality = parts
+                    .find_map(|parameter| parameter.trim().strip_prefix("q="))
+                    .map(|quality| quality.trim().parse::<f32>().ok())
+                    .unwrap_or(Some(1.0))?;
+
+                (quality > 0.0).then_some((locale, quality))
+            })
+            .collect();
+
+        // Stable sort keeps the header order between locales with the same quality
+        locales.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+        locales.into_iter().map(|(locale, _)| locale).collect()
+    }
+}
+
+impl AsRef<str> for SubscriberLocale {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriberLocale;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn locales_are_normalised() {
+        let locale = assert_ok!(SubscriberLocale::parse(String::from("pt_br")));
+
+        assert_eq!(locale.as_ref(), "pt-BR");
+        assert_eq!(locale.language(), "pt");
+        assert_eq!(locale.region(), Some("BR"));
+    }
+
+    #[test]
+    fn invalid_locales_are_rejected() {
+        for locale in ["", "e", "english", "es-", "es-ES!", "*"] {
+            assert_err!(SubscriberLocale::parse(String::from(locale)));
+        }
+    }
+
+    #[test]
+    fn accept_language_is_sorted_by_quality() {
+        let locales =
+            SubscriberLocale::parse_accept_language("fr;q=0.5, es-ES, *;q=0.1, en;q=0.8, de;q=0");
+
+        assert_eq!(
+            locales
+                .iter()
+                .map(|locale| locale.as_ref())
+                .collect::<Vec<&str>>(),
+            vec!["es-ES", "en", "fr"]
+        );
+    }
+}
@@ -0,0 +1,52 @@
+const MAX_CHAR_LENGTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct SubscriberTag(String);
+
+impl SubscriberTag {
+    pub fn parse(tag: String) -> Result<SubscriberTag, String> {
+        let is_empty = tag.is_empty();
+        let is_too_long = tag.len() > MAX_CHAR_LENGTH;
+        let has_valid_chars = tag.chars().all(|char| {
+            char.is_ascii_lowercase() || char.is_ascii_digit() || char == '-' || char == '_'
+        });
+
+        if is_empty || is_too_long || !has_valid_chars {
+            return Err(format!("{} is not a valid tag", tag));
+        }
+
+        Ok(Self(tag))
+    }
+}
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriberTag;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn empty_tag_is_rejected() {
+        assert_err!(SubscriberTag::parse(String::from("")));
+    }
+
+    #[test]
+    fn tag_with_whitespaces_is_rejected() {
+        assert_err!(SubscriberTag::parse(String::from("early adopter")));
+    }
+
+    #[test]
+    fn tag_greater_than_64_chars_is_rejected() {
+        assert_err!(SubscriberTag::parse("a".repeat(65)));
+    }
+
+    #[test]
+    fn tag_valid() {
+        assert_ok!(SubscriberTag::parse(String::from("early-adopter")));
+    }
+}
@@ -0,0 +1,126 @@
+use chrono_tz::Tz;
+use std::str::FromStr;
+
+use crate::domain::subscriber_locale::SubscriberLocale;
+
+/// Timezone of the most populated area of each region, used when the subscriber did not choose one. Regions that are
+/// missing are not inferred.
+const REGION_TIMEZONES: [(&str, &str); 46] = [
+    ("AR", "America/Argentina/Buenos_Aires"),
+    ("AT", "Europe/Vienna"),
+    ("AU", "Australia/Sydney"),
+    ("BE", "Europe/Brussels"),
+    ("BR", "America/Sao_Paulo"),
+    ("CA", "America/Toronto"),
+    ("CH", "Europe/Zurich"),
+    ("CL", "America/Santiago"),
+    ("CN", "Asia/Shanghai"),
+    ("CO", "America/Bogota"),
+    ("CZ", "Europe/Prague"),
+    ("DE", "Europe/Berlin"),
+    ("DK", "Europe/Copenhagen"),
+    ("EG", "Africa/Cairo"),
+    ("ES", "Europe/Madrid"),
+    ("FI", "Europe/Helsinki"),
+    ("FR", "Europe/Paris"),
+    ("GB", "Europe/London"),
+    ("GR", "Europe/Athens"),
+    ("HK", "Asia/Hong_Kong"),
+    ("ID", "Asia/Jakarta"),
+    ("IE", "Europe/Dublin"),
+    ("IL", "Asia/Jerusalem"),
+    ("IN", "Asia/Kolkata"),
+    ("IT", "Europe/Rome"),
+    ("JP", "Asia/Tokyo"),
+    ("KR", "Asia/Seoul"),
+    ("MX", "America/Mexico_City"),
+    ("NG", "Africa/Lagos"),
+    ("NL", "Europe/Amsterdam"),
+    ("NO", "Europe/Oslo"),
+    ("NZ", "Pacific/Auckland"),
+    ("PE", "America/Lima"),
+    ("PH", "Asia/Manila"),
+    ("PL", "Europe/Warsaw"),
+    ("PT", "Europe/Lisbon"),
+    ("RO", "Europe/Bucharest"),
+    ("RU", "Europe/Moscow"),
+    ("SE", "Europe/Stockholm"),
+    ("SG", "Asia/Singapore"),
+    ("TR", "Europe/Istanbul"),
+    ("TW", "Asia/Taipei"),
+    ("UA", "Europe/Kiev"),
+    ("US", "America/New_York"),
+    ("VE", "America/Caracas"),
+    ("ZA", "Africa/Johannesburg"),
+];
+
+/// IANA timezone of the subscriber (e.g. `Europe/Madrid`), used to deliver newsletters at a local time.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct SubscriberTimezone(String);
+
+impl SubscriberTimezone {
+    pub fn parse(timezone: String) -> Result<SubscriberTimezone, String> {
+        let timezone = Tz::from_str(timezone.trim())
+            .map_err(|_| format!("{} is not a valid timezone", timezone))?;
+
+        Ok(Self(String::from(timezone.name())))
+    }
+
+    /// Infers the timezone from the region of the locale, e.g. `Europe/Madrid` for `es-ES`
+    pub fn from_locale(locale: &SubscriberLocale) -> Option<SubscriberTimezone> {
+        let region = locale.region()?;
+
+        REGION_TIMEZONES
+            .iter()
+            .find(|(timezone_region, _)| *timezone_region == region)
+            .map(|(_, timezone)| Self(String::from(*timezone)))
+    }
+}
+
+impl AsRef<str> for SubscriberTimezone {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SubscriberTimezone, REGION_TIMEZONES};
+    use crate::domain::subscriber_locale::SubscriberLocale;
+    use claim::{assert_err, assert_none, assert_ok, assert_some_eq};
+
+    fn locale(locale: &str) -> SubscriberLocale {
+        SubscriberLocale::parse(String::from(locale)).unwrap()
+    }
+
+    #[test]
+    fn valid_timezones_are_accepted() {
+        let timezone = assert_ok!(SubscriberTimezone::parse(String::from(" Asia/Tokyo ")));
+
+        assert_eq!(timezone.as_ref(), "Asia/Tokyo");
+    }
+
+    #[test]
+    fn invalid_timezones_are_rejected() {
+        for timezone in ["", "Madrid", "Europe/Atlantis", "+02:00"] {
+            assert_err!(SubscriberTimezone::parse(String::from(timezone)));
+        }
+    }
+
+    #[test]
+    fn timezones_are_inferred_from_the_region_of_the_locale() {
+        assert_some_eq!(
+            SubscriberTimezone::from_locale(&locale("es-es")).map(|timezone| timezone.0),
+            "Europe/Madrid"
+        );
+        assert_none!(SubscriberTimezone::from_locale(&locale("es")));
+        assert_none!(SubscriberTimezone::from_locale(&locale("es-419")));
+    }
+
+    #[test]
+    fn inferred_timezones_are_valid() {
+        for (_, timezone) in REGION_TIMEZONES {
+            assert_ok!(SubscriberTimezone::parse(String::from(timezone)));
+        }
+    }
+}
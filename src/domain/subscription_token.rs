@@ -0,0 +1,105 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use uuid::Uuid;
+
+/// Value stored in Redis for every confirmation token, e.g. `<subscriber id>:<issued at unix timestamp>`. Tokens
+/// stored before they had an issue date contain only the subscriber id and never expire.
+#[derive(Debug, PartialEq)]
+pub struct SubscriptionTokenRecord {
+    pub subscriber_id: Uuid,
+    pub issued_at: Option<DateTime<Utc>>,
+}
+
+impl SubscriptionTokenRecord {
+    pub fn new(subscriber_id: Uuid) -> SubscriptionTokenRecord {
+        Self {
+            subscriber_id,
+            issued_at: Some(Utc::now()),
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<SubscriptionTokenRecord, String> {
+        let (subscriber_id, issued_at) = match value.split_once(':') {
+            Some((subscriber_id, issued_at)) => {
+                let issued_at = issued_at
+                    .parse::<i64>()
+                    .ok()
+                    .and_then(|timestamp| Utc.timestamp_opt(timestamp, 0).single())
+                    .ok_or_else(|| format!("{} is not a valid token issue date", issued_at))?;
+
+                (subscriber_id, Some(issued_at))
+            }
+            None => (value, None),
+        };
+        let subscriber_id = Uuid::parse_str(subscriber_id)
+            .map_err(|_| format!("{} is not a valid subscriber id", subscriber_id))?;
+
+        Ok(Self {
+            subscriber_id,
+            issued_at,
+        })
+    }
+
+    pub fn is_expired(&self, validity: Duration) -> bool {
+        self.issued_at
+            .map(|issued_at| Utc::now() >= issued_at + validity)
+            .unwrap_or(false)
+    }
+}
+
+impl std::fmt::Display for SubscriptionTokenRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.issued_at {
+            Some(issued_at) => write!(f, "{}:{}", self.subscriber_id, issued_at.timestamp()),
+            None => write!(f, "{}", self.subscriber_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriptionTokenRecord;
+    use chrono::{Duration, Utc};
+    use claim::{assert_err, assert_ok};
+    use uuid::Uuid;
+
+    #[test]
+    fn records_are_parsed_back() {
+        let record = SubscriptionTokenRecord::new(Uuid::new_v4());
+        let parsed = assert_ok!(SubscriptionTokenRecord::parse(&record.to_string()));
+
+        assert_eq!(parsed.subscriber_id, record.subscriber_id);
+        assert_eq!(
+            parsed.issued_at.unwrap().timestamp(),
+            record.issued_at.unwrap().timestamp()
+        );
+    }
+
+    #[test]
+    fn records_without_issue_date_never_expire() {
+        let subscriber_id = Uuid::new_v4();
+        let record = assert_ok!(SubscriptionTokenRecord::parse(&subscriber_id.to_string()));
+
+        assert_eq!(record.issued_at, None);
+        assert!(!record.is_expired(Duration::zero()));
+    }
+
+    #[test]
+    fn records_expire_after_their_validity() {
+        let record = SubscriptionTokenRecord {
+            subscriber_id: Uuid::new_v4(),
+            issued_at: Some(Utc::now() - Duration::hours(73)),
+        };
+
+        assert!(record.is_expired(Duration::hours(72)));
+        assert!(!record.is_expired(Duration::hours(74)));
+    }
+
+    #[test]
+    fn invalid_records_are_rejected() {
+        assert_err!(SubscriptionTokenRecord::parse("not-an-id"));
+        assert_err!(SubscriptionTokenRecord::parse(&format!(
+            "{}:yesterday",
+            Uuid::new_v4()
+        )));
+    }
+}
@@ -1,8 +1,11 @@
 use reqwest::Client;
 use secrecy::{ExposeSecret, Secret};
+use std::collections::HashMap;
 use std::time;
+use uuid::Uuid;
 
 use crate::domain::subscriber_email::SubscriberEmail;
+use crate::rate_limit::RateLimiter;
 
 const REQUEST_TIMEOUT: time::Duration = time::Duration::from_secs(10);
 
@@ -11,6 +14,7 @@ pub struct EmailClient {
     base_url: String,
     sender: SubscriberEmail,
     api_key: Secret<String>,
+    rate_limiter: Option<RateLimiter>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
@@ -29,6 +33,11 @@ pub struct SengridEmail {
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub struct SengridPersonalization {
     pub to: Vec<SengridEmail>,
+    /// Values Sendgrid sends back in the events of the email, see `handle_sendgrid_events`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub custom_args: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, String>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
@@ -55,70 +64,107 @@ impl EmailClient {
             base_url,
             sender,
             api_key,
+            rate_limiter: None,
         }
     }
 
+    /// Waits for the rate limiter before sending each email
+    pub fn with_rate_limiter(mut self, rate_limiter: RateLimiter) -> EmailClient {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Sends a multipart/alternative email with a plain-text and an HTML part.
     pub async fn send_email(
         &self,
         recipent: SubscriberEmail,
         subject: &str,
         html_content: &str,
+        text_content: &str,
     ) -> Result<(), reqwest::Error> {
-        let url = format!("{}/mail/send", self.base_url);
-        let body = SendEmailBody {
-            from: SengridEmail {
-                email: String::from(self.sender.as_ref()),
-            },
-            personalizations: vec![SengridPersonalization {
-                to: vec![SengridEmail {
-                    email: String::from(recipent.as_ref()),
-                }],
-            }],
-            subject: String::from(subject),
-            content: vec![SengridContent {
-                content_type: String::from("text/html"),
-                value: String::from(html_content),
-            }],
-        };
-
-        self.http_client
-            .post(&url)
-            .header(
-                "Authorization",
-                format!("Bearer {}", self.api_key.expose_secret()),
-            )
-            .json(&body)
-            .send()
-            .await?
-            .error_for_status()?; // return an error when server response status code is 4xx or 5xx
+        self.send(
+            recipent,
+            subject,
+            html_content,
+            text_content,
+            HashMap::new(),
+            HashMap::new(),
+        )
+        .await
+    }
 
-        Ok(())
+    /// Sends the email of a newsletter delivery. The delivery id is attached to the email, so the events Sendgrid
+    /// reports for it (delivered, bounced, etc.) can be matched with the delivery. The unsubscribe URL is also sent
+    /// in the headers for one-click unsubscribe (RFC 8058), email clients POST to it.
+    pub async fn send_delivery_email(
+        &self,
+        recipent: SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        delivery_id: &Uuid,
+        unsubscribe_url: &str,
+    ) -> Result<(), reqwest::Error> {
+        let custom_args = HashMap::from([(String::from("delivery_id"), delivery_id.to_string())]);
+        let headers = HashMap::from([
+            (
+                String::from("List-Unsubscribe"),
+                format!("<{}>", unsubscribe_url),
+            ),
+            (
+                String::from("List-Unsubscribe-Post"),
+                String::from("List-Unsubscribe=One-Click"),
+            ),
+        ]);
+
+        self.send(
+            recipent,
+            subject,
+            html_content,
+            text_content,
+            custom_args,
+            headers,
+        )
+        .await
     }
 
-    pub async fn broadcast_email(
+    async fn send(
         &self,
-        recipents: Vec<SubscriberEmail>,
+        recipent: SubscriberEmail,
         subject: &str,
         html_content: &str,
+        text_content: &str,
+        custom_args: HashMap<String, String>,
+        headers: HashMap<String, String>,
     ) -> Result<(), reqwest::Error> {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.acquire(&recipent).await;
+        }
+
         let url = format!("{}/mail/send", self.base_url);
         let body = SendEmailBody {
             from: SengridEmail {
                 email: String::from(self.sender.as_ref()),
             },
             personalizations: vec![SengridPersonalization {
-                to: recipents
-                    .into_iter()
-                    .map(|recipent| SengridEmail {
-                        email: String::from(recipent.as_ref()),
-                    })
-                    .collect(),
+                to: vec![SengridEmail {
+                    email: String::from(recipent.as_ref()),
+                }],
+                custom_args,
+                headers,
             }],
             subject: String::from(subject),
-            content: vec![SengridContent {
-                content_type: String::from("text/html"),
-                value: String::from(html_content),
-            }],
+            // Sendgrid requires the text/plain part to be the first one
+            content: vec![
+                SengridContent {
+                    content_type: String::from("text/plain"),
+                    value: String::from(text_content),
+                },
+                SengridContent {
+                    content_type: String::from("text/html"),
+                    value: String::from(html_content),
+                },
+            ],
         };
 
         self.http_client
@@ -156,7 +202,8 @@ mod tests {
                 return body.get("from").is_some()
                     && body.get("personalizations").is_some()
                     && body.get("subject").is_some()
-                    && body.get("content").is_some();
+                    && body["content"][0]["type"] == "text/plain"
+                    && body["content"][1]["type"] == "text/html";
             }
 
             false
@@ -185,7 +232,7 @@ mod tests {
         let content: String = Paragraph(1..10).fake();
 
         let response = email_client
-            .send_email(subscriber_email, &subject, &content)
+            .send_email(subscriber_email, &subject, &content, &content)
             .await;
 
         assert_ok!(response);
@@ -209,7 +256,7 @@ mod tests {
         let content: String = Paragraph(1..10).fake();
 
         let response = email_client
-            .send_email(subscriber_email, &subject, &content)
+            .send_email(subscriber_email, &subject, &content, &content)
             .await;
 
         assert_err!(response);
@@ -237,7 +284,7 @@ mod tests {
         let content: String = Paragraph(1..10).fake();
 
         let response = email_client
-            .send_email(subscriber_email, &subject, &content)
+            .send_email(subscriber_email, &subject, &content, &content)
             .await;
 
         assert_err!(response);
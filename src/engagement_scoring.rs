@@ -0,0 +1,169 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Row, Transaction};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::config::Settings;
+use crate::domain::engagement_score::{EngagementEvent, EngagementScoring};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(3600);
+/// Scores are refreshed once they are this old, so segments and sending priorities see the decay of subscribers
+/// without new events
+const REFRESH_AFTER_HOURS: i64 = 24;
+/// Subscribers rescored in each transaction, so a backlog does not hold a long transaction
+const BATCH_SIZE: i64 = 1000;
+
+/// Updates the score of the subscriber with an event that happened at `now`, in the transaction that records the
+/// event. The row is locked while the score is computed, so concurrent events are not lost.
+#[tracing::instrument(
+    name = "Update the engagement score of a subscriber",
+    skip(transaction, scoring)
+)]
+pub async fn record_engagement(
+    transaction: &mut Transaction<'_, Postgres>,
+    scoring: &EngagementScoring,
+    subscriber_id: Uuid,
+    event: EngagementEvent,
+    now: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    let row = sqlx::query(
+        r#"
+        SELECT engagement_score, engagement_scored_at, subscribed_at
+        FROM subscriptions
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+    )
+    .bind(subscriber_id)
+    .fetch_optional(&mut *transaction)
+    .await?;
+    let Some(row) = row else {
+        return Ok(());
+    };
+    let score = scoring.add_event(
+        row.try_get("engagement_score")?,
+        row.try_get("engagement_scored_at")?,
+        row.try_get("subscribed_at")?,
+        event,
+        now,
+    );
+
+    sqlx::query(
+        r#"
+        UPDATE subscriptions
+        SET engagement_score = $2, engagement_scored_at = $3
+        WHERE id = $1
+        "#,
+    )
+    .bind(subscriber_id)
+    .bind(score)
+    .bind(now)
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+/// Applies the decay to the scores of the confirmed subscribers that were not updated recently
+pub struct EngagementScoringJob {
+    db_pool: PgPool,
+    scoring: EngagementScoring,
+}
+
+impl EngagementScoringJob {
+    pub fn new(db_pool: PgPool, config: &Settings) -> Self {
+        Self {
+            db_pool,
+            scoring: config.get_engagement_scoring(),
+        }
+    }
+
+    pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+
+            // Errors are retried in the next run, the scores stay due until they are refreshed
+            let _ = self.run(Utc::now()).await;
+        }
+    }
+
+    /// Returns the number of subscribers rescored
+    #[tracing::instrument(name = "Refresh the engagement scores", skip(self), err(Debug))]
+    pub async fn run(&self, now: DateTime<Utc>) -> Result<usize, sqlx::Error> {
+        let mut rescored = 0;
+
+        loop {
+            let batch = self.refresh_batch(now).await?;
+
+            if batch == 0 {
+                break;
+            }
+
+            rescored += batch;
+        }
+
+        tracing::info!(
+            engagement_scores_refreshed = rescored,
+            "Engagement scores refreshed."
+        );
+
+        Ok(rescored)
+    }
+
+    async fn refresh_batch(&self, now: DateTime<Utc>) -> Result<usize, sqlx::Error> {
+        let mut transaction = self.db_pool.begin().await?;
+        // SKIP LOCKED lets the events update the scores of the subscribers in the batch after it is committed
+        let scores: Vec<(Uuid, f64)> = sqlx::query(
+            r#"
+            SELECT id, engagement_score, engagement_scored_at, subscribed_at
+            FROM subscriptions
+            WHERE status = 'confirmed'
+                AND (engagement_scored_at IS NULL OR engagement_scored_at <= $1 - make_interval(hours => $2))
+            FOR UPDATE SKIP LOCKED
+            LIMIT $3
+            "#,
+        )
+        .bind(now)
+        .bind(REFRESH_AFTER_HOURS as i32)
+        .bind(BATCH_SIZE)
+        .fetch_all(&mut transaction)
+        .await?
+        .into_iter()
+        .map(|row| {
+            Ok((
+                row.try_get("id")?,
+                self.scoring.decay(
+                    row.try_get("engagement_score")?,
+                    row.try_get("engagement_scored_at")?,
+                    row.try_get("subscribed_at")?,
+                    now,
+                ),
+            ))
+        })
+        .collect::<Result<_, sqlx::Error>>()?;
+
+        if scores.is_empty() {
+            return Ok(0);
+        }
+
+        let (ids, scores): (Vec<Uuid>, Vec<f64>) = scores.into_iter().unzip();
+
+        sqlx::query(
+            r#"
+            UPDATE subscriptions
+            SET engagement_score = refreshed.score, engagement_scored_at = $3
+            FROM unnest($1::uuid[], $2::float8[]) AS refreshed(id, score)
+            WHERE subscriptions.id = refreshed.id
+            "#,
+        )
+        .bind(&ids)
+        .bind(scores)
+        .bind(now)
+        .execute(&mut transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(ids.len())
+    }
+}
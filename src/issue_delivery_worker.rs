@@ -0,0 +1,378 @@
+use secrecy::Secret;
+use sqlx::{postgres::PgRow, types::Json, PgPool, Row};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::config::Settings;
+use crate::content::archive::{add_view_in_browser_link, archive_url};
+use crate::content::html_to_text::html_to_text;
+use crate::content::template::{MergeContext, MergeTemplate};
+use crate::content::tracking::{
+    add_open_tracking_pixel, is_trackable_link, rewrite_html_links, rewrite_text_links,
+    sign_click_token, sign_preferences_token, sign_unsubscribe_token,
+};
+use crate::domain::delivery_status::DeliveryStatus;
+use crate::domain::subscriber_attributes::AttributesMap;
+use crate::domain::subscriber_email::SubscriberEmail;
+use crate::domain::subscriber_status::SubscriberStatus;
+use crate::email_client::EmailClient;
+use crate::startup::{get_connection_db_pool, get_email_client};
+
+const EMPTY_QUEUE_DELAY: Duration = Duration::from_secs(10);
+const ERROR_DELAY: Duration = Duration::from_secs(1);
+/// Deliveries claimed by a worker at once
+const BATCH_SIZE: i64 = 50;
+/// Time a worker has to send the batch it claimed, before other workers can claim its deliveries again
+const CLAIM_LEASE: Duration = Duration::from_secs(300);
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+/// Newsletter issue addressed to one subscriber
+pub struct IssueDelivery {
+    pub id: Uuid,
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    /// Subject of the email: the title, or a subject of the test when the issue has one
+    pub subject: String,
+    pub slug: String,
+    pub html_content: MergeTemplate,
+    pub text_content: Option<MergeTemplate>,
+    pub track_opens: bool,
+    pub track_clicks: bool,
+    pub is_private: bool,
+    pub subscriber_id: Uuid,
+    pub subscriber_email: String,
+    pub subscriber_name: String,
+    pub subscriber_status: String,
+    pub subscriber_attributes: AttributesMap,
+}
+
+/// Sends the pending newsletter deliveries, one email per subscriber.
+pub async fn run_worker_until_stopped(config: Settings) -> Result<(), std::io::Error> {
+    let db_pool = get_connection_db_pool(&config.database);
+    let email_client = get_email_client(&config);
+
+    worker_loop(
+        db_pool,
+        email_client,
+        config.get_app_base_url(),
+        config.get_hmac_secret(),
+    )
+    .await
+}
+
+async fn worker_loop(
+    db_pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+    hmac_secret: Secret<String>,
+) -> Result<(), std::io::Error> {
+    loop {
+        match try_execute_task(&db_pool, &email_client, &base_url, &hmac_secret).await {
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+            Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(EMPTY_QUEUE_DELAY).await,
+            Err(_) => tokio::time::sleep(ERROR_DELAY).await,
+        }
+    }
+}
+
+/// Sends a batch of the oldest pending deliveries. The batch is claimed in one statement and sent without holding
+/// any lock, each delivery is completed right after its send, so a worker that stops sends again at most one email.
+/// Deliveries paused or cancelled once claimed are still sent.
+#[tracing::instrument(name = "Execute a newsletter delivery task", skip_all, err(Debug))]
+pub async fn try_execute_task(
+    db_pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+) -> Result<ExecutionOutcome, sqlx::Error> {
+    let deliveries = claim_deliveries(db_pool).await?;
+
+    if deliveries.is_empty() {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    }
+
+    for delivery in deliveries {
+        let status = deliver(&delivery, email_client, base_url, hmac_secret).await;
+
+        complete_delivery(db_pool, &delivery.id, status).await?;
+    }
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+#[tracing::instrument(
+    name = "Deliver a newsletter issue",
+    skip_all,
+    fields(
+        newsletter_issue_id = %delivery.newsletter_issue_id,
+        subscriber_email = %delivery.subscriber_email
+    )
+)]
+async fn deliver(
+    delivery: &IssueDelivery,
+    email_client: &EmailClient,
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+) -> DeliveryStatus {
+    // Subscribers can unsubscribe while an issue is being delivered
+    let is_confirmed = SubscriberStatus::parse(delivery.subscriber_status.clone())
+        .map(|status| status.is_confirmed())
+        .unwrap_or(false);
+
+    if !is_confirmed {
+        tracing::info!("Skipping delivery to a subscriber that is no longer confirmed.");
+        return DeliveryStatus::Skipped;
+    }
+
+    // A corrupted address only skips its own delivery, the rest of the issue is still sent
+    let email = match SubscriberEmail::parse(delivery.subscriber_email.clone()) {
+        Ok(email) => email,
+        Err(err) => {
+            tracing::warn!("Skipping delivery to an invalid subscriber email: {}.", err);
+            return DeliveryStatus::Skipped;
+        }
+    };
+    let links = SubscriptionLinks::new(base_url, hmac_secret, delivery);
+    let (html_content, text_content) =
+        match render_delivery(delivery, base_url, hmac_secret, &links) {
+            Ok(content) => content,
+            Err(err) => {
+                tracing::error!("Failed to render the newsletter issue: {}.", err);
+                return DeliveryStatus::Failed;
+            }
+        };
+
+    match email_client
+        .send_delivery_email(
+            email,
+            &delivery.subject,
+            &html_content,
+            &text_content,
+            &delivery.id,
+            &links.unsubscribe_url,
+        )
+        .await
+    {
+        Ok(_) => DeliveryStatus::Sent,
+        Err(err) => {
+            tracing::error!("Failed to deliver the newsletter issue: {:?}.", err);
+            DeliveryStatus::Failed
+        }
+    }
+}
+
+/// Renders the HTML and plain-text parts of the issue for the subscriber
+pub fn render_delivery(
+    delivery: &IssueDelivery,
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+    links: &SubscriptionLinks,
+) -> Result<(String, String), String> {
+    let subscription_paths = [
+        format!("{}/subscriptions/unsubscribe", base_url),
+        format!("{}/subscriptions/preferences", base_url),
+    ];
+    // Private issues have no archive page, they cannot use the merge tag
+    let view_in_browser_url = if delivery.is_private {
+        String::new()
+    } else {
+        archive_url(base_url, &delivery.slug)
+    };
+    let merge_context = MergeContext {
+        subscriber_name: &delivery.subscriber_name,
+        subscriber_email: &delivery.subscriber_email,
+        attributes: &delivery.subscriber_attributes,
+        unsubscribe_url: links.unsubscribe_url.clone(),
+        preferences_url: links.preferences_url.clone(),
+        view_in_browser_url: view_in_browser_url.clone(),
+    };
+    let mut html_content = delivery.html_content.render(&merge_context)?;
+    let mut text_content = match &delivery.text_content {
+        Some(text_content) => text_content.render_text(&merge_context)?,
+        None => html_to_text(&html_content),
+    };
+
+    if delivery.track_clicks {
+        // Unsubscribe and preferences links are never tracked, they must keep working without going through a
+        // redirect
+        let track_link = |url: &str| {
+            let is_subscription_link = subscription_paths.iter().any(|path| url.starts_with(path));
+
+            (is_trackable_link(url) && !is_subscription_link).then(|| {
+                format!(
+                    "{}/t/c/{}",
+                    base_url,
+                    sign_click_token(hmac_secret, &delivery.id, url)
+                )
+            })
+        };
+
+        html_content = rewrite_html_links(&html_content, track_link);
+        text_content = rewrite_text_links(&text_content, track_link);
+    }
+
+    // The link is added unless the content already places it with the merge tag
+    let has_view_in_browser_link = [Some(&delivery.html_content), delivery.text_content.as_ref()]
+        .into_iter()
+        .flatten()
+        .any(|content| content.as_ref().contains("view_in_browser_url"));
+
+    if !delivery.is_private && !has_view_in_browser_link {
+        html_content = add_view_in_browser_link(&html_content, &view_in_browser_url);
+        text_content = format!(
+            "View in browser: {}\n\n{}",
+            view_in_browser_url, text_content
+        );
+    }
+
+    if delivery.track_opens {
+        html_content = add_open_tracking_pixel(
+            &html_content,
+            &format!("{}/t/o/{}.gif", base_url, delivery.id),
+        );
+    }
+
+    Ok((html_content, text_content))
+}
+
+/// Links of an email that change the subscription of its recipient
+pub struct SubscriptionLinks {
+    /// Following the link shows a confirmation page, the subscriber is only unsubscribed when it is submitted or when
+    /// the email client unsubscribes with one click
+    pub unsubscribe_url: String,
+    /// Page where the subscriber chooses their timezone
+    pub preferences_url: String,
+}
+
+impl SubscriptionLinks {
+    pub fn new(base_url: &str, hmac_secret: &Secret<String>, delivery: &IssueDelivery) -> Self {
+        Self {
+            unsubscribe_url: format!(
+                "{}/subscriptions/unsubscribe?token={}",
+                base_url,
+                sign_unsubscribe_token(hmac_secret, &delivery.id)
+            ),
+            preferences_url: format!(
+                "{}/subscriptions/preferences?token={}",
+                base_url,
+                sign_preferences_token(hmac_secret, &delivery.subscriber_id)
+            ),
+        }
+    }
+
+    /// Links of previews and test emails. Their tokens have no signature, so the routes reject them.
+    pub fn preview(base_url: &str) -> Self {
+        Self {
+            unsubscribe_url: format!("{}/subscriptions/unsubscribe?token=preview", base_url),
+            preferences_url: format!("{}/subscriptions/preferences?token=preview", base_url),
+        }
+    }
+}
+
+/// Claims the oldest pending deliveries that are due and not claimed by another worker
+#[tracing::instrument(name = "Claim pending newsletter deliveries", skip(db_pool))]
+async fn claim_deliveries(db_pool: &PgPool) -> Result<Vec<IssueDelivery>, sqlx::Error> {
+    // SKIP LOCKED lets several workers claim batches concurrently without claiming a delivery twice
+    sqlx::query(
+        r#"
+        WITH claimed AS (
+            UPDATE issue_deliveries
+            SET claimed_until = now() + make_interval(secs => $2)
+            WHERE id IN (
+                SELECT id FROM issue_deliveries
+                WHERE status = 'pending'
+                    AND (send_at IS NULL OR send_at <= now())
+                    AND (claimed_until IS NULL OR claimed_until <= now())
+                ORDER BY created_at, priority DESC
+                FOR UPDATE SKIP LOCKED
+                LIMIT $1
+            )
+            RETURNING id, newsletter_issue_id, subscriber_id, subject_variant, created_at, priority
+        )
+        SELECT
+            claimed.id,
+            claimed.newsletter_issue_id,
+            newsletter_issues.title,
+            coalesce(
+                subject_tests.subjects[claimed.subject_variant + 1],
+                subject_tests.subjects[subject_tests.winner + 1],
+                newsletter_issues.title
+            ) AS subject,
+            newsletter_issues.slug,
+            newsletter_issues.html_content,
+            newsletter_issues.text_content,
+            newsletter_issues.track_opens,
+            newsletter_issues.track_clicks,
+            newsletter_issues.is_private,
+            claimed.subscriber_id,
+            subscriptions.email,
+            subscriptions.name,
+            subscriptions.status,
+            subscriptions.attributes
+        FROM claimed
+        JOIN newsletter_issues ON newsletter_issues.id = claimed.newsletter_issue_id
+        JOIN subscriptions ON subscriptions.id = claimed.subscriber_id
+        LEFT JOIN subject_tests ON subject_tests.newsletter_issue_id = claimed.newsletter_issue_id
+        ORDER BY claimed.created_at, claimed.priority DESC
+        "#,
+    )
+    .bind(BATCH_SIZE)
+    .bind(CLAIM_LEASE.as_secs_f64())
+    .map(|row: PgRow| IssueDelivery {
+        id: row.get("id"),
+        newsletter_issue_id: row.get("newsletter_issue_id"),
+        title: row.get("title"),
+        subject: row.get("subject"),
+        slug: row.get("slug"),
+        html_content: MergeTemplate::from(row.get::<String, _>("html_content")),
+        text_content: row
+            .get::<Option<String>, _>("text_content")
+            .map(MergeTemplate::from),
+        track_opens: row.get("track_opens"),
+        track_clicks: row.get("track_clicks"),
+        is_private: row.get("is_private"),
+        subscriber_id: row.get("subscriber_id"),
+        subscriber_email: row.get("email"),
+        subscriber_name: row.get("name"),
+        subscriber_status: row.get("status"),
+        subscriber_attributes: row.get::<Json<AttributesMap>, _>("attributes").0,
+    })
+    .fetch_all(db_pool)
+    .await
+}
+
+/// The status is recorded even if the delivery was paused or cancelled since it was claimed, the email was sent
+#[tracing::instrument(
+    name = "Complete a newsletter delivery",
+    skip(db_pool),
+    fields(status = %status.as_ref())
+)]
+async fn complete_delivery(
+    db_pool: &PgPool,
+    delivery_id: &Uuid,
+    status: DeliveryStatus,
+) -> Result<(), sqlx::Error> {
+    let sent_at = match status {
+        DeliveryStatus::Sent => Some(chrono::Utc::now()),
+        _ => None,
+    };
+
+    sqlx::query(
+        r#"
+        UPDATE issue_deliveries
+        SET status = $2, sent_at = $3, claimed_until = NULL
+        WHERE id = $1
+        "#,
+    )
+    .bind(delivery_id)
+    .bind(status.as_ref())
+    .bind(sent_at)
+    .execute(db_pool)
+    .await?;
+
+    Ok(())
+}
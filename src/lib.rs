@@ -1,6 +1,15 @@
 pub mod config;
+pub mod content;
 pub mod domain;
 pub mod email_client;
+pub mod engagement_scoring;
+pub mod issue_delivery_worker;
+pub mod pending_subscriptions;
+pub mod rate_limit;
+pub mod repository;
 pub mod routes;
+pub mod scheduler;
+pub mod sequences;
 pub mod startup;
+pub mod sunset_policy;
 pub mod telemetry;
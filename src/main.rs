@@ -1,6 +1,9 @@
 use email_newsletter::config::get_configuration;
+use email_newsletter::issue_delivery_worker::run_worker_until_stopped;
 use email_newsletter::startup::Application;
 use email_newsletter::telemetry::{get_subscriber, init_subscriber};
+use std::fmt::{Debug, Display};
+use tokio::task::JoinError;
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
@@ -15,7 +18,38 @@ async fn main() -> std::io::Result<()> {
 
     tracing::info!("Server listening on {}", config.get_address());
 
-    application.run_until_stop().await?;
+    let application_task = tokio::spawn(application.run_until_stop());
+    let worker_task = tokio::spawn(run_worker_until_stopped(config));
+
+    // The process stops as soon as either the API or the delivery worker stops
+    tokio::select! {
+        outcome = application_task => report_exit("API", outcome),
+        outcome = worker_task => report_exit("Newsletter delivery worker", outcome),
+    };
 
     Ok(())
 }
+
+fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
+    match outcome {
+        Ok(Ok(())) => {
+            tracing::info!("{} has exited", task_name)
+        }
+        Ok(Err(err)) => {
+            tracing::error!(
+                error.cause_chain = ?err,
+                error.message = %err,
+                "{} failed",
+                task_name
+            )
+        }
+        Err(err) => {
+            tracing::error!(
+                error.cause_chain = ?err,
+                error.message = %err,
+                "{} task failed to complete",
+                task_name
+            )
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use chrono::{DateTime, Utc};
+use sqlx::{postgres::PgRow, PgPool};
+use std::collections::HashSet;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::config::{PendingCleanup, Settings, SubscriptionConfirmationSettings};
+use crate::content::email_template::LocalizedEmailTemplates;
+use crate::email_client::EmailClient;
+use crate::repository::postgres_subscriber_repository::parse_subscriber;
+use crate::repository::subscriber_repository::SubscriberRepositoryError;
+use crate::routes::{
+    generate_subscription_token, revoke_subscription_tokens, send_confirmation_email,
+    store_subscription_token, CreateSubscriptionError,
+};
+use crate::startup::{get_confirmation_reminder_email_templates, get_email_client};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(600);
+/// Subscriptions handled in each query, so a backlog does not hold a long transaction
+const BATCH_SIZE: i64 = 100;
+
+/// Actions of a run of the job. They are the metrics of the job: each run logs them as the fields of a single
+/// structured log event, which the log pipeline aggregates, as the application has no metrics exporter.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PendingSubscriptionsOutcome {
+    pub reminders_sent: usize,
+    pub reminders_failed: usize,
+    pub deleted: usize,
+    pub anonymised: usize,
+    /// Confirmation links revoked in Redis, a subscriber may have several
+    pub tokens_revoked: usize,
+}
+
+/// Reminds the subscribers who did not confirm their subscription, and cleans up the subscriptions that were never
+/// confirmed once their retention period ends. Subscriptions are claimed before being handled, so every instance of
+/// the application can run the job.
+pub struct PendingSubscriptionsJob {
+    db_pool: PgPool,
+    email_client: EmailClient,
+    redis_client: redis::Client,
+    reminder_email_templates: LocalizedEmailTemplates,
+    base_url: String,
+    settings: SubscriptionConfirmationSettings,
+}
+
+impl PendingSubscriptionsJob {
+    pub fn new(db_pool: PgPool, config: &Settings) -> Self {
+        Self {
+            db_pool,
+            email_client: get_email_client(config),
+            redis_client: redis::Client::open(config.get_redis_address())
+                .expect("Failed to connect redis server."),
+            reminder_email_templates: get_confirmation_reminder_email_templates(config),
+            base_url: config.get_app_base_url(),
+            settings: config.get_subscription_confirmation(),
+        }
+    }
+
+    pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+
+            // Errors are retried in the next run, the subscriptions stay due until they are handled
+            let _ = self.run(Utc::now()).await;
+        }
+    }
+
+    /// Sends the reminders and cleans up the subscriptions that are due at `now`. The outcome is logged as the
+    /// metrics of the run.
+    #[tracing::instrument(name = "Run the pending subscriptions job", skip(self), err(Debug))]
+    pub async fn run(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<PendingSubscriptionsOutcome, sqlx::Error> {
+        let mut outcome = PendingSubscriptionsOutcome::default();
+
+        if let Some(reminder_delay) = self.settings.get_reminder_delay() {
+            self.send_reminders(now - reminder_delay, now, &mut outcome)
+                .await?;
+        }
+
+        self.clean_up(now - self.settings.get_pending_retention(), &mut outcome)
+            .await?;
+
+        tracing::info!(
+            reminders_sent = outcome.reminders_sent,
+            reminders_failed = outcome.reminders_failed,
+            pending_subscriptions_deleted = outcome.deleted,
+            pending_subscriptions_anonymised = outcome.anonymised,
+            subscription_tokens_revoked = outcome.tokens_revoked,
+            "Pending subscriptions job completed."
+        );
+
+        Ok(outcome)
+    }
+
+    /// Each subscriber gets a single reminder, marked as sent before sending it so two instances cannot both send
+    /// it. A reminder that fails to be sent is not retried.
+    async fn send_reminders(
+        &self,
+        subscribed_before: DateTime<Utc>,
+        now: DateTime<Utc>,
+        outcome: &mut PendingSubscriptionsOutcome,
+    ) -> Result<(), sqlx::Error> {
+        // Subscriptions about to be cleaned up are not worth a reminder
+        let retention_start = now - self.settings.get_pending_retention();
+
+        loop {
+            let subscribers = sqlx::query(
+                r#"
+                UPDATE subscriptions
+                SET confirmation_reminder_sent_at = $1
+                WHERE id IN (
+                    SELECT id FROM subscriptions
+                    WHERE status = 'pending_confirmation'
+                        AND anonymised_at IS NULL
+                        AND confirmation_reminder_sent_at IS NULL
+                        AND subscribed_at <= $2
+                        AND subscribed_at > $3
+                    ORDER BY subscribed_at
+                    FOR UPDATE SKIP LOCKED
+                    LIMIT $4
+                )
+                RETURNING id, email, name, subscribed_at, status, attributes, locale, timezone, engagement_score, list
+                "#,
+            )
+            .bind(now)
+            .bind(subscribed_before)
+            .bind(retention_start)
+            .bind(BATCH_SIZE)
+            .fetch_all(&self.db_pool)
+            .await?;
+
+            if subscribers.is_empty() {
+                return Ok(());
+            }
+
+            for row in subscribers {
+                match self.send_reminder(row).await {
+                    Ok(()) => outcome.reminders_sent += 1,
+                    Err(err) => {
+                        tracing::error!("Failed to send a confirmation reminder: {:?}.", err);
+                        outcome.reminders_failed += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// The reminder has a new confirmation link, the first one may have expired
+    async fn send_reminder(&self, row: PgRow) -> Result<(), ReminderError> {
+        let subscriber = parse_subscriber(row)?;
+        let subscription_token = generate_subscription_token();
+
+        store_subscription_token(
+            &self.db_pool,
+            &self.redis_client,
+            &subscription_token,
+            &subscriber.id,
+            self.settings.get_token_validity(),
+        )
+        .await
+        .map_err(CreateSubscriptionError::from)?;
+        send_confirmation_email(
+            &self.email_client,
+            &self.reminder_email_templates,
+            &subscriber,
+            &self.base_url,
+            &subscription_token,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Deletes or anonymises the subscriptions never confirmed, and revokes their confirmation links so they cannot be
+    /// confirmed afterwards
+    async fn clean_up(
+        &self,
+        subscribed_before: DateTime<Utc>,
+        outcome: &mut PendingSubscriptionsOutcome,
+    ) -> Result<(), sqlx::Error> {
+        loop {
+            // Each row is a subscriber with one of their tokens, or with none when they have no token
+            let subscriber_tokens: Vec<(Uuid, Option<String>)> = match self.settings.pending_cleanup {
+                // The tokens are deleted with the subscriptions, they are read from the snapshot of the statement
+                PendingCleanup::Delete => {
+                    sqlx::query_as(
+                        r#"
+                        WITH deleted AS (
+                            DELETE FROM subscriptions
+                            WHERE id IN (
+                                SELECT id FROM subscriptions
+                                WHERE status = 'pending_confirmation'
+                                    AND anonymised_at IS NULL
+                                    AND subscribed_at <= $1
+                                FOR UPDATE SKIP LOCKED
+                                LIMIT $2
+                            )
+                            RETURNING id
+                        )
+                        SELECT deleted.id, subscription_tokens.subscription_token
+                        FROM deleted
+                        LEFT JOIN subscription_tokens ON subscription_tokens.subscriber_id = deleted.id
+                        "#,
+                    )
+                    .bind(subscribed_before)
+                    .bind(BATCH_SIZE)
+                    .fetch_all(&self.db_pool)
+                    .await?
+                }
+                // The email must stay unique and valid, so it is made from the id
+                PendingCleanup::Anonymise => {
+                    sqlx::query_as(
+                        r#"
+                        WITH anonymised AS (
+                            UPDATE subscriptions
+                            SET
+                                email = id || '@anonymised.invalid',
+                                name = 'Anonymised',
+                                attributes = '{}',
+                                timezone = NULL,
+                                anonymised_at = now()
+                            WHERE id IN (
+                                SELECT id FROM subscriptions
+                                WHERE status = 'pending_confirmation'
+                                    AND anonymised_at IS NULL
+                                    AND subscribed_at <= $1
+                                FOR UPDATE SKIP LOCKED
+                                LIMIT $2
+                            )
+                            RETURNING id
+                        ), tags AS (
+                            DELETE FROM subscriber_tags
+                            WHERE subscriber_id IN (SELECT id FROM anonymised)
+                        ), tokens AS (
+                            DELETE FROM subscription_tokens
+                            WHERE subscriber_id IN (SELECT id FROM anonymised)
+                            RETURNING subscriber_id, subscription_token
+                        )
+                        SELECT anonymised.id, tokens.subscription_token
+                        FROM anonymised
+                        LEFT JOIN tokens ON tokens.subscriber_id = anonymised.id
+                        "#,
+                    )
+                    .bind(subscribed_before)
+                    .bind(BATCH_SIZE)
+                    .fetch_all(&self.db_pool)
+                    .await?
+                }
+            };
+
+            if subscriber_tokens.is_empty() {
+                return Ok(());
+            }
+
+            let subscribers = subscriber_tokens
+                .iter()
+                .map(|(subscriber_id, _)| subscriber_id)
+                .collect::<HashSet<_>>()
+                .len();
+            let subscription_tokens: Vec<String> = subscriber_tokens
+                .into_iter()
+                .filter_map(|(_, subscription_token)| subscription_token)
+                .collect();
+
+            // The subscription is already cleaned up, a link left behind expires with its token
+            match revoke_subscription_tokens(&self.redis_client, &subscription_tokens).await {
+                Ok(()) => outcome.tokens_revoked += subscription_tokens.len(),
+                Err(err) => tracing::error!("Failed to revoke the subscription tokens: {:?}.", err),
+            }
+
+            match self.settings.pending_cleanup {
+                PendingCleanup::Delete => outcome.deleted += subscribers,
+                PendingCleanup::Anonymise => outcome.anonymised += subscribers,
+            }
+        }
+    }
+}
+
+#[derive(thiserror::Error)]
+enum ReminderError {
+    #[error("Failed to parse the pending subscriber.")]
+    CorruptRow(#[from] SubscriberRepositoryError),
+    #[error("Failed to send the confirmation reminder.")]
+    SendError(#[from] CreateSubscriptionError),
+}
+
+impl std::fmt::Debug for ReminderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Caused by:\n\t({})", self)
+    }
+}
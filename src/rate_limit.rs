@@ -0,0 +1,309 @@
+use redis::aio::MultiplexedConnection;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::config::RateLimitSettings;
+use crate::domain::subscriber_email::SubscriberEmail;
+
+/// Idle buckets are removed from Redis after this time, by then they are full again
+const BUCKET_TTL_MILLISECONDS: u64 = 60_000;
+/// Wait before retrying a token after Redis failed, doubled on each failure up to the maximum
+const MIN_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Refills the bucket in `KEYS[1]` at `ARGV[1]` tokens per second, up to one second of sends, and takes a token.
+/// Returns 0 when the token was taken, or the milliseconds to wait for the next one. Redis runs scripts one at a
+/// time, so instances taking tokens at once never read the same state, and its clock is the only one used.
+const TAKE_TOKEN_SCRIPT: &str = r#"
+local rate = tonumber(ARGV[1])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'updated_at')
+local tokens = tonumber(bucket[1])
+local updated_at = tonumber(bucket[2])
+
+if tokens == nil or updated_at == nil then
+    tokens = rate
+    updated_at = now
+end
+
+tokens = math.min(rate, tokens + math.max(0, now - updated_at) * rate / 1000)
+
+if tokens < 1 then
+    return math.max(1, math.ceil((1 - tokens) * 1000 / rate))
+end
+
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens - 1), 'updated_at', now)
+redis.call('PEXPIRE', KEYS[1], ARGV[2])
+
+return 0
+"#;
+
+/// Token buckets stored in Redis, shared by every instance of the application. One bucket limits all the emails
+/// sent and another one those sent to each recipient domain. Each bucket holds one second of sends, so bursts are
+/// spread over time instead of being rejected.
+///
+/// Tokens are taken with a script, so the bucket is refilled and updated in one atomic step and the limit holds
+/// however many instances send at once. Clones share a single multiplexed connection.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    redis_client: redis::Client,
+    redis_conn: Arc<Mutex<Option<MultiplexedConnection>>>,
+    take_token_script: redis::Script,
+    messages_per_second: u32,
+    messages_per_second_per_domain: u32,
+    fail_open: bool,
+}
+
+impl RateLimiter {
+    pub fn new(redis_client: redis::Client, settings: &RateLimitSettings) -> Self {
+        Self {
+            redis_client,
+            redis_conn: Arc::new(Mutex::new(None)),
+            take_token_script: redis::Script::new(TAKE_TOKEN_SCRIPT),
+            messages_per_second: settings.messages_per_second.max(1),
+            messages_per_second_per_domain: settings.messages_per_second_per_domain.max(1),
+            fail_open: settings.fail_open,
+        }
+    }
+
+    /// Waits until an email can be sent to the recipient. While Redis is unavailable the token is retried with a
+    /// growing backoff, or the email is sent without the limit when the limiter fails open.
+    #[tracing::instrument(name = "Wait for the send rate limit", skip_all)]
+    pub async fn acquire(&self, recipient: &SubscriberEmail) {
+        let domain = recipient
+            .as_ref()
+            .rsplit_once('@')
+            .map(|(_, domain)| domain.to_lowercase())
+            .unwrap_or_default();
+        let buckets = [
+            (
+                format!("rate_limit:domain:{}", domain),
+                self.messages_per_second_per_domain,
+            ),
+            (
+                String::from("rate_limit:messages"),
+                self.messages_per_second,
+            ),
+        ];
+
+        for (key, rate) in buckets {
+            let mut backoff = MIN_RETRY_BACKOFF;
+
+            while let Err(err) = self.take_token(&key, rate).await {
+                if self.fail_open {
+                    tracing::error!(
+                        "Failed to apply the send rate limit {}, the email is sent without it: {:?}",
+                        key,
+                        err
+                    );
+                    break;
+                }
+
+                tracing::error!(
+                    "Failed to apply the send rate limit {}, retrying in {:?}: {:?}",
+                    key,
+                    backoff,
+                    err
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_RETRY_BACKOFF);
+            }
+        }
+    }
+
+    async fn take_token(&self, key: &str, rate: u32) -> Result<(), redis::RedisError> {
+        let mut redis_conn = self.get_connection().await?;
+
+        loop {
+            let wait: u64 = match self
+                .take_token_script
+                .key(key)
+                .arg(rate)
+                .arg(BUCKET_TTL_MILLISECONDS)
+                .invoke_async(&mut redis_conn)
+                .await
+            {
+                Ok(wait) => wait,
+                Err(err) => {
+                    // The next call opens a new connection
+                    if err.is_connection_dropped() || err.is_io_error() {
+                        self.redis_conn.lock().unwrap().take();
+                    }
+
+                    return Err(err);
+                }
+            };
+
+            if wait == 0 {
+                return Ok(());
+            }
+
+            tokio::time::sleep(Duration::from_millis(wait)).await;
+        }
+    }
+
+    async fn get_connection(&self) -> Result<MultiplexedConnection, redis::RedisError> {
+        if let Some(redis_conn) = self.redis_conn.lock().unwrap().as_ref() {
+            return Ok(redis_conn.clone());
+        }
+
+        let redis_conn = self.redis_client.get_multiplexed_tokio_connection().await?;
+
+        *self.redis_conn.lock().unwrap() = Some(redis_conn.clone());
+
+        Ok(redis_conn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mlua::Lua;
+
+    /// Limiter whose Redis refuses every connection
+    fn unreachable_rate_limiter(fail_open: bool) -> RateLimiter {
+        RateLimiter::new(
+            redis::Client::open("redis://127.0.0.1:1").unwrap(),
+            &RateLimitSettings {
+                messages_per_second: 10,
+                messages_per_second_per_domain: 10,
+                fail_open,
+            },
+        )
+    }
+
+    fn recipient() -> SubscriberEmail {
+        SubscriberEmail::parse(String::from("frank@test.com")).unwrap()
+    }
+
+    /// Runs the script against an in-memory stand-in of the Redis commands it calls, with a clock set by the test
+    struct FakeRedis {
+        lua: Lua,
+    }
+
+    impl FakeRedis {
+        fn new() -> Self {
+            let lua = Lua::new();
+
+            lua.load(
+                r#"
+                now = 0
+                store = {}
+                redis = {}
+
+                function redis.call(command, key, ...)
+                    local args = {...}
+
+                    if command == 'TIME' then
+                        return { tostring(math.floor(now / 1000)), tostring((now % 1000) * 1000) }
+                    elseif command == 'HMGET' then
+                        local hash = store[key] or {}
+                        local values = {}
+
+                        -- Redis returns missing fields as false
+                        for i, field in ipairs(args) do
+                            values[i] = hash[field] or false
+                        end
+
+                        return values
+                    elseif command == 'HSET' then
+                        store[key] = store[key] or {}
+
+                        for i = 1, #args, 2 do
+                            store[key][args[i]] = tostring(args[i + 1])
+                        end
+
+                        return 1
+                    elseif command == 'PEXPIRE' then
+                        return 1
+                    end
+
+                    error('Unexpected command ' .. command)
+                end
+                "#,
+            )
+            .exec()
+            .unwrap();
+
+            Self { lua }
+        }
+
+        /// Milliseconds to wait for a token at `now`, 0 when the token was taken
+        fn take_token(&self, now: u64, rate: u32) -> u64 {
+            let globals = self.lua.globals();
+
+            globals.set("now", now).unwrap();
+            globals.set("KEYS", vec!["bucket"]).unwrap();
+            globals
+                .set("ARGV", vec![rate as u64, BUCKET_TTL_MILLISECONDS])
+                .unwrap();
+
+            self.lua.load(TAKE_TOKEN_SCRIPT).eval().unwrap()
+        }
+    }
+
+    #[test]
+    fn new_buckets_allow_one_second_of_sends_at_once() {
+        let redis = FakeRedis::new();
+
+        for _ in 0..5 {
+            assert_eq!(redis.take_token(1_000, 5), 0);
+        }
+
+        assert_eq!(redis.take_token(1_000, 5), 200);
+    }
+
+    #[test]
+    fn tokens_are_refilled_at_the_rate() {
+        let redis = FakeRedis::new();
+
+        assert_eq!(redis.take_token(1_000, 2), 0);
+        assert_eq!(redis.take_token(1_000, 2), 0);
+        assert_eq!(redis.take_token(1_000, 2), 500);
+        assert_eq!(redis.take_token(1_250, 2), 250);
+        assert_eq!(redis.take_token(1_500, 2), 0);
+        assert_eq!(redis.take_token(1_500, 2), 500);
+    }
+
+    #[test]
+    fn idle_buckets_hold_at_most_one_second_of_sends() {
+        let redis = FakeRedis::new();
+
+        assert_eq!(redis.take_token(1_000, 2), 0);
+        assert_eq!(redis.take_token(60_000, 2), 0);
+        assert_eq!(redis.take_token(60_000, 2), 0);
+        assert_eq!(redis.take_token(60_000, 2), 500);
+    }
+
+    #[test]
+    fn waits_are_at_least_a_millisecond() {
+        let redis = FakeRedis::new();
+
+        for _ in 0..5_000 {
+            redis.take_token(1_000, 5_000);
+        }
+
+        assert_eq!(redis.take_token(1_000, 5_000), 1);
+    }
+
+    #[tokio::test]
+    async fn sends_wait_for_redis_by_default() {
+        let rate_limiter = unreachable_rate_limiter(false);
+
+        let acquired =
+            tokio::time::timeout(Duration::from_secs(1), rate_limiter.acquire(&recipient())).await;
+
+        assert!(acquired.is_err());
+    }
+
+    #[tokio::test]
+    async fn sends_skip_the_limit_without_redis_when_failing_open() {
+        let rate_limiter = unreachable_rate_limiter(true);
+
+        let acquired =
+            tokio::time::timeout(Duration::from_secs(1), rate_limiter.acquire(&recipient())).await;
+
+        assert!(acquired.is_ok());
+    }
+}
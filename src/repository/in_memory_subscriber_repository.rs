@@ -0,0 +1,194 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+use crate::domain::new_subscriber::NewSubscriber;
+use crate::domain::status_history::StatusHistoryEntry;
+use crate::domain::subscriber::Subscriber;
+use crate::domain::subscriber_attributes::SubscriberAttributes;
+use crate::domain::subscriber_locale::SubscriberLocale;
+use crate::domain::subscriber_status::SubscriberStatus;
+use crate::domain::subscriber_tag::SubscriberTag;
+use crate::domain::subscriber_timezone::SubscriberTimezone;
+use crate::repository::subscriber_repository::{SubscriberRepository, SubscriberRepositoryError};
+
+/// Subscribers kept in memory, for the tests of the code depending on the repository. There are no sequences, so
+/// confirming a subscriber does not enrol them in any.
+#[derive(Default)]
+pub struct InMemorySubscriberRepository {
+    subscribers: Mutex<HashMap<Uuid, Subscriber>>,
+    tags: Mutex<BTreeSet<(Uuid, String)>>,
+    status_history: Mutex<Vec<(Uuid, StatusHistoryEntry)>>,
+}
+
+impl InMemorySubscriberRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, subscriber_id: Uuid) -> Option<Subscriber> {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .get(&subscriber_id)
+            .cloned()
+    }
+
+    pub fn get_tags(&self, subscriber_id: Uuid) -> Vec<String> {
+        self.tags
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(id, _)| *id == subscriber_id)
+            .map(|(_, tag)| tag.clone())
+            .collect()
+    }
+
+    /// The sunset policy only runs against the database, tests add the history it would record
+    pub fn add_status_history(&self, subscriber_id: Uuid, entry: StatusHistoryEntry) {
+        self.status_history
+            .lock()
+            .unwrap()
+            .push((subscriber_id, entry));
+    }
+
+    fn update(
+        &self,
+        subscriber_id: Uuid,
+        change: impl FnOnce(&mut Subscriber),
+    ) -> Option<Subscriber> {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let subscriber = subscribers.get_mut(&subscriber_id)?;
+
+        change(subscriber);
+
+        Some(subscriber.clone())
+    }
+}
+
+#[async_trait::async_trait]
+impl SubscriberRepository for InMemorySubscriberRepository {
+    async fn create(
+        &self,
+        new_subscriber: &NewSubscriber,
+        locale: &SubscriberLocale,
+        timezone: Option<&SubscriberTimezone>,
+    ) -> Result<Subscriber, SubscriberRepositoryError> {
+        let mut subscribers = self.subscribers.lock().unwrap();
+
+        if subscribers
+            .values()
+            .any(|subscriber| subscriber.email.as_ref() == new_subscriber.email.as_ref())
+        {
+            return Err(SubscriberRepositoryError::DuplicateEmail);
+        }
+
+        let subscriber = Subscriber {
+            id: Uuid::new_v4(),
+            email: new_subscriber.email.clone(),
+            name: new_subscriber.name.clone(),
+            status: SubscriberStatus::Pending,
+            subscribed_at: chrono::Utc::now(),
+            attributes: new_subscriber.attributes.clone(),
+            locale: locale.clone(),
+            timezone: timezone.cloned(),
+            engagement_score: 0.0,
+            list: new_subscriber.list.clone(),
+        };
+
+        subscribers.insert(subscriber.id, subscriber.clone());
+
+        Ok(subscriber)
+    }
+
+    async fn get(&self, subscriber_id: Uuid) -> Result<Option<Subscriber>, SubscriberRepositoryError> {
+        Ok(InMemorySubscriberRepository::get(self, subscriber_id))
+    }
+
+    async fn confirm(
+        &self,
+        subscriber_id: Uuid,
+    ) -> Result<Option<Subscriber>, SubscriberRepositoryError> {
+        if !self
+            .get(subscriber_id)
+            .map(|subscriber| subscriber.status.is_pending())
+            .unwrap_or(false)
+        {
+            return Ok(None);
+        }
+
+        Ok(self.update(subscriber_id, |subscriber| {
+            subscriber.status = SubscriberStatus::Confirmed
+        }))
+    }
+
+    async fn update_attributes(
+        &self,
+        subscriber_id: Uuid,
+        attributes: &SubscriberAttributes,
+    ) -> Result<Option<Subscriber>, SubscriberRepositoryError> {
+        Ok(self.update(subscriber_id, |subscriber| {
+            subscriber.attributes = attributes.clone()
+        }))
+    }
+
+    async fn update_timezone(
+        &self,
+        subscriber_id: Uuid,
+        timezone: Option<&SubscriberTimezone>,
+    ) -> Result<Option<Subscriber>, SubscriberRepositoryError> {
+        Ok(self.update(subscriber_id, |subscriber| {
+            subscriber.timezone = timezone.cloned()
+        }))
+    }
+
+    async fn add_tag(
+        &self,
+        subscriber_id: Uuid,
+        tag: &SubscriberTag,
+    ) -> Result<bool, SubscriberRepositoryError> {
+        if self.get(subscriber_id).is_none() {
+            return Ok(false);
+        }
+
+        self.tags
+            .lock()
+            .unwrap()
+            .insert((subscriber_id, tag.as_ref().to_owned()));
+
+        Ok(true)
+    }
+
+    async fn remove_tag(
+        &self,
+        subscriber_id: Uuid,
+        tag: &str,
+    ) -> Result<(), SubscriberRepositoryError> {
+        self.tags
+            .lock()
+            .unwrap()
+            .remove(&(subscriber_id, tag.to_owned()));
+
+        Ok(())
+    }
+
+    async fn get_status_history(
+        &self,
+        subscriber_id: Uuid,
+    ) -> Result<Option<Vec<StatusHistoryEntry>>, SubscriberRepositoryError> {
+        if self.get(subscriber_id).is_none() {
+            return Ok(None);
+        }
+
+        let history = self
+            .status_history
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(id, _)| *id == subscriber_id)
+            .map(|(_, entry)| entry.clone())
+            .collect();
+
+        Ok(Some(history))
+    }
+}
@@ -0,0 +1,3 @@
+pub mod in_memory_subscriber_repository;
+pub mod postgres_subscriber_repository;
+pub mod subscriber_repository;
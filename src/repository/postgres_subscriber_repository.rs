@@ -0,0 +1,281 @@
+use chrono::Utc;
+use sqlx::{postgres::PgRow, types::Json, PgPool, Row};
+use uuid::Uuid;
+
+use crate::domain::new_subscriber::NewSubscriber;
+use crate::domain::status_history::{StatusHistoryAction, StatusHistoryEntry};
+use crate::domain::subscriber::Subscriber;
+use crate::domain::subscriber_attributes::{AttributesMap, SubscriberAttributes};
+use crate::domain::subscriber_email::SubscriberEmail;
+use crate::domain::subscriber_locale::SubscriberLocale;
+use crate::domain::subscriber_name::SubscriberName;
+use crate::domain::subscriber_status::SubscriberStatus;
+use crate::domain::subscriber_tag::SubscriberTag;
+use crate::domain::subscriber_timezone::SubscriberTimezone;
+use crate::repository::subscriber_repository::{SubscriberRepository, SubscriberRepositoryError};
+use crate::sequences::enrol_in_sequences;
+
+pub struct PostgresSubscriberRepository {
+    db_pool: PgPool,
+}
+
+impl PostgresSubscriberRepository {
+    pub fn new(db_pool: PgPool) -> Self {
+        Self { db_pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl SubscriberRepository for PostgresSubscriberRepository {
+    #[tracing::instrument(
+        name = "Insert a new subscriber into the database",
+        skip(self, new_subscriber)
+    )]
+    async fn create(
+        &self,
+        new_subscriber: &NewSubscriber,
+        locale: &SubscriberLocale,
+        timezone: Option<&SubscriberTimezone>,
+    ) -> Result<Subscriber, SubscriberRepositoryError> {
+        let row = sqlx::query(
+            r#"
+            INSERT INTO subscriptions (id, email, name, subscribed_at, status, attributes, locale, timezone, list)
+            VALUES ($1, $2, $3, $4, 'pending_confirmation', $5, $6, $7, $8)
+            RETURNING id, email, name, subscribed_at, status, attributes, locale, timezone, engagement_score, list
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(new_subscriber.email.as_ref())
+        .bind(new_subscriber.name.as_ref())
+        .bind(Utc::now())
+        .bind(Json(new_subscriber.attributes.as_map()))
+        .bind(locale.as_ref())
+        .bind(timezone.map(|timezone| timezone.as_ref()))
+        .bind(&new_subscriber.list)
+        .fetch_one(&self.db_pool)
+        .await
+        .map_err(|err| match &err {
+            sqlx::Error::Database(db_err) if db_err.code().as_deref() == Some("23505") => {
+                SubscriberRepositoryError::DuplicateEmail
+            }
+            _ => SubscriberRepositoryError::DatabaseError(err),
+        })?;
+
+        parse_subscriber(row)
+    }
+
+    #[tracing::instrument(name = "Get subscriber status", skip(self))]
+    async fn get(&self, subscriber_id: Uuid) -> Result<Option<Subscriber>, SubscriberRepositoryError> {
+        sqlx::query(
+            r#"
+            SELECT id, email, name, subscribed_at, status, attributes, locale, timezone, engagement_score, list
+            FROM subscriptions
+            WHERE id = $1
+            "#,
+        )
+        .bind(subscriber_id)
+        .fetch_optional(&self.db_pool)
+        .await?
+        .map(parse_subscriber)
+        .transpose()
+    }
+
+    #[tracing::instrument(name = "Change subscriber status to confirmed", skip(self))]
+    async fn confirm(
+        &self,
+        subscriber_id: Uuid,
+    ) -> Result<Option<Subscriber>, SubscriberRepositoryError> {
+        let mut transaction = self.db_pool.begin().await?;
+        let Some(row) = sqlx::query(
+            r#"
+            UPDATE subscriptions
+            SET status = 'confirmed'
+            WHERE id = $1 AND status = 'pending_confirmation'
+            RETURNING id, email, name, subscribed_at, status, attributes, locale, timezone, engagement_score, list
+            "#,
+        )
+        .bind(subscriber_id)
+        .fetch_optional(&mut transaction)
+        .await?
+        else {
+            return Ok(None);
+        };
+        let subscriber = parse_subscriber(row)?;
+
+        // A subscriber is never confirmed without the sequences, failing to enrol lets them follow the link again
+        enrol_in_sequences(&mut transaction, subscriber.id, Utc::now()).await?;
+        transaction.commit().await?;
+
+        Ok(Some(subscriber))
+    }
+
+    #[tracing::instrument(
+        name = "Update subscriber attributes in the database",
+        skip(self, attributes)
+    )]
+    async fn update_attributes(
+        &self,
+        subscriber_id: Uuid,
+        attributes: &SubscriberAttributes,
+    ) -> Result<Option<Subscriber>, SubscriberRepositoryError> {
+        sqlx::query(
+            r#"
+            UPDATE subscriptions
+            SET attributes = $2
+            WHERE id = $1
+            RETURNING id, email, name, subscribed_at, status, attributes, locale, timezone, engagement_score, list
+            "#,
+        )
+        .bind(subscriber_id)
+        .bind(Json(attributes.as_map()))
+        .fetch_optional(&self.db_pool)
+        .await?
+        .map(parse_subscriber)
+        .transpose()
+    }
+
+    #[tracing::instrument(name = "Update subscriber timezone in the database", skip(self))]
+    async fn update_timezone(
+        &self,
+        subscriber_id: Uuid,
+        timezone: Option<&SubscriberTimezone>,
+    ) -> Result<Option<Subscriber>, SubscriberRepositoryError> {
+        sqlx::query(
+            r#"
+            UPDATE subscriptions
+            SET timezone = $2
+            WHERE id = $1
+            RETURNING id, email, name, subscribed_at, status, attributes, locale, timezone, engagement_score, list
+            "#,
+        )
+        .bind(subscriber_id)
+        .bind(timezone.map(|timezone| timezone.as_ref()))
+        .fetch_optional(&self.db_pool)
+        .await?
+        .map(parse_subscriber)
+        .transpose()
+    }
+
+    #[tracing::instrument(name = "Insert a subscriber tag into the database", skip(self))]
+    async fn add_tag(
+        &self,
+        subscriber_id: Uuid,
+        tag: &SubscriberTag,
+    ) -> Result<bool, SubscriberRepositoryError> {
+        let subscriber = sqlx::query(
+            r#"
+            INSERT INTO subscriber_tags (subscriber_id, tag, created_at)
+            SELECT id, $2, $3
+            FROM subscriptions
+            WHERE id = $1
+            ON CONFLICT (subscriber_id, tag) DO UPDATE SET tag = EXCLUDED.tag
+            RETURNING subscriber_id
+            "#,
+        )
+        .bind(subscriber_id)
+        .bind(tag.as_ref())
+        .bind(Utc::now())
+        .fetch_optional(&self.db_pool)
+        .await?;
+
+        Ok(subscriber.is_some())
+    }
+
+    #[tracing::instrument(name = "Delete a subscriber tag from the database", skip(self))]
+    async fn remove_tag(
+        &self,
+        subscriber_id: Uuid,
+        tag: &str,
+    ) -> Result<(), SubscriberRepositoryError> {
+        sqlx::query(
+            r#"
+            DELETE FROM subscriber_tags
+            WHERE subscriber_id = $1 AND tag = $2
+            "#,
+        )
+        .bind(subscriber_id)
+        .bind(tag)
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Get the status history of a subscriber", skip(self))]
+    async fn get_status_history(
+        &self,
+        subscriber_id: Uuid,
+    ) -> Result<Option<Vec<StatusHistoryEntry>>, SubscriberRepositoryError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT subscriber_status_history.action, subscriber_status_history.occurred_at
+            FROM subscriptions
+            LEFT JOIN subscriber_status_history ON subscriber_status_history.subscriber_id = subscriptions.id
+            WHERE subscriptions.id = $1
+            ORDER BY subscriber_status_history.occurred_at, subscriber_status_history.id
+            "#,
+        )
+        .bind(subscriber_id)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        if rows.is_empty() {
+            return Ok(None);
+        }
+
+        let mut history = vec![];
+
+        // A subscriber without history has a single row with the columns of the history set to null
+        for row in rows {
+            if let Some(action) = row.try_get::<Option<String>, _>("action")? {
+                history.push(StatusHistoryEntry {
+                    action: StatusHistoryAction::parse(action)
+                        .map_err(corrupt_row(subscriber_id, "status_history"))?,
+                    occurred_at: row.try_get("occurred_at")?,
+                });
+            }
+        }
+
+        Ok(Some(history))
+    }
+}
+
+/// Rows are checked with the same rules as the requests, a value that does not pass them is reported instead of
+/// being trusted
+pub(crate) fn parse_subscriber(row: PgRow) -> Result<Subscriber, SubscriberRepositoryError> {
+    let subscriber_id: Uuid = row.try_get("id")?;
+
+    Ok(Subscriber {
+        id: subscriber_id,
+        email: SubscriberEmail::parse(row.try_get("email")?)
+            .map_err(corrupt_row(subscriber_id, "email"))?,
+        name: SubscriberName::parse(row.try_get("name")?)
+            .map_err(corrupt_row(subscriber_id, "name"))?,
+        subscribed_at: row.try_get("subscribed_at")?,
+        status: SubscriberStatus::parse(row.try_get("status")?)
+            .map_err(corrupt_row(subscriber_id, "status"))?,
+        attributes: SubscriberAttributes::from(
+            row.try_get::<Json<AttributesMap>, _>("attributes")?.0,
+        ),
+        locale: SubscriberLocale::parse(row.try_get("locale")?)
+            .map_err(corrupt_row(subscriber_id, "locale"))?,
+        timezone: row
+            .try_get::<Option<String>, _>("timezone")?
+            .map(SubscriberTimezone::parse)
+            .transpose()
+            .map_err(corrupt_row(subscriber_id, "timezone"))?,
+        engagement_score: row.try_get("engagement_score")?,
+        list: row.try_get("list")?,
+    })
+}
+
+fn corrupt_row(
+    subscriber_id: Uuid,
+    column: &'static str,
+) -> impl FnOnce(String) -> SubscriberRepositoryError {
+    move |message| SubscriberRepositoryError::CorruptRow {
+        subscriber_id,
+        column,
+        message,
+    }
+}
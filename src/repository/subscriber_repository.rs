@@ -0,0 +1,84 @@
+use uuid::Uuid;
+
+use crate::domain::new_subscriber::NewSubscriber;
+use crate::domain::status_history::StatusHistoryEntry;
+use crate::domain::subscriber::Subscriber;
+use crate::domain::subscriber_attributes::SubscriberAttributes;
+use crate::domain::subscriber_locale::SubscriberLocale;
+use crate::domain::subscriber_tag::SubscriberTag;
+use crate::domain::subscriber_timezone::SubscriberTimezone;
+
+/// Storage of the subscribers. Routes depend on this trait instead of the database, so their handlers can be tested
+/// with the in-memory implementation.
+#[async_trait::async_trait]
+pub trait SubscriberRepository: Send + Sync {
+    /// Inserts a subscriber pending confirmation
+    async fn create(
+        &self,
+        new_subscriber: &NewSubscriber,
+        locale: &SubscriberLocale,
+        timezone: Option<&SubscriberTimezone>,
+    ) -> Result<Subscriber, SubscriberRepositoryError>;
+
+    async fn get(&self, subscriber_id: Uuid) -> Result<Option<Subscriber>, SubscriberRepositoryError>;
+
+    /// Confirms the subscriber and enrols them in the sequences, together. Returns `None` when the subscriber does not
+    /// exist or is not pending confirmation.
+    async fn confirm(
+        &self,
+        subscriber_id: Uuid,
+    ) -> Result<Option<Subscriber>, SubscriberRepositoryError>;
+
+    async fn update_attributes(
+        &self,
+        subscriber_id: Uuid,
+        attributes: &SubscriberAttributes,
+    ) -> Result<Option<Subscriber>, SubscriberRepositoryError>;
+
+    /// The timezone is removed when it is `None`
+    async fn update_timezone(
+        &self,
+        subscriber_id: Uuid,
+        timezone: Option<&SubscriberTimezone>,
+    ) -> Result<Option<Subscriber>, SubscriberRepositoryError>;
+
+    /// Returns false when the subscriber does not exist. Adding a tag twice is not an error.
+    async fn add_tag(
+        &self,
+        subscriber_id: Uuid,
+        tag: &SubscriberTag,
+    ) -> Result<bool, SubscriberRepositoryError>;
+
+    async fn remove_tag(
+        &self,
+        subscriber_id: Uuid,
+        tag: &str,
+    ) -> Result<(), SubscriberRepositoryError>;
+
+    /// Actions of the sunset policy on the subscriber, oldest first. Returns `None` when the subscriber does not exist.
+    async fn get_status_history(
+        &self,
+        subscriber_id: Uuid,
+    ) -> Result<Option<Vec<StatusHistoryEntry>>, SubscriberRepositoryError>;
+}
+
+#[derive(thiserror::Error)]
+pub enum SubscriberRepositoryError {
+    #[error("A subscriber with the same email already exists.")]
+    DuplicateEmail,
+    /// A stored value that the domain rejects, e.g. an email edited by hand in the database
+    #[error("The stored {column} of the subscriber {subscriber_id} is not valid: {message}")]
+    CorruptRow {
+        subscriber_id: Uuid,
+        column: &'static str,
+        message: String,
+    },
+    #[error("Failed to access the subscribers in the database.")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+impl std::fmt::Debug for SubscriberRepositoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Caused by:\n\t({})", self)
+    }
+}
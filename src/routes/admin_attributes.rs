@@ -0,0 +1,146 @@
+use actix_web::{web, HttpResponse, ResponseError};
+use chrono::Utc;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use sqlx::{postgres::PgRow, PgPool, Row};
+
+use crate::domain::subscriber_attributes::{AttributeDefinition, AttributeKey, AttributeType};
+
+#[derive(Deserialize, Debug)]
+pub struct NewAttributeDefinitionBody {
+    pub key: String,
+    #[serde(rename = "type")]
+    pub value_type: String,
+}
+
+#[tracing::instrument(
+    name = "Registering a new subscriber attribute",
+    skip(body, db_pool),
+    fields(
+        attribute_key = %body.key,
+        attribute_type = %body.value_type
+    )
+)]
+pub async fn handle_create_attribute_definition(
+    body: web::Json<NewAttributeDefinitionBody>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AttributeDefinitionError> {
+    let key =
+        AttributeKey::parse(body.key.clone()).map_err(AttributeDefinitionError::ValidationError)?;
+    let value_type = AttributeType::parse(body.value_type.clone())
+        .map_err(AttributeDefinitionError::ValidationError)?;
+    let definition = create_attribute_definition(&db_pool, &key, &value_type)
+        .await?
+        .ok_or(AttributeDefinitionError::AlreadyExists)?;
+
+    Ok(HttpResponse::Created().json(definition))
+}
+
+#[tracing::instrument(name = "Listing subscriber attributes", skip(db_pool))]
+pub async fn handle_get_attribute_definitions(
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AttributeDefinitionError> {
+    let definitions = get_attribute_definitions(&db_pool).await?;
+
+    Ok(HttpResponse::Ok().json(definitions))
+}
+
+#[tracing::instrument(
+    name = "Insert a new attribute definition into the database",
+    skip(db_pool)
+)]
+async fn create_attribute_definition(
+    db_pool: &PgPool,
+    key: &AttributeKey,
+    value_type: &AttributeType,
+) -> Result<Option<AttributeDefinition>, AttributeDefinitionError> {
+    sqlx::query(
+        r#"
+        INSERT INTO subscriber_attribute_definitions (key, value_type, created_at)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (key) DO NOTHING
+        RETURNING key, value_type, created_at
+        "#,
+    )
+    .bind(key.as_ref())
+    .bind(value_type.as_ref())
+    .bind(Utc::now())
+    .fetch_optional(db_pool)
+    .await?
+    .map(map_attribute_definition)
+    .transpose()
+}
+
+#[tracing::instrument(name = "Get attribute definitions from database.", skip(db_pool))]
+pub async fn get_attribute_definitions(
+    db_pool: &PgPool,
+) -> Result<Vec<AttributeDefinition>, AttributeDefinitionError> {
+    sqlx::query(
+        r#"
+        SELECT key, value_type, created_at
+        FROM subscriber_attribute_definitions
+        ORDER BY key
+        "#,
+    )
+    .fetch_all(db_pool)
+    .await?
+    .into_iter()
+    .map(map_attribute_definition)
+    .collect()
+}
+
+/// Rows are checked with the same rules as the requests, a value that does not pass them is reported instead of
+/// being trusted
+fn map_attribute_definition(row: PgRow) -> Result<AttributeDefinition, AttributeDefinitionError> {
+    let key: String = row.try_get("key")?;
+
+    Ok(AttributeDefinition {
+        key: AttributeKey::parse(key.clone()).map_err(corrupt_row(&key, "key"))?,
+        value_type: AttributeType::parse(row.try_get("value_type")?)
+            .map_err(corrupt_row(&key, "value_type"))?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+fn corrupt_row(key: &str, column: &'static str) -> impl FnOnce(String) -> AttributeDefinitionError {
+    let key = String::from(key);
+
+    move |message| AttributeDefinitionError::CorruptRow {
+        key,
+        column,
+        message,
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum AttributeDefinitionError {
+    #[error("Validation error: {0}")]
+    ValidationError(String),
+    #[error("The attribute is already registered.")]
+    AlreadyExists,
+    /// A stored value that the domain rejects, e.g. a type edited by hand in the database
+    #[error("The stored {column} of the attribute {key} is not valid: {message}")]
+    CorruptRow {
+        key: String,
+        column: &'static str,
+        message: String,
+    },
+    #[error("Failed to access the attribute definitions in the database.")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+impl std::fmt::Debug for AttributeDefinitionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Caused by:\n\t({})", self)
+    }
+}
+
+impl ResponseError for AttributeDefinitionError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::AlreadyExists => StatusCode::CONFLICT,
+            Self::CorruptRow { .. } | Self::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
@@ -0,0 +1,921 @@
+use actix_web::{http::header::ContentType, web, HttpResponse, ResponseError};
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use sqlx::{postgres::PgRow, types::Json, PgExecutor, PgPool, Row};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::content::template::MergeTemplate;
+use crate::domain::delivery_status::DeliveryStatus;
+use crate::domain::subject_test::{SubjectTestMetric, SubjectVariantResult};
+use crate::domain::subscriber_attributes::AttributesMap;
+use crate::domain::subscriber_email::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::issue_delivery_worker::{render_delivery, IssueDelivery, SubscriptionLinks};
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+
+/// Maximum number of addresses a test email is sent to
+const MAX_TEST_RECIPIENTS: usize = 10;
+
+#[derive(Deserialize, Debug)]
+pub struct PreviewParameters {
+    pub subscriber_id: Uuid,
+}
+
+/// Returns the HTML of the issue rendered for the subscriber, with the same merge tags and links of their email.
+/// Issues are previewed while they are scheduled, before they are sent. The preview is not a delivery: the open
+/// tracking pixel is left out, clicks are not recorded and the unsubscribe link does not unsubscribe.
+#[tracing::instrument(
+    name = "Previewing a newsletter issue",
+    skip(db_pool, base_url, hmac_secret),
+    fields(
+        newsletter_issue_id = %newsletter_issue_id,
+        subscriber_id = %parameters.subscriber_id
+    )
+)]
+pub async fn handle_preview_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    parameters: web::Query<PreviewParameters>,
+    db_pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, IssueError> {
+    let delivery = get_issue_delivery(
+        &db_pool,
+        &newsletter_issue_id,
+        Some(&parameters.subscriber_id),
+    )
+    .await?;
+    let (html_content, _) = render_delivery(
+        &delivery,
+        &base_url.0,
+        &hmac_secret.0,
+        &SubscriptionLinks::preview(&base_url.0),
+    )
+    .map_err(IssueError::RenderError)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_content))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TestSendBody {
+    pub emails: Vec<String>,
+    /// Subscriber whose name and attributes fill the merge tags. They are empty when missing.
+    pub subscriber_id: Option<Uuid>,
+}
+
+/// Sends the rendered issue to internal addresses while it is scheduled, with a `[TEST]` subject prefix. Test
+/// emails are not tracked and their unsubscribe link does not unsubscribe.
+#[tracing::instrument(
+    name = "Sending a test email of a newsletter issue",
+    skip(body, db_pool, email_client, base_url, hmac_secret),
+    fields(
+        newsletter_issue_id = %newsletter_issue_id,
+        emails = ?body.emails,
+        subscriber_id = ?body.subscriber_id
+    )
+)]
+pub async fn handle_test_send_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    body: web::Json<TestSendBody>,
+    db_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, IssueError> {
+    if body.emails.is_empty() || body.emails.len() > MAX_TEST_RECIPIENTS {
+        return Err(IssueError::ValidationError(format!(
+            "Test emails are sent to between 1 and {} addresses.",
+            MAX_TEST_RECIPIENTS
+        )));
+    }
+
+    let recipients = body
+        .emails
+        .iter()
+        .map(|email| SubscriberEmail::parse(email.clone()))
+        .collect::<Result<Vec<SubscriberEmail>, String>>()
+        .map_err(IssueError::ValidationError)?;
+    let mut delivery =
+        get_issue_delivery(&db_pool, &newsletter_issue_id, body.subscriber_id.as_ref()).await?;
+
+    for recipient in recipients {
+        if body.subscriber_id.is_none() {
+            delivery.subscriber_email = String::from(recipient.as_ref());
+        }
+
+        let (html_content, text_content) = render_delivery(
+            &delivery,
+            &base_url.0,
+            &hmac_secret.0,
+            &SubscriptionLinks::preview(&base_url.0),
+        )
+        .map_err(IssueError::RenderError)?;
+
+        email_client
+            .send_email(
+                recipient,
+                &format!("[TEST] {}", delivery.subject),
+                &html_content,
+                &text_content,
+            )
+            .await?;
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Returns the issue addressed to the subscriber, with the subject of their delivery if it was already enqueued. The
+/// id is nil rather than the one of their delivery, so links of previews cannot record events for the subscriber.
+/// Opens and clicks are not tracked, there is no delivery to record them against.
+#[tracing::instrument(name = "Get a newsletter issue for a subscriber", skip(db_pool))]
+async fn get_issue_delivery(
+    db_pool: &PgPool,
+    newsletter_issue_id: &Uuid,
+    subscriber_id: Option<&Uuid>,
+) -> Result<IssueDelivery, IssueError> {
+    let (delivery, is_subscriber_found) = sqlx::query(
+        r#"
+        SELECT
+            newsletter_issues.title,
+            coalesce(
+                subject_tests.subjects[issue_deliveries.subject_variant + 1],
+                subject_tests.subjects[subject_tests.winner + 1],
+                newsletter_issues.title
+            ) AS subject,
+            newsletter_issues.slug,
+            newsletter_issues.html_content,
+            newsletter_issues.text_content,
+            newsletter_issues.is_private,
+            subscriptions.id AS subscriber_id,
+            subscriptions.email,
+            subscriptions.name,
+            subscriptions.status,
+            subscriptions.attributes
+        FROM newsletter_issues
+        LEFT JOIN subscriptions ON subscriptions.id = $2
+        LEFT JOIN issue_deliveries ON issue_deliveries.newsletter_issue_id = newsletter_issues.id
+            AND issue_deliveries.subscriber_id = subscriptions.id
+        LEFT JOIN subject_tests ON subject_tests.newsletter_issue_id = newsletter_issues.id
+        WHERE newsletter_issues.id = $1
+        "#,
+    )
+    .bind(newsletter_issue_id)
+    .bind(subscriber_id)
+    .map(|row: PgRow| {
+        let delivery = IssueDelivery {
+            id: Uuid::nil(),
+            newsletter_issue_id: *newsletter_issue_id,
+            title: row.get("title"),
+            subject: row.get("subject"),
+            slug: row.get("slug"),
+            html_content: MergeTemplate::from(row.get::<String, _>("html_content")),
+            text_content: row
+                .get::<Option<String>, _>("text_content")
+                .map(MergeTemplate::from),
+            track_opens: false,
+            track_clicks: false,
+            is_private: row.get("is_private"),
+            subscriber_id: row
+                .get::<Option<Uuid>, _>("subscriber_id")
+                .unwrap_or_else(Uuid::nil),
+            subscriber_email: row.get::<Option<String>, _>("email").unwrap_or_default(),
+            subscriber_name: row.get::<Option<String>, _>("name").unwrap_or_default(),
+            subscriber_status: row.get::<Option<String>, _>("status").unwrap_or_default(),
+            subscriber_attributes: row
+                .get::<Option<Json<AttributesMap>>, _>("attributes")
+                .map(|attributes| attributes.0)
+                .unwrap_or_default(),
+        };
+
+        (
+            delivery,
+            row.get::<Option<Uuid>, _>("subscriber_id").is_some(),
+        )
+    })
+    .fetch_optional(db_pool)
+    .await
+    .map_err(IssueError::DatabaseError)?
+    .ok_or(IssueError::NotFound)?;
+
+    if subscriber_id.is_some() && !is_subscriber_found {
+        return Err(IssueError::SubscriberNotFound);
+    }
+
+    Ok(delivery)
+}
+
+/// Deliveries of a newsletter issue by status
+#[derive(Debug, serde::Serialize)]
+pub struct IssueDeliveryCounts {
+    pub newsletter_issue_id: Uuid,
+    pub recipients: i64,
+    pub pending: i64,
+    pub paused: i64,
+    pub sent: i64,
+    pub failed: i64,
+    pub skipped: i64,
+    pub cancelled: i64,
+}
+
+/// Change an admin makes to the delivery of an issue that has not finished
+#[derive(Debug, Clone, Copy)]
+enum DeliveryChange {
+    Pause,
+    Resume,
+    Cancel,
+}
+
+impl DeliveryChange {
+    /// Statuses of the deliveries that are changed, and their new status
+    fn deliveries(&self) -> (Vec<DeliveryStatus>, DeliveryStatus) {
+        match self {
+            Self::Pause => (vec![DeliveryStatus::Pending], DeliveryStatus::Paused),
+            Self::Resume => (vec![DeliveryStatus::Paused], DeliveryStatus::Pending),
+            Self::Cancel => (
+                vec![DeliveryStatus::Pending, DeliveryStatus::Paused],
+                DeliveryStatus::Cancelled,
+            ),
+        }
+    }
+
+    /// Statuses of the schedule of an issue whose deliveries are not enqueued yet, and its new status
+    fn schedule(&self) -> (Vec<&'static str>, &'static str) {
+        match self {
+            Self::Pause => (vec!["scheduled"], "paused"),
+            Self::Resume => (vec!["paused"], "scheduled"),
+            Self::Cancel => (vec!["scheduled", "paused"], "cancelled"),
+        }
+    }
+
+    /// Statuses of the subject test of the issue, and its new status. Paused tests do not pick their winner, even once
+    /// every sample is sent and the test only waits for its window to end.
+    fn subject_test(&self) -> (Vec<&'static str>, &'static str) {
+        match self {
+            Self::Pause => (vec!["running"], "paused"),
+            Self::Resume => (vec!["paused"], "running"),
+            Self::Cancel => (vec!["scheduled", "running", "paused"], "cancelled"),
+        }
+    }
+}
+
+/// Holds back the pending deliveries of the issue, e.g. to stop a send with a typo while deciding what to do.
+/// Emails being sent at that moment still go out.
+#[tracing::instrument(
+    name = "Pausing the delivery of a newsletter issue",
+    skip(db_pool),
+    fields(newsletter_issue_id = %newsletter_issue_id)
+)]
+pub async fn handle_pause_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, IssueError> {
+    let counts = change_delivery(&db_pool, &newsletter_issue_id, DeliveryChange::Pause).await?;
+
+    Ok(HttpResponse::Ok().json(counts))
+}
+
+#[tracing::instrument(
+    name = "Resuming the delivery of a newsletter issue",
+    skip(db_pool),
+    fields(newsletter_issue_id = %newsletter_issue_id)
+)]
+pub async fn handle_resume_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, IssueError> {
+    let counts = change_delivery(&db_pool, &newsletter_issue_id, DeliveryChange::Resume).await?;
+
+    Ok(HttpResponse::Ok().json(counts))
+}
+
+/// Cancels the pending and paused deliveries of the issue. Cancelled deliveries cannot be resumed.
+#[tracing::instrument(
+    name = "Cancelling the delivery of a newsletter issue",
+    skip(db_pool),
+    fields(newsletter_issue_id = %newsletter_issue_id)
+)]
+pub async fn handle_cancel_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, IssueError> {
+    let counts = change_delivery(&db_pool, &newsletter_issue_id, DeliveryChange::Cancel).await?;
+
+    Ok(HttpResponse::Ok().json(counts))
+}
+
+#[tracing::instrument(name = "Change the delivery of a newsletter issue", skip(db_pool))]
+async fn change_delivery(
+    db_pool: &PgPool,
+    newsletter_issue_id: &Uuid,
+    change: DeliveryChange,
+) -> Result<IssueDeliveryCounts, IssueError> {
+    let mut transaction = db_pool.begin().await.map_err(IssueError::DatabaseError)?;
+    let issue_exists = sqlx::query("SELECT id FROM newsletter_issues WHERE id = $1 FOR UPDATE")
+        .bind(newsletter_issue_id)
+        .fetch_optional(&mut transaction)
+        .await
+        .map_err(IssueError::DatabaseError)?
+        .is_some();
+
+    if !issue_exists {
+        return Err(IssueError::NotFound);
+    }
+
+    let (statuses, new_status) = change.deliveries();
+    // The worker locks the delivery it is sending, so this waits for it and leaves it alone once it is sent
+    let changed_deliveries = sqlx::query(
+        r#"
+        UPDATE issue_deliveries
+        SET status = $3
+        WHERE newsletter_issue_id = $1 AND status = ANY($2)
+        "#,
+    )
+    .bind(newsletter_issue_id)
+    .bind(
+        statuses
+            .iter()
+            .map(|status| status.as_ref())
+            .collect::<Vec<&str>>(),
+    )
+    .bind(new_status.as_ref())
+    .execute(&mut transaction)
+    .await
+    .map_err(IssueError::DatabaseError)?
+    .rows_affected();
+    let (statuses, new_status) = change.schedule();
+    let changed_schedules = sqlx::query(
+        r#"
+        UPDATE scheduled_issues
+        SET status = $3
+        WHERE newsletter_issue_id = $1 AND status = ANY($2)
+        "#,
+    )
+    .bind(newsletter_issue_id)
+    .bind(statuses)
+    .bind(new_status)
+    .execute(&mut transaction)
+    .await
+    .map_err(IssueError::DatabaseError)?
+    .rows_affected();
+    // The winner of a cancelled subject test is not sent to the rest of the audience
+    let (statuses, new_status) = change.subject_test();
+    let changed_subject_tests = sqlx::query(
+        r#"
+        UPDATE subject_tests
+        SET status = $3
+        WHERE newsletter_issue_id = $1 AND status = ANY($2)
+        "#,
+    )
+    .bind(newsletter_issue_id)
+    .bind(statuses)
+    .bind(new_status)
+    .execute(&mut transaction)
+    .await
+    .map_err(IssueError::DatabaseError)?
+    .rows_affected();
+
+    if changed_deliveries == 0 && changed_schedules == 0 && changed_subject_tests == 0 {
+        return Err(IssueError::Conflict(match change {
+            DeliveryChange::Pause => {
+                "The newsletter issue has no pending deliveries nor running subject test."
+            }
+            DeliveryChange::Resume => {
+                "The newsletter issue has no paused deliveries nor paused subject test."
+            }
+            DeliveryChange::Cancel => "The newsletter issue has no pending nor paused deliveries.",
+        }));
+    }
+
+    let counts = sqlx::query(
+        r#"
+        SELECT
+            count(*) AS recipients,
+            count(*) FILTER (WHERE status = 'pending') AS pending,
+            count(*) FILTER (WHERE status = 'paused') AS paused,
+            count(*) FILTER (WHERE status = 'sent') AS sent,
+            count(*) FILTER (WHERE status = 'failed') AS failed,
+            count(*) FILTER (WHERE status = 'skipped') AS skipped,
+            count(*) FILTER (WHERE status = 'cancelled') AS cancelled
+        FROM issue_deliveries
+        WHERE newsletter_issue_id = $1
+        "#,
+    )
+    .bind(newsletter_issue_id)
+    .map(|row: PgRow| IssueDeliveryCounts {
+        newsletter_issue_id: *newsletter_issue_id,
+        recipients: row.get("recipients"),
+        pending: row.get("pending"),
+        paused: row.get("paused"),
+        sent: row.get("sent"),
+        failed: row.get("failed"),
+        skipped: row.get("skipped"),
+        cancelled: row.get("cancelled"),
+    })
+    .fetch_one(&mut transaction)
+    .await
+    .map_err(IssueError::DatabaseError)?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(IssueError::DatabaseError)?;
+
+    Ok(counts)
+}
+
+/// Subject test of an issue. The results are those stored when the winner was picked, or the current ones while the
+/// test runs.
+#[derive(Debug, serde::Serialize)]
+pub struct SubjectTestReport {
+    pub newsletter_issue_id: Uuid,
+    /// `scheduled`, `running`, `paused`, `completed`, `failed` or `cancelled`
+    pub status: String,
+    pub metric: SubjectTestMetric,
+    pub sample_percent: i16,
+    pub window_hours: i32,
+    pub ends_at: Option<DateTime<Utc>>,
+    pub winner: Option<String>,
+    pub results: Vec<SubjectVariantResult>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(
+    name = "Getting the subject test of a newsletter issue",
+    skip(db_pool),
+    fields(newsletter_issue_id = %newsletter_issue_id)
+)]
+pub async fn handle_get_subject_test(
+    newsletter_issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, IssueError> {
+    let (mut report, subjects, results) = sqlx::query(
+        r#"
+        SELECT
+            subjects,
+            sample_percent,
+            window_hours,
+            metric,
+            status,
+            ends_at,
+            winner,
+            results,
+            completed_at
+        FROM subject_tests
+        WHERE newsletter_issue_id = $1
+        "#,
+    )
+    .bind(*newsletter_issue_id)
+    .fetch_optional(db_pool.get_ref())
+    .await
+    .map_err(IssueError::DatabaseError)?
+    .map(|row| map_subject_test_report(*newsletter_issue_id, row))
+    .transpose()?
+    .ok_or(IssueError::NotFound)?;
+
+    report.results = match results {
+        Some(results) => results,
+        None => get_subject_test_results(db_pool.get_ref(), &newsletter_issue_id, &subjects)
+            .await
+            .map_err(IssueError::DatabaseError)?,
+    };
+
+    Ok(HttpResponse::Ok().json(report))
+}
+
+/// Report of a subject test with its subjects and the results stored once the test completed
+type StoredSubjectTest = (
+    SubjectTestReport,
+    Vec<String>,
+    Option<Vec<SubjectVariantResult>>,
+);
+
+/// Rows are checked with the same rules as the requests, a value that does not pass them is reported instead of
+/// being trusted
+fn map_subject_test_report(
+    newsletter_issue_id: Uuid,
+    row: PgRow,
+) -> Result<StoredSubjectTest, IssueError> {
+    let subjects: Vec<String> = row.try_get("subjects").map_err(IssueError::DatabaseError)?;
+    let metric =
+        SubjectTestMetric::parse(row.try_get("metric").map_err(IssueError::DatabaseError)?)
+            .map_err(|message| IssueError::CorruptRow {
+                newsletter_issue_id,
+                column: "metric",
+                message,
+            })?;
+    let report = SubjectTestReport {
+        newsletter_issue_id,
+        status: row.try_get("status").map_err(IssueError::DatabaseError)?,
+        metric,
+        sample_percent: row
+            .try_get("sample_percent")
+            .map_err(IssueError::DatabaseError)?,
+        window_hours: row
+            .try_get("window_hours")
+            .map_err(IssueError::DatabaseError)?,
+        ends_at: row.try_get("ends_at").map_err(IssueError::DatabaseError)?,
+        winner: row
+            .try_get::<Option<i16>, _>("winner")
+            .map_err(IssueError::DatabaseError)?
+            .and_then(|winner| subjects.get(winner as usize).cloned()),
+        results: vec![],
+        completed_at: row
+            .try_get("completed_at")
+            .map_err(IssueError::DatabaseError)?,
+    };
+    let results = row
+        .try_get::<Option<Json<Vec<SubjectVariantResult>>>, _>("results")
+        .map_err(IssueError::DatabaseError)?
+        .map(|results| results.0);
+
+    Ok((report, subjects, results))
+}
+
+/// Returns the sent emails, unique opens and unique clicks of the sample of each subject
+#[tracing::instrument(name = "Get the results of a subject test", skip(executor, subjects))]
+pub async fn get_subject_test_results(
+    executor: impl PgExecutor<'_>,
+    newsletter_issue_id: &Uuid,
+    subjects: &[String],
+) -> Result<Vec<SubjectVariantResult>, sqlx::Error> {
+    let counts = sqlx::query(
+        r#"
+        SELECT
+            subject_variant,
+            count(*) FILTER (WHERE status = 'sent') AS sent,
+            count(*) FILTER (
+                WHERE EXISTS (SELECT 1 FROM issue_opens WHERE issue_delivery_id = issue_deliveries.id)
+            ) AS opened,
+            count(*) FILTER (
+                WHERE EXISTS (SELECT 1 FROM issue_clicks WHERE issue_delivery_id = issue_deliveries.id)
+            ) AS clicked
+        FROM issue_deliveries
+        WHERE newsletter_issue_id = $1 AND subject_variant IS NOT NULL
+        GROUP BY subject_variant
+        "#,
+    )
+    .bind(newsletter_issue_id)
+    .map(|row: PgRow| {
+        (
+            row.get::<i16, _>("subject_variant") as usize,
+            (row.get("sent"), row.get("opened"), row.get("clicked")),
+        )
+    })
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .collect::<HashMap<usize, (i64, i64, i64)>>();
+
+    Ok(subjects
+        .iter()
+        .enumerate()
+        .map(|(position, subject)| {
+            let (sent, opened, clicked) = counts.get(&position).copied().unwrap_or_default();
+
+            SubjectVariantResult {
+                subject: subject.clone(),
+                sent,
+                opened,
+                clicked,
+                open_rate: rate(opened, sent),
+                click_rate: rate(clicked, sent),
+            }
+        })
+        .collect())
+}
+
+/// Opens of a newsletter issue. Unique opens count each delivery once, however many times it was opened.
+#[derive(Debug, serde::Serialize)]
+pub struct IssueOpenSummary {
+    pub newsletter_issue_id: Uuid,
+    pub track_opens: bool,
+    pub sent: i64,
+    pub opens: i64,
+    pub unique_opens: i64,
+    /// Unique opens per sent email
+    pub unique_open_rate: f64,
+}
+
+#[tracing::instrument(
+    name = "Summarizing the opens of a newsletter issue",
+    skip(db_pool),
+    fields(newsletter_issue_id = %newsletter_issue_id)
+)]
+pub async fn handle_get_issue_opens(
+    newsletter_issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, IssueError> {
+    let summary = sqlx::query(
+        r#"
+        SELECT
+            newsletter_issues.track_opens,
+            count(DISTINCT issue_deliveries.id) FILTER (WHERE issue_deliveries.status = 'sent') AS sent,
+            count(issue_opens.id) AS opens,
+            count(DISTINCT issue_opens.issue_delivery_id) AS unique_opens
+        FROM newsletter_issues
+        LEFT JOIN issue_deliveries ON issue_deliveries.newsletter_issue_id = newsletter_issues.id
+        LEFT JOIN issue_opens ON issue_opens.issue_delivery_id = issue_deliveries.id
+        WHERE newsletter_issues.id = $1
+        GROUP BY newsletter_issues.id
+        "#,
+    )
+    .bind(*newsletter_issue_id)
+    .map(|row: PgRow| {
+        let sent: i64 = row.get("sent");
+        let unique_opens: i64 = row.get("unique_opens");
+
+        IssueOpenSummary {
+            newsletter_issue_id: *newsletter_issue_id,
+            track_opens: row.get("track_opens"),
+            sent,
+            opens: row.get("opens"),
+            unique_opens,
+            unique_open_rate: rate(unique_opens, sent),
+        }
+    })
+    .fetch_optional(db_pool.get_ref())
+    .await
+    .map_err(IssueError::DatabaseError)?
+    .ok_or(IssueError::NotFound)?;
+
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+/// Counts of a newsletter issue. Each count is the number of deliveries with the event, however many times it
+/// happened, except for `opens` and `clicks`, which count every open and click.
+#[derive(Debug, serde::Serialize)]
+pub struct IssueStats {
+    pub newsletter_issue_id: Uuid,
+    pub recipients: i64,
+    pub pending: i64,
+    pub paused: i64,
+    pub cancelled: i64,
+    pub sent: i64,
+    pub delivered: i64,
+    pub bounced: i64,
+    pub opened: i64,
+    pub opens: i64,
+    pub clicked: i64,
+    pub clicks: i64,
+    pub unsubscribed: i64,
+    pub complained: i64,
+    pub rates: IssueRates,
+    pub top_links: Vec<LinkStats>,
+    pub timeline: Vec<TimelinePeriod>,
+}
+
+/// Unique rates per sent email, except `click_to_open`, which is the share of openers who clicked
+#[derive(Debug, serde::Serialize)]
+pub struct IssueRates {
+    pub delivered: f64,
+    pub bounced: f64,
+    pub opened: f64,
+    pub clicked: f64,
+    pub click_to_open: f64,
+    pub unsubscribed: f64,
+    pub complained: f64,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct LinkStats {
+    pub url: String,
+    pub clicks: i64,
+    pub unique_clicks: i64,
+}
+
+/// Opens and clicks of an hour
+#[derive(Debug, serde::Serialize)]
+pub struct TimelinePeriod {
+    pub period: chrono::DateTime<chrono::Utc>,
+    pub opens: i64,
+    pub clicks: i64,
+}
+
+const TOP_LINKS_LIMIT: i64 = 10;
+
+/// Aggregates the delivery queue, the tracked opens and clicks, and the events reported by the email provider
+#[tracing::instrument(
+    name = "Getting the stats of a newsletter issue",
+    skip(db_pool),
+    fields(newsletter_issue_id = %newsletter_issue_id)
+)]
+pub async fn handle_get_issue_stats(
+    newsletter_issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, IssueError> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let issue_exists = sqlx::query("SELECT id FROM newsletter_issues WHERE id = $1")
+        .bind(newsletter_issue_id)
+        .fetch_optional(db_pool.get_ref())
+        .await
+        .map_err(IssueError::DatabaseError)?
+        .is_some();
+
+    if !issue_exists {
+        return Err(IssueError::NotFound);
+    }
+
+    let top_links = get_top_links(&db_pool, &newsletter_issue_id)
+        .await
+        .map_err(IssueError::DatabaseError)?;
+    let timeline = get_timeline(&db_pool, &newsletter_issue_id)
+        .await
+        .map_err(IssueError::DatabaseError)?;
+    let stats = sqlx::query(
+        r#"
+        SELECT
+            count(*) AS recipients,
+            count(*) FILTER (WHERE issue_deliveries.status = 'pending') AS pending,
+            count(*) FILTER (WHERE issue_deliveries.status = 'paused') AS paused,
+            count(*) FILTER (WHERE issue_deliveries.status = 'cancelled') AS cancelled,
+            count(*) FILTER (WHERE issue_deliveries.status = 'sent') AS sent,
+            count(*) FILTER (WHERE events.delivered) AS delivered,
+            count(*) FILTER (WHERE events.bounced) AS bounced,
+            count(*) FILTER (WHERE opens.count > 0) AS opened,
+            coalesce(sum(opens.count), 0)::bigint AS opens,
+            count(*) FILTER (WHERE clicks.count > 0) AS clicked,
+            coalesce(sum(clicks.count), 0)::bigint AS clicks,
+            count(*) FILTER (WHERE events.unsubscribed) AS unsubscribed,
+            count(*) FILTER (WHERE events.complained) AS complained
+        FROM issue_deliveries
+        LEFT JOIN LATERAL (
+            SELECT
+                bool_or(event = 'delivered') AS delivered,
+                bool_or(event = 'bounced') AS bounced,
+                bool_or(event = 'unsubscribed') AS unsubscribed,
+                bool_or(event = 'complained') AS complained
+            FROM issue_delivery_events
+            WHERE issue_delivery_id = issue_deliveries.id
+        ) events ON true
+        LEFT JOIN LATERAL (
+            SELECT count(*) FROM issue_opens WHERE issue_delivery_id = issue_deliveries.id
+        ) opens ON true
+        LEFT JOIN LATERAL (
+            SELECT count(*) FROM issue_clicks WHERE issue_delivery_id = issue_deliveries.id
+        ) clicks ON true
+        WHERE issue_deliveries.newsletter_issue_id = $1
+        "#,
+    )
+    .bind(newsletter_issue_id)
+    .fetch_one(db_pool.get_ref())
+    .await
+    .map_err(IssueError::DatabaseError)?;
+    let sent: i64 = stats.get("sent");
+    let opened: i64 = stats.get("opened");
+    let per_sent = |count: i64| rate(count, sent);
+
+    Ok(HttpResponse::Ok().json(IssueStats {
+        newsletter_issue_id,
+        recipients: stats.get("recipients"),
+        pending: stats.get("pending"),
+        paused: stats.get("paused"),
+        cancelled: stats.get("cancelled"),
+        sent,
+        delivered: stats.get("delivered"),
+        bounced: stats.get("bounced"),
+        opened,
+        opens: stats.get("opens"),
+        clicked: stats.get("clicked"),
+        clicks: stats.get("clicks"),
+        unsubscribed: stats.get("unsubscribed"),
+        complained: stats.get("complained"),
+        rates: IssueRates {
+            delivered: per_sent(stats.get("delivered")),
+            bounced: per_sent(stats.get("bounced")),
+            opened: per_sent(opened),
+            clicked: per_sent(stats.get("clicked")),
+            click_to_open: rate(stats.get("clicked"), opened),
+            unsubscribed: per_sent(stats.get("unsubscribed")),
+            complained: per_sent(stats.get("complained")),
+        },
+        top_links,
+        timeline,
+    }))
+}
+
+#[tracing::instrument(
+    name = "Get the most clicked links of a newsletter issue",
+    skip(db_pool)
+)]
+async fn get_top_links(
+    db_pool: &PgPool,
+    newsletter_issue_id: &Uuid,
+) -> Result<Vec<LinkStats>, sqlx::Error> {
+    sqlx::query(
+        r#"
+        SELECT
+            issue_clicks.url,
+            count(*) AS clicks,
+            count(DISTINCT issue_clicks.issue_delivery_id) AS unique_clicks
+        FROM issue_clicks
+        JOIN issue_deliveries ON issue_deliveries.id = issue_clicks.issue_delivery_id
+        WHERE issue_deliveries.newsletter_issue_id = $1
+        GROUP BY issue_clicks.url
+        ORDER BY clicks DESC, issue_clicks.url
+        LIMIT $2
+        "#,
+    )
+    .bind(newsletter_issue_id)
+    .bind(TOP_LINKS_LIMIT)
+    .map(|row: PgRow| LinkStats {
+        url: row.get("url"),
+        clicks: row.get("clicks"),
+        unique_clicks: row.get("unique_clicks"),
+    })
+    .fetch_all(db_pool)
+    .await
+}
+
+#[tracing::instrument(
+    name = "Get the hourly opens and clicks of a newsletter issue",
+    skip(db_pool)
+)]
+async fn get_timeline(
+    db_pool: &PgPool,
+    newsletter_issue_id: &Uuid,
+) -> Result<Vec<TimelinePeriod>, sqlx::Error> {
+    sqlx::query(
+        r#"
+        SELECT
+            date_trunc('hour', tracked_events.occurred_at) AS period,
+            count(*) FILTER (WHERE tracked_events.kind = 'open') AS opens,
+            count(*) FILTER (WHERE tracked_events.kind = 'click') AS clicks
+        FROM (
+            SELECT issue_delivery_id, opened_at AS occurred_at, 'open' AS kind FROM issue_opens
+            UNION ALL
+            SELECT issue_delivery_id, clicked_at AS occurred_at, 'click' AS kind FROM issue_clicks
+        ) tracked_events
+        JOIN issue_deliveries ON issue_deliveries.id = tracked_events.issue_delivery_id
+        WHERE issue_deliveries.newsletter_issue_id = $1
+        GROUP BY period
+        ORDER BY period
+        "#,
+    )
+    .bind(newsletter_issue_id)
+    .map(|row: PgRow| TimelinePeriod {
+        period: row.get("period"),
+        opens: row.get("opens"),
+        clicks: row.get("clicks"),
+    })
+    .fetch_all(db_pool)
+    .await
+}
+
+fn rate(count: i64, total: i64) -> f64 {
+    if total > 0 {
+        count as f64 / total as f64
+    } else {
+        0.0
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum IssueError {
+    #[error("Validation error: {0}")]
+    ValidationError(String),
+    #[error("The newsletter issue does not exist.")]
+    NotFound,
+    #[error("The subscriber does not exist.")]
+    SubscriberNotFound,
+    #[error("{0}")]
+    Conflict(&'static str),
+    #[error("Failed to render the newsletter issue: {0}")]
+    RenderError(String),
+    #[error("Failed to send a test email of the newsletter issue.")]
+    SendEmailError(#[from] reqwest::Error),
+    /// A stored value that the domain rejects, e.g. a metric edited by hand in the database
+    #[error(
+        "The stored {column} of the newsletter issue {newsletter_issue_id} is not valid: {message}"
+    )]
+    CorruptRow {
+        newsletter_issue_id: Uuid,
+        column: &'static str,
+        message: String,
+    },
+    #[error("Failed to get the newsletter issue from the database.")]
+    DatabaseError(#[source] sqlx::Error),
+}
+
+impl std::fmt::Debug for IssueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Caused by:\n\t({})", self)
+    }
+}
+
+impl ResponseError for IssueError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::NotFound | Self::SubscriberNotFound => StatusCode::NOT_FOUND,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::RenderError(_)
+            | Self::SendEmailError(_)
+            | Self::CorruptRow { .. }
+            | Self::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use actix_web::{web, HttpResponse, ResponseError};
+use chrono::Utc;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use sqlx::{postgres::PgRow, PgPool, Row};
+use uuid::Uuid;
+
+use crate::content::layout::LayoutTemplate;
+use crate::domain::layout::{Layout, LayoutName};
+
+#[derive(Deserialize, Debug)]
+pub struct LayoutBody {
+    pub name: String,
+    pub html: String,
+}
+
+#[tracing::instrument(
+    name = "Creating a new layout",
+    skip(body, db_pool),
+    fields(
+        layout_name = %body.name
+    )
+)]
+pub async fn handle_create_layout(
+    body: web::Json<LayoutBody>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, LayoutError> {
+    let (name, html) = parse_layout_body(body.into_inner())?;
+    let layout = sqlx::query(
+        r#"
+        INSERT INTO layouts (id, name, html, created_at, updated_at)
+        VALUES ($1, $2, $3, $4, $4)
+        ON CONFLICT (name) DO NOTHING
+        RETURNING id, name, html, created_at, updated_at
+        "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(name.as_ref())
+    .bind(html.as_ref())
+    .bind(Utc::now())
+    .fetch_optional(db_pool.get_ref())
+    .await?
+    .map(map_layout)
+    .transpose()?
+    .ok_or(LayoutError::AlreadyExists)?;
+
+    Ok(HttpResponse::Created().json(layout))
+}
+
+#[tracing::instrument(name = "Listing layouts", skip(db_pool))]
+pub async fn handle_get_layouts(db_pool: web::Data<PgPool>) -> Result<HttpResponse, LayoutError> {
+    let layouts = sqlx::query(
+        r#"
+        SELECT id, name, html, created_at, updated_at
+        FROM layouts
+        ORDER BY name
+        "#,
+    )
+    .fetch_all(db_pool.get_ref())
+    .await?
+    .into_iter()
+    .map(map_layout)
+    .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(HttpResponse::Ok().json(layouts))
+}
+
+/// Issues that were already published keep the layout they were rendered with
+#[tracing::instrument(
+    name = "Updating a layout",
+    skip(body, db_pool),
+    fields(
+        layout_id = %layout_id,
+        layout_name = %body.name
+    )
+)]
+pub async fn handle_update_layout(
+    layout_id: web::Path<Uuid>,
+    body: web::Json<LayoutBody>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, LayoutError> {
+    let (name, html) = parse_layout_body(body.into_inner())?;
+    let layout = sqlx::query(
+        r#"
+        UPDATE layouts
+        SET name = $2, html = $3, updated_at = $4
+        WHERE id = $1
+        RETURNING id, name, html, created_at, updated_at
+        "#,
+    )
+    .bind(*layout_id)
+    .bind(name.as_ref())
+    .bind(html.as_ref())
+    .bind(Utc::now())
+    .fetch_optional(db_pool.get_ref())
+    .await
+    .map_err(|err| match &err {
+        sqlx::Error::Database(db_err) if db_err.code().as_deref() == Some("23505") => {
+            LayoutError::AlreadyExists
+        }
+        _ => LayoutError::DatabaseError(err),
+    })?
+    .map(map_layout)
+    .transpose()?
+    .ok_or_else(|| LayoutError::NotFound(layout_id.to_string()))?;
+
+    Ok(HttpResponse::Ok().json(layout))
+}
+
+#[tracing::instrument(name = "Deleting a layout", skip(db_pool))]
+pub async fn handle_delete_layout(
+    layout_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, LayoutError> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM layouts
+        WHERE id = $1
+        "#,
+    )
+    .bind(*layout_id)
+    .execute(db_pool.get_ref())
+    .await
+    .map_err(LayoutError::DatabaseError)?;
+
+    if result.rows_affected() == 0 {
+        return Err(LayoutError::NotFound(layout_id.to_string()));
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(name = "Get a layout by name from database.", skip(db_pool))]
+pub async fn get_layout_by_name(db_pool: &PgPool, name: &str) -> Result<Layout, LayoutError> {
+    sqlx::query(
+        r#"
+        SELECT id, name, html, created_at, updated_at
+        FROM layouts
+        WHERE name = $1
+        "#,
+    )
+    .bind(name)
+    .fetch_optional(db_pool)
+    .await?
+    .map(map_layout)
+    .transpose()?
+    .ok_or_else(|| LayoutError::NotFound(String::from(name)))
+}
+
+fn parse_layout_body(body: LayoutBody) -> Result<(LayoutName, LayoutTemplate), LayoutError> {
+    let name = LayoutName::parse(body.name).map_err(LayoutError::ValidationError)?;
+    let html = LayoutTemplate::parse(body.html).map_err(LayoutError::ValidationError)?;
+
+    Ok((name, html))
+}
+
+/// Rows are checked with the same rules as the requests, a value that does not pass them is reported instead of
+/// being trusted
+fn map_layout(row: PgRow) -> Result<Layout, LayoutError> {
+    let layout_id: Uuid = row.try_get("id")?;
+
+    Ok(Layout {
+        id: layout_id,
+        name: LayoutName::parse(row.try_get("name")?).map_err(|message| {
+            LayoutError::CorruptRow {
+                layout_id,
+                column: "name",
+                message,
+            }
+        })?,
+        html: LayoutTemplate::from(row.try_get::<String, _>("html")?),
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+#[derive(thiserror::Error)]
+pub enum LayoutError {
+    #[error("Validation error: {0}")]
+    ValidationError(String),
+    #[error("A layout with the same name already exists.")]
+    AlreadyExists,
+    #[error("Layout {0} does not exist.")]
+    NotFound(String),
+    /// A stored value that the domain rejects, e.g. a name edited by hand in the database
+    #[error("The stored {column} of the layout {layout_id} is not valid: {message}")]
+    CorruptRow {
+        layout_id: Uuid,
+        column: &'static str,
+        message: String,
+    },
+    #[error("Failed to access the layouts in the database.")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+impl std::fmt::Debug for LayoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Caused by:\n\t({})", self)
+    }
+}
+
+impl ResponseError for LayoutError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::AlreadyExists => StatusCode::CONFLICT,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::CorruptRow { .. } | Self::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
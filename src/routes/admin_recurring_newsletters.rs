@@ -0,0 +1,165 @@
+use actix_web::{web, HttpResponse, ResponseError};
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use sqlx::{postgres::PgRow, types::Json, PgPool, Row};
+use uuid::Uuid;
+
+use crate::domain::cron_schedule::CronSchedule;
+use crate::routes::{prepare_newsletter, NewNewsletter, PublishNewsletterError};
+
+#[derive(Deserialize, Debug)]
+pub struct RecurringNewsletterBody {
+    /// Five-field cron expression, e.g. `0 8 * * MON` for every Monday at 08:00
+    pub cron: String,
+    /// Timezone the cron expression is evaluated in, e.g. `Europe/Madrid`
+    pub timezone: String,
+    /// Newsletter published every time the schedule fires
+    pub newsletter: NewNewsletter,
+}
+
+#[derive(serde::Serialize)]
+pub struct RecurringNewsletter {
+    pub id: Uuid,
+    pub cron: String,
+    pub timezone: String,
+    pub newsletter: NewNewsletter,
+    pub next_run_at: DateTime<Utc>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The newsletter is validated like a new issue, so the scheduled runs do not fail because of its content
+#[tracing::instrument(
+    name = "Creating a recurring newsletter",
+    skip(body, db_pool),
+    fields(
+        cron = %body.cron,
+        timezone = %body.timezone,
+        title = %body.newsletter.title
+    )
+)]
+pub async fn handle_create_recurring_newsletter(
+    body: web::Json<RecurringNewsletterBody>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, RecurringNewsletterError> {
+    let body = body.into_inner();
+    let schedule = CronSchedule::parse(body.cron, &body.timezone)
+        .map_err(RecurringNewsletterError::ValidationError)?;
+    let now = Utc::now();
+    let next_run_at = schedule.next_run_after(&now).ok_or_else(|| {
+        RecurringNewsletterError::ValidationError(format!("{} never fires.", schedule.as_ref()))
+    })?;
+
+    if body.newsletter.send_at.is_some() || body.newsletter.local_send_at.is_some() {
+        return Err(RecurringNewsletterError::ValidationError(String::from(
+            "Recurring newsletters are sent on their schedule, remove the send time.",
+        )));
+    }
+
+    prepare_newsletter(&db_pool, &body.newsletter).await?;
+
+    let recurring_newsletter = sqlx::query(
+        r#"
+        INSERT INTO recurring_newsletters (id, cron, timezone, newsletter, next_run_at, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, cron, timezone, newsletter, next_run_at, last_run_at, created_at
+        "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(schedule.as_ref())
+    .bind(schedule.timezone())
+    .bind(Json(&body.newsletter))
+    .bind(next_run_at)
+    .bind(now)
+    .map(map_recurring_newsletter)
+    .fetch_one(db_pool.get_ref())
+    .await
+    .map_err(RecurringNewsletterError::DatabaseError)?;
+
+    Ok(HttpResponse::Created().json(recurring_newsletter))
+}
+
+#[tracing::instrument(name = "Listing recurring newsletters", skip(db_pool))]
+pub async fn handle_get_recurring_newsletters(
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, RecurringNewsletterError> {
+    let recurring_newsletters = sqlx::query(
+        r#"
+        SELECT id, cron, timezone, newsletter, next_run_at, last_run_at, created_at
+        FROM recurring_newsletters
+        ORDER BY next_run_at
+        "#,
+    )
+    .map(map_recurring_newsletter)
+    .fetch_all(db_pool.get_ref())
+    .await
+    .map_err(RecurringNewsletterError::DatabaseError)?;
+
+    Ok(HttpResponse::Ok().json(recurring_newsletters))
+}
+
+/// Issues already published by the recurring newsletter are kept
+#[tracing::instrument(name = "Deleting a recurring newsletter", skip(db_pool))]
+pub async fn handle_delete_recurring_newsletter(
+    recurring_newsletter_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, RecurringNewsletterError> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM recurring_newsletters
+        WHERE id = $1
+        "#,
+    )
+    .bind(*recurring_newsletter_id)
+    .execute(db_pool.get_ref())
+    .await
+    .map_err(RecurringNewsletterError::DatabaseError)?;
+
+    if result.rows_affected() == 0 {
+        return Err(RecurringNewsletterError::NotFound);
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+fn map_recurring_newsletter(row: PgRow) -> RecurringNewsletter {
+    RecurringNewsletter {
+        id: row.get("id"),
+        cron: row.get("cron"),
+        timezone: row.get("timezone"),
+        newsletter: row.get::<Json<NewNewsletter>, _>("newsletter").0,
+        next_run_at: row.get("next_run_at"),
+        last_run_at: row.get("last_run_at"),
+        created_at: row.get("created_at"),
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum RecurringNewsletterError {
+    #[error("Validation error: {0}")]
+    ValidationError(String),
+    #[error("The recurring newsletter does not exist.")]
+    NotFound,
+    #[error("Failed to access the recurring newsletters in the database.")]
+    DatabaseError(#[source] sqlx::Error),
+    #[error(transparent)]
+    PublishNewsletterError(#[from] PublishNewsletterError),
+}
+
+impl std::fmt::Debug for RecurringNewsletterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Caused by:\n\t({})", self)
+    }
+}
+
+impl ResponseError for RecurringNewsletterError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::PublishNewsletterError(err) => err.status_code(),
+        }
+    }
+}
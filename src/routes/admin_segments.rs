@@ -0,0 +1,167 @@
+use actix_web::{web, HttpResponse, ResponseError};
+use chrono::Utc;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use sqlx::{postgres::PgRow, PgPool, Row};
+use uuid::Uuid;
+
+use crate::domain::segment::{Segment, SegmentName};
+use crate::domain::segment_filter::SegmentFilter;
+
+#[derive(Deserialize, Debug)]
+pub struct NewSegmentBody {
+    pub name: String,
+    pub filter: String,
+}
+
+#[tracing::instrument(
+    name = "Creating a new segment",
+    skip(body, db_pool),
+    fields(
+        segment_name = %body.name,
+        segment_filter = %body.filter
+    )
+)]
+pub async fn handle_create_segment(
+    body: web::Json<NewSegmentBody>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SegmentError> {
+    let name = SegmentName::parse(body.name.clone()).map_err(SegmentError::ValidationError)?;
+    let filter =
+        SegmentFilter::parse(body.filter.clone()).map_err(SegmentError::ValidationError)?;
+    let segment = create_segment(&db_pool, &name, &filter)
+        .await?
+        .ok_or(SegmentError::AlreadyExists)?;
+
+    Ok(HttpResponse::Created().json(segment))
+}
+
+#[tracing::instrument(name = "Listing segments", skip(db_pool))]
+pub async fn handle_get_segments(db_pool: web::Data<PgPool>) -> Result<HttpResponse, SegmentError> {
+    let segments = sqlx::query(
+        r#"
+        SELECT id, name, filter, created_at
+        FROM segments
+        ORDER BY name
+        "#,
+    )
+    .fetch_all(db_pool.get_ref())
+    .await?
+    .into_iter()
+    .map(map_segment)
+    .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(HttpResponse::Ok().json(segments))
+}
+
+#[tracing::instrument(name = "Insert a new segment into the database", skip(db_pool))]
+async fn create_segment(
+    db_pool: &PgPool,
+    name: &SegmentName,
+    filter: &SegmentFilter,
+) -> Result<Option<Segment>, SegmentError> {
+    sqlx::query(
+        r#"
+        INSERT INTO segments (id, name, filter, created_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (name) DO NOTHING
+        RETURNING id, name, filter, created_at
+        "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(name.as_ref())
+    .bind(filter.as_ref())
+    .bind(Utc::now())
+    .fetch_optional(db_pool)
+    .await?
+    .map(map_segment)
+    .transpose()
+}
+
+#[tracing::instrument(name = "Get segments by name from database.", skip(db_pool))]
+pub async fn get_segments_by_name(
+    db_pool: &PgPool,
+    names: &[String],
+) -> Result<Vec<Segment>, SegmentError> {
+    let segments = sqlx::query(
+        r#"
+        SELECT id, name, filter, created_at
+        FROM segments
+        WHERE name = ANY($1)
+        "#,
+    )
+    .bind(names)
+    .fetch_all(db_pool)
+    .await?
+    .into_iter()
+    .map(map_segment)
+    .collect::<Result<Vec<_>, _>>()?;
+
+    if let Some(missing) = names.iter().find(|name| {
+        !segments
+            .iter()
+            .any(|segment| segment.name.as_ref() == *name)
+    }) {
+        return Err(SegmentError::NotFound(missing.clone()));
+    }
+
+    Ok(segments)
+}
+
+/// Rows are checked with the same rules as the requests, a value that does not pass them is reported instead of
+/// being trusted
+fn map_segment(row: PgRow) -> Result<Segment, SegmentError> {
+    let segment_id: Uuid = row.try_get("id")?;
+
+    Ok(Segment {
+        id: segment_id,
+        name: SegmentName::parse(row.try_get("name")?).map_err(corrupt_row(segment_id, "name"))?,
+        filter: SegmentFilter::parse(row.try_get("filter")?)
+            .map_err(corrupt_row(segment_id, "filter"))?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+fn corrupt_row(segment_id: Uuid, column: &'static str) -> impl FnOnce(String) -> SegmentError {
+    move |message| SegmentError::CorruptRow {
+        segment_id,
+        column,
+        message,
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum SegmentError {
+    #[error("Validation error: {0}")]
+    ValidationError(String),
+    #[error("A segment with the same name already exists.")]
+    AlreadyExists,
+    #[error("Segment {0} does not exist.")]
+    NotFound(String),
+    /// A stored value that the domain rejects, e.g. a filter edited by hand in the database
+    #[error("The stored {column} of the segment {segment_id} is not valid: {message}")]
+    CorruptRow {
+        segment_id: Uuid,
+        column: &'static str,
+        message: String,
+    },
+    #[error("Failed to access the segments in the database.")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+impl std::fmt::Debug for SegmentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Caused by:\n\t({})", self)
+    }
+}
+
+impl ResponseError for SegmentError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::AlreadyExists => StatusCode::CONFLICT,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::CorruptRow { .. } | Self::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use actix_web::{web, HttpResponse, ResponseError};
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use sqlx::{postgres::PgRow, PgPool, Row};
+use uuid::Uuid;
+
+use crate::domain::sequence::{validate_step_delays, Sequence, SequenceName, SequenceStep};
+use crate::routes::{
+    prepare_newsletter, store_newsletter_issue, NewNewsletter, PublishNewsletterError,
+};
+
+#[derive(Deserialize, Debug)]
+pub struct NewSequenceBody {
+    pub name: String,
+    pub steps: Vec<NewSequenceStep>,
+}
+
+/// Newsletter sent the number of days after the confirmation. It is sent to each subscriber individually, so it has
+/// no audience nor send time.
+#[derive(Deserialize, Debug)]
+pub struct NewSequenceStep {
+    pub delay_days: u32,
+    #[serde(flatten)]
+    pub newsletter: NewNewsletter,
+}
+
+/// Progress of a subscriber through a sequence
+#[derive(serde::Serialize)]
+pub struct SequenceEnrolment {
+    pub subscriber_id: Uuid,
+    /// `active` while steps are pending, `completed` when every step was delivered and `stopped` when the
+    /// subscriber left the sequence, e.g. by unsubscribing
+    pub status: String,
+    pub enrolled_at: DateTime<Utc>,
+    pub stopped_at: Option<DateTime<Utc>>,
+    pub steps: i64,
+    pub steps_sent: i64,
+    pub next_step_at: Option<DateTime<Utc>>,
+}
+
+/// The steps are stored as private issues, so they stay out of the archive and their stats are reported like those
+/// of any issue
+#[tracing::instrument(
+    name = "Creating a sequence",
+    skip(body, db_pool),
+    fields(
+        sequence_name = %body.name,
+        steps = body.steps.len()
+    )
+)]
+pub async fn handle_create_sequence(
+    body: web::Json<NewSequenceBody>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SequenceError> {
+    let mut body = body.into_inner();
+    let name = SequenceName::parse(body.name).map_err(SequenceError::ValidationError)?;
+    let delays: Vec<u32> = body.steps.iter().map(|step| step.delay_days).collect();
+
+    validate_step_delays(&delays).map_err(SequenceError::ValidationError)?;
+
+    let mut prepared_steps = Vec::with_capacity(body.steps.len());
+
+    for step in body.steps.iter_mut() {
+        let newsletter = &mut step.newsletter;
+
+        if newsletter.segment.is_some()
+            || !newsletter.exclude_segments.is_empty()
+            || newsletter.send_at.is_some()
+            || newsletter.local_send_at.is_some()
+            || newsletter.subject_test.is_some()
+        {
+            return Err(SequenceError::ValidationError(String::from(
+                "Steps are sent to each subscriber after they confirm, they cannot have a segment, a send time or \
+                a subject test.",
+            )));
+        }
+
+        newsletter.is_private = true;
+        prepared_steps.push(prepare_newsletter(&db_pool, newsletter).await?);
+    }
+
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .map_err(SequenceError::DatabaseError)?;
+    let sequence_id = Uuid::new_v4();
+    let created_at = Utc::now();
+    let is_inserted = sqlx::query(
+        r#"
+        INSERT INTO sequences (id, name, created_at)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (name) DO NOTHING
+        "#,
+    )
+    .bind(sequence_id)
+    .bind(name.as_ref())
+    .bind(created_at)
+    .execute(&mut transaction)
+    .await
+    .map_err(SequenceError::DatabaseError)?
+    .rows_affected()
+        > 0;
+
+    if !is_inserted {
+        return Err(SequenceError::AlreadyExists);
+    }
+
+    let mut steps = Vec::with_capacity(prepared_steps.len());
+
+    for (position, (step, prepared_step)) in body.steps.iter().zip(prepared_steps).enumerate() {
+        let newsletter_issue =
+            store_newsletter_issue(&mut transaction, &step.newsletter, prepared_step).await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO sequence_steps (sequence_id, position, delay_days, newsletter_issue_id)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(sequence_id)
+        .bind(position as i16)
+        .bind(step.delay_days as i32)
+        .bind(newsletter_issue.id)
+        .execute(&mut transaction)
+        .await
+        .map_err(SequenceError::DatabaseError)?;
+
+        steps.push(SequenceStep {
+            delay_days: step.delay_days,
+            newsletter_issue_id: newsletter_issue.id,
+            title: newsletter_issue.title,
+        });
+    }
+
+    transaction
+        .commit()
+        .await
+        .map_err(SequenceError::DatabaseError)?;
+
+    Ok(HttpResponse::Created().json(Sequence {
+        id: sequence_id,
+        name,
+        steps,
+        created_at,
+    }))
+}
+
+#[tracing::instrument(name = "Listing sequences", skip(db_pool))]
+pub async fn handle_get_sequences(
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SequenceError> {
+    let rows = sqlx::query(
+        r#"
+        SELECT
+            sequences.id,
+            sequences.name,
+            sequences.created_at,
+            sequence_steps.delay_days,
+            sequence_steps.newsletter_issue_id,
+            newsletter_issues.title
+        FROM sequences
+        JOIN sequence_steps ON sequence_steps.sequence_id = sequences.id
+        JOIN newsletter_issues ON newsletter_issues.id = sequence_steps.newsletter_issue_id
+        ORDER BY sequences.name, sequence_steps.position
+        "#,
+    )
+    .fetch_all(db_pool.get_ref())
+    .await
+    .map_err(SequenceError::DatabaseError)?;
+    let mut sequences: Vec<Sequence> = Vec::new();
+
+    for row in rows {
+        let id: Uuid = row.get("id");
+        let step = SequenceStep {
+            delay_days: row.get::<i32, _>("delay_days") as u32,
+            newsletter_issue_id: row.get("newsletter_issue_id"),
+            title: row.get("title"),
+        };
+
+        match sequences.last_mut() {
+            Some(sequence) if sequence.id == id => sequence.steps.push(step),
+            _ => sequences.push(Sequence {
+                id,
+                name: SequenceName::parse(row.get("name"))
+                    .map_err(|err| SequenceError::DatabaseError(sqlx::Error::Decode(err.into())))?,
+                steps: vec![step],
+                created_at: row.get("created_at"),
+            }),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(sequences))
+}
+
+/// The steps not delivered yet are cancelled. Those already delivered are kept with their issues.
+#[tracing::instrument(name = "Deleting a sequence", skip(db_pool))]
+pub async fn handle_delete_sequence(
+    sequence_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SequenceError> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .map_err(SequenceError::DatabaseError)?;
+
+    sqlx::query(
+        r#"
+        UPDATE issue_deliveries
+        SET status = 'cancelled'
+        FROM sequence_steps
+        WHERE sequence_steps.sequence_id = $1
+            AND issue_deliveries.newsletter_issue_id = sequence_steps.newsletter_issue_id
+            AND issue_deliveries.status = 'pending'
+        "#,
+    )
+    .bind(*sequence_id)
+    .execute(&mut transaction)
+    .await
+    .map_err(SequenceError::DatabaseError)?;
+
+    let result = sqlx::query("DELETE FROM sequences WHERE id = $1")
+        .bind(*sequence_id)
+        .execute(&mut transaction)
+        .await
+        .map_err(SequenceError::DatabaseError)?;
+
+    if result.rows_affected() == 0 {
+        return Err(SequenceError::NotFound);
+    }
+
+    transaction
+        .commit()
+        .await
+        .map_err(SequenceError::DatabaseError)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(name = "Listing the enrolments of a sequence", skip(db_pool))]
+pub async fn handle_get_sequence_enrolments(
+    sequence_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SequenceError> {
+    let sequence = sqlx::query("SELECT id FROM sequences WHERE id = $1")
+        .bind(*sequence_id)
+        .fetch_optional(db_pool.get_ref())
+        .await
+        .map_err(SequenceError::DatabaseError)?;
+
+    if sequence.is_none() {
+        return Err(SequenceError::NotFound);
+    }
+
+    let enrolments = sqlx::query(
+        r#"
+        SELECT
+            sequence_enrolments.subscriber_id,
+            CASE
+                WHEN sequence_enrolments.stopped_at IS NOT NULL THEN 'stopped'
+                WHEN count(*) FILTER (WHERE issue_deliveries.status = 'pending') > 0 THEN 'active'
+                ELSE 'completed'
+            END AS status,
+            sequence_enrolments.enrolled_at,
+            sequence_enrolments.stopped_at,
+            count(*) AS steps,
+            count(*) FILTER (WHERE issue_deliveries.status = 'sent') AS steps_sent,
+            min(issue_deliveries.send_at) FILTER (WHERE issue_deliveries.status = 'pending') AS next_step_at
+        FROM sequence_enrolments
+        JOIN sequence_steps ON sequence_steps.sequence_id = sequence_enrolments.sequence_id
+        LEFT JOIN issue_deliveries
+            ON issue_deliveries.newsletter_issue_id = sequence_steps.newsletter_issue_id
+            AND issue_deliveries.subscriber_id = sequence_enrolments.subscriber_id
+        WHERE sequence_enrolments.sequence_id = $1
+        GROUP BY sequence_enrolments.subscriber_id, sequence_enrolments.enrolled_at, sequence_enrolments.stopped_at
+        ORDER BY sequence_enrolments.enrolled_at
+        "#,
+    )
+    .bind(*sequence_id)
+    .map(|row: PgRow| SequenceEnrolment {
+        subscriber_id: row.get("subscriber_id"),
+        status: row.get("status"),
+        enrolled_at: row.get("enrolled_at"),
+        stopped_at: row.get("stopped_at"),
+        steps: row.get("steps"),
+        steps_sent: row.get("steps_sent"),
+        next_step_at: row.get("next_step_at"),
+    })
+    .fetch_all(db_pool.get_ref())
+    .await
+    .map_err(SequenceError::DatabaseError)?;
+
+    Ok(HttpResponse::Ok().json(enrolments))
+}
+
+#[derive(thiserror::Error)]
+pub enum SequenceError {
+    #[error("Validation error: {0}")]
+    ValidationError(String),
+    #[error("A sequence with the same name already exists.")]
+    AlreadyExists,
+    #[error("The sequence does not exist.")]
+    NotFound,
+    #[error("Failed to access the sequences in the database.")]
+    DatabaseError(#[source] sqlx::Error),
+    #[error(transparent)]
+    PublishNewsletterError(#[from] PublishNewsletterError),
+}
+
+impl std::fmt::Debug for SequenceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Caused by:\n\t({})", self)
+    }
+}
+
+impl ResponseError for SequenceError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::AlreadyExists => StatusCode::CONFLICT,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::PublishNewsletterError(err) => err.status_code(),
+        }
+    }
+}
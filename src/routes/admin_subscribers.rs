@@ -0,0 +1,370 @@
+use actix_web::{web, HttpResponse, ResponseError};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::{
+    subscriber_attributes::SubscriberAttributes, subscriber_tag::SubscriberTag,
+    subscriber_timezone::SubscriberTimezone,
+};
+use crate::repository::subscriber_repository::{SubscriberRepository, SubscriberRepositoryError};
+use crate::routes::{get_attribute_definitions, AttributeDefinitionError};
+
+#[derive(Deserialize, Debug)]
+pub struct SubscriberAttributesBody {
+    pub attributes: serde_json::Map<String, serde_json::Value>,
+}
+
+#[tracing::instrument(
+    name = "Replacing the attributes of a subscriber",
+    skip(body, db_pool, subscriber_repository),
+    fields(
+        subscriber_id = %subscriber_id
+    )
+)]
+pub async fn handle_update_subscriber_attributes(
+    subscriber_id: web::Path<Uuid>,
+    body: web::Json<SubscriberAttributesBody>,
+    db_pool: web::Data<PgPool>,
+    subscriber_repository: web::Data<dyn SubscriberRepository>,
+) -> Result<HttpResponse, UpdateSubscriberError> {
+    let attributes = SubscriberAttributes::parse(body.into_inner().attributes)
+        .map_err(UpdateSubscriberError::ValidationError)?;
+    let definitions = get_attribute_definitions(&db_pool).await?;
+
+    attributes
+        .validate(&definitions)
+        .map_err(UpdateSubscriberError::ValidationError)?;
+
+    let subscriber = subscriber_repository
+        .update_attributes(*subscriber_id, &attributes)
+        .await
+        .map_err(UpdateSubscriberError::DatabaseError)?
+        .ok_or(UpdateSubscriberError::NotFound)?;
+
+    Ok(HttpResponse::Ok().json(subscriber))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SubscriberTimezoneBody {
+    /// IANA timezone, e.g. `Europe/Madrid`. The timezone is removed when it is null.
+    pub timezone: Option<String>,
+}
+
+/// Timezone preference of the subscriber, used by the issues sent at a local time
+#[tracing::instrument(
+    name = "Changing the timezone of a subscriber",
+    skip(body, subscriber_repository),
+    fields(
+        subscriber_id = %subscriber_id,
+        timezone = ?body.timezone
+    )
+)]
+pub async fn handle_update_subscriber_timezone(
+    subscriber_id: web::Path<Uuid>,
+    body: web::Json<SubscriberTimezoneBody>,
+    subscriber_repository: web::Data<dyn SubscriberRepository>,
+) -> Result<HttpResponse, UpdateSubscriberError> {
+    let timezone = body
+        .into_inner()
+        .timezone
+        .map(SubscriberTimezone::parse)
+        .transpose()
+        .map_err(UpdateSubscriberError::ValidationError)?;
+    let subscriber = subscriber_repository
+        .update_timezone(*subscriber_id, timezone.as_ref())
+        .await
+        .map_err(UpdateSubscriberError::DatabaseError)?
+        .ok_or(UpdateSubscriberError::NotFound)?;
+
+    Ok(HttpResponse::Ok().json(subscriber))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SubscriberTagBody {
+    pub tag: String,
+}
+
+#[tracing::instrument(
+    name = "Tagging a subscriber",
+    skip(body, subscriber_repository),
+    fields(
+        subscriber_id = %subscriber_id,
+        tag = %body.tag
+    )
+)]
+pub async fn handle_add_subscriber_tag(
+    subscriber_id: web::Path<Uuid>,
+    body: web::Json<SubscriberTagBody>,
+    subscriber_repository: web::Data<dyn SubscriberRepository>,
+) -> Result<HttpResponse, UpdateSubscriberError> {
+    let tag =
+        SubscriberTag::parse(body.tag.clone()).map_err(UpdateSubscriberError::ValidationError)?;
+    let is_tagged = subscriber_repository
+        .add_tag(*subscriber_id, &tag)
+        .await
+        .map_err(UpdateSubscriberError::DatabaseError)?;
+
+    if !is_tagged {
+        return Err(UpdateSubscriberError::NotFound);
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(
+    name = "Removing a tag from a subscriber",
+    skip(path, subscriber_repository),
+    fields(
+        subscriber_id = %path.0,
+        tag = %path.1
+    )
+)]
+pub async fn handle_remove_subscriber_tag(
+    path: web::Path<(Uuid, String)>,
+    subscriber_repository: web::Data<dyn SubscriberRepository>,
+) -> Result<HttpResponse, UpdateSubscriberError> {
+    let (subscriber_id, tag) = path.into_inner();
+
+    subscriber_repository
+        .remove_tag(subscriber_id, &tag)
+        .await
+        .map_err(UpdateSubscriberError::DatabaseError)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Actions of the sunset policy on the subscriber, e.g. when they were marked as inactive or unsubscribed
+#[tracing::instrument(
+    name = "Getting the status history of a subscriber",
+    skip(subscriber_repository),
+    fields(
+        subscriber_id = %subscriber_id
+    )
+)]
+pub async fn handle_get_subscriber_status_history(
+    subscriber_id: web::Path<Uuid>,
+    subscriber_repository: web::Data<dyn SubscriberRepository>,
+) -> Result<HttpResponse, UpdateSubscriberError> {
+    let history = subscriber_repository
+        .get_status_history(*subscriber_id)
+        .await
+        .map_err(UpdateSubscriberError::DatabaseError)?
+        .ok_or(UpdateSubscriberError::NotFound)?;
+
+    Ok(HttpResponse::Ok().json(history))
+}
+
+#[derive(thiserror::Error)]
+pub enum UpdateSubscriberError {
+    #[error("Validation error: {0}")]
+    ValidationError(String),
+    #[error("The subscriber does not exist.")]
+    NotFound,
+    #[error("Failed to get the attributes schema.")]
+    AttributeDefinitionError(#[from] AttributeDefinitionError),
+    #[error("Failed to update the subscriber in the database.")]
+    DatabaseError(#[source] SubscriberRepositoryError),
+}
+
+impl std::fmt::Debug for UpdateSubscriberError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Caused by:\n\t({})", self)
+    }
+}
+
+impl ResponseError for UpdateSubscriberError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, web};
+    use claim::assert_ok;
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    use super::{
+        handle_add_subscriber_tag, handle_get_subscriber_status_history,
+        handle_remove_subscriber_tag, handle_update_subscriber_timezone, SubscriberTagBody,
+        SubscriberTimezoneBody,
+    };
+    use crate::domain::{
+        new_subscriber::NewSubscriber,
+        status_history::{StatusHistoryAction, StatusHistoryEntry},
+        subscriber_email::SubscriberEmail,
+        subscriber_locale::SubscriberLocale,
+        subscriber_name::SubscriberName,
+        subscriber_timezone::SubscriberTimezone,
+    };
+    use crate::repository::{
+        in_memory_subscriber_repository::InMemorySubscriberRepository,
+        subscriber_repository::SubscriberRepository,
+    };
+
+    async fn create_subscriber(repository: &InMemorySubscriberRepository) -> Uuid {
+        let new_subscriber = NewSubscriber {
+            email: SubscriberEmail::parse(String::from("frank@test.com")).unwrap(),
+            name: SubscriberName::parse(String::from("Frank")).unwrap(),
+            attributes: Default::default(),
+            locale: None,
+            timezone: None,
+            list: None,
+        };
+        let locale = SubscriberLocale::parse(String::from("en")).unwrap();
+
+        repository
+            .create(&new_subscriber, &locale, None)
+            .await
+            .unwrap()
+            .id
+    }
+
+    fn repository_data(
+        repository: &Arc<InMemorySubscriberRepository>,
+    ) -> web::Data<dyn SubscriberRepository> {
+        let repository: Arc<dyn SubscriberRepository> = repository.clone();
+
+        web::Data::from(repository)
+    }
+
+    #[tokio::test]
+    async fn timezones_are_stored_on_the_subscriber() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let subscriber_id = create_subscriber(&repository).await;
+
+        let response = assert_ok!(
+            handle_update_subscriber_timezone(
+                web::Path::from(subscriber_id),
+                web::Json(SubscriberTimezoneBody {
+                    timezone: Some(String::from("Europe/Madrid")),
+                }),
+                repository_data(&repository),
+            )
+            .await
+        );
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            repository.get(subscriber_id).unwrap().timezone,
+            Some(SubscriberTimezone::parse(String::from("Europe/Madrid")).unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_subscribers_are_rejected_with_404() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+
+        let err = handle_update_subscriber_timezone(
+            web::Path::from(Uuid::new_v4()),
+            web::Json(SubscriberTimezoneBody { timezone: None }),
+            repository_data(&repository),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(
+            actix_web::ResponseError::status_code(&err),
+            StatusCode::NOT_FOUND
+        );
+
+        let err = handle_add_subscriber_tag(
+            web::Path::from(Uuid::new_v4()),
+            web::Json(SubscriberTagBody {
+                tag: String::from("vip"),
+            }),
+            repository_data(&repository),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(
+            actix_web::ResponseError::status_code(&err),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn tags_are_added_and_removed() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let subscriber_id = create_subscriber(&repository).await;
+
+        for _ in 0..2 {
+            assert_ok!(
+                handle_add_subscriber_tag(
+                    web::Path::from(subscriber_id),
+                    web::Json(SubscriberTagBody {
+                        tag: String::from("vip"),
+                    }),
+                    repository_data(&repository),
+                )
+                .await
+            );
+        }
+
+        assert_eq!(repository.get_tags(subscriber_id), vec!["vip"]);
+
+        assert_ok!(
+            handle_remove_subscriber_tag(
+                web::Path::from((subscriber_id, String::from("vip"))),
+                repository_data(&repository),
+            )
+            .await
+        );
+
+        assert!(repository.get_tags(subscriber_id).is_empty());
+    }
+
+    #[tokio::test]
+    async fn status_history_is_returned_oldest_first() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let subscriber_id = create_subscriber(&repository).await;
+        let marked_inactive = StatusHistoryEntry {
+            action: StatusHistoryAction::MarkedInactive,
+            occurred_at: chrono::Utc::now(),
+        };
+        let reactivated = StatusHistoryEntry {
+            action: StatusHistoryAction::Reactivated,
+            occurred_at: chrono::Utc::now(),
+        };
+
+        repository.add_status_history(subscriber_id, marked_inactive.clone());
+        repository.add_status_history(subscriber_id, reactivated.clone());
+        repository.add_status_history(Uuid::new_v4(), marked_inactive.clone());
+
+        let response = assert_ok!(
+            handle_get_subscriber_status_history(
+                web::Path::from(subscriber_id),
+                repository_data(&repository),
+            )
+            .await
+        );
+        let body = actix_web::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            serde_json::to_value(vec![marked_inactive, reactivated]).unwrap()
+        );
+
+        let err = handle_get_subscriber_status_history(
+            web::Path::from(Uuid::new_v4()),
+            repository_data(&repository),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(
+            actix_web::ResponseError::status_code(&err),
+            StatusCode::NOT_FOUND
+        );
+    }
+}
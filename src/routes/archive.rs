@@ -0,0 +1,182 @@
+use actix_web::{http::header::ContentType, web, HttpResponse, ResponseError};
+use reqwest::StatusCode;
+use sqlx::{postgres::PgRow, PgPool, Row};
+
+use crate::config::ArchiveSettings;
+use crate::content::archive::{
+    archive_url, render_archive_index, render_atom_feed, render_rss_feed, ArchiveChannel,
+    ArchiveEntry,
+};
+use crate::content::template::{MergeContext, MergeTemplate};
+use crate::domain::subscriber_attributes::AttributesMap;
+use crate::startup::ApplicationBaseUrl;
+
+/// Number of issues in the feeds
+const FEED_ENTRIES_LIMIT: i64 = 20;
+
+#[tracing::instrument(name = "Listing the archived issues", skip_all)]
+pub async fn handle_get_archive(
+    db_pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    archive: web::Data<ArchiveSettings>,
+) -> Result<HttpResponse, ArchiveError> {
+    let entries = get_archive_entries(&db_pool, &base_url.0, None, None).await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(render_archive_index(
+            &channel(&archive, &base_url.0),
+            &entries,
+        )))
+}
+
+#[tracing::instrument(name = "Showing an archived issue", skip(db_pool, base_url))]
+pub async fn handle_get_archived_issue(
+    slug: web::Path<String>,
+    db_pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, ArchiveError> {
+    let entry = get_archive_entries(&db_pool, &base_url.0, Some(&slug), None)
+        .await?
+        .pop()
+        .ok_or(ArchiveError::NotFound)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(entry.html))
+}
+
+#[tracing::instrument(name = "Generating the RSS feed", skip_all)]
+pub async fn handle_get_rss_feed(
+    db_pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    archive: web::Data<ArchiveSettings>,
+) -> Result<HttpResponse, ArchiveError> {
+    let entries =
+        get_archive_entries(&db_pool, &base_url.0, None, Some(FEED_ENTRIES_LIMIT)).await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/rss+xml; charset=utf-8")
+        .body(render_rss_feed(&channel(&archive, &base_url.0), &entries)))
+}
+
+#[tracing::instrument(name = "Generating the Atom feed", skip_all)]
+pub async fn handle_get_atom_feed(
+    db_pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    archive: web::Data<ArchiveSettings>,
+) -> Result<HttpResponse, ArchiveError> {
+    let entries =
+        get_archive_entries(&db_pool, &base_url.0, None, Some(FEED_ENTRIES_LIMIT)).await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/atom+xml; charset=utf-8")
+        .body(render_atom_feed(&channel(&archive, &base_url.0), &entries)))
+}
+
+fn channel<'a>(archive: &'a ArchiveSettings, base_url: &'a str) -> ArchiveChannel<'a> {
+    ArchiveChannel {
+        title: &archive.title,
+        description: &archive.description,
+        base_url,
+    }
+}
+
+/// Returns the public issues that were sent to at least one subscriber, newest first. An issue is published when
+/// its first email is sent.
+#[tracing::instrument(
+    name = "Get the archived issues from the database",
+    skip(db_pool, base_url)
+)]
+async fn get_archive_entries(
+    db_pool: &PgPool,
+    base_url: &str,
+    slug: Option<&str>,
+    limit: Option<i64>,
+) -> Result<Vec<ArchiveEntry>, ArchiveError> {
+    let rows = sqlx::query(
+        r#"
+        SELECT
+            newsletter_issues.id,
+            newsletter_issues.title,
+            newsletter_issues.slug,
+            newsletter_issues.html_content,
+            min(issue_deliveries.sent_at) AS published_at
+        FROM newsletter_issues
+        JOIN issue_deliveries ON issue_deliveries.newsletter_issue_id = newsletter_issues.id
+        WHERE NOT newsletter_issues.is_private
+            AND issue_deliveries.status = 'sent'
+            AND ($1::TEXT IS NULL OR newsletter_issues.slug = $1)
+        GROUP BY newsletter_issues.id
+        ORDER BY published_at DESC
+        LIMIT $2
+        "#,
+    )
+    .bind(slug)
+    .bind(limit)
+    .map(|row: PgRow| {
+        let slug: String = row.get("slug");
+
+        (
+            row.get("id"),
+            row.get::<String, _>("title"),
+            archive_url(base_url, &slug),
+            MergeTemplate::from(row.get::<String, _>("html_content")),
+            row.get("published_at"),
+        )
+    })
+    .fetch_all(db_pool)
+    .await
+    .map_err(ArchiveError::DatabaseError)?;
+
+    rows.into_iter()
+        .map(|(id, title, url, html_content, published_at)| {
+            Ok(ArchiveEntry {
+                id,
+                html: render_for_archive(&html_content, &url)
+                    .map_err(ArchiveError::TemplateError)?,
+                title,
+                url,
+                published_at,
+            })
+        })
+        .collect()
+}
+
+/// Renders the issue for any reader, so merge tags of subscriber values are empty
+fn render_for_archive(html_content: &MergeTemplate, url: &str) -> Result<String, String> {
+    html_content.render(&MergeContext {
+        subscriber_name: "",
+        subscriber_email: "",
+        attributes: &AttributesMap::new(),
+        // Readers of the archive are not identified, there is nobody to unsubscribe or to set preferences for
+        unsubscribe_url: String::from("#"),
+        preferences_url: String::from("#"),
+        view_in_browser_url: String::from(url),
+    })
+}
+
+#[derive(thiserror::Error)]
+pub enum ArchiveError {
+    #[error("The issue does not exist or is not published.")]
+    NotFound,
+    #[error("Failed to render an archived issue: {0}")]
+    TemplateError(String),
+    #[error("Failed to get the archived issues from the database.")]
+    DatabaseError(#[source] sqlx::Error),
+}
+
+impl std::fmt::Debug for ArchiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Caused by:\n\t({})", self)
+    }
+}
+
+impl ResponseError for ArchiveError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::TemplateError(_) | Self::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
@@ -1,9 +1,35 @@
+mod admin_attributes;
+mod admin_issues;
+mod admin_layouts;
+mod admin_recurring_newsletters;
+mod admin_segments;
+mod admin_sequences;
+mod admin_subscribers;
+mod archive;
 mod health_check;
 mod newsletters;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_preferences;
+mod subscriptions_reactivate;
+mod subscriptions_unsubscribe;
+mod tracking;
+mod webhooks;
 
+pub use admin_attributes::*;
+pub use admin_issues::*;
+pub use admin_layouts::*;
+pub use admin_recurring_newsletters::*;
+pub use admin_segments::*;
+pub use admin_sequences::*;
+pub use admin_subscribers::*;
+pub use archive::*;
 pub use health_check::*;
 pub use newsletters::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_preferences::*;
+pub use subscriptions_reactivate::*;
+pub use subscriptions_unsubscribe::*;
+pub use tracking::*;
+pub use webhooks::*;
@@ -1,69 +1,594 @@
-use crate::domain::subscriber_email::SubscriberEmail;
-use crate::email_client::EmailClient;
+use crate::content::check::check_html;
+use crate::content::css::inline_css;
+use crate::content::html_to_text::html_to_text;
+use crate::content::markdown::{markdown_to_html, wrap_in_email_layout};
+use crate::content::template::MergeTemplate;
+use crate::domain::layout::Layout;
+use crate::domain::newsletter_issue::{slugify, NewsletterIssue};
+use crate::domain::segment::Segment;
+use crate::domain::subject_test::{SubjectTest, SubjectTestMetric};
+use crate::routes::{get_layout_by_name, get_segments_by_name, LayoutError, SegmentError};
 use actix_web::{web, HttpResponse, ResponseError};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
 use reqwest::StatusCode;
 use serde::Deserialize;
-use sqlx::{postgres::PgRow, PgPool, Row};
+use sqlx::{PgPool, Postgres, QueryBuilder, Row, Transaction};
+use uuid::Uuid;
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, serde::Serialize, Debug)]
 pub struct NewNewsletter {
     pub title: String,
     pub content: NewsletterContent,
+    /// Name of the stored layout the content is placed in.
+    pub layout: Option<String>,
+    /// Name of the segment to deliver the newsletter to. All confirmed subscribers are targeted when missing.
+    pub segment: Option<String>,
+    /// Names of the segments whose subscribers will not receive the newsletter.
+    #[serde(default)]
+    pub exclude_segments: Vec<String>,
+    /// Records when subscribers open the emails, with a tracking pixel. Enabled when missing.
+    #[serde(default = "default_tracking")]
+    pub track_opens: bool,
+    /// Records which links subscribers follow, rewriting them to tracked redirects. Enabled when missing.
+    #[serde(default = "default_tracking")]
+    pub track_clicks: bool,
+    /// Keeps the issue out of the web archive and the feeds.
+    #[serde(default)]
+    pub is_private: bool,
+    /// Publishes the newsletter even if the content check finds errors in the HTML.
+    #[serde(default)]
+    pub ignore_content_errors: bool,
+    /// UTC time at which the issue is sent. It is sent right away when missing.
+    pub send_at: Option<DateTime<Utc>>,
+    /// Wall-clock time at which each subscriber receives the issue in their own timezone, e.g.
+    /// `2026-10-20T09:00:00`. Subscribers without a timezone use UTC, and those whose local time already passed
+    /// receive the issue right away.
+    pub local_send_at: Option<NaiveDateTime>,
+    /// Subject lines tested on samples of the audience before sending the best one to the rest. The title is the
+    /// subject when missing.
+    pub subject_test: Option<SubjectTest>,
 }
 
-#[derive(Deserialize, Debug)]
+fn default_tracking() -> bool {
+    true
+}
+
+#[derive(serde::Serialize)]
+pub struct PublishedNewsletter {
+    #[serde(flatten)]
+    pub newsletter_issue: NewsletterIssue,
+    pub content_warnings: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub send_at: Option<DateTime<Utc>>,
+}
+
+/// Newsletter whose content and audience passed the validation, ready to be stored
+pub struct PreparedNewsletter {
+    html_content: MergeTemplate,
+    text_content: MergeTemplate,
+    segment: Option<Segment>,
+    excluded_segments: Vec<Segment>,
+    content_warnings: Vec<String>,
+}
+
+/// The newsletter is written either in HTML or in Markdown. Markdown is rendered with an email-safe layout when the
+/// newsletter does not use a stored one.
+#[derive(Deserialize, serde::Serialize, Debug)]
 pub struct NewsletterContent {
-    pub html: String,
+    pub html: Option<String>,
+    pub markdown: Option<String>,
+    /// Plain-text version of the newsletter. It is generated from the HTML when missing.
+    pub text: Option<String>,
+}
+
+impl NewsletterContent {
+    /// Returns the HTML template of the newsletter, with its style sheets inlined
+    fn render_html(&self, layout: Option<&Layout>) -> Result<String, String> {
+        let html = match (&self.html, &self.markdown) {
+            (Some(html), None) => match layout {
+                Some(layout) => layout.html.wrap(html),
+                None => html.clone(),
+            },
+            (None, Some(markdown)) => match layout {
+                Some(layout) => layout.html.wrap(&markdown_to_html(markdown)),
+                None => wrap_in_email_layout(&markdown_to_html(markdown)),
+            },
+            (Some(_), Some(_)) => {
+                return Err(String::from(
+                    "The content must be either HTML or Markdown, not both.",
+                ))
+            }
+            (None, None) => return Err(String::from("The content must have HTML or Markdown.")),
+        };
+        Ok(inline_css(&html))
+    }
 }
 
+/// Stores a new newsletter issue and enqueues one delivery per recipient. Emails are sent by the delivery worker,
+/// which renders the issue for each subscriber. The HTML is sanitised before, and the warnings of the content check
+/// are returned with the issue. Issues with a send time are stored right away but their deliveries are enqueued by
+/// the scheduler.
 #[tracing::instrument(
     name = "Publishing a newsletter to all subscribers",
-    skip(body, db_pool, email_client),
+    skip(body, db_pool),
     fields(
         title = %body.title,
-        content_html = %body.content.html
+        layout = ?body.layout,
+        segment = ?body.segment,
+        exclude_segments = ?body.exclude_segments,
+        track_opens = %body.track_opens,
+        track_clicks = %body.track_clicks,
+        is_private = %body.is_private,
+        send_at = ?body.send_at,
+        local_send_at = ?body.local_send_at,
+        subject_test = ?body.subject_test
     )
 )]
 pub async fn handle_publish_newsletter(
     body: web::Json<NewNewsletter>,
     db_pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
 ) -> Result<HttpResponse, PublishNewsletterError> {
-    let subscriber_emails = get_subscribers(&db_pool).await?;
+    if matches!(body.send_at, Some(send_at) if send_at <= Utc::now()) {
+        return Err(PublishNewsletterError::ValidationError(String::from(
+            "The send time must be in the future.",
+        )));
+    }
 
-    if !subscriber_emails.is_empty() {
-        email_client
-            .broadcast_email(subscriber_emails, &body.title, &body.content.html)
-            .await
-            .map_err(PublishNewsletterError::SendEmailError)?;
+    if body.send_at.is_some() && body.local_send_at.is_some() {
+        return Err(PublishNewsletterError::ValidationError(String::from(
+            "The issue is sent either at a time or at a local time, not both.",
+        )));
     }
 
-    Ok(HttpResponse::Ok().finish())
+    // UTC-12 is the last timezone to reach a local time
+    let latest_local_time = Utc::now().naive_utc() - Duration::hours(12);
+
+    if matches!(body.local_send_at, Some(local_send_at) if local_send_at <= latest_local_time) {
+        return Err(PublishNewsletterError::ValidationError(String::from(
+            "The local send time has already passed in every timezone.",
+        )));
+    }
+
+    let newsletter = prepare_newsletter(&db_pool, &body).await?;
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .map_err(PublishNewsletterError::DatabaseError)?;
+    let published_newsletter = match body.send_at {
+        Some(send_at) => schedule_newsletter(&mut transaction, &body, newsletter, send_at).await,
+        None => publish_newsletter(&mut transaction, &body, newsletter).await,
+    }?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(PublishNewsletterError::DatabaseError)?;
+
+    Ok(HttpResponse::Ok().json(published_newsletter))
 }
 
-#[tracing::instrument(name = "Get subscribers from database.", skip(db_pool))]
-pub async fn get_subscribers(
-    db_pool: &web::Data<PgPool>,
-) -> Result<Vec<SubscriberEmail>, PublishNewsletterError> {
+/// Renders and checks the content of the newsletter and looks up its audience
+#[tracing::instrument(name = "Prepare a newsletter", skip_all)]
+pub async fn prepare_newsletter(
+    db_pool: &PgPool,
+    body: &NewNewsletter,
+) -> Result<PreparedNewsletter, PublishNewsletterError> {
+    let layout = match &body.layout {
+        Some(name) => Some(get_layout_by_name(db_pool, name).await?),
+        None => None,
+    };
+    let html = body
+        .content
+        .render_html(layout.as_ref())
+        .map_err(PublishNewsletterError::ValidationError)?;
+    let content_check = check_html(&html);
+
+    if content_check.has_errors() && !body.ignore_content_errors {
+        return Err(PublishNewsletterError::ContentError(content_check.errors));
+    }
+
+    for warning in content_check.warnings.iter() {
+        tracing::warn!("Content check warning: {}", warning);
+    }
+
+    let text = body
+        .content
+        .text
+        .clone()
+        .unwrap_or_else(|| html_to_text(&content_check.html));
+    let html_content = MergeTemplate::parse(content_check.html)
+        .map_err(PublishNewsletterError::ValidationError)?;
+    let text_content =
+        MergeTemplate::parse(text).map_err(PublishNewsletterError::ValidationError)?;
+
+    if body.is_private
+        && [&html_content, &text_content]
+            .iter()
+            .any(|content| content.as_ref().contains("view_in_browser_url"))
+    {
+        return Err(PublishNewsletterError::ValidationError(String::from(
+            "Private issues have no archive page, remove {{ view_in_browser_url }}.",
+        )));
+    }
+
+    if let Some(subject_test) = &body.subject_test {
+        validate_subject_test(body, subject_test)
+            .map_err(PublishNewsletterError::ValidationError)?;
+    }
+
+    let segment = match &body.segment {
+        Some(name) => get_segments_by_name(db_pool, std::slice::from_ref(name))
+            .await?
+            .pop(),
+        None => None,
+    };
+    let excluded_segments = get_segments_by_name(db_pool, &body.exclude_segments).await?;
+
+    Ok(PreparedNewsletter {
+        html_content,
+        text_content,
+        segment,
+        excluded_segments,
+        content_warnings: content_check.warnings,
+    })
+}
+
+fn validate_subject_test(body: &NewNewsletter, subject_test: &SubjectTest) -> Result<(), String> {
+    subject_test.validate()?;
+
+    // The samples would receive the issue at different times, and so would have different chances to open it
+    if body.local_send_at.is_some() {
+        return Err(String::from(
+            "Issues sent at a local time cannot test their subject.",
+        ));
+    }
+
+    match subject_test.metric {
+        SubjectTestMetric::Opens if !body.track_opens => Err(String::from(
+            "Subject tests on opens need the opens to be tracked.",
+        )),
+        SubjectTestMetric::Clicks if !body.track_clicks => Err(String::from(
+            "Subject tests on clicks need the clicks to be tracked.",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Stores the issue and enqueues its deliveries, or only the samples of its subject test
+pub async fn publish_newsletter(
+    transaction: &mut Transaction<'_, Postgres>,
+    body: &NewNewsletter,
+    newsletter: PreparedNewsletter,
+) -> Result<PublishedNewsletter, PublishNewsletterError> {
+    let newsletter_issue = insert_newsletter_issue(
+        transaction,
+        body.title.clone(),
+        newsletter.html_content,
+        newsletter.text_content,
+        body.track_opens,
+        body.track_clicks,
+        body.is_private,
+    )
+    .await?;
+
+    match &body.subject_test {
+        Some(subject_test) => {
+            insert_subject_test(transaction, &newsletter_issue.id, body, subject_test).await?;
+            start_subject_test(
+                transaction,
+                &newsletter_issue.id,
+                subject_test,
+                newsletter.segment.as_ref(),
+                &newsletter.excluded_segments,
+                Utc::now(),
+            )
+            .await?;
+        }
+        None => {
+            enqueue_deliveries(
+                transaction,
+                &newsletter_issue.id,
+                newsletter.segment.as_ref(),
+                &newsletter.excluded_segments,
+                body.local_send_at,
+            )
+            .await?;
+        }
+    }
+
+    Ok(PublishedNewsletter {
+        newsletter_issue,
+        content_warnings: newsletter.content_warnings,
+        send_at: None,
+    })
+}
+
+/// Stores the issue and the audience it is sent to at the send time
+#[tracing::instrument(
+    name = "Schedule a newsletter issue",
+    skip(transaction, body, newsletter)
+)]
+async fn schedule_newsletter(
+    transaction: &mut Transaction<'_, Postgres>,
+    body: &NewNewsletter,
+    newsletter: PreparedNewsletter,
+    send_at: DateTime<Utc>,
+) -> Result<PublishedNewsletter, PublishNewsletterError> {
+    let newsletter_issue = insert_newsletter_issue(
+        transaction,
+        body.title.clone(),
+        newsletter.html_content,
+        newsletter.text_content,
+        body.track_opens,
+        body.track_clicks,
+        body.is_private,
+    )
+    .await?;
+
     sqlx::query(
         r#"
-        SELECT email
-        FROM subscriptions
-        WHERE status = 'confirmed'
+        INSERT INTO scheduled_issues (newsletter_issue_id, send_at, segment, exclude_segments, status)
+        VALUES ($1, $2, $3, $4, 'scheduled')
         "#,
     )
-    .map(|row: PgRow| SubscriberEmail::parse(row.get("email")).unwrap())
-    .fetch_all(db_pool.as_ref())
+    .bind(newsletter_issue.id)
+    .bind(send_at)
+    .bind(&body.segment)
+    .bind(&body.exclude_segments)
+    .execute(&mut *transaction)
+    .await?;
+
+    if let Some(subject_test) = &body.subject_test {
+        insert_subject_test(transaction, &newsletter_issue.id, body, subject_test).await?;
+    }
+
+    Ok(PublishedNewsletter {
+        newsletter_issue,
+        content_warnings: newsletter.content_warnings,
+        send_at: Some(send_at),
+    })
+}
+
+/// Stores the issue without enqueueing any delivery, for issues delivered by other means such as the steps of a
+/// sequence
+pub async fn store_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    body: &NewNewsletter,
+    newsletter: PreparedNewsletter,
+) -> Result<NewsletterIssue, PublishNewsletterError> {
+    insert_newsletter_issue(
+        transaction,
+        body.title.clone(),
+        newsletter.html_content,
+        newsletter.text_content,
+        body.track_opens,
+        body.track_clicks,
+        body.is_private,
+    )
     .await
-    .map_err(PublishNewsletterError::GetSubscribersError)
+}
+
+#[tracing::instrument(
+    name = "Insert a newsletter issue into the database",
+    skip(transaction, html_content, text_content)
+)]
+async fn insert_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    title: String,
+    html_content: MergeTemplate,
+    text_content: MergeTemplate,
+    track_opens: bool,
+    track_clicks: bool,
+    is_private: bool,
+) -> Result<NewsletterIssue, PublishNewsletterError> {
+    let id = Uuid::new_v4();
+    let mut newsletter_issue = NewsletterIssue {
+        id,
+        slug: slugify(&title),
+        title,
+        html_content,
+        text_content,
+        track_opens,
+        track_clicks,
+        is_private,
+        created_at: Utc::now(),
+    };
+
+    // Issues published at once with the same title wait for each other on the slug instead of failing. Only the first
+    // one gets the slug of the title, the others are told apart by the beginning of their id.
+    if !try_insert_newsletter_issue(transaction, &newsletter_issue).await? {
+        newsletter_issue.slug = format!("{}-{}", newsletter_issue.slug, &id.to_string()[..8]);
+
+        if !try_insert_newsletter_issue(transaction, &newsletter_issue).await? {
+            return Err(PublishNewsletterError::SlugConflict(newsletter_issue.slug));
+        }
+    }
+
+    Ok(newsletter_issue)
+}
+
+/// Returns whether the issue was inserted, which it is not when another issue has its slug
+async fn try_insert_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue: &NewsletterIssue,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO newsletter_issues
+            (id, title, slug, html_content, text_content, track_opens, track_clicks, is_private, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        ON CONFLICT (slug) DO NOTHING
+        "#,
+    )
+    .bind(newsletter_issue.id)
+    .bind(&newsletter_issue.title)
+    .bind(&newsletter_issue.slug)
+    .bind(newsletter_issue.html_content.as_ref())
+    .bind(newsletter_issue.text_content.as_ref())
+    .bind(newsletter_issue.track_opens)
+    .bind(newsletter_issue.track_clicks)
+    .bind(newsletter_issue.is_private)
+    .bind(newsletter_issue.created_at)
+    .execute(transaction)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+/// Deliveries of issues sent at a local time are released by the worker when the time arrives in the timezone of
+/// each subscriber
+#[tracing::instrument(
+    name = "Enqueue newsletter issue deliveries",
+    skip(transaction, segment, excluded_segments)
+)]
+pub async fn enqueue_deliveries(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: &Uuid,
+    segment: Option<&Segment>,
+    excluded_segments: &[Segment],
+    local_send_at: Option<NaiveDateTime>,
+) -> Result<u64, sqlx::Error> {
+    let mut query: QueryBuilder<Postgres> = QueryBuilder::new(
+        r#"
+        INSERT INTO issue_deliveries (id, newsletter_issue_id, subscriber_id, status, created_at, priority, send_at)
+        SELECT gen_random_uuid(), "#,
+    );
+
+    query.push_bind(*newsletter_issue_id);
+    query.push(", id, 'pending', now(), engagement_score, ");
+    query.push_bind(local_send_at);
+    query.push("::timestamp AT TIME ZONE coalesce(timezone, 'UTC') FROM subscriptions WHERE ");
+    push_audience_filter(&mut query, segment, excluded_segments);
+    // Subscribers in the samples of a subject test already have their delivery
+    query.push(" ON CONFLICT (newsletter_issue_id, subscriber_id) DO NOTHING");
+
+    let result = query.build().execute(transaction).await?;
+
+    tracing::info!("{} deliveries enqueued.", result.rows_affected());
+
+    Ok(result.rows_affected())
+}
+
+/// Pushes the condition matching the confirmed subscribers of the segment, without those of the excluded ones
+fn push_audience_filter(
+    query: &mut QueryBuilder<Postgres>,
+    segment: Option<&Segment>,
+    excluded_segments: &[Segment],
+) {
+    query.push("status = 'confirmed'");
+
+    if let Some(segment) = segment {
+        query.push(" AND ");
+        segment.filter.push_sql(query);
+    }
+
+    for excluded_segment in excluded_segments {
+        query.push(" AND NOT ");
+        excluded_segment.filter.push_sql(query);
+    }
+}
+
+#[tracing::instrument(name = "Store a subject test", skip(transaction, body))]
+async fn insert_subject_test(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: &Uuid,
+    body: &NewNewsletter,
+    subject_test: &SubjectTest,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO subject_tests
+            (newsletter_issue_id, subjects, sample_percent, window_hours, metric, segment, exclude_segments, status)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, 'scheduled')
+        "#,
+    )
+    .bind(newsletter_issue_id)
+    .bind(&subject_test.subjects)
+    .bind(subject_test.sample_percent as i16)
+    .bind(subject_test.window_hours as i32)
+    .bind(subject_test.metric.as_ref())
+    .bind(&body.segment)
+    .bind(&body.exclude_segments)
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+/// Enqueues a random sample of the audience for each subject, and starts the window after which the winner is sent
+/// to the rest
+#[tracing::instrument(
+    name = "Start a subject test",
+    skip(transaction, subject_test, segment, excluded_segments)
+)]
+pub async fn start_subject_test(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: &Uuid,
+    subject_test: &SubjectTest,
+    segment: Option<&Segment>,
+    excluded_segments: &[Segment],
+    now: DateTime<Utc>,
+) -> Result<u64, sqlx::Error> {
+    let mut query: QueryBuilder<Postgres> =
+        QueryBuilder::new("SELECT count(*) FROM subscriptions WHERE ");
+
+    push_audience_filter(&mut query, segment, excluded_segments);
+
+    let audience: i64 = query.build().fetch_one(&mut *transaction).await?.get(0);
+    let subjects = subject_test.subjects.len() as i64;
+    // Subscribers are shuffled and dealt to the subjects in turns, so every sample has the same size
+    let mut query: QueryBuilder<Postgres> = QueryBuilder::new(
+        r#"
+        INSERT INTO issue_deliveries (id, newsletter_issue_id, subscriber_id, status, created_at, priority, subject_variant)
+        SELECT gen_random_uuid(), "#,
+    );
+
+    query.push_bind(*newsletter_issue_id);
+    query.push(", id, 'pending', now(), engagement_score, (row_number() OVER () - 1) % ");
+    query.push_bind(subjects);
+    query.push(" FROM (SELECT id, engagement_score FROM subscriptions WHERE ");
+    push_audience_filter(&mut query, segment, excluded_segments);
+    query.push(" ORDER BY random() LIMIT ");
+    query.push_bind(subject_test.sample_size(audience) * subjects);
+    query.push(") AS sample");
+
+    let result = query.build().execute(&mut *transaction).await?;
+
+    sqlx::query(
+        r#"
+        UPDATE subject_tests
+        SET status = 'running', ends_at = $2
+        WHERE newsletter_issue_id = $1
+        "#,
+    )
+    .bind(newsletter_issue_id)
+    .bind(now + Duration::hours(subject_test.window_hours as i64))
+    .execute(&mut *transaction)
+    .await?;
+
+    tracing::info!(
+        "{} deliveries enqueued for the samples of the subject test.",
+        result.rows_affected()
+    );
+
+    Ok(result.rows_affected())
 }
 
 #[derive(thiserror::Error)]
 pub enum PublishNewsletterError {
-    #[error("Failed to send a confirmation email to a new subscriber.")]
-    SendEmailError(#[from] reqwest::Error),
-    #[error("Failed to get subscribers from the database.")]
-    GetSubscribersError(#[source] sqlx::Error),
+    #[error("Validation error: {0}")]
+    ValidationError(String),
+    #[error("The content check found errors: {}", .0.join(" "))]
+    ContentError(Vec<String>),
+    /// Another issue took the slug of the title and the one told apart by the id, e.g. issues published at once
+    #[error("The slug {0} is already taken.")]
+    SlugConflict(String),
+    #[error("Failed to store the newsletter issue in the database.")]
+    DatabaseError(#[from] sqlx::Error),
+    #[error(transparent)]
+    SegmentError(#[from] SegmentError),
+    #[error(transparent)]
+    LayoutError(#[from] LayoutError),
 }
 
 impl std::fmt::Debug for PublishNewsletterError {
@@ -75,8 +600,12 @@ impl std::fmt::Debug for PublishNewsletterError {
 impl ResponseError for PublishNewsletterError {
     fn status_code(&self) -> StatusCode {
         match self {
-            PublishNewsletterError::SendEmailError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            PublishNewsletterError::GetSubscribersError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            PublishNewsletterError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            PublishNewsletterError::ContentError(_) => StatusCode::BAD_REQUEST,
+            PublishNewsletterError::SlugConflict(_) => StatusCode::CONFLICT,
+            PublishNewsletterError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            PublishNewsletterError::SegmentError(err) => err.status_code(),
+            PublishNewsletterError::LayoutError(err) => err.status_code(),
         }
     }
 }
@@ -1,25 +1,47 @@
-use actix_web::{web, HttpResponse, ResponseError};
-use chrono::Utc;
+use actix_web::{http::header::ACCEPT_LANGUAGE, web, HttpRequest, HttpResponse, ResponseError};
+use minijinja::{context, Value};
 use rand::Rng;
 use reqwest::StatusCode;
-use sqlx::{postgres::PgRow, PgPool, Row};
+use sqlx::PgPool;
 use uuid::Uuid;
 
 use crate::{
+    config::SubscriptionConfirmationSettings,
+    content::email_template::LocalizedEmailTemplates,
     domain::{
         new_subscriber::{NewSubscriber, NewSubscriberBody},
         subscriber::Subscriber,
-        subscriber_email::SubscriberEmail,
-        subscriber_name::SubscriberName,
-        subscriber_status::SubscriberStatus,
+        subscriber_locale::SubscriberLocale,
+        subscriber_timezone::SubscriberTimezone,
+        subscription_token::SubscriptionTokenRecord,
     },
     email_client::EmailClient,
-    startup::ApplicationBaseUrl,
+    repository::subscriber_repository::{SubscriberRepository, SubscriberRepositoryError},
+    routes::{get_attribute_definitions, AttributeDefinitionError},
+    startup::{ApplicationBaseUrl, ConfirmationEmailTemplates},
 };
 
+/// Days an expired subscription token is kept, so the confirmation page can tell it apart from an unknown one
+const EXPIRED_TOKEN_RETENTION_DAYS: i64 = 30;
+
+/// Variables available in the confirmation email templates
+pub const CONFIRMATION_EMAIL_VARIABLES: [&str; 3] =
+    ["subscriber.name", "subscriber.email", "confirmation_link"];
+
+#[allow(clippy::too_many_arguments)]
 #[tracing::instrument(
     name = "Creating a new subscriber handler",
-    skip(body, db_pool, email_client, base_url, redis_client),
+    skip(
+        request,
+        body,
+        db_pool,
+        subscriber_repository,
+        email_client,
+        base_url,
+        redis_client,
+        confirmation_email_templates,
+        subscription_confirmation
+    ),
     fields(
         subscriber_email = %body.email,
         subscriber_name = %body.name
@@ -27,24 +49,76 @@ use crate::{
     )
 )]
 pub async fn handle_create_subscription(
+    request: HttpRequest,
     body: web::Json<NewSubscriberBody>,
     db_pool: web::Data<PgPool>,
+    subscriber_repository: web::Data<dyn SubscriberRepository>,
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
     redis_client: web::Data<redis::Client>,
+    confirmation_email_templates: web::Data<ConfirmationEmailTemplates>,
+    subscription_confirmation: web::Data<SubscriptionConfirmationSettings>,
 ) -> Result<HttpResponse, CreateSubscriptionError> {
     let new_subscriber: NewSubscriber = body
         .try_into()
         .map_err(CreateSubscriptionError::ValidationError)?;
-    let subscriber = create_subscription(&new_subscriber, &db_pool)
+
+    if let Some(list) = &new_subscriber.list {
+        if !subscription_confirmation.has_list(list) {
+            return Err(CreateSubscriptionError::ValidationError(format!(
+                "{} is not a list",
+                list
+            )));
+        }
+    }
+
+    let attribute_definitions = get_attribute_definitions(&db_pool).await?;
+
+    new_subscriber
+        .attributes
+        .validate(&attribute_definitions)
+        .map_err(CreateSubscriptionError::ValidationError)?;
+
+    let preferred_locales = match &new_subscriber.locale {
+        Some(locale) => vec![locale.clone()],
+        None => {
+            let accept_language = request
+                .headers()
+                .get(ACCEPT_LANGUAGE)
+                .and_then(|header| header.to_str().ok())
+                .unwrap_or_default();
+
+            SubscriberLocale::parse_accept_language(accept_language)
+        }
+    };
+    let locale = match &new_subscriber.locale {
+        Some(locale) => locale.clone(),
+        None => confirmation_email_templates.0.negotiate(&preferred_locales),
+    };
+    // The negotiated locale can lose the region, so the timezone is inferred from the preferred ones
+    let timezone = new_subscriber.timezone.clone().or_else(|| {
+        preferred_locales
+            .iter()
+            .find_map(SubscriberTimezone::from_locale)
+    });
+    let subscriber = subscriber_repository
+        .create(&new_subscriber, &locale, timezone.as_ref())
         .await
         .map_err(CreateSubscriptionError::InsertSubscriptionError)?;
     let subscription_token = generate_subscription_token();
 
-    store_subscription_token(&redis_client, &subscription_token, &subscriber.id).await?;
+    store_subscription_token(
+        &db_pool,
+        &redis_client,
+        &subscription_token,
+        &subscriber.id,
+        subscription_confirmation.get_token_validity(),
+    )
+    .await?;
     send_confirmation_email(
         &email_client,
-        &new_subscriber,
+        &confirmation_email_templates.0,
+        &subscriber,
         base_url.0.as_str(),
         subscription_token.as_str(),
     )
@@ -53,103 +127,127 @@ pub async fn handle_create_subscription(
     Ok(HttpResponse::Created().finish())
 }
 
-#[tracing::instrument(
-    name = "Insert a new subscriber into the database",
-    skip(new_subscriber, db_pool)
-)]
-async fn create_subscription(
-    new_subscriber: &NewSubscriber,
-    db_pool: &web::Data<PgPool>,
-) -> Result<Subscriber, sqlx::Error> {
-    sqlx::query(
-        r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at, status) 
-        VALUES ($1, $2, $3, $4, 'pending_confirmation')
-        RETURNING id, email, name, subscribed_at, status
-        "#,
-    )
-    .bind(Uuid::new_v4())
-    .bind(new_subscriber.email.as_ref())
-    .bind(new_subscriber.name.as_ref())
-    .bind(Utc::now())
-    .map(|row: PgRow| Subscriber {
-        id: row.get("id"),
-        email: SubscriberEmail::parse(row.get("email")).unwrap(),
-        name: SubscriberName::parse(row.get("name")).unwrap(),
-        subscribed_at: row.get("subscribed_at"),
-        status: SubscriberStatus::parse(row.get("status")).unwrap(),
-    })
-    .fetch_one(db_pool.get_ref())
-    .await
-}
-
+/// Sends the confirmation link rendered with the templates, those of the confirmation email or of its reminder
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
     fields(
         subscription_token = %subscription_token,
-        base_url = %base_url
+        base_url = %base_url,
+        locale = %subscriber.locale.as_ref()
     ),
-    skip(email_client, new_subscriber)
+    skip(email_client, confirmation_email_templates, subscriber)
 )]
-async fn send_confirmation_email(
+pub async fn send_confirmation_email(
     email_client: &EmailClient,
-    new_subscriber: &NewSubscriber,
+    confirmation_email_templates: &LocalizedEmailTemplates,
+    subscriber: &Subscriber,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), CreateSubscriptionError> {
     let confirmation_link = format!(
         "{}/subscriptions/confirm?token={}",
         base_url, subscription_token
     );
-    let html_body = format!(
-        r#"
-            <div>
-                <h1>Welcome to our newsletter!</>
-                <p>Click <a href="{}">here</a> to confirm your subscription!</p>
-            </div>
-        "#,
-        confirmation_link
-    );
+    let email = confirmation_email_templates
+        .render(
+            &subscriber.locale,
+            context! {
+                subscriber => context! {
+                    name => subscriber.name.as_ref(),
+                    email => subscriber.email.as_ref(),
+                },
+                confirmation_link => Value::from_safe_string(confirmation_link),
+            },
+        )
+        .map_err(CreateSubscriptionError::TemplateError)?;
 
     email_client
         .send_email(
-            new_subscriber.email.clone(),
-            "Welcome to our newsletter",
-            html_body.as_str(),
+            subscriber.email.clone(),
+            &email.subject,
+            &email.html,
+            &email.text,
         )
-        .await
+        .await?;
+
+    Ok(())
 }
 
+/// The token is also recorded among the tokens of the subscriber in Postgres, so they can be revoked together. It is
+/// recorded first, a token that fails to be stored in Redis is only revoked for nothing.
 #[tracing::instrument(
     name = "Store a subscription token in Redis",
-    skip(redis_client)
+    skip(db_pool, redis_client, token_validity)
     fields(
         subscription_token = %subscription_token,
         subscriber_id = %subscriber_id
     )
 )]
-async fn store_subscription_token(
+pub async fn store_subscription_token(
+    db_pool: &PgPool,
     redis_client: &redis::Client,
     subscription_token: &str,
     subscriber_id: &Uuid,
+    token_validity: chrono::Duration,
 ) -> Result<(), StoreTokenError> {
+    sqlx::query(
+        r#"
+        INSERT INTO subscription_tokens (subscription_token, subscriber_id, created_at)
+        VALUES ($1, $2, now())
+        "#,
+    )
+    .bind(subscription_token)
+    .bind(subscriber_id)
+    .execute(db_pool)
+    .await?;
+
     let mut redis_conn = redis_client.get_tokio_connection().await.map_err(|err| {
         tracing::error!("Failed to connect to Redis: {:?}", err);
-        StoreTokenError(err)
+        StoreTokenError::RedisError(err)
     })?;
+    let expiration =
+        (token_validity + chrono::Duration::days(EXPIRED_TOKEN_RETENTION_DAYS)).num_seconds();
 
     redis::cmd("SET")
         .arg(format!(
             "subscription_token:{}:subscriber_id",
             subscription_token
         ))
-        .arg(subscriber_id.to_string())
-        .query_async(&mut redis_conn)
-        .await
-        .map_err(StoreTokenError)
+        .arg(SubscriptionTokenRecord::new(*subscriber_id).to_string())
+        .arg("EX")
+        .arg(expiration)
+        .query_async::<_, ()>(&mut redis_conn)
+        .await?;
+
+    Ok(())
 }
 
-fn generate_subscription_token() -> String {
+/// Makes the confirmation links stop working. The keys are overwritten to expire right away, the token store only
+/// relies on GET and SET.
+#[tracing::instrument(name = "Revoke subscription tokens", skip(redis_client))]
+pub async fn revoke_subscription_tokens(
+    redis_client: &redis::Client,
+    subscription_tokens: &[String],
+) -> Result<(), redis::RedisError> {
+    let mut redis_conn = redis_client.get_tokio_connection().await?;
+
+    for subscription_token in subscription_tokens {
+        redis::cmd("SET")
+            .arg(format!(
+                "subscription_token:{}:subscriber_id",
+                subscription_token
+            ))
+            .arg("")
+            .arg("PX")
+            .arg(1)
+            .query_async::<_, ()>(&mut redis_conn)
+            .await?;
+    }
+
+    Ok(())
+}
+
+pub fn generate_subscription_token() -> String {
     let mut rng = rand::thread_rng();
 
     std::iter::repeat_with(|| rng.sample(rand::distributions::Alphanumeric))
@@ -162,12 +260,16 @@ fn generate_subscription_token() -> String {
 pub enum CreateSubscriptionError {
     #[error("Validation error: {0}")]
     ValidationError(String),
+    #[error("Failed to get the attributes schema.")]
+    AttributeDefinitionError(#[from] AttributeDefinitionError),
     #[error("Failed to store the confirmation token for a new subscriber.")]
     StoreTokenError(#[from] StoreTokenError),
+    #[error("Failed to render the confirmation email: {0}")]
+    TemplateError(String),
     #[error("Failed to send a confirmation email to a new subscriber.")]
     SendEmailError(#[from] reqwest::Error),
     #[error("Failed to insert a new subscriber into the database.")]
-    InsertSubscriptionError(#[source] sqlx::Error),
+    InsertSubscriptionError(#[source] SubscriberRepositoryError),
 }
 
 impl std::fmt::Debug for CreateSubscriptionError {
@@ -185,25 +287,16 @@ impl ResponseError for CreateSubscriptionError {
     }
 }
 
-pub struct StoreTokenError(redis::RedisError);
-
-impl std::fmt::Display for StoreTokenError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "A database error was encountered while storing a subscription token."
-        )
-    }
+#[derive(thiserror::Error)]
+pub enum StoreTokenError {
+    #[error("A database error was encountered while storing a subscription token.")]
+    RedisError(#[from] redis::RedisError),
+    #[error("Failed to record the subscription token of the subscriber.")]
+    DatabaseError(#[from] sqlx::Error),
 }
 
 impl std::fmt::Debug for StoreTokenError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}\nCaused by:\n\t({})", self, self.0)
-    }
-}
-
-impl std::error::Error for StoreTokenError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        Some(&self.0)
+        write!(f, "Caused by:\n\t({})", self)
     }
 }
@@ -1,107 +1,223 @@
 use actix_web::{
+    http::{
+        header::{ContentType, LOCATION},
+        StatusCode,
+    },
     web::{self, Query},
-    HttpResponse, Responder,
+    HttpResponse,
 };
 use serde::Deserialize;
-use sqlx::{PgPool, Row, postgres::PgRow};
-use uuid::Uuid;
 
-use crate::domain::{subscriber::Subscriber, subscriber_email::SubscriberEmail, subscriber_name::SubscriberName, subscriber_status::SubscriberStatus};
+use crate::config::{ConfirmationRedirects, SubscriptionConfirmationSettings};
+use crate::domain::subscriber_status::SubscriberStatus;
+use crate::domain::subscription_token::SubscriptionTokenRecord;
+use crate::repository::subscriber_repository::SubscriberRepository;
 
 #[derive(Deserialize, Debug)]
 pub struct Parameters {
     pub token: String,
 }
 
+/// Result of following a confirmation link
+#[derive(Debug, PartialEq)]
+enum ConfirmationOutcome {
+    Confirmed,
+    AlreadyConfirmed,
+    Expired,
+    InvalidToken,
+    Error,
+}
+
+impl ConfirmationOutcome {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Confirmed | Self::AlreadyConfirmed => StatusCode::OK,
+            Self::Expired => StatusCode::GONE,
+            Self::InvalidToken => StatusCode::NOT_FOUND,
+            Self::Error => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn redirect_url<'a>(&self, redirects: &'a ConfirmationRedirects) -> Option<&'a str> {
+        match self {
+            Self::Confirmed => redirects.success_redirect_url.as_deref(),
+            Self::AlreadyConfirmed => redirects.already_confirmed_redirect_url.as_deref(),
+            Self::Expired => redirects.expired_redirect_url.as_deref(),
+            Self::InvalidToken | Self::Error => redirects.error_redirect_url.as_deref(),
+        }
+    }
+
+    fn page(&self) -> String {
+        let (title, message) = match self {
+            Self::Confirmed => (
+                "Subscription confirmed",
+                "Thanks for confirming your email, you will receive our next newsletter.",
+            ),
+            Self::AlreadyConfirmed => (
+                "Subscription already confirmed",
+                "Your email was already confirmed, there is nothing else to do.",
+            ),
+            Self::Expired => (
+                "Confirmation link expired",
+                "This confirmation link has expired, please subscribe again to get a new one.",
+            ),
+            Self::InvalidToken => (
+                "Invalid confirmation link",
+                "This confirmation link is not valid, please check that you copied it entirely.",
+            ),
+            Self::Error => (
+                "Something went wrong",
+                "We could not confirm your subscription, please try again later.",
+            ),
+        };
+
+        format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+</head>
+<body style="margin: 0; padding: 48px 16px; background-color: #f4f4f5; font-family: Helvetica, Arial, sans-serif; color: #18181b;">
+<main style="max-width: 480px; margin: 0 auto; padding: 32px; background-color: #ffffff; border-radius: 8px; text-align: center;">
+<h1 style="margin: 0 0 16px; font-size: 24px;">{title}</h1>
+<p style="margin: 0; font-size: 16px; line-height: 1.5;">{message}</p>
+</main>
+</body>
+</html>"#
+        )
+    }
+
+    /// Redirects to the URL configured for the outcome, or shows a page explaining it
+    fn into_response(self, redirects: &ConfirmationRedirects) -> HttpResponse {
+        match self.redirect_url(redirects) {
+            Some(url) => HttpResponse::SeeOther()
+                .insert_header((LOCATION, url))
+                .finish(),
+            None => HttpResponse::build(self.status_code())
+                .content_type(ContentType::html())
+                .body(self.page()),
+        }
+    }
+}
+
 #[tracing::instrument(
   name = "Confirm a newsletter subscription",
-  skip(redis_client, db_pool), 
+  skip(redis_client, subscriber_repository, subscription_confirmation),
   fields(
     token = %parameters.token,
   )
 )]
 pub async fn handle_confirm_subscription(
     redis_client: web::Data<redis::Client>,
-        db_pool: web::Data<PgPool>,
+    subscriber_repository: web::Data<dyn SubscriberRepository>,
+    subscription_confirmation: web::Data<SubscriptionConfirmationSettings>,
     parameters: Query<Parameters>,
-) -> impl Responder {
-  let subscription_token = &parameters.token;
-
-  match  get_subscriber_id_from_token(&redis_client, subscription_token).await {
-      Ok(Some(subscriber_id)) => {
-          match confirm_subscriber(&db_pool, subscriber_id).await {
-              Ok(_) => {
-                  tracing::info!("Subscriber confirmed.");
-                  HttpResponse::Ok().finish()
-              }
-              Err(err) => {
-                  tracing::error!("Failed to confirm subscriber: {}.", err);
-                  HttpResponse::InternalServerError().finish()
-              }
-          }
-      },
-      Ok(None) => {
-          tracing::error!("Subscription token not found.");
-          HttpResponse::NotFound().finish()
-      }
-      Err(_) => {
-          tracing::error!("Invalid subscription token.");
-          HttpResponse::BadRequest().finish()
-      }
-  }
+) -> HttpResponse {
+    let (outcome, list) = confirm_subscription_token(
+        &redis_client,
+        subscriber_repository.get_ref(),
+        &parameters.token,
+        subscription_confirmation.get_token_validity(),
+    )
+    .await;
 
+    outcome.into_response(&subscription_confirmation.get_redirects(list.as_deref()))
 }
 
-#[tracing::instrument(
-  name = "Get subscriber id from token.",
-  skip(redis_client), 
-  fields(
-    subscription_token
-  )
-)]
-pub async fn get_subscriber_id_from_token(
-  redis_client: &redis::Client,
+/// Returns the outcome together with the list of the subscriber, which sets where the outcome redirects
+async fn confirm_subscription_token(
+    redis_client: &redis::Client,
+    subscriber_repository: &dyn SubscriberRepository,
     subscription_token: &str,
-) -> Result<Option<Uuid>, redis::RedisError> {
-    let mut redis_conn = redis_client.get_tokio_connection().await?;
+    token_validity: chrono::Duration,
+) -> (ConfirmationOutcome, Option<String>) {
+    let token_record = match get_subscription_token_record(redis_client, subscription_token).await {
+        Ok(Some(token_record)) => token_record,
+        Ok(None) => {
+            tracing::error!("Subscription token not found.");
+            return (ConfirmationOutcome::InvalidToken, None);
+        }
+        Err(err) => {
+            tracing::error!("Failed to get the subscription token: {}.", err);
+            return (ConfirmationOutcome::Error, None);
+        }
+    };
 
-    let subscriber_id: String = redis::cmd("GET")
-    .arg(format!("subscription_token:{}:subscriber_id", subscription_token))
-    .query_async(&mut redis_conn).await?;
+    let subscriber = match subscriber_repository.get(token_record.subscriber_id).await {
+        Ok(Some(subscriber)) => subscriber,
+        Ok(None) => {
+            tracing::error!("Subscriber of the subscription token not found.");
+            return (ConfirmationOutcome::InvalidToken, None);
+        }
+        Err(err) => {
+            tracing::error!("Failed to get the subscriber: {}.", err);
+            return (ConfirmationOutcome::Error, None);
+        }
+    };
+
+    let outcome = match subscriber.status {
+        SubscriberStatus::Confirmed => {
+            tracing::info!("Subscriber already confirmed.");
+            ConfirmationOutcome::AlreadyConfirmed
+        }
+        // Tokens are kept after confirming, they must not subscribe again those who unsubscribed
+        SubscriberStatus::Unsubscribed => {
+            tracing::info!("Subscriber is not pending confirmation.");
+            ConfirmationOutcome::InvalidToken
+        }
+        SubscriberStatus::Pending if token_record.is_expired(token_validity) => {
+            tracing::info!("Subscription token expired.");
+            ConfirmationOutcome::Expired
+        }
+        SubscriberStatus::Pending => {
+            confirm_subscriber(subscriber_repository, token_record.subscriber_id).await
+        }
+    };
+
+    (outcome, subscriber.list)
+}
 
-    match Uuid::parse_str(&subscriber_id) {
-        Ok(subscriber_id) => Ok(Some(subscriber_id)),
-        Err(_) => Ok(None),
+async fn confirm_subscriber(
+    subscriber_repository: &dyn SubscriberRepository,
+    subscriber_id: uuid::Uuid,
+) -> ConfirmationOutcome {
+    match subscriber_repository.confirm(subscriber_id).await {
+        Ok(Some(_)) => {
+            tracing::info!("Subscriber confirmed.");
+            ConfirmationOutcome::Confirmed
+        }
+        Ok(None) => {
+            tracing::error!("Subscriber of the subscription token is no longer pending confirmation.");
+            ConfirmationOutcome::InvalidToken
+        }
+        Err(err) => {
+            tracing::error!("Failed to confirm subscriber: {}.", err);
+            ConfirmationOutcome::Error
+        }
     }
 }
 
+/// Returns the subscriber the token was issued for, or `None` when the token is unknown or its value is corrupt.
+/// Tokens are kept after confirming, so following the link twice tells the subscriber it was already confirmed.
 #[tracing::instrument(
-  name = "Change subscriber status to confirmed.",
-  skip(db_pool), 
+  name = "Get subscription token record.",
+  skip(redis_client),
   fields(
-    subscriber_id
+    subscription_token
   )
 )]
-pub async fn confirm_subscriber(db_pool: &PgPool, subscriber_id: Uuid) -> Result<Subscriber, sqlx::Error> {
-    let updated_subscriber = sqlx::query(
-        r#"
-        UPDATE subscriptions
-        SET status = 'confirmed'
-        WHERE id = $1
-        RETURNING id, email, name, status, subscribed_at
-        "#,
-    )
-    .bind(subscriber_id)
-    .map(|row: PgRow| Subscriber {
-      id: row.get("id"),
-      email: SubscriberEmail::parse(row.get("email")).unwrap(),
-      name: SubscriberName::parse(row.get("name")).unwrap(),
-      subscribed_at: row.get("subscribed_at"),
-      status: SubscriberStatus::parse(row.get("status")).unwrap(),
-    })
-    .fetch_one(db_pool)
-    .await?;
-
-    Ok(updated_subscriber)
-
-}
\ No newline at end of file
+pub async fn get_subscription_token_record(
+    redis_client: &redis::Client,
+    subscription_token: &str,
+) -> Result<Option<SubscriptionTokenRecord>, redis::RedisError> {
+    let mut redis_conn = redis_client.get_tokio_connection().await?;
+
+    let token_record: Option<String> = redis::cmd("GET")
+        .arg(format!("subscription_token:{}:subscriber_id", subscription_token))
+        .query_async(&mut redis_conn)
+        .await?;
+
+    Ok(token_record.and_then(|token_record| SubscriptionTokenRecord::parse(&token_record).ok()))
+}
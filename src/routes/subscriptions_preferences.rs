@@ -0,0 +1,140 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse, ResponseError};
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+use crate::content::html::escape_text;
+use crate::content::tracking::verify_preferences_token;
+use crate::domain::subscriber_timezone::SubscriberTimezone;
+use crate::repository::subscriber_repository::{SubscriberRepository, SubscriberRepositoryError};
+use crate::startup::HmacSecret;
+
+#[derive(Deserialize, Debug)]
+pub struct PreferencesParameters {
+    /// Signed token of the preferences link of a delivery
+    pub token: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PreferencesForm {
+    /// IANA timezone, e.g. `Europe/Madrid`. The timezone is removed when it is empty.
+    pub timezone: String,
+}
+
+/// Shows the preferences of the subscriber of the link, so they can choose the timezone of the issues sent at a local
+/// time
+#[tracing::instrument(
+    name = "Show the subscriber preferences",
+    skip(hmac_secret, subscriber_repository, parameters),
+    fields(
+        token = %parameters.token,
+    )
+)]
+pub async fn handle_get_preferences(
+    hmac_secret: web::Data<HmacSecret>,
+    subscriber_repository: web::Data<dyn SubscriberRepository>,
+    parameters: web::Query<PreferencesParameters>,
+) -> Result<HttpResponse, PreferencesError> {
+    let subscriber_id = verify_preferences_token(&hmac_secret.0, &parameters.token)
+        .map_err(PreferencesError::ValidationError)?;
+    let subscriber = subscriber_repository
+        .get(subscriber_id)
+        .await?
+        .ok_or(PreferencesError::NotFound)?;
+    let timezone = subscriber
+        .timezone
+        .as_ref()
+        .map(|timezone| escape_text(timezone.as_ref()))
+        .unwrap_or_default();
+
+    // The form is posted to the same URL, so the token is kept in the query
+    Ok(page(&format!(
+        r#"<form method="post">
+<label for="timezone" style="display: block; margin: 0 0 8px; font-size: 16px;">Timezone</label>
+<input id="timezone" name="timezone" value="{timezone}" placeholder="Europe/Madrid" style="width: 100%; box-sizing: border-box; margin: 0 0 24px; padding: 12px; font-size: 16px;">
+<button type="submit" style="padding: 12px 24px; font-size: 16px;">Save</button>
+</form>"#
+    )))
+}
+
+/// Saves the preferences of the subscriber of the link. The timezone is checked with the same rules as at signup.
+#[tracing::instrument(
+    name = "Update the subscriber preferences",
+    skip(hmac_secret, subscriber_repository, parameters, form),
+    fields(
+        token = %parameters.token,
+        timezone = %form.timezone
+    )
+)]
+pub async fn handle_update_preferences(
+    hmac_secret: web::Data<HmacSecret>,
+    subscriber_repository: web::Data<dyn SubscriberRepository>,
+    parameters: web::Query<PreferencesParameters>,
+    form: web::Form<PreferencesForm>,
+) -> Result<HttpResponse, PreferencesError> {
+    let subscriber_id = verify_preferences_token(&hmac_secret.0, &parameters.token)
+        .map_err(PreferencesError::ValidationError)?;
+    let timezone = match form.timezone.trim() {
+        "" => None,
+        timezone => Some(
+            SubscriberTimezone::parse(String::from(timezone))
+                .map_err(PreferencesError::ValidationError)?,
+        ),
+    };
+
+    subscriber_repository
+        .update_timezone(subscriber_id, timezone.as_ref())
+        .await?
+        .ok_or(PreferencesError::NotFound)?;
+
+    tracing::info!("Subscriber preferences saved.");
+
+    Ok(page(
+        r#"<p style="margin: 0; font-size: 16px; line-height: 1.5;">Your preferences were saved.</p>"#,
+    ))
+}
+
+fn page(content: &str) -> HttpResponse {
+    HttpResponse::Ok().content_type(ContentType::html()).body(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Preferences</title>
+</head>
+<body style="margin: 0; padding: 48px 16px; background-color: #f4f4f5; font-family: Helvetica, Arial, sans-serif; color: #18181b;">
+<main style="max-width: 480px; margin: 0 auto; padding: 32px; background-color: #ffffff; border-radius: 8px; text-align: center;">
+<h1 style="margin: 0 0 16px; font-size: 24px;">Preferences</h1>
+{content}
+</main>
+</body>
+</html>"#
+    ))
+}
+
+#[derive(thiserror::Error)]
+pub enum PreferencesError {
+    #[error("Validation error: {0}")]
+    ValidationError(String),
+    #[error("The subscriber does not exist.")]
+    NotFound,
+    #[error("Failed to access the subscriber preferences.")]
+    DatabaseError(#[from] SubscriberRepositoryError),
+}
+
+impl std::fmt::Debug for PreferencesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Caused by:\n\t({})", self)
+    }
+}
+
+impl ResponseError for PreferencesError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use actix_web::{web, HttpResponse, ResponseError};
+use chrono::Utc;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::content::tracking::verify_re_engagement_token;
+use crate::startup::HmacSecret;
+use crate::sunset_policy::reactivate_subscriber;
+
+#[derive(Deserialize, Debug)]
+pub struct ReactivateParameters {
+    /// Signed token of the link in the re-engagement email
+    pub token: String,
+}
+
+/// Keeps an inactive subscriber subscribed. Following the link again, or after the subscriber engaged in some other
+/// way, is not an error.
+#[tracing::instrument(
+    name = "Reactivate an inactive subscription",
+    skip(db_pool, hmac_secret, parameters),
+    fields(
+        token = %parameters.token,
+    )
+)]
+pub async fn handle_reactivate_subscription(
+    db_pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+    parameters: web::Query<ReactivateParameters>,
+) -> Result<HttpResponse, ReactivateError> {
+    let subscriber_id = verify_re_engagement_token(&hmac_secret.0, &parameters.token)
+        .map_err(ReactivateError::ValidationError)?;
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .map_err(ReactivateError::DatabaseError)?;
+    let is_reactivated = reactivate_subscriber(&mut transaction, subscriber_id, Utc::now())
+        .await
+        .map_err(ReactivateError::DatabaseError)?;
+
+    if !is_reactivated {
+        let status: Option<String> =
+            sqlx::query_scalar("SELECT status FROM subscriptions WHERE id = $1")
+                .bind(subscriber_id)
+                .fetch_optional(&mut transaction)
+                .await
+                .map_err(ReactivateError::DatabaseError)?;
+
+        match status.as_deref() {
+            Some("confirmed") => {}
+            Some(_) => return Err(ReactivateError::NotSubscribed),
+            None => return Err(ReactivateError::NotFound),
+        }
+    }
+
+    transaction
+        .commit()
+        .await
+        .map_err(ReactivateError::DatabaseError)?;
+
+    tracing::info!(is_reactivated, "Subscription kept.");
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(thiserror::Error)]
+pub enum ReactivateError {
+    #[error("Validation error: {0}")]
+    ValidationError(String),
+    #[error("The subscriber does not exist.")]
+    NotFound,
+    /// The grace period ended, or the subscriber unsubscribed
+    #[error("The subscriber is no longer subscribed.")]
+    NotSubscribed,
+    #[error("Failed to reactivate the subscriber.")]
+    DatabaseError(#[source] sqlx::Error),
+}
+
+impl std::fmt::Debug for ReactivateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Caused by:\n\t({})", self)
+    }
+}
+
+impl ResponseError for ReactivateError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::NotSubscribed => StatusCode::GONE,
+            Self::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse, ResponseError};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+
+use crate::content::tracking::verify_unsubscribe_token;
+use crate::sequences::stop_sequences;
+use crate::startup::HmacSecret;
+
+#[derive(Deserialize, Debug)]
+pub struct UnsubscribeParameters {
+    /// Signed token of the delivery that contained the unsubscribe link
+    pub token: String,
+}
+
+/// Shows the page to confirm the unsubscribe. Following the link does not unsubscribe, so link scanners of email
+/// providers do not unsubscribe anyone.
+#[tracing::instrument(
+    name = "Show the unsubscribe confirmation",
+    skip(hmac_secret, parameters),
+    fields(
+        token = %parameters.token,
+    )
+)]
+pub async fn handle_unsubscribe_confirmation(
+    hmac_secret: web::Data<HmacSecret>,
+    parameters: web::Query<UnsubscribeParameters>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    verify_unsubscribe_token(&hmac_secret.0, &parameters.token)
+        .map_err(UnsubscribeError::ValidationError)?;
+
+    // The form is posted to the same URL, so the token is kept in the query
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Unsubscribe</title>
+</head>
+<body style="margin: 0; padding: 48px 16px; background-color: #f4f4f5; font-family: Helvetica, Arial, sans-serif; color: #18181b;">
+<main style="max-width: 480px; margin: 0 auto; padding: 32px; background-color: #ffffff; border-radius: 8px; text-align: center;">
+<h1 style="margin: 0 0 16px; font-size: 24px;">Unsubscribe</h1>
+<p style="margin: 0 0 24px; font-size: 16px; line-height: 1.5;">You will not receive our newsletters anymore.</p>
+<form method="post">
+<button type="submit" style="padding: 12px 24px; font-size: 16px;">Unsubscribe</button>
+</form>
+</main>
+</body>
+</html>"#,
+        ))
+}
+
+/// Unsubscribes the subscriber of the delivery, from the confirmation page or with the one-click unsubscribe of the
+/// email client (RFC 8058). The `List-Unsubscribe=One-Click` body sent by email clients is ignored.
+#[tracing::instrument(
+    name = "Unsubscribe from the newsletter",
+    skip(db_pool, hmac_secret, parameters),
+    fields(
+        token = %parameters.token,
+    )
+)]
+pub async fn handle_unsubscribe(
+    db_pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+    parameters: web::Query<UnsubscribeParameters>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    let delivery_id = verify_unsubscribe_token(&hmac_secret.0, &parameters.token)
+        .map_err(UnsubscribeError::ValidationError)?;
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .map_err(UnsubscribeError::DatabaseError)?;
+    // The unsubscribe is recorded as an event of the delivery, so it is counted in the stats of the issue
+    let subscriber_id: Uuid = sqlx::query(
+        r#"
+        WITH delivery AS (
+            SELECT id, subscriber_id FROM issue_deliveries WHERE id = $1
+        ), event AS (
+            INSERT INTO issue_delivery_events (id, issue_delivery_id, event, occurred_at)
+            SELECT $2, id, 'unsubscribed', now() FROM delivery
+        )
+        UPDATE subscriptions
+        SET status = 'unsubscribed'
+        WHERE id = (SELECT subscriber_id FROM delivery)
+        RETURNING id
+        "#,
+    )
+    .bind(delivery_id)
+    .bind(Uuid::new_v4())
+    .fetch_optional(&mut transaction)
+    .await
+    .map_err(UnsubscribeError::DatabaseError)?
+    .ok_or(UnsubscribeError::NotFound)?
+    .get("id");
+
+    stop_sequences(&mut transaction, subscriber_id)
+        .await
+        .map_err(UnsubscribeError::DatabaseError)?;
+    transaction
+        .commit()
+        .await
+        .map_err(UnsubscribeError::DatabaseError)?;
+
+    tracing::info!("Subscriber unsubscribed.");
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error("Validation error: {0}")]
+    ValidationError(String),
+    #[error("The unsubscribe token does not exist.")]
+    NotFound,
+    #[error("Failed to unsubscribe the subscriber.")]
+    DatabaseError(#[source] sqlx::Error),
+}
+
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Caused by:\n\t({})", self)
+    }
+}
+
+impl ResponseError for UnsubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
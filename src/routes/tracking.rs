@@ -0,0 +1,261 @@
+use actix_web::{
+    http::header::{CacheControl, CacheDirective, LOCATION, USER_AGENT},
+    web, HttpRequest, HttpResponse, ResponseError,
+};
+use chrono::Utc;
+use reqwest::StatusCode;
+use sqlx::{PgPool, Postgres, Row, Transaction};
+use uuid::Uuid;
+
+use crate::content::tracking::{is_trackable_link, verify_click_token};
+use crate::domain::engagement_score::{EngagementEvent, EngagementScoring};
+use crate::engagement_scoring::record_engagement;
+use crate::startup::HmacSecret;
+
+/// Transparent 1x1 GIF
+const TRACKING_PIXEL: [u8; 43] = [
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+/// Records that the email of a delivery was opened. The pixel is always returned, so email clients never show a
+/// broken image, even when the token is unknown or the issue no longer tracks opens.
+#[tracing::instrument(
+    name = "Track a newsletter open",
+    skip(request, db_pool, scoring),
+    fields(delivery_token = %delivery_token)
+)]
+pub async fn handle_track_open(
+    request: HttpRequest,
+    delivery_token: web::Path<String>,
+    db_pool: web::Data<PgPool>,
+    scoring: web::Data<EngagementScoring>,
+) -> HttpResponse {
+    let user_agent = get_user_agent(&request);
+
+    match Uuid::parse_str(&delivery_token) {
+        Ok(delivery_id) => {
+            if let Err(err) = record_open(&db_pool, &scoring, &delivery_id, user_agent).await {
+                tracing::error!("Failed to record the newsletter open: {}.", err);
+            }
+        }
+        Err(_) => tracing::warn!("Invalid delivery token."),
+    }
+
+    HttpResponse::Ok()
+        .content_type("image/gif")
+        .insert_header(CacheControl(vec![
+            CacheDirective::NoCache,
+            CacheDirective::NoStore,
+            CacheDirective::MustRevalidate,
+        ]))
+        .body(TRACKING_PIXEL.as_slice())
+}
+
+/// Only the first open of each delivery counts towards the engagement score, email clients may load the pixel every
+/// time the email is displayed
+#[tracing::instrument(
+    name = "Insert a newsletter open into the database",
+    skip(db_pool, scoring)
+)]
+async fn record_open(
+    db_pool: &PgPool,
+    scoring: &EngagementScoring,
+    delivery_id: &Uuid,
+    user_agent: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+    let mut transaction = db_pool.begin().await?;
+    let subscriber_id = match lock_delivery(&mut transaction, delivery_id).await? {
+        Some(delivery) if delivery.track_opens => delivery.subscriber_id,
+        _ => {
+            tracing::info!(
+                "Open not recorded, the delivery does not exist or does not track opens."
+            );
+            return Ok(());
+        }
+    };
+    // The open being inserted is not visible to the rest of the statement, only the previous ones are counted
+    let is_first_open: bool = sqlx::query_scalar(
+        r#"
+        WITH open AS (
+            INSERT INTO issue_opens (id, issue_delivery_id, opened_at, user_agent)
+            VALUES ($1, $2, $3, $4)
+        )
+        SELECT NOT EXISTS (SELECT 1 FROM issue_opens WHERE issue_delivery_id = $2)
+        "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(delivery_id)
+    .bind(now)
+    .bind(user_agent)
+    .fetch_one(&mut transaction)
+    .await?;
+
+    if is_first_open {
+        record_engagement(
+            &mut transaction,
+            scoring,
+            subscriber_id,
+            EngagementEvent::Open,
+            now,
+        )
+        .await?;
+    }
+
+    transaction.commit().await
+}
+
+/// Delivery being tracked, locked until the event is recorded
+struct TrackedDelivery {
+    subscriber_id: Uuid,
+    track_opens: bool,
+    track_clicks: bool,
+}
+
+/// Concurrent events of the delivery wait for the lock, so only one of them is counted as the first. The lock is
+/// taken in its own statement: the next statements see the events committed while waiting, a single one would not.
+async fn lock_delivery(
+    transaction: &mut Transaction<'_, Postgres>,
+    delivery_id: &Uuid,
+) -> Result<Option<TrackedDelivery>, sqlx::Error> {
+    sqlx::query(
+        r#"
+        SELECT issue_deliveries.subscriber_id, newsletter_issues.track_opens, newsletter_issues.track_clicks
+        FROM issue_deliveries
+        JOIN newsletter_issues ON newsletter_issues.id = issue_deliveries.newsletter_issue_id
+        WHERE issue_deliveries.id = $1
+        FOR UPDATE OF issue_deliveries
+        "#,
+    )
+    .bind(delivery_id)
+    .fetch_optional(transaction)
+    .await?
+    .map(|row| {
+        Ok(TrackedDelivery {
+            subscriber_id: row.try_get("subscriber_id")?,
+            track_opens: row.try_get("track_opens")?,
+            track_clicks: row.try_get("track_clicks")?,
+        })
+    })
+    .transpose()
+}
+
+/// Records that a subscriber followed a link of a delivery and redirects to it. Only links signed when the email
+/// was sent are redirected, so the endpoint cannot be used to send people to arbitrary URLs.
+#[tracing::instrument(
+    name = "Track a newsletter link click",
+    skip(request, token, db_pool, hmac_secret, scoring)
+)]
+pub async fn handle_track_click(
+    request: HttpRequest,
+    token: web::Path<String>,
+    db_pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+    scoring: web::Data<EngagementScoring>,
+) -> Result<HttpResponse, TrackClickError> {
+    let (delivery_id, url) =
+        verify_click_token(&hmac_secret.0, &token).map_err(TrackClickError::InvalidToken)?;
+
+    if !is_trackable_link(&url) {
+        return Err(TrackClickError::InvalidToken(format!(
+            "{} is not a web link",
+            url
+        )));
+    }
+
+    if let Err(err) = record_click(
+        &db_pool,
+        &scoring,
+        &delivery_id,
+        &url,
+        get_user_agent(&request),
+    )
+    .await
+    {
+        tracing::error!("Failed to record the link click: {}.", err);
+    }
+
+    Ok(HttpResponse::Found()
+        .insert_header((LOCATION, url))
+        .finish())
+}
+
+/// Like opens, only the first click of each delivery counts towards the engagement score
+#[tracing::instrument(name = "Insert a link click into the database", skip(db_pool, scoring))]
+async fn record_click(
+    db_pool: &PgPool,
+    scoring: &EngagementScoring,
+    delivery_id: &Uuid,
+    url: &str,
+    user_agent: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+    let mut transaction = db_pool.begin().await?;
+    let subscriber_id = match lock_delivery(&mut transaction, delivery_id).await? {
+        Some(delivery) if delivery.track_clicks => delivery.subscriber_id,
+        _ => {
+            tracing::info!(
+                "Click not recorded, the delivery does not exist or does not track clicks."
+            );
+            return Ok(());
+        }
+    };
+    let is_first_click: bool = sqlx::query_scalar(
+        r#"
+        WITH click AS (
+            INSERT INTO issue_clicks (id, issue_delivery_id, url, clicked_at, user_agent)
+            VALUES ($1, $2, $3, $4, $5)
+        )
+        SELECT NOT EXISTS (SELECT 1 FROM issue_clicks WHERE issue_delivery_id = $2)
+        "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(delivery_id)
+    .bind(url)
+    .bind(now)
+    .bind(user_agent)
+    .fetch_one(&mut transaction)
+    .await?;
+
+    if is_first_click {
+        record_engagement(
+            &mut transaction,
+            scoring,
+            subscriber_id,
+            EngagementEvent::Click,
+            now,
+        )
+        .await?;
+    }
+
+    transaction.commit().await
+}
+
+fn get_user_agent(request: &HttpRequest) -> Option<&str> {
+    request
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|header| header.to_str().ok())
+}
+
+#[derive(thiserror::Error)]
+pub enum TrackClickError {
+    #[error("Invalid tracked link: {0}")]
+    InvalidToken(String),
+}
+
+impl std::fmt::Debug for TrackClickError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Caused by:\n\t({})", self)
+    }
+}
+
+impl ResponseError for TrackClickError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidToken(_) => StatusCode::NOT_FOUND,
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use chrono::{TimeZone, Utc};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::delivery_event::DeliveryEvent;
+use crate::domain::engagement_score::{EngagementEvent, EngagementScoring};
+use crate::domain::sendgrid_webhook_key::SendgridWebhookKey;
+use crate::engagement_scoring::record_engagement;
+
+/// Event of the Sendgrid event webhook. The custom arguments of the email are sent as fields of the event.
+#[derive(Deserialize, Debug)]
+pub struct SendgridEvent {
+    pub event: String,
+    pub timestamp: i64,
+    pub sg_event_id: Option<String>,
+    pub delivery_id: Option<String>,
+}
+
+/// Records the events Sendgrid reports for newsletter deliveries. Events of other emails, like the confirmation
+/// email, and event types that are not tracked are ignored. Bounces lower the engagement score of the subscriber the
+/// first time they are reported. Requests that are not signed by Sendgrid, or signed more than a few minutes ago, are
+/// rejected.
+#[tracing::instrument(
+    name = "Recording Sendgrid events",
+    skip(request, body, db_pool, scoring, webhook_key),
+    fields(events = tracing::field::Empty)
+)]
+pub async fn handle_sendgrid_events(
+    request: HttpRequest,
+    body: web::Bytes,
+    db_pool: web::Data<PgPool>,
+    scoring: web::Data<EngagementScoring>,
+    webhook_key: web::Data<SendgridWebhookKey>,
+) -> Result<HttpResponse, WebhookError> {
+    let signature = get_header(&request, SIGNATURE_HEADER)?;
+    let timestamp = get_header(&request, TIMESTAMP_HEADER)?;
+
+    check_timestamp(timestamp, Utc::now().timestamp())?;
+    webhook_key
+        .verify(timestamp, &body, signature)
+        .map_err(WebhookError::InvalidSignature)?;
+
+    let events: Vec<SendgridEvent> = serde_json::from_slice(&body)
+        .map_err(|err| WebhookError::ValidationError(err.to_string()))?;
+
+    tracing::Span::current().record("events", events.len());
+
+    for sendgrid_event in events.iter() {
+        let Some(event) = DeliveryEvent::from_sendgrid(&sendgrid_event.event) else {
+            continue;
+        };
+        let Some(delivery_id) = sendgrid_event
+            .delivery_id
+            .as_deref()
+            .and_then(|delivery_id| Uuid::parse_str(delivery_id).ok())
+        else {
+            continue;
+        };
+        let occurred_at = Utc
+            .timestamp_opt(sendgrid_event.timestamp, 0)
+            .single()
+            .unwrap_or_else(Utc::now);
+        // The bounce is counted in the score only if its event is stored, so both are committed together
+        let mut transaction = db_pool.begin().await.map_err(WebhookError::DatabaseError)?;
+        let subscriber_id: Option<Uuid> = sqlx::query_scalar(
+            r#"
+            WITH delivery AS (
+                SELECT id, subscriber_id FROM issue_deliveries WHERE id = $2
+            ), event AS (
+                INSERT INTO issue_delivery_events (id, issue_delivery_id, event, occurred_at, provider_event_id)
+                SELECT $1, id, $3, $4, $5
+                FROM delivery
+                ON CONFLICT (provider_event_id) DO NOTHING
+                RETURNING issue_delivery_id
+            )
+            SELECT delivery.subscriber_id
+            FROM delivery
+            JOIN event ON event.issue_delivery_id = delivery.id
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(delivery_id)
+        .bind(event.as_ref())
+        .bind(occurred_at)
+        .bind(&sendgrid_event.sg_event_id)
+        .fetch_optional(&mut transaction)
+        .await
+        .map_err(WebhookError::DatabaseError)?;
+
+        if let (DeliveryEvent::Bounced, Some(subscriber_id)) = (event, subscriber_id) {
+            record_engagement(
+                &mut transaction,
+                &scoring,
+                subscriber_id,
+                EngagementEvent::Bounce,
+                Utc::now(),
+            )
+            .await
+            .map_err(WebhookError::DatabaseError)?;
+        }
+
+        transaction
+            .commit()
+            .await
+            .map_err(WebhookError::DatabaseError)?;
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+const SIGNATURE_HEADER: &str = "X-Twilio-Email-Event-Webhook-Signature";
+const TIMESTAMP_HEADER: &str = "X-Twilio-Email-Event-Webhook-Timestamp";
+/// Seconds between the signed timestamp and the current time, so a captured request cannot be replayed later
+const TIMESTAMP_TOLERANCE: i64 = 300;
+
+fn get_header<'a>(request: &'a HttpRequest, name: &str) -> Result<&'a str, WebhookError> {
+    request
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .ok_or_else(|| WebhookError::InvalidSignature(format!("The {} header is missing", name)))
+}
+
+fn check_timestamp(timestamp: &str, now: i64) -> Result<(), WebhookError> {
+    let timestamp: i64 = timestamp.parse().map_err(|_| {
+        WebhookError::InvalidSignature(format!("The {} header is not a number", TIMESTAMP_HEADER))
+    })?;
+
+    if (now - timestamp).abs() > TIMESTAMP_TOLERANCE {
+        return Err(WebhookError::InvalidSignature(format!(
+            "The request was signed more than {} seconds away from now",
+            TIMESTAMP_TOLERANCE
+        )));
+    }
+
+    Ok(())
+}
+
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+    #[error("The request is not signed by Sendgrid: {0}")]
+    InvalidSignature(String),
+    #[error("Validation error: {0}")]
+    ValidationError(String),
+    #[error("Failed to store the events in the database.")]
+    DatabaseError(#[source] sqlx::Error),
+}
+
+impl std::fmt::Debug for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Caused by:\n\t({})", self)
+    }
+}
+
+impl ResponseError for WebhookError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidSignature(_) => StatusCode::UNAUTHORIZED,
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
@@ -0,0 +1,488 @@
+use chrono::{DateTime, Utc};
+use sqlx::{postgres::PgRow, types::Json, PgPool, Postgres, Row, Transaction};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::domain::cron_schedule::CronSchedule;
+use crate::domain::segment::Segment;
+use crate::domain::subject_test::{pick_winner, SubjectTest, SubjectTestMetric};
+use crate::routes::{
+    enqueue_deliveries, get_segments_by_name, get_subject_test_results, prepare_newsletter,
+    publish_newsletter, start_subject_test, NewNewsletter, PublishNewsletterError, SegmentError,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Key of the advisory lock held while the scheduler runs, so only one instance fires each job
+pub const SCHEDULER_LOCK_ID: i64 = 0x6e65_7773_6c65_7474;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SchedulerOutcome {
+    /// Number of scheduled issues whose deliveries were enqueued, of recurring newsletters published and of subject
+    /// tests whose winner was sent to the rest of the audience
+    Completed {
+        scheduled_issues: usize,
+        recurring_newsletters: usize,
+        subject_tests: usize,
+    },
+    /// Another instance is running the scheduler
+    Locked,
+}
+
+/// Fires the scheduled and recurring sends when they are due. It runs in every instance of the application, the
+/// advisory lock makes the other instances skip a run while one of them is firing the jobs.
+pub struct Scheduler {
+    db_pool: PgPool,
+}
+
+impl Scheduler {
+    pub fn new(db_pool: PgPool) -> Self {
+        Self { db_pool }
+    }
+
+    pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+
+            // Errors are retried in the next run, the jobs stay due until they are fired
+            let _ = run_due_jobs(&self.db_pool, Utc::now()).await;
+        }
+    }
+}
+
+/// Enqueues the deliveries of the scheduled issues, publishes the recurring newsletters and completes the subject
+/// tests that are due at `now`. Recurring newsletters fire once even if several runs were missed, e.g. while the
+/// application was down.
+#[tracing::instrument(name = "Run the due scheduled jobs", skip(db_pool), err(Debug))]
+pub async fn run_due_jobs(
+    db_pool: &PgPool,
+    now: DateTime<Utc>,
+) -> Result<SchedulerOutcome, SchedulerError> {
+    let mut transaction = db_pool.begin().await?;
+    // The lock is released when the transaction ends
+    let is_locked: bool = sqlx::query("SELECT pg_try_advisory_xact_lock($1)")
+        .bind(SCHEDULER_LOCK_ID)
+        .fetch_one(&mut transaction)
+        .await?
+        .get(0);
+
+    if !is_locked {
+        return Ok(SchedulerOutcome::Locked);
+    }
+
+    let scheduled_issues = enqueue_scheduled_issues(db_pool, &mut transaction, now).await?;
+    let recurring_newsletters =
+        publish_recurring_newsletters(db_pool, &mut transaction, now).await?;
+    let subject_tests = complete_subject_tests(db_pool, &mut transaction, now).await?;
+
+    transaction.commit().await?;
+
+    Ok(SchedulerOutcome::Completed {
+        scheduled_issues,
+        recurring_newsletters,
+        subject_tests,
+    })
+}
+
+async fn enqueue_scheduled_issues(
+    db_pool: &PgPool,
+    transaction: &mut Transaction<'_, Postgres>,
+    now: DateTime<Utc>,
+) -> Result<usize, SchedulerError> {
+    let scheduled_issues = sqlx::query(
+        r#"
+        SELECT
+            scheduled_issues.newsletter_issue_id,
+            scheduled_issues.segment,
+            scheduled_issues.exclude_segments,
+            subject_tests.subjects,
+            subject_tests.sample_percent,
+            subject_tests.window_hours,
+            subject_tests.metric
+        FROM scheduled_issues
+        LEFT JOIN subject_tests ON subject_tests.newsletter_issue_id = scheduled_issues.newsletter_issue_id
+            AND subject_tests.status = 'scheduled'
+        WHERE scheduled_issues.status = 'scheduled' AND scheduled_issues.send_at <= $1
+        ORDER BY scheduled_issues.send_at
+        "#,
+    )
+    .bind(now)
+    .fetch_all(&mut *transaction)
+    .await?;
+    let mut enqueued = 0;
+
+    for row in scheduled_issues {
+        let newsletter_issue_id: Uuid = row.try_get("newsletter_issue_id")?;
+        // A stored value that no longer passes the checks must not stop the other jobs of the run, the issue fails
+        // like one whose segment was deleted
+        let status = match map_scheduled_issue(newsletter_issue_id, &row) {
+            Ok((segment, exclude_segments, subject_test)) => {
+                // Segments are looked up again, they could have been deleted since the issue was scheduled
+                match get_audience(db_pool, segment, &exclude_segments).await {
+                    Ok((segment, excluded_segments)) => {
+                        match subject_test {
+                            Some(subject_test) => {
+                                start_subject_test(
+                                    transaction,
+                                    &newsletter_issue_id,
+                                    &subject_test,
+                                    segment.as_ref(),
+                                    &excluded_segments,
+                                    now,
+                                )
+                                .await?;
+                            }
+                            None => {
+                                enqueue_deliveries(
+                                    transaction,
+                                    &newsletter_issue_id,
+                                    segment.as_ref(),
+                                    &excluded_segments,
+                                    None,
+                                )
+                                .await?;
+                            }
+                        }
+                        enqueued += 1;
+                        "enqueued"
+                    }
+                    Err(err) => {
+                        tracing::error!(
+                            %newsletter_issue_id,
+                            "Failed to send a scheduled issue: {}",
+                            err
+                        );
+                        "failed"
+                    }
+                }
+            }
+            Err(err) => {
+                tracing::error!(
+                    %newsletter_issue_id,
+                    "Skipped a scheduled issue that cannot be parsed: {}",
+                    err
+                );
+                "failed"
+            }
+        };
+
+        sqlx::query(
+            r#"
+            UPDATE scheduled_issues
+            SET status = $2, enqueued_at = now()
+            WHERE newsletter_issue_id = $1
+            "#,
+        )
+        .bind(newsletter_issue_id)
+        .bind(status)
+        .execute(&mut *transaction)
+        .await?;
+
+        if status == "failed" {
+            sqlx::query(
+                r#"
+                UPDATE subject_tests
+                SET status = 'failed'
+                WHERE newsletter_issue_id = $1 AND status = 'scheduled'
+                "#,
+            )
+            .bind(newsletter_issue_id)
+            .execute(&mut *transaction)
+            .await?;
+        }
+    }
+
+    Ok(enqueued)
+}
+
+/// Picks the winner of the subject tests whose window ended, stores the results of every subject and enqueues the
+/// winner for the rest of the audience. Tests of paused issues are paused too, they wait until the issue is resumed.
+async fn complete_subject_tests(
+    db_pool: &PgPool,
+    transaction: &mut Transaction<'_, Postgres>,
+    now: DateTime<Utc>,
+) -> Result<usize, SchedulerError> {
+    let subject_tests = sqlx::query(
+        r#"
+        SELECT newsletter_issue_id, subjects, metric, segment, exclude_segments
+        FROM subject_tests
+        WHERE status = 'running' AND ends_at <= $1
+        ORDER BY ends_at
+        "#,
+    )
+    .bind(now)
+    .fetch_all(&mut *transaction)
+    .await?;
+    let mut completed = 0;
+
+    for row in subject_tests {
+        let newsletter_issue_id: Uuid = row.try_get("newsletter_issue_id")?;
+        let (subjects, metric, segment, exclude_segments) =
+            match map_subject_test(newsletter_issue_id, &row) {
+                Ok(subject_test) => subject_test,
+                // The test fails instead of staying due, its samples were already sent
+                Err(err) => {
+                    tracing::error!(
+                        %newsletter_issue_id,
+                        "Failed a subject test that cannot be parsed: {}",
+                        err
+                    );
+                    sqlx::query(
+                        r#"
+                        UPDATE subject_tests
+                        SET status = 'failed', completed_at = $2
+                        WHERE newsletter_issue_id = $1
+                        "#,
+                    )
+                    .bind(newsletter_issue_id)
+                    .bind(now)
+                    .execute(&mut *transaction)
+                    .await?;
+                    continue;
+                }
+            };
+        let results =
+            get_subject_test_results(&mut *transaction, &newsletter_issue_id, &subjects).await?;
+        let winner = pick_winner(&results, metric);
+        let status = match get_audience(db_pool, segment, &exclude_segments).await {
+            Ok((segment, excluded_segments)) => {
+                enqueue_deliveries(
+                    transaction,
+                    &newsletter_issue_id,
+                    segment.as_ref(),
+                    &excluded_segments,
+                    None,
+                )
+                .await?;
+                completed += 1;
+                "completed"
+            }
+            Err(err) => {
+                tracing::error!(
+                    %newsletter_issue_id,
+                    "Failed to send the winner of a subject test: {}",
+                    err
+                );
+                "failed"
+            }
+        };
+
+        sqlx::query(
+            r#"
+            UPDATE subject_tests
+            SET status = $2, winner = $3, results = $4, completed_at = $5
+            WHERE newsletter_issue_id = $1
+            "#,
+        )
+        .bind(newsletter_issue_id)
+        .bind(status)
+        .bind(winner as i16)
+        .bind(Json(&results))
+        .bind(now)
+        .execute(&mut *transaction)
+        .await?;
+    }
+
+    Ok(completed)
+}
+
+/// Audience of a scheduled issue and the subject test it is sent with
+type ScheduledIssue = (Option<String>, Vec<String>, Option<SubjectTest>);
+
+/// Rows are checked with the same rules as the requests, a value that does not pass them is reported instead of
+/// being trusted
+fn map_scheduled_issue(
+    newsletter_issue_id: Uuid,
+    row: &PgRow,
+) -> Result<ScheduledIssue, SchedulerError> {
+    let subject_test = match row.try_get::<Option<Vec<String>>, _>("subjects")? {
+        Some(subjects) => Some(SubjectTest {
+            subjects,
+            sample_percent: row.try_get::<i16, _>("sample_percent")? as u8,
+            window_hours: row.try_get::<i32, _>("window_hours")? as u32,
+            metric: SubjectTestMetric::parse(row.try_get("metric")?).map_err(corrupt_row(
+                newsletter_issue_id,
+                "subject_tests",
+                "metric",
+            ))?,
+        }),
+        None => None,
+    };
+
+    Ok((
+        row.try_get("segment")?,
+        row.try_get("exclude_segments")?,
+        subject_test,
+    ))
+}
+
+/// Subjects, metric and audience of a running subject test
+type RunningSubjectTest = (Vec<String>, SubjectTestMetric, Option<String>, Vec<String>);
+
+fn map_subject_test(
+    newsletter_issue_id: Uuid,
+    row: &PgRow,
+) -> Result<RunningSubjectTest, SchedulerError> {
+    Ok((
+        row.try_get("subjects")?,
+        SubjectTestMetric::parse(row.try_get("metric")?).map_err(corrupt_row(
+            newsletter_issue_id,
+            "subject_tests",
+            "metric",
+        ))?,
+        row.try_get("segment")?,
+        row.try_get("exclude_segments")?,
+    ))
+}
+
+fn corrupt_row(
+    id: Uuid,
+    table: &'static str,
+    column: &'static str,
+) -> impl FnOnce(String) -> SchedulerError {
+    move |message| SchedulerError::CorruptRow {
+        id,
+        table,
+        column,
+        message,
+    }
+}
+
+fn map_recurring_newsletter(
+    id: Uuid,
+    row: &PgRow,
+) -> Result<(CronSchedule, NewNewsletter), SchedulerError> {
+    let schedule = CronSchedule::parse(row.try_get("cron")?, row.try_get("timezone")?)
+        .map_err(corrupt_row(id, "recurring_newsletters", "cron"))?;
+    let newsletter = row.try_get::<Json<NewNewsletter>, _>("newsletter")?.0;
+
+    Ok((schedule, newsletter))
+}
+
+async fn get_audience(
+    db_pool: &PgPool,
+    segment: Option<String>,
+    exclude_segments: &[String],
+) -> Result<(Option<Segment>, Vec<Segment>), SegmentError> {
+    let segment = match segment {
+        Some(name) => get_segments_by_name(db_pool, &[name]).await?.pop(),
+        None => None,
+    };
+
+    Ok((
+        segment,
+        get_segments_by_name(db_pool, exclude_segments).await?,
+    ))
+}
+
+async fn publish_recurring_newsletters(
+    db_pool: &PgPool,
+    transaction: &mut Transaction<'_, Postgres>,
+    now: DateTime<Utc>,
+) -> Result<usize, sqlx::Error> {
+    let recurring_newsletters = sqlx::query(
+        r#"
+        SELECT id, cron, timezone, newsletter
+        FROM recurring_newsletters
+        WHERE next_run_at <= $1
+        ORDER BY next_run_at
+        "#,
+    )
+    .bind(now)
+    .fetch_all(&mut *transaction)
+    .await?;
+    let mut published = 0;
+
+    for row in recurring_newsletters {
+        let id: Uuid = row.try_get("id")?;
+        // A stored expression that no longer parses must not stop the scheduler, which runs next to the API. The
+        // newsletter stays due and is reported on every run until it is fixed or deleted.
+        let (schedule, newsletter) = match map_recurring_newsletter(id, &row) {
+            Ok(recurring_newsletter) => recurring_newsletter,
+            Err(err) => {
+                tracing::error!(
+                    recurring_newsletter_id = %id,
+                    "Skipped a recurring newsletter that cannot be parsed: {}",
+                    err
+                );
+                continue;
+            }
+        };
+
+        // A run that fails to publish is skipped, the newsletter fires again at its next run
+        let published_newsletter = match prepare_newsletter(db_pool, &newsletter).await {
+            Ok(prepared_newsletter) => {
+                match publish_newsletter(transaction, &newsletter, prepared_newsletter).await {
+                    // The transaction of the run cannot be used after a database error
+                    Err(PublishNewsletterError::DatabaseError(err)) => return Err(err),
+                    published_newsletter => published_newsletter,
+                }
+            }
+            Err(err) => Err(err),
+        };
+
+        match published_newsletter {
+            Ok(published_newsletter) => {
+                tracing::info!(
+                    recurring_newsletter_id = %id,
+                    newsletter_issue_id = %published_newsletter.newsletter_issue.id,
+                    "Published a recurring newsletter."
+                );
+                published += 1;
+            }
+            Err(err) => {
+                tracing::error!(
+                    recurring_newsletter_id = %id,
+                    "Failed to publish a recurring newsletter: {}",
+                    err
+                );
+            }
+        }
+
+        match schedule.next_run_after(&now) {
+            Some(next_run_at) => {
+                sqlx::query(
+                    r#"
+                    UPDATE recurring_newsletters
+                    SET next_run_at = $2, last_run_at = $3
+                    WHERE id = $1
+                    "#,
+                )
+                .bind(id)
+                .bind(next_run_at)
+                .bind(now)
+                .execute(&mut *transaction)
+                .await?;
+            }
+            // The expression never fires again, e.g. it only matches the 30th of February
+            None => {
+                sqlx::query("DELETE FROM recurring_newsletters WHERE id = $1")
+                    .bind(id)
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+        }
+    }
+
+    Ok(published)
+}
+
+#[derive(thiserror::Error)]
+pub enum SchedulerError {
+    /// A stored value that the domain rejects, e.g. a metric edited by hand in the database
+    #[error("The stored {column} of {table} {id} is not valid: {message}")]
+    CorruptRow {
+        id: Uuid,
+        table: &'static str,
+        column: &'static str,
+        message: String,
+    },
+    #[error("Failed to access the scheduled jobs in the database.")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+impl std::fmt::Debug for SchedulerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Caused by:\n\t({})", self)
+    }
+}
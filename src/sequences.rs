@@ -0,0 +1,76 @@
+use chrono::{DateTime, Utc};
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+/// Enrols a newly confirmed subscriber in every sequence and enqueues its steps, each one waiting in the queue until
+/// its day comes. Subscribers already enrolled in a sequence are not enrolled again.
+#[tracing::instrument(name = "Enrol a subscriber in the sequences", skip(transaction))]
+pub async fn enrol_in_sequences(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    enrolled_at: DateTime<Utc>,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        WITH enrolments AS (
+            INSERT INTO sequence_enrolments (sequence_id, subscriber_id, enrolled_at)
+            SELECT id, $1, $2 FROM sequences
+            ON CONFLICT (sequence_id, subscriber_id) DO NOTHING
+            RETURNING sequence_id
+        )
+        INSERT INTO issue_deliveries (id, newsletter_issue_id, subscriber_id, status, created_at, send_at)
+        SELECT
+            gen_random_uuid(),
+            sequence_steps.newsletter_issue_id,
+            $1,
+            'pending',
+            $2,
+            $2 + make_interval(days => sequence_steps.delay_days)
+        FROM sequence_steps
+        JOIN enrolments ON enrolments.sequence_id = sequence_steps.sequence_id
+        ON CONFLICT (newsletter_issue_id, subscriber_id) DO NOTHING
+        "#,
+    )
+    .bind(subscriber_id)
+    .bind(enrolled_at)
+    .execute(transaction)
+    .await?;
+
+    tracing::info!("{} sequence steps enqueued.", result.rows_affected());
+
+    Ok(result.rows_affected())
+}
+
+/// Stops the sequences of a subscriber and cancels the steps not delivered yet
+#[tracing::instrument(name = "Stop the sequences of a subscriber", skip(transaction))]
+pub async fn stop_sequences(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE sequence_enrolments
+        SET stopped_at = now()
+        WHERE subscriber_id = $1 AND stopped_at IS NULL
+        "#,
+    )
+    .bind(subscriber_id)
+    .execute(&mut *transaction)
+    .await?;
+
+    sqlx::query(
+        r#"
+        UPDATE issue_deliveries
+        SET status = 'cancelled'
+        FROM sequence_steps
+        WHERE issue_deliveries.subscriber_id = $1
+            AND issue_deliveries.newsletter_issue_id = sequence_steps.newsletter_issue_id
+            AND issue_deliveries.status = 'pending'
+        "#,
+    )
+    .bind(subscriber_id)
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
@@ -1,61 +1,97 @@
 use actix_web::dev::Server;
 use actix_web::{web, App, HttpServer};
+use secrecy::Secret;
 use sqlx::postgres::PgPoolOptions;
 use sqlx::{PgPool, Pool, Postgres};
 use std::net::TcpListener;
+use std::sync::Arc;
 use tracing_actix_web::TracingLogger;
 
 use crate::config::{DatabaseSettings, Settings};
+use crate::content::email_template::LocalizedEmailTemplates;
 use crate::email_client::EmailClient;
+use crate::engagement_scoring::EngagementScoringJob;
+use crate::pending_subscriptions::PendingSubscriptionsJob;
+use crate::rate_limit::RateLimiter;
+use crate::repository::postgres_subscriber_repository::PostgresSubscriberRepository;
+use crate::repository::subscriber_repository::SubscriberRepository;
 use crate::routes::{
-    handle_confirm_subscription, handle_create_subscription, handle_publish_newsletter,
-    health_check,
+    handle_add_subscriber_tag, handle_cancel_issue, handle_confirm_subscription,
+    handle_create_attribute_definition, handle_create_layout, handle_create_recurring_newsletter,
+    handle_create_segment, handle_create_sequence, handle_create_subscription,
+    handle_delete_layout, handle_delete_recurring_newsletter, handle_delete_sequence,
+    handle_get_archive, handle_get_archived_issue, handle_get_atom_feed,
+    handle_get_attribute_definitions, handle_get_issue_opens, handle_get_issue_stats,
+    handle_get_layouts, handle_get_preferences, handle_get_recurring_newsletters,
+    handle_get_rss_feed, handle_get_segments, handle_get_sequence_enrolments, handle_get_sequences,
+    handle_get_subject_test, handle_get_subscriber_status_history, handle_pause_issue,
+    handle_preview_issue, handle_publish_newsletter, handle_reactivate_subscription,
+    handle_remove_subscriber_tag, handle_resume_issue, handle_sendgrid_events,
+    handle_test_send_issue, handle_track_click, handle_track_open, handle_unsubscribe,
+    handle_unsubscribe_confirmation, handle_update_layout, handle_update_preferences,
+    handle_update_subscriber_attributes, handle_update_subscriber_timezone, health_check,
+    CONFIRMATION_EMAIL_VARIABLES,
 };
+use crate::scheduler::Scheduler;
+use crate::sunset_policy::{SunsetPolicyJob, RE_ENGAGEMENT_EMAIL_VARIABLES};
 
 pub struct Application {
     pub port: u16,
     pub server: Server,
+    pub scheduler: Scheduler,
+    pub pending_subscriptions_job: PendingSubscriptionsJob,
+    pub sunset_policy_job: SunsetPolicyJob,
+    pub engagement_scoring_job: EngagementScoringJob,
 }
 
 pub struct ApplicationBaseUrl(pub String);
 
+pub struct HmacSecret(pub Secret<String>);
+
+pub struct ConfirmationEmailTemplates(pub LocalizedEmailTemplates);
+
 impl Application {
     pub async fn build(config: Settings) -> Result<Self, std::io::Error> {
         let db_pool = PgPoolOptions::new()
             .acquire_timeout(std::time::Duration::from_secs(2))
             .connect_lazy_with(config.get_db_options());
-        let sender_email = config
-            .get_email_client_sender()
-            .expect("Sender email is not valid");
-        let email_client = EmailClient::new(
-            config.get_email_client_base_url(),
-            sender_email,
-            config.get_email_client_api(),
-            None,
-        );
+        let email_client = get_email_client(&config);
         let redis_client = redis::Client::open(config.get_redis_address())
             .expect("Failed to connect redis server.");
 
         let listener =
             TcpListener::bind(config.get_address()).expect("Failed to bind the address.");
         let port = listener.local_addr().unwrap().port();
-        let server = run(
-            listener,
-            db_pool,
-            email_client,
-            redis_client,
-            config.get_app_base_url(),
-        )?;
-
-        Ok(Self { port, server })
+        let scheduler = Scheduler::new(db_pool.clone());
+        let pending_subscriptions_job = PendingSubscriptionsJob::new(db_pool.clone(), &config);
+        let sunset_policy_job = SunsetPolicyJob::new(db_pool.clone(), &config);
+        let engagement_scoring_job = EngagementScoringJob::new(db_pool.clone(), &config);
+        let server = run(listener, db_pool, email_client, redis_client, &config)?;
+
+        Ok(Self {
+            port,
+            server,
+            scheduler,
+            pending_subscriptions_job,
+            sunset_policy_job,
+            engagement_scoring_job,
+        })
     }
 
     pub fn get_port(&self) -> u16 {
         self.port
     }
 
+    /// Runs the API together with the scheduler of the newsletter sends and the background jobs of the subscriptions,
+    /// until any of them stops
     pub async fn run_until_stop(self) -> Result<(), std::io::Error> {
-        self.server.await
+        tokio::select! {
+            outcome = self.server => outcome,
+            outcome = self.scheduler.run_until_stopped() => outcome,
+            outcome = self.pending_subscriptions_job.run_until_stopped() => outcome,
+            outcome = self.sunset_policy_job.run_until_stopped() => outcome,
+            outcome = self.engagement_scoring_job.run_until_stopped() => outcome,
+        }
     }
 }
 
@@ -64,12 +100,25 @@ pub fn run(
     db_pool: PgPool,
     email_client: EmailClient,
     redis_client: redis::Client,
-    base_url: String,
+    config: &Settings,
 ) -> Result<Server, std::io::Error> {
+    let subscriber_repository: Arc<dyn SubscriberRepository> =
+        Arc::new(PostgresSubscriberRepository::new(db_pool.clone()));
+    let subscriber_repository = web::Data::from(subscriber_repository);
     let db_pool = web::Data::new(db_pool);
     let email_client = web::Data::new(email_client);
     let redis_client = web::Data::new(redis_client);
-    let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let base_url = web::Data::new(ApplicationBaseUrl(config.get_app_base_url()));
+    let hmac_secret = web::Data::new(HmacSecret(config.get_hmac_secret()));
+    let confirmation_email_templates = web::Data::new(get_confirmation_email_templates(config));
+    let subscription_confirmation = web::Data::new(config.get_subscription_confirmation());
+    let archive = web::Data::new(config.get_archive());
+    let engagement_scoring = web::Data::new(config.get_engagement_scoring());
+    let sendgrid_webhook_key = web::Data::new(
+        config
+            .get_email_client_webhook_key()
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?,
+    );
 
     let server = HttpServer::new(move || {
         // App is where your application logic lives: routing, middlewares, request handler, etc
@@ -83,11 +132,142 @@ pub fn run(
                 "/subscriptions/confirm",
                 web::get().to(handle_confirm_subscription),
             )
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(handle_unsubscribe_confirmation),
+            )
+            .route(
+                "/subscriptions/unsubscribe",
+                web::post().to(handle_unsubscribe),
+            )
+            .route(
+                "/subscriptions/preferences",
+                web::get().to(handle_get_preferences),
+            )
+            .route(
+                "/subscriptions/preferences",
+                web::post().to(handle_update_preferences),
+            )
+            .route(
+                "/subscriptions/reactivate",
+                web::get().to(handle_reactivate_subscription),
+            )
             .route("/newsletters", web::post().to(handle_publish_newsletter))
+            .route(
+                "/t/o/{delivery_token}.gif",
+                web::get().to(handle_track_open),
+            )
+            .route("/t/c/{token}", web::get().to(handle_track_click))
+            .route("/archive", web::get().to(handle_get_archive))
+            .route("/archive/{slug}", web::get().to(handle_get_archived_issue))
+            .route("/feed.xml", web::get().to(handle_get_rss_feed))
+            .route("/feed.atom", web::get().to(handle_get_atom_feed))
+            .route(
+                "/admin/attributes",
+                web::post().to(handle_create_attribute_definition),
+            )
+            .route(
+                "/admin/attributes",
+                web::get().to(handle_get_attribute_definitions),
+            )
+            .route(
+                "/admin/subscribers/{subscriber_id}/attributes",
+                web::put().to(handle_update_subscriber_attributes),
+            )
+            .route(
+                "/admin/subscribers/{subscriber_id}/timezone",
+                web::put().to(handle_update_subscriber_timezone),
+            )
+            .route(
+                "/admin/subscribers/{subscriber_id}/status_history",
+                web::get().to(handle_get_subscriber_status_history),
+            )
+            .route(
+                "/admin/subscribers/{subscriber_id}/tags",
+                web::post().to(handle_add_subscriber_tag),
+            )
+            .route(
+                "/admin/subscribers/{subscriber_id}/tags/{tag}",
+                web::delete().to(handle_remove_subscriber_tag),
+            )
+            .route("/admin/segments", web::post().to(handle_create_segment))
+            .route("/admin/segments", web::get().to(handle_get_segments))
+            .route("/admin/sequences", web::post().to(handle_create_sequence))
+            .route("/admin/sequences", web::get().to(handle_get_sequences))
+            .route(
+                "/admin/sequences/{sequence_id}",
+                web::delete().to(handle_delete_sequence),
+            )
+            .route(
+                "/admin/sequences/{sequence_id}/enrolments",
+                web::get().to(handle_get_sequence_enrolments),
+            )
+            .route("/admin/layouts", web::post().to(handle_create_layout))
+            .route("/admin/layouts", web::get().to(handle_get_layouts))
+            .route(
+                "/admin/layouts/{layout_id}",
+                web::put().to(handle_update_layout),
+            )
+            .route(
+                "/admin/layouts/{layout_id}",
+                web::delete().to(handle_delete_layout),
+            )
+            .route(
+                "/admin/recurring-newsletters",
+                web::post().to(handle_create_recurring_newsletter),
+            )
+            .route(
+                "/admin/recurring-newsletters",
+                web::get().to(handle_get_recurring_newsletters),
+            )
+            .route(
+                "/admin/recurring-newsletters/{recurring_newsletter_id}",
+                web::delete().to(handle_delete_recurring_newsletter),
+            )
+            .route(
+                "/admin/issues/{newsletter_issue_id}/preview",
+                web::get().to(handle_preview_issue),
+            )
+            .route(
+                "/admin/issues/{newsletter_issue_id}/test-send",
+                web::post().to(handle_test_send_issue),
+            )
+            .route(
+                "/admin/issues/{newsletter_issue_id}/pause",
+                web::post().to(handle_pause_issue),
+            )
+            .route(
+                "/admin/issues/{newsletter_issue_id}/resume",
+                web::post().to(handle_resume_issue),
+            )
+            .route(
+                "/admin/issues/{newsletter_issue_id}/cancel",
+                web::post().to(handle_cancel_issue),
+            )
+            .route(
+                "/admin/issues/{newsletter_issue_id}/subject-test",
+                web::get().to(handle_get_subject_test),
+            )
+            .route(
+                "/admin/issues/{newsletter_issue_id}/opens",
+                web::get().to(handle_get_issue_opens),
+            )
+            .route(
+                "/admin/issues/{newsletter_issue_id}/stats",
+                web::get().to(handle_get_issue_stats),
+            )
+            .route("/webhooks/sendgrid", web::post().to(handle_sendgrid_events))
             .app_data(db_pool.clone())
+            .app_data(subscriber_repository.clone())
             .app_data(email_client.clone())
             .app_data(redis_client.clone())
             .app_data(base_url.clone())
+            .app_data(hmac_secret.clone())
+            .app_data(sendgrid_webhook_key.clone())
+            .app_data(confirmation_email_templates.clone())
+            .app_data(subscription_confirmation.clone())
+            .app_data(archive.clone())
+            .app_data(engagement_scoring.clone())
     })
     .listen(listener)?
     .run();
@@ -95,6 +275,75 @@ pub fn run(
     Ok(server)
 }
 
+pub fn get_email_client(config: &Settings) -> EmailClient {
+    let sender_email = config
+        .get_email_client_sender()
+        .expect("Sender email is not valid");
+
+    let email_client = EmailClient::new(
+        config.get_email_client_base_url(),
+        sender_email,
+        config.get_email_client_api(),
+        None,
+    );
+
+    match config.get_email_client_rate_limit() {
+        Some(rate_limit) => {
+            let redis_client = redis::Client::open(config.get_redis_address())
+                .expect("Failed to connect redis server.");
+
+            email_client.with_rate_limiter(RateLimiter::new(redis_client, &rate_limit))
+        }
+        None => email_client,
+    }
+}
+
+pub fn get_confirmation_email_templates(config: &Settings) -> ConfirmationEmailTemplates {
+    let default_locale = config
+        .get_default_locale()
+        .expect("Default locale is not valid");
+    let templates = LocalizedEmailTemplates::load(
+        &config.get_email_templates_path().join("confirmation_email"),
+        default_locale,
+        &CONFIRMATION_EMAIL_VARIABLES,
+    )
+    .expect("Failed to load the confirmation email templates");
+
+    ConfirmationEmailTemplates(templates)
+}
+
+/// Reminder of the confirmation link, sent to the subscribers who did not confirm
+pub fn get_confirmation_reminder_email_templates(config: &Settings) -> LocalizedEmailTemplates {
+    let default_locale = config
+        .get_default_locale()
+        .expect("Default locale is not valid");
+
+    LocalizedEmailTemplates::load(
+        &config
+            .get_email_templates_path()
+            .join("confirmation_reminder_email"),
+        default_locale,
+        &CONFIRMATION_EMAIL_VARIABLES,
+    )
+    .expect("Failed to load the confirmation reminder email templates")
+}
+
+/// Email sent to the subscribers marked as inactive by the sunset policy
+pub fn get_re_engagement_email_templates(config: &Settings) -> LocalizedEmailTemplates {
+    let default_locale = config
+        .get_default_locale()
+        .expect("Default locale is not valid");
+
+    LocalizedEmailTemplates::load(
+        &config
+            .get_email_templates_path()
+            .join("re_engagement_email"),
+        default_locale,
+        &RE_ENGAGEMENT_EMAIL_VARIABLES,
+    )
+    .expect("Failed to load the re-engagement email templates")
+}
+
 pub fn get_connection_db_pool(config: &DatabaseSettings) -> Pool<Postgres> {
     PgPoolOptions::new()
         .acquire_timeout(std::time::Duration::from_secs(2))
@@ -0,0 +1,464 @@
+use chrono::{DateTime, Utc};
+use minijinja::{context, Value};
+use secrecy::Secret;
+use sqlx::{postgres::PgRow, PgPool, Postgres, Row, Transaction};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::config::{Settings, SunsetPolicySettings};
+use crate::content::email_template::LocalizedEmailTemplates;
+use crate::content::tracking::sign_re_engagement_token;
+use crate::domain::status_history::StatusHistoryAction;
+use crate::email_client::EmailClient;
+use crate::repository::postgres_subscriber_repository::parse_subscriber;
+use crate::repository::subscriber_repository::SubscriberRepositoryError;
+use crate::sequences::stop_sequences;
+use crate::startup::{get_email_client, get_re_engagement_email_templates};
+
+pub const RE_ENGAGEMENT_EMAIL_VARIABLES: [&str; 3] =
+    ["subscriber.name", "subscriber.email", "reactivation_link"];
+
+/// Segment created with the policy, so the inactive subscribers can be excluded from sends or targeted
+pub const INACTIVE_SEGMENT: &str = "inactive";
+
+const POLL_INTERVAL: Duration = Duration::from_secs(3600);
+/// Subscribers marked as inactive in each query, so a backlog does not hold a long transaction
+const BATCH_SIZE: i64 = 100;
+
+/// Actions of a run of the policy, also logged so they can be graphed
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SunsetPolicyOutcome {
+    pub marked_inactive: usize,
+    pub re_engagements_sent: usize,
+    pub re_engagements_failed: usize,
+    pub reactivated: usize,
+    pub unsubscribed: usize,
+}
+
+/// Applies the sunset policy to the confirmed subscribers: the ones who stopped engaging are marked as inactive, which
+/// puts them in the `inactive` segment, and sent a re-engagement email. Those who open, click or follow its link are
+/// reactivated, the rest are unsubscribed once the grace period ends. Every action is added to the status history.
+pub struct SunsetPolicyJob {
+    db_pool: PgPool,
+    email_client: EmailClient,
+    re_engagement_email_templates: LocalizedEmailTemplates,
+    base_url: String,
+    hmac_secret: Secret<String>,
+    settings: SunsetPolicySettings,
+}
+
+impl SunsetPolicyJob {
+    pub fn new(db_pool: PgPool, config: &Settings) -> Self {
+        Self {
+            db_pool,
+            email_client: get_email_client(config),
+            re_engagement_email_templates: get_re_engagement_email_templates(config),
+            base_url: config.get_app_base_url(),
+            hmac_secret: config.get_hmac_secret(),
+            settings: config.get_sunset_policy(),
+        }
+    }
+
+    pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+
+            // Errors are retried in the next run, the subscribers stay due until they are handled
+            let _ = self.run(Utc::now()).await;
+        }
+    }
+
+    /// Reactivates the inactive subscribers who engaged, unsubscribes the ones whose grace period ended at `now` and
+    /// marks the subscribers who stopped engaging as inactive
+    #[tracing::instrument(name = "Run the sunset policy", skip(self), err(Debug))]
+    pub async fn run(&self, now: DateTime<Utc>) -> Result<SunsetPolicyOutcome, sqlx::Error> {
+        let mut outcome = SunsetPolicyOutcome::default();
+
+        if !self.settings.is_enabled() {
+            return Ok(outcome);
+        }
+
+        create_inactive_segment(&self.db_pool, now).await?;
+
+        outcome.reactivated = reactivate_engaged_subscribers(&self.db_pool, now).await?;
+        outcome.unsubscribed = self
+            .unsubscribe_unresponsive_subscribers(now - self.settings.get_grace_period(), now)
+            .await?;
+        outcome.marked_inactive = self.mark_inactive_subscribers(now).await?;
+        self.send_re_engagement_emails(now, &mut outcome).await?;
+
+        tracing::info!(
+            subscribers_marked_inactive = outcome.marked_inactive,
+            re_engagements_sent = outcome.re_engagements_sent,
+            re_engagements_failed = outcome.re_engagements_failed,
+            subscribers_reactivated = outcome.reactivated,
+            subscribers_unsubscribed = outcome.unsubscribed,
+            "Sunset policy completed."
+        );
+
+        Ok(outcome)
+    }
+
+    /// The grace period starts when the re-engagement email is sent, subscribers who never received it are kept
+    async fn unsubscribe_unresponsive_subscribers(
+        &self,
+        re_engaged_before: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<usize, sqlx::Error> {
+        let mut transaction = self.db_pool.begin().await?;
+        let subscriber_ids: Vec<Uuid> = sqlx::query_scalar(
+            r#"
+            WITH unsubscribed AS (
+                UPDATE subscriptions
+                SET status = 'unsubscribed', inactive_since = NULL, re_engagement_sent_at = NULL
+                WHERE id IN (
+                    SELECT id FROM subscriptions
+                    WHERE status = 'confirmed'
+                        AND inactive_since IS NOT NULL
+                        AND re_engagement_sent_at <= $2
+                    FOR UPDATE SKIP LOCKED
+                )
+                RETURNING id
+            ), history AS (
+                INSERT INTO subscriber_status_history (subscriber_id, action, occurred_at)
+                SELECT id, $3, $1 FROM unsubscribed
+            )
+            SELECT id FROM unsubscribed
+            "#,
+        )
+        .bind(now)
+        .bind(re_engaged_before)
+        .bind(StatusHistoryAction::Unsubscribed.as_ref())
+        .fetch_all(&mut transaction)
+        .await?;
+
+        for subscriber_id in subscriber_ids.iter() {
+            stop_sequences(&mut transaction, *subscriber_id).await?;
+        }
+
+        transaction.commit().await?;
+
+        Ok(subscriber_ids.len())
+    }
+
+    /// Subscribers are inactive when none of their last tracked sends was opened or clicked, or when they did not
+    /// open or click anything in the last months although tracked issues were sent to them. Only the sends since they
+    /// were last reactivated are taken into account. Returns the number of subscribers marked.
+    async fn mark_inactive_subscribers(&self, now: DateTime<Utc>) -> Result<usize, sqlx::Error> {
+        let mut marked_inactive = 0;
+
+        loop {
+            let result = sqlx::query(
+                r#"
+                WITH marked AS (
+                    UPDATE subscriptions
+                    SET inactive_since = $1
+                    WHERE id IN (
+                        SELECT subscriptions.id
+                        FROM subscriptions
+                        LEFT JOIN LATERAL (
+                            SELECT MAX(engaged_at) AS engaged_at
+                            FROM (
+                                SELECT issue_opens.opened_at AS engaged_at
+                                FROM issue_deliveries
+                                JOIN issue_opens ON issue_opens.issue_delivery_id = issue_deliveries.id
+                                WHERE issue_deliveries.subscriber_id = subscriptions.id
+                                UNION ALL
+                                SELECT issue_clicks.clicked_at
+                                FROM issue_deliveries
+                                JOIN issue_clicks ON issue_clicks.issue_delivery_id = issue_deliveries.id
+                                WHERE issue_deliveries.subscriber_id = subscriptions.id
+                            ) AS engagements
+                        ) AS last_engagement ON TRUE
+                        LEFT JOIN LATERAL (
+                            SELECT issue_deliveries.sent_at
+                            FROM issue_deliveries
+                            JOIN newsletter_issues ON newsletter_issues.id = issue_deliveries.newsletter_issue_id
+                            WHERE issue_deliveries.subscriber_id = subscriptions.id
+                                AND issue_deliveries.status = 'sent'
+                                AND (newsletter_issues.track_opens OR newsletter_issues.track_clicks)
+                                AND issue_deliveries.sent_at > COALESCE(subscriptions.reactivated_at, '-infinity')
+                            ORDER BY issue_deliveries.sent_at DESC
+                            OFFSET $2::INTEGER - 1
+                            LIMIT 1
+                        ) AS oldest_recent_send ON TRUE
+                        LEFT JOIN LATERAL (
+                            SELECT MAX(issue_deliveries.sent_at) AS sent_at
+                            FROM issue_deliveries
+                            JOIN newsletter_issues ON newsletter_issues.id = issue_deliveries.newsletter_issue_id
+                            WHERE issue_deliveries.subscriber_id = subscriptions.id
+                                AND issue_deliveries.status = 'sent'
+                                AND (newsletter_issues.track_opens OR newsletter_issues.track_clicks)
+                        ) AS last_send ON TRUE
+                        WHERE subscriptions.status = 'confirmed'
+                            AND subscriptions.inactive_since IS NULL
+                            AND (
+                                (
+                                    $2::INTEGER IS NOT NULL
+                                    AND oldest_recent_send.sent_at IS NOT NULL
+                                    AND COALESCE(last_engagement.engaged_at < oldest_recent_send.sent_at, TRUE)
+                                )
+                                OR (
+                                    $3::INTEGER IS NOT NULL
+                                    AND COALESCE(subscriptions.reactivated_at, subscriptions.subscribed_at)
+                                        <= $1 - make_interval(months => $3::INTEGER)
+                                    AND last_send.sent_at > $1 - make_interval(months => $3::INTEGER)
+                                    AND COALESCE(
+                                        last_engagement.engaged_at <= $1 - make_interval(months => $3::INTEGER),
+                                        TRUE
+                                    )
+                                )
+                            )
+                        FOR UPDATE OF subscriptions SKIP LOCKED
+                        LIMIT $4
+                    )
+                    RETURNING id
+                )
+                INSERT INTO subscriber_status_history (subscriber_id, action, occurred_at)
+                SELECT id, $5, $1 FROM marked
+                "#,
+            )
+            .bind(now)
+            .bind(self.settings.inactive_after_sends.map(|sends| sends.get() as i32))
+            .bind(self.settings.inactive_after_months.map(|months| months.get() as i32))
+            .bind(BATCH_SIZE)
+            .bind(StatusHistoryAction::MarkedInactive.as_ref())
+            .execute(&self.db_pool)
+            .await?;
+
+            if result.rows_affected() == 0 {
+                return Ok(marked_inactive);
+            }
+
+            marked_inactive += result.rows_affected() as usize;
+        }
+    }
+
+    /// Sends the re-engagement email to the inactive subscribers who did not receive it yet. Each subscriber is locked
+    /// while its email is sent, so concurrent runs do not send it twice, and the email is marked as sent only once it
+    /// was accepted. Failed sends are retried in the next run.
+    async fn send_re_engagement_emails(
+        &self,
+        now: DateTime<Utc>,
+        outcome: &mut SunsetPolicyOutcome,
+    ) -> Result<(), sqlx::Error> {
+        let mut failed_ids: Vec<Uuid> = vec![];
+
+        loop {
+            let mut transaction = self.db_pool.begin().await?;
+            let row = sqlx::query(
+                r#"
+                SELECT id, email, name, subscribed_at, status, attributes, locale, timezone, engagement_score, list
+                FROM subscriptions
+                WHERE status = 'confirmed'
+                    AND inactive_since IS NOT NULL
+                    AND re_engagement_sent_at IS NULL
+                    AND id <> ALL($1)
+                FOR UPDATE SKIP LOCKED
+                LIMIT 1
+                "#,
+            )
+            .bind(&failed_ids)
+            .fetch_optional(&mut transaction)
+            .await?;
+            let Some(row) = row else {
+                return Ok(());
+            };
+            let subscriber_id: Uuid = row.get("id");
+
+            match self.send_re_engagement_email(row).await {
+                Ok(()) => {
+                    sqlx::query(
+                        "UPDATE subscriptions SET re_engagement_sent_at = $2 WHERE id = $1",
+                    )
+                    .bind(subscriber_id)
+                    .bind(now)
+                    .execute(&mut transaction)
+                    .await?;
+                    record_status_history(
+                        &mut transaction,
+                        subscriber_id,
+                        StatusHistoryAction::ReEngagementSent,
+                        now,
+                    )
+                    .await?;
+                    transaction.commit().await?;
+                    outcome.re_engagements_sent += 1;
+                }
+                Err(err) => {
+                    tracing::error!("Failed to send a re-engagement email: {:?}.", err);
+                    transaction.rollback().await?;
+                    failed_ids.push(subscriber_id);
+                    outcome.re_engagements_failed += 1;
+                }
+            }
+        }
+    }
+
+    async fn send_re_engagement_email(&self, row: PgRow) -> Result<(), ReEngagementError> {
+        let subscriber = parse_subscriber(row)?;
+        let reactivation_link = format!(
+            "{}/subscriptions/reactivate?token={}",
+            self.base_url,
+            sign_re_engagement_token(&self.hmac_secret, &subscriber.id)
+        );
+        let email = self
+            .re_engagement_email_templates
+            .render(
+                &subscriber.locale,
+                context! {
+                    subscriber => context! {
+                        name => subscriber.name.as_ref(),
+                        email => subscriber.email.as_ref(),
+                    },
+                    reactivation_link => Value::from_safe_string(reactivation_link),
+                },
+            )
+            .map_err(ReEngagementError::TemplateError)?;
+
+        self.email_client
+            .send_email(
+                subscriber.email.clone(),
+                &email.subject,
+                &email.html,
+                &email.text,
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Admins can change the filter of the segment, it is only created if missing
+async fn create_inactive_segment(db_pool: &PgPool, now: DateTime<Utc>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO segments (id, name, filter, created_at)
+        VALUES ($1, $2, 'inactive', $3)
+        ON CONFLICT (name) DO NOTHING
+        "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(INACTIVE_SEGMENT)
+    .bind(now)
+    .execute(db_pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Inactive subscribers who opened or clicked anything since they were marked are kept subscribed
+async fn reactivate_engaged_subscribers(
+    db_pool: &PgPool,
+    now: DateTime<Utc>,
+) -> Result<usize, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        WITH reactivated AS (
+            UPDATE subscriptions
+            SET inactive_since = NULL, re_engagement_sent_at = NULL, reactivated_at = $1
+            WHERE id IN (
+                SELECT id FROM subscriptions
+                WHERE status = 'confirmed'
+                    AND inactive_since IS NOT NULL
+                    AND (
+                        EXISTS (
+                            SELECT 1 FROM issue_deliveries
+                            JOIN issue_opens ON issue_opens.issue_delivery_id = issue_deliveries.id
+                            WHERE issue_deliveries.subscriber_id = subscriptions.id
+                                AND issue_opens.opened_at >= subscriptions.inactive_since
+                        )
+                        OR EXISTS (
+                            SELECT 1 FROM issue_deliveries
+                            JOIN issue_clicks ON issue_clicks.issue_delivery_id = issue_deliveries.id
+                            WHERE issue_deliveries.subscriber_id = subscriptions.id
+                                AND issue_clicks.clicked_at >= subscriptions.inactive_since
+                        )
+                    )
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id
+        )
+        INSERT INTO subscriber_status_history (subscriber_id, action, occurred_at)
+        SELECT id, $2, $1 FROM reactivated
+        "#,
+    )
+    .bind(now)
+    .bind(StatusHistoryAction::Reactivated.as_ref())
+    .execute(db_pool)
+    .await?;
+
+    Ok(result.rows_affected() as usize)
+}
+
+/// Keeps an inactive subscriber who followed the link of the re-engagement email. Returns false when the subscriber
+/// is not inactive, e.g. because the grace period already ended.
+#[tracing::instrument(name = "Reactivate an inactive subscriber", skip(transaction))]
+pub async fn reactivate_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    now: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        UPDATE subscriptions
+        SET inactive_since = NULL, re_engagement_sent_at = NULL, reactivated_at = $2
+        WHERE id = $1 AND status = 'confirmed' AND inactive_since IS NOT NULL
+        "#,
+    )
+    .bind(subscriber_id)
+    .bind(now)
+    .execute(&mut *transaction)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    record_status_history(
+        &mut *transaction,
+        subscriber_id,
+        StatusHistoryAction::Reactivated,
+        now,
+    )
+    .await?;
+
+    Ok(true)
+}
+
+async fn record_status_history(
+    executor: impl sqlx::PgExecutor<'_>,
+    subscriber_id: Uuid,
+    action: StatusHistoryAction,
+    occurred_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO subscriber_status_history (subscriber_id, action, occurred_at)
+        VALUES ($1, $2, $3)
+        "#,
+    )
+    .bind(subscriber_id)
+    .bind(action.as_ref())
+    .bind(occurred_at)
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+#[derive(thiserror::Error)]
+enum ReEngagementError {
+    #[error("Failed to parse the inactive subscriber.")]
+    CorruptRow(#[from] SubscriberRepositoryError),
+    #[error("Failed to render the re-engagement email: {0}")]
+    TemplateError(String),
+    #[error("Failed to send the re-engagement email.")]
+    SendEmailError(#[from] reqwest::Error),
+}
+
+impl std::fmt::Debug for ReEngagementError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Caused by:\n\t({})", self)
+    }
+}
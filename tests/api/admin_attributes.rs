@@ -0,0 +1,184 @@
+use sqlx::{types::Json, Row};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::TestApp;
+use email_newsletter::domain::subscriber_attributes::AttributesMap;
+
+#[tokio::test]
+async fn attribute_definitions_are_registered() {
+    let test_app = TestApp::spawn_app().await;
+
+    let response = test_app
+        .post_attribute_definition(serde_json::json!({ "key": "company", "type": "text" }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/admin/attributes", test_app.address))
+        .send()
+        .await
+        .unwrap();
+    let definitions: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(definitions[0]["key"], "company");
+    assert_eq!(definitions[0]["type"], "text");
+}
+
+#[tokio::test]
+async fn attribute_definitions_cannot_be_registered_twice() {
+    let test_app = TestApp::spawn_app().await;
+    let body = serde_json::json!({ "key": "company", "type": "text" });
+
+    test_app.post_attribute_definition(body.clone()).await;
+    let response = test_app.post_attribute_definition(body).await;
+
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn attribute_definitions_returns_400_when_body_is_invalid() {
+    let test_app = TestApp::spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({ "key": "Company", "type": "text" }),
+            "invalid key",
+        ),
+        (
+            serde_json::json!({ "key": "company", "type": "list" }),
+            "invalid type",
+        ),
+    ];
+
+    for (invalid_body, error_message) in test_cases {
+        let response = test_app.post_attribute_definition(invalid_body).await;
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 status when payload was {}",
+            error_message
+        );
+    }
+}
+
+#[tokio::test]
+async fn subscribe_persists_the_registered_attributes() {
+    let test_app = TestApp::spawn_app().await;
+
+    test_app
+        .post_attribute_definition(serde_json::json!({ "key": "country", "type": "text" }))
+        .await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_subscription(serde_json::json!({
+            "name": "Frank",
+            "email": "frank@test.com",
+            "attributes": { "country": "ES" }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let attributes: Json<AttributesMap> = sqlx::query("SELECT attributes FROM subscriptions;")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.")
+        .get("attributes");
+
+    assert_eq!(attributes.0["country"], "ES");
+}
+
+#[tokio::test]
+async fn subscribe_returns_400_when_attributes_do_not_match_the_schema() {
+    let test_app = TestApp::spawn_app().await;
+
+    test_app
+        .post_attribute_definition(serde_json::json!({ "key": "employees", "type": "number" }))
+        .await;
+
+    let test_cases = vec![
+        (
+            serde_json::json!({ "plan": "pro" }),
+            "unregistered attribute",
+        ),
+        (
+            serde_json::json!({ "employees": "many" }),
+            "attribute with the wrong type",
+        ),
+    ];
+
+    for (attributes, error_message) in test_cases {
+        let response = test_app
+            .post_subscription(serde_json::json!({
+                "name": "Frank",
+                "email": "frank@test.com",
+                "attributes": attributes
+            }))
+            .await;
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 status when payload had an {}",
+            error_message
+        );
+    }
+}
+
+#[tokio::test]
+async fn subscriber_attributes_are_replaced_by_admin() {
+    let test_app = TestApp::spawn_app().await;
+
+    test_app
+        .post_attribute_definition(serde_json::json!({ "key": "plan", "type": "text" }))
+        .await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app
+        .post_subscription(serde_json::json!({ "name": "Frank", "email": "frank@test.com" }))
+        .await;
+
+    let subscriber_id: Uuid = sqlx::query("SELECT id FROM subscriptions;")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .get("id");
+
+    let response = test_app
+        .put_subscriber_attributes(
+            &subscriber_id,
+            serde_json::json!({ "attributes": { "plan": "pro" } }),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let subscriber: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(subscriber["attributes"]["plan"], "pro");
+}
+
+#[tokio::test]
+async fn subscriber_attributes_returns_404_when_subscriber_does_not_exist() {
+    let test_app = TestApp::spawn_app().await;
+
+    let response = test_app
+        .put_subscriber_attributes(&Uuid::new_v4(), serde_json::json!({ "attributes": {} }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
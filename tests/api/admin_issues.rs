@@ -0,0 +1,569 @@
+use email_newsletter::content::tracking::sign_unsubscribe_token;
+use email_newsletter::email_client::SendEmailBody;
+use email_newsletter::issue_delivery_worker::try_execute_task;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::TestApp;
+
+#[tokio::test]
+async fn issue_stats_aggregate_deliveries_tracking_and_provider_events() {
+    let test_app = TestApp::spawn_app().await;
+
+    test_app.create_confirmed_subscriber("frank@test.com").await;
+    test_app.create_confirmed_subscriber("tom@test.com").await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "content": {
+            "html": r#"<p><a href="https://blog.test.com">Blog</a> <a href="{{ unsubscribe_url }}">Unsubscribe</a></p>"#
+          }
+        }))
+        .await;
+    let newsletter_issue: serde_json::Value = response.json().await.unwrap();
+
+    test_app.dispatch_all_pending_emails().await;
+
+    // Emails sent to each subscriber, identified by the delivery id attached for the provider events
+    let received_requests = test_app.email_server.received_requests().await.unwrap();
+    let emails: Vec<(Uuid, String)> = received_requests
+        .iter()
+        .rev()
+        .take(2)
+        .map(|request| {
+            let body: SendEmailBody = request.body_json().unwrap();
+            let delivery_id = &body.personalizations[0].custom_args["delivery_id"];
+
+            (
+                Uuid::parse_str(delivery_id).unwrap(),
+                body.content[1].value.clone(),
+            )
+        })
+        .collect();
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    let (opener_id, opener_html) = &emails[0];
+    let (unsubscriber_id, unsubscriber_html) = &emails[1];
+
+    for _ in 0..2 {
+        client
+            .get(format!("{}/t/o/{}.gif", test_app.address, opener_id))
+            .send()
+            .await
+            .unwrap();
+    }
+
+    let link_start = opener_html.find("/t/c/").unwrap();
+    let link_end = link_start + opener_html[link_start..].find('"').unwrap();
+
+    client
+        .get(format!(
+            "{}{}",
+            test_app.address,
+            &opener_html[link_start..link_end]
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    let unsubscribe_token = sign_unsubscribe_token(&test_app.hmac_secret, unsubscriber_id);
+
+    assert!(unsubscriber_html.contains(&unsubscribe_token));
+
+    client
+        .post(format!(
+            "{}/subscriptions/unsubscribe?token={}",
+            test_app.address, unsubscribe_token
+        ))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Events of other emails and untracked event types are ignored, and repeated events are counted once
+    let response = test_app
+        .post_sendgrid_events(serde_json::json!([
+            { "event": "delivered", "timestamp": 1760000000, "sg_event_id": "1", "delivery_id": opener_id },
+            { "event": "spamreport", "timestamp": 1760000000, "sg_event_id": "2", "delivery_id": unsubscriber_id },
+            { "event": "spamreport", "timestamp": 1760000000, "sg_event_id": "2", "delivery_id": unsubscriber_id },
+            { "event": "processed", "timestamp": 1760000000, "sg_event_id": "3", "delivery_id": opener_id },
+            { "event": "bounce", "timestamp": 1760000000, "sg_event_id": "4" },
+            { "event": "bounce", "timestamp": 1760000000, "sg_event_id": "5", "delivery_id": "not-an-id" }
+        ]))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let stats: serde_json::Value = client
+        .get(format!(
+            "{}/admin/issues/{}/stats",
+            test_app.address,
+            newsletter_issue["id"].as_str().unwrap()
+        ))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(stats["recipients"], 2);
+    assert_eq!(stats["sent"], 2);
+    assert_eq!(stats["delivered"], 1);
+    assert_eq!(stats["bounced"], 0);
+    assert_eq!(stats["opened"], 1);
+    assert_eq!(stats["opens"], 2);
+    assert_eq!(stats["clicked"], 1);
+    assert_eq!(stats["clicks"], 1);
+    assert_eq!(stats["unsubscribed"], 1);
+    assert_eq!(stats["complained"], 1);
+    assert_eq!(stats["rates"]["opened"], 0.5);
+    assert_eq!(stats["rates"]["click_to_open"], 1.0);
+    assert_eq!(
+        stats["top_links"],
+        serde_json::json!([{ "url": "https://blog.test.com", "clicks": 1, "unique_clicks": 1 }])
+    );
+    assert_eq!(stats["timeline"].as_array().unwrap().len(), 1);
+    assert_eq!(stats["timeline"][0]["opens"], 2);
+    assert_eq!(stats["timeline"][0]["clicks"], 1);
+}
+
+#[tokio::test]
+async fn issue_stats_returns_404_when_issue_does_not_exist() {
+    let test_app = TestApp::spawn_app().await;
+
+    let response = reqwest::get(format!(
+        "{}/admin/issues/{}/stats",
+        test_app.address,
+        Uuid::new_v4()
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn issue_preview_is_rendered_for_the_subscriber() {
+    let test_app = TestApp::spawn_app().await;
+    let subscriber_id = test_app.create_confirmed_subscriber("frank@test.com").await;
+
+    let response = test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "content": {
+            "html": r#"<p>Hi {{ subscriber.name }}</p><a href="https://blog.test.com">Blog</a><a href="{{ unsubscribe_url }}">Unsubscribe</a>"#
+          }
+        }))
+        .await;
+    let newsletter_issue: serde_json::Value = response.json().await.unwrap();
+    let delivery_id: Uuid = sqlx::query_scalar("SELECT id FROM issue_deliveries")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    let response = reqwest::get(format!(
+        "{}/admin/issues/{}/preview?subscriber_id={}",
+        test_app.address,
+        newsletter_issue["id"].as_str().unwrap(),
+        subscriber_id
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["content-type"],
+        "text/html; charset=utf-8"
+    );
+
+    let html = response.text().await.unwrap();
+
+    assert!(html.contains("Hi Frank"));
+    assert!(html.contains("/subscriptions/unsubscribe?token=preview"));
+    assert!(!html.contains(&sign_unsubscribe_token(&test_app.hmac_secret, &delivery_id)));
+    assert!(!html.contains("/t/o/"));
+    assert!(html.contains(r#"href="https://blog.test.com""#));
+    assert!(!html.contains("/t/c/"));
+
+    // Following the unsubscribe link of a preview does not unsubscribe anyone
+    let response = reqwest::Client::new()
+        .post(format!(
+            "{}/subscriptions/unsubscribe?token=preview",
+            test_app.address
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    let status: String = sqlx::query_scalar("SELECT status FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    assert_eq!(status, "confirmed");
+}
+
+#[tokio::test]
+async fn issue_preview_returns_404_when_issue_or_subscriber_does_not_exist() {
+    let test_app = TestApp::spawn_app().await;
+    let subscriber_id = test_app.create_confirmed_subscriber("frank@test.com").await;
+    let response = test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "send_at": "2100-01-01T00:00:00Z",
+          "content": { "html": "<p>Newsletter content</p>" }
+        }))
+        .await;
+    let newsletter_issue: serde_json::Value = response.json().await.unwrap();
+    let newsletter_issue_id = Uuid::parse_str(newsletter_issue["id"].as_str().unwrap()).unwrap();
+    let test_cases = vec![
+        (Uuid::new_v4(), subscriber_id, "issue does not exist"),
+        (
+            newsletter_issue_id,
+            Uuid::new_v4(),
+            "subscriber does not exist",
+        ),
+    ];
+
+    for (newsletter_issue_id, subscriber_id, error_message) in test_cases {
+        let response = reqwest::get(format!(
+            "{}/admin/issues/{}/preview?subscriber_id={}",
+            test_app.address, newsletter_issue_id, subscriber_id
+        ))
+        .await
+        .unwrap();
+
+        assert_eq!(
+            404,
+            response.status().as_u16(),
+            "The API did not fail with 404 status when the {}",
+            error_message
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_emails_are_sent_to_the_given_addresses() {
+    let test_app = TestApp::spawn_app().await;
+    let subscriber_id = test_app.create_confirmed_subscriber("frank@test.com").await;
+    let response = test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "send_at": "2100-01-01T00:00:00Z",
+          "content": {
+            "html": r#"<p>Hi {{ subscriber.name }}</p><a href="https://blog.test.com">Blog</a>"#
+          }
+        }))
+        .await;
+    let newsletter_issue: serde_json::Value = response.json().await.unwrap();
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = reqwest::Client::new()
+        .post(format!(
+            "{}/admin/issues/{}/test-send",
+            test_app.address,
+            newsletter_issue["id"].as_str().unwrap()
+        ))
+        .json(&serde_json::json!({
+            "emails": ["editor@test.com", "reviewer@test.com"],
+            "subscriber_id": subscriber_id
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let received_requests = test_app.email_server.received_requests().await.unwrap();
+    let recipients: Vec<String> = received_requests
+        .iter()
+        .rev()
+        .take(2)
+        .map(|request| {
+            let body: SendEmailBody = request.body_json().unwrap();
+
+            assert_eq!(body.subject, "[TEST] Newsletter title");
+            assert!(body.content[1].value.contains("Hi Frank"));
+            assert!(body.content[1].value.contains("https://blog.test.com"));
+            assert!(!body.content[1].value.contains("/t/"));
+
+            body.personalizations[0].to[0].email.clone()
+        })
+        .collect();
+
+    assert_eq!(recipients, vec!["reviewer@test.com", "editor@test.com"]);
+
+    // Test emails are not deliveries of the issue
+    let deliveries: i64 = sqlx::query_scalar("SELECT count(*) FROM issue_deliveries")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    assert_eq!(deliveries, 0);
+}
+
+#[tokio::test]
+async fn test_send_returns_400_when_emails_are_invalid() {
+    let test_app = TestApp::spawn_app().await;
+    let response = test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "content": { "html": "<p>Newsletter content</p>" }
+        }))
+        .await;
+    let newsletter_issue: serde_json::Value = response.json().await.unwrap();
+    let test_cases = vec![
+        (serde_json::json!({ "emails": [] }), "no addresses"),
+        (
+            serde_json::json!({ "emails": ["not-an-email"] }),
+            "an invalid address",
+        ),
+        (
+            serde_json::json!({ "emails": vec!["editor@test.com"; 11] }),
+            "too many addresses",
+        ),
+    ];
+
+    for (body, error_message) in test_cases {
+        let response = reqwest::Client::new()
+            .post(format!(
+                "{}/admin/issues/{}/test-send",
+                test_app.address,
+                newsletter_issue["id"].as_str().unwrap()
+            ))
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 status when the test email had {}",
+            error_message
+        );
+    }
+}
+
+#[tokio::test]
+async fn paused_deliveries_are_sent_once_resumed() {
+    let test_app = TestApp::spawn_app().await;
+
+    test_app.create_confirmed_subscriber("frank@test.com").await;
+    test_app.create_confirmed_subscriber("tom@test.com").await;
+
+    let response = test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "content": { "html": "<p>Newsletter content</p>" }
+        }))
+        .await;
+    let newsletter_issue: serde_json::Value = response.json().await.unwrap();
+    let newsletter_issue_id = newsletter_issue["id"].as_str().unwrap();
+
+    let response = test_app
+        .post_issue_delivery_change(newsletter_issue_id, "pause")
+        .await;
+    let counts: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(counts["paused"], 2);
+    assert_eq!(counts["pending"], 0);
+
+    {
+        let _mock_guard = Mock::given(path("/mail/send"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount_as_scoped(&test_app.email_server)
+            .await;
+
+        test_app.dispatch_all_pending_emails().await;
+    }
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_issue_delivery_change(newsletter_issue_id, "resume")
+        .await;
+    let counts: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(counts["pending"], 2);
+
+    test_app.dispatch_all_pending_emails().await;
+
+    let response = test_app
+        .post_issue_delivery_change(newsletter_issue_id, "resume")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn cancelled_deliveries_report_the_emails_sent_before_the_stop() {
+    let test_app = TestApp::spawn_app().await;
+
+    test_app.create_confirmed_subscriber("frank@test.com").await;
+    test_app.create_confirmed_subscriber("tom@test.com").await;
+    test_app.create_confirmed_subscriber("jerry@test.com").await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "content": { "html": "<p>Newsletter content</p>" }
+        }))
+        .await;
+    let newsletter_issue: serde_json::Value = response.json().await.unwrap();
+    let newsletter_issue_id = newsletter_issue["id"].as_str().unwrap();
+
+    // Another worker holds two of the deliveries, so this one only sends the third one before the stop
+    let mut other_worker = test_app.db_pool.begin().await.unwrap();
+
+    sqlx::query("SELECT id FROM issue_deliveries LIMIT 2 FOR UPDATE")
+        .execute(&mut other_worker)
+        .await
+        .unwrap();
+    try_execute_task(
+        &test_app.db_pool,
+        &test_app.email_client,
+        &test_app.base_url,
+        &test_app.hmac_secret,
+    )
+    .await
+    .unwrap();
+    other_worker.rollback().await.unwrap();
+    test_app
+        .post_issue_delivery_change(newsletter_issue_id, "pause")
+        .await;
+
+    let response = test_app
+        .post_issue_delivery_change(newsletter_issue_id, "cancel")
+        .await;
+    let counts: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(counts["recipients"], 3);
+    assert_eq!(counts["sent"], 1);
+    assert_eq!(counts["cancelled"], 2);
+
+    test_app.dispatch_all_pending_emails().await;
+
+    let stats: serde_json::Value = reqwest::get(format!(
+        "{}/admin/issues/{}/stats",
+        test_app.address, newsletter_issue_id
+    ))
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+
+    assert_eq!(stats["sent"], 1);
+    assert_eq!(stats["cancelled"], 2);
+
+    for change in ["pause", "resume", "cancel"] {
+        let response = test_app
+            .post_issue_delivery_change(newsletter_issue_id, change)
+            .await;
+
+        assert_eq!(
+            409,
+            response.status().as_u16(),
+            "The API did not fail with 409 status when trying to {} a cancelled issue",
+            change
+        );
+    }
+}
+
+#[tokio::test]
+async fn paused_scheduled_issues_are_not_enqueued() {
+    let test_app = TestApp::spawn_app().await;
+    let send_at = chrono::Utc::now() + chrono::Duration::hours(1);
+
+    test_app.create_confirmed_subscriber("frank@test.com").await;
+
+    let response = test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "send_at": send_at,
+          "content": { "html": "<p>Newsletter content</p>" }
+        }))
+        .await;
+    let newsletter_issue: serde_json::Value = response.json().await.unwrap();
+    let newsletter_issue_id = newsletter_issue["id"].as_str().unwrap();
+
+    let response = test_app
+        .post_issue_delivery_change(newsletter_issue_id, "pause")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    test_app.run_scheduler(send_at).await;
+
+    let response = test_app
+        .post_issue_delivery_change(newsletter_issue_id, "resume")
+        .await;
+    let counts: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(counts["recipients"], 0);
+
+    test_app.run_scheduler(send_at).await;
+
+    let response = test_app
+        .post_issue_delivery_change(newsletter_issue_id, "cancel")
+        .await;
+    let counts: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(counts["recipients"], 1);
+    assert_eq!(counts["cancelled"], 1);
+}
+
+#[tokio::test]
+async fn delivery_changes_return_404_when_issue_does_not_exist() {
+    let test_app = TestApp::spawn_app().await;
+    let newsletter_issue_id = Uuid::new_v4().to_string();
+
+    for change in ["pause", "resume", "cancel"] {
+        let response = test_app
+            .post_issue_delivery_change(&newsletter_issue_id, change)
+            .await;
+
+        assert_eq!(
+            404,
+            response.status().as_u16(),
+            "The API did not fail with 404 status when trying to {} a missing issue",
+            change
+        );
+    }
+}
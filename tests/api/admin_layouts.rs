@@ -0,0 +1,155 @@
+use email_newsletter::email_client::SendEmailBody;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::TestApp;
+
+const LAYOUT_HTML: &str = r#"<html><head><style>
+  .footer a { color: #71717a; }
+  @media (max-width: 600px) { .footer { padding: 8px; } }
+</style></head><body>{{ content }}<div class="footer"><a href="{{ unsubscribe_url }}">Unsubscribe</a></div></body></html>"#;
+
+#[tokio::test]
+async fn layouts_are_created() {
+    let test_app = TestApp::spawn_app().await;
+
+    let response = test_app
+        .post_layout(serde_json::json!({ "name": "Weekly", "html": LAYOUT_HTML }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = reqwest::get(format!("{}/admin/layouts", test_app.address))
+        .await
+        .unwrap();
+    let layouts: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(layouts[0]["name"], "Weekly");
+    assert_eq!(layouts[0]["html"], LAYOUT_HTML);
+}
+
+#[tokio::test]
+async fn layouts_cannot_be_created_twice() {
+    let test_app = TestApp::spawn_app().await;
+    let body = serde_json::json!({ "name": "Weekly", "html": LAYOUT_HTML });
+
+    test_app.post_layout(body.clone()).await;
+    let response = test_app.post_layout(body).await;
+
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn layouts_returns_400_when_html_is_invalid() {
+    let test_app = TestApp::spawn_app().await;
+    let test_cases = vec![
+        ("<body><p>No slot</p></body>", "missing content slot"),
+        (
+            "<body>{{ content }}{{ content }}</body>",
+            "duplicated content slot",
+        ),
+        (
+            "<body>{{ content }}{{ subscriber.nmae }}</body>",
+            "unknown merge tag",
+        ),
+    ];
+
+    for (html, error_message) in test_cases {
+        let response = test_app
+            .post_layout(serde_json::json!({ "name": "Invalid", "html": html }))
+            .await;
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 status when layout had a {}",
+            error_message
+        );
+    }
+}
+
+#[tokio::test]
+async fn layouts_are_updated_and_deleted() {
+    let test_app = TestApp::spawn_app().await;
+    let client = reqwest::Client::new();
+    let response = test_app
+        .post_layout(serde_json::json!({ "name": "Weekly", "html": LAYOUT_HTML }))
+        .await;
+    let layout: serde_json::Value = response.json().await.unwrap();
+    let url = format!(
+        "{}/admin/layouts/{}",
+        test_app.address,
+        layout["id"].as_str().unwrap()
+    );
+
+    let response = client
+        .put(&url)
+        .json(&serde_json::json!({ "name": "Monthly", "html": "<body>{{ content }}</body>" }))
+        .send()
+        .await
+        .unwrap();
+    let layout: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(layout["name"], "Monthly");
+    assert_eq!(layout["html"], "<body>{{ content }}</body>");
+
+    let response = client.delete(&url).send().await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = client.delete(&url).send().await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn newsletters_are_rendered_with_the_layout_and_inlined_css() {
+    let test_app = TestApp::spawn_app().await;
+
+    test_app.create_confirmed_subscriber("frank@test.com").await;
+    test_app
+        .post_layout(serde_json::json!({ "name": "Weekly", "html": LAYOUT_HTML }))
+        .await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "layout": "Weekly",
+          "content": { "markdown": "Hi {{ subscriber.name }}" }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    test_app.dispatch_all_pending_emails().await;
+
+    let received_requests = test_app.email_server.received_requests().await.unwrap();
+    let body: SendEmailBody = received_requests.last().unwrap().body_json().unwrap();
+    let html = &body.content[1].value;
+
+    assert!(html.contains(r#"<p style="margin: 0 0 16px;">Hi Frank</p>"#));
+    assert!(html.contains(r#"style="color: #71717a;">Unsubscribe</a>"#));
+    assert!(html.contains("<style>@media (max-width: 600px)"));
+    assert!(!html.contains(".footer a"));
+}
+
+#[tokio::test]
+async fn newsletters_returns_404_when_layout_does_not_exist() {
+    let test_app = TestApp::spawn_app().await;
+
+    let response = test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "layout": "Missing",
+          "content": { "html": "<p>Newsletter content</p>" }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
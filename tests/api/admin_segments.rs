@@ -0,0 +1,187 @@
+use wiremock::matchers::{any, body_string_contains};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::TestApp;
+
+#[tokio::test]
+async fn segments_are_created() {
+    let test_app = TestApp::spawn_app().await;
+
+    let response = test_app
+        .post_segment(serde_json::json!({
+            "name": "Spanish beta testers",
+            "filter": "tag:beta AND subscribed_at > 2026-01-01 AND attr.country = \"ES\""
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = reqwest::get(format!("{}/admin/segments", test_app.address))
+        .await
+        .unwrap();
+    let segments: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(segments[0]["name"], "Spanish beta testers");
+}
+
+#[tokio::test]
+async fn segments_cannot_be_created_twice() {
+    let test_app = TestApp::spawn_app().await;
+    let body = serde_json::json!({ "name": "Beta", "filter": "tag:beta" });
+
+    test_app.post_segment(body.clone()).await;
+    let response = test_app.post_segment(body).await;
+
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn segments_returns_400_when_filter_is_invalid() {
+    let test_app = TestApp::spawn_app().await;
+    let test_cases = vec![
+        ("tag:beta AND", "incomplete expression"),
+        ("country = \"ES\"", "unknown field"),
+        ("subscribed_at > yesterday", "invalid date"),
+    ];
+
+    for (filter, error_message) in test_cases {
+        let response = test_app
+            .post_segment(serde_json::json!({ "name": "Invalid", "filter": filter }))
+            .await;
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 status when filter had an {}",
+            error_message
+        );
+    }
+}
+
+#[tokio::test]
+async fn segments_with_a_corrupt_filter_return_500() {
+    let test_app = TestApp::spawn_app().await;
+
+    sqlx::query(
+        "INSERT INTO segments (id, name, filter, created_at) VALUES ($1, 'Corrupt', 'tag:beta AND', now())",
+    )
+    .bind(uuid::Uuid::new_v4())
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    // The handler reports the row instead of panicking, so the server keeps answering
+    let response = reqwest::get(format!("{}/admin/segments", test_app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 500);
+
+    let response = reqwest::get(format!("{}/health_check", test_app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn tagging_a_missing_subscriber_returns_404() {
+    let test_app = TestApp::spawn_app().await;
+
+    let response = test_app
+        .post_subscriber_tag(&uuid::Uuid::new_v4(), "beta")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn newsletters_are_delivered_only_to_the_target_segment() {
+    let test_app = TestApp::spawn_app().await;
+    let beta_subscriber = test_app.create_confirmed_subscriber("beta@test.com").await;
+
+    test_app.create_confirmed_subscriber("other@test.com").await;
+    test_app.post_subscriber_tag(&beta_subscriber, "beta").await;
+    test_app
+        .post_segment(serde_json::json!({ "name": "Beta", "filter": "tag:beta" }))
+        .await;
+
+    Mock::given(body_string_contains("beta@test.com"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    Mock::given(body_string_contains("other@test.com"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_newsletter(serde_json::json!({
+            "title": "Newsletter title",
+            "content": { "html": "<p>Newsletter content</p>" },
+            "segment": "Beta"
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    test_app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_excluded_segments() {
+    let test_app = TestApp::spawn_app().await;
+    let beta_subscriber = test_app.create_confirmed_subscriber("beta@test.com").await;
+
+    test_app.create_confirmed_subscriber("other@test.com").await;
+    test_app.post_subscriber_tag(&beta_subscriber, "beta").await;
+    test_app
+        .post_segment(serde_json::json!({ "name": "Beta", "filter": "tag:beta" }))
+        .await;
+
+    Mock::given(body_string_contains("other@test.com"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    Mock::given(body_string_contains("beta@test.com"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_newsletter(serde_json::json!({
+            "title": "Newsletter title",
+            "content": { "html": "<p>Newsletter content</p>" },
+            "exclude_segments": ["Beta"]
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    test_app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn newsletters_returns_404_when_segment_does_not_exist() {
+    let test_app = TestApp::spawn_app().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_newsletter(serde_json::json!({
+            "title": "Newsletter title",
+            "content": { "html": "<p>Newsletter content</p>" },
+            "segment": "Missing"
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
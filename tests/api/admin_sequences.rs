@@ -0,0 +1,318 @@
+use email_newsletter::content::tracking::sign_unsubscribe_token;
+use sqlx::Row;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::TestApp;
+
+fn welcome_sequence() -> serde_json::Value {
+    serde_json::json!({
+        "name": "Welcome",
+        "steps": [
+            {
+                "delay_days": 0,
+                "title": "Welcome aboard",
+                "content": {
+                    "html": r#"<p>Welcome {{ subscriber.name }}! <a href="{{ unsubscribe_url }}">Unsubscribe</a></p>"#
+                }
+            },
+            {
+                "delay_days": 3,
+                "title": "The best of the blog",
+                "content": { "markdown": "Our best posts." }
+            }
+        ]
+    })
+}
+
+async fn get_enrolments(test_app: &TestApp, sequence_id: &str) -> Vec<serde_json::Value> {
+    reqwest::get(format!(
+        "{}/admin/sequences/{}/enrolments",
+        test_app.address, sequence_id
+    ))
+    .await
+    .unwrap()
+    .error_for_status()
+    .unwrap()
+    .json()
+    .await
+    .unwrap()
+}
+
+/// Brings the steps waiting in the queue the number of days closer
+async fn skip_days(test_app: &TestApp, days: i32) {
+    sqlx::query("UPDATE issue_deliveries SET send_at = send_at - make_interval(days => $1)")
+        .bind(days)
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_receive_each_step_on_its_day() {
+    let test_app = TestApp::spawn_app().await;
+    let response = test_app.post_sequence(welcome_sequence()).await;
+
+    assert_eq!(response.status(), 201);
+
+    let sequence: serde_json::Value = response.json().await.unwrap();
+    let sequence_id = sequence["id"].as_str().unwrap();
+    let subscriber_id = test_app.create_confirmed_subscriber("frank@test.com").await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app.dispatch_all_pending_emails().await;
+
+    let subjects = |requests: Vec<wiremock::Request>| -> Vec<String> {
+        requests
+            .iter()
+            .filter_map(|request| request.body_json::<serde_json::Value>().ok())
+            .filter_map(|body| body["subject"].as_str().map(String::from))
+            .collect()
+    };
+    let sent_subjects = subjects(test_app.email_server.received_requests().await.unwrap());
+
+    assert_eq!(sent_subjects.last().unwrap(), "Welcome aboard");
+
+    let enrolments = get_enrolments(&test_app, sequence_id).await;
+
+    assert_eq!(enrolments.len(), 1);
+    assert_eq!(enrolments[0]["subscriber_id"], subscriber_id.to_string());
+    assert_eq!(enrolments[0]["status"], "active");
+    assert_eq!(enrolments[0]["steps"], 2);
+    assert_eq!(enrolments[0]["steps_sent"], 1);
+
+    // Nothing else is due before the third day
+    test_app.dispatch_all_pending_emails().await;
+    skip_days(&test_app, 3).await;
+    test_app.dispatch_all_pending_emails().await;
+
+    let sent_subjects = subjects(test_app.email_server.received_requests().await.unwrap());
+
+    assert_eq!(
+        &sent_subjects[sent_subjects.len() - 2..],
+        ["Welcome aboard", "The best of the blog"]
+    );
+
+    let enrolments = get_enrolments(&test_app, sequence_id).await;
+
+    assert_eq!(enrolments[0]["status"], "completed");
+    assert_eq!(enrolments[0]["steps_sent"], 2);
+    assert_eq!(enrolments[0]["next_step_at"], serde_json::Value::Null);
+
+    // The steps are private issues, kept out of the archive
+    let archive = reqwest::get(format!("{}/archive", test_app.address))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert!(!archive.contains("Welcome aboard"));
+}
+
+#[tokio::test]
+async fn unsubscribing_stops_the_sequence() {
+    let test_app = TestApp::spawn_app().await;
+    let sequence: serde_json::Value = test_app
+        .post_sequence(welcome_sequence())
+        .await
+        .json()
+        .await
+        .unwrap();
+    let sequence_id = sequence["id"].as_str().unwrap();
+    let subscriber_id = test_app.create_confirmed_subscriber("frank@test.com").await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app.dispatch_all_pending_emails().await;
+
+    let welcome_delivery_id: Uuid =
+        sqlx::query("SELECT id FROM issue_deliveries WHERE subscriber_id = $1 AND status = 'sent'")
+            .bind(subscriber_id)
+            .fetch_one(&test_app.db_pool)
+            .await
+            .unwrap()
+            .get("id");
+
+    reqwest::Client::new()
+        .post(format!(
+            "{}/subscriptions/unsubscribe?token={}",
+            test_app.address,
+            sign_unsubscribe_token(&test_app.hmac_secret, &welcome_delivery_id)
+        ))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let enrolments = get_enrolments(&test_app, sequence_id).await;
+
+    assert_eq!(enrolments[0]["status"], "stopped");
+    assert_ne!(enrolments[0]["stopped_at"], serde_json::Value::Null);
+
+    let statuses: Vec<String> = sqlx::query(
+        "SELECT status FROM issue_deliveries WHERE subscriber_id = $1 ORDER BY send_at",
+    )
+    .bind(subscriber_id)
+    .fetch_all(&test_app.db_pool)
+    .await
+    .unwrap()
+    .iter()
+    .map(|row| row.get("status"))
+    .collect();
+
+    assert_eq!(statuses, ["sent", "cancelled"]);
+}
+
+#[tokio::test]
+async fn subscribers_confirmed_before_a_sequence_are_not_enrolled() {
+    let test_app = TestApp::spawn_app().await;
+
+    test_app.create_confirmed_subscriber("frank@test.com").await;
+
+    let sequence: serde_json::Value = test_app
+        .post_sequence(welcome_sequence())
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    assert!(get_enrolments(&test_app, sequence["id"].as_str().unwrap())
+        .await
+        .is_empty());
+
+    let response = test_app.post_sequence(welcome_sequence()).await;
+
+    assert_eq!(response.status(), 409);
+
+    let sequences: Vec<serde_json::Value> =
+        reqwest::get(format!("{}/admin/sequences", test_app.address))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+    assert_eq!(sequences.len(), 1);
+    assert_eq!(sequences[0]["steps"][1]["delay_days"], 3);
+    assert_eq!(sequences[0]["steps"][1]["title"], "The best of the blog");
+}
+
+#[tokio::test]
+async fn deleting_a_sequence_cancels_the_pending_steps() {
+    let test_app = TestApp::spawn_app().await;
+    let sequence: serde_json::Value = test_app
+        .post_sequence(welcome_sequence())
+        .await
+        .json()
+        .await
+        .unwrap();
+    let subscriber_id = test_app.create_confirmed_subscriber("frank@test.com").await;
+    let client = reqwest::Client::new();
+    let url = format!(
+        "{}/admin/sequences/{}",
+        test_app.address,
+        sequence["id"].as_str().unwrap()
+    );
+
+    let response = client.delete(&url).send().await.unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let pending: i64 = sqlx::query(
+        "SELECT count(*) FROM issue_deliveries WHERE subscriber_id = $1 AND status = 'pending'",
+    )
+    .bind(subscriber_id)
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap()
+    .get(0);
+
+    assert_eq!(pending, 0);
+
+    let response = client.delete(&url).send().await.unwrap();
+
+    assert_eq!(response.status(), 404);
+}
+
+#[tokio::test]
+async fn invalid_sequences_are_rejected_with_400() {
+    let test_app = TestApp::spawn_app().await;
+    let step = |delay_days: u32| {
+        serde_json::json!({
+            "delay_days": delay_days,
+            "title": "Step",
+            "content": { "markdown": "Hello" }
+        })
+    };
+    let test_cases = [
+        (
+            serde_json::json!({ "name": " ", "steps": [step(0)] }),
+            "empty name",
+        ),
+        (
+            serde_json::json!({ "name": "Welcome", "steps": [] }),
+            "no steps",
+        ),
+        (
+            serde_json::json!({ "name": "Welcome", "steps": [step(3), step(0)] }),
+            "steps out of order",
+        ),
+        (
+            serde_json::json!({
+                "name": "Welcome",
+                "steps": [{
+                    "delay_days": 0,
+                    "title": "Step",
+                    "content": { "markdown": "Hello" },
+                    "segment": "vip"
+                }]
+            }),
+            "step with a segment",
+        ),
+        (
+            serde_json::json!({
+                "name": "Welcome",
+                "steps": [{ "delay_days": 0, "title": "Step", "content": {} }]
+            }),
+            "step without content",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        let response = test_app.post_sequence(body).await;
+
+        assert_eq!(
+            response.status(),
+            400,
+            "The API did not fail with 400 Bad Request when the payload had {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn unknown_sequences_are_rejected_with_404() {
+    let test_app = TestApp::spawn_app().await;
+
+    let response = reqwest::get(format!(
+        "{}/admin/sequences/{}/enrolments",
+        test_app.address,
+        Uuid::new_v4()
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status(), 404);
+}
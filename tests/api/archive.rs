@@ -0,0 +1,299 @@
+use email_newsletter::email_client::SendEmailBody;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::TestApp;
+
+/// Publishes and delivers a newsletter to a confirmed subscriber, returning the created issue
+async fn send_newsletter(test_app: &TestApp, newsletter: serde_json::Value) -> serde_json::Value {
+    let response = test_app.post_newsletter(newsletter).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let newsletter_issue = response.json().await.unwrap();
+
+    test_app.dispatch_all_pending_emails().await;
+
+    newsletter_issue
+}
+
+async fn get_text(test_app: &TestApp, path: &str) -> (u16, Option<String>, String) {
+    let response = reqwest::get(format!("{}{}", test_app.address, path))
+        .await
+        .unwrap();
+    let status = response.status().as_u16();
+    let content_type = response
+        .headers()
+        .get("Content-Type")
+        .map(|header| String::from(header.to_str().unwrap()));
+
+    (status, content_type, response.text().await.unwrap())
+}
+
+async fn spawn_app_with_subscriber() -> TestApp {
+    let test_app = TestApp::spawn_app().await;
+
+    test_app.create_confirmed_subscriber("frank@test.com").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app
+}
+
+#[tokio::test]
+async fn sent_issues_are_published_in_the_archive_and_the_feeds() {
+    let test_app = spawn_app_with_subscriber().await;
+    let newsletter_issue = send_newsletter(
+        &test_app,
+        serde_json::json!({
+          "title": "Weekly digest #1",
+          "content": { "html": "<p>Hi {{ subscriber.name }}, news & more</p>" }
+        }),
+    )
+    .await;
+
+    assert_eq!(newsletter_issue["slug"], "weekly-digest-1");
+
+    let (status, _, html) = get_text(&test_app, "/archive").await;
+
+    assert_eq!(status, 200);
+    assert!(html.contains(r#"<a href="http://localhost/archive/weekly-digest-1""#));
+    assert!(html.contains("Weekly digest #1"));
+
+    let (status, _, html) = get_text(&test_app, "/archive/weekly-digest-1").await;
+
+    assert_eq!(status, 200);
+    assert!(html.contains("<p>Hi , news & more</p>"));
+    assert!(!html.contains("frank@test.com"));
+
+    let (status, content_type, rss) = get_text(&test_app, "/feed.xml").await;
+
+    assert_eq!(status, 200);
+    assert!(content_type.unwrap().starts_with("application/rss+xml"));
+    assert!(rss.contains(r#"<rss version="2.0""#));
+    assert!(rss.contains("<link>http://localhost/archive/weekly-digest-1</link>"));
+
+    let (status, content_type, atom) = get_text(&test_app, "/feed.atom").await;
+
+    assert_eq!(status, 200);
+    assert!(content_type.unwrap().starts_with("application/atom+xml"));
+    assert!(atom.contains(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#));
+    assert!(atom.contains(r#"href="http://localhost/archive/weekly-digest-1""#));
+}
+
+#[tokio::test]
+async fn private_and_unsent_issues_are_not_published() {
+    let test_app = TestApp::spawn_app().await;
+
+    // Without subscribers the issue is never sent
+    send_newsletter(
+        &test_app,
+        serde_json::json!({
+          "title": "Unsent issue",
+          "content": { "html": "<p>Newsletter content</p>" }
+        }),
+    )
+    .await;
+    test_app.create_confirmed_subscriber("frank@test.com").await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    send_newsletter(
+        &test_app,
+        serde_json::json!({
+          "title": "Private issue",
+          "content": { "html": "<p>Newsletter content</p>" },
+          "is_private": true
+        }),
+    )
+    .await;
+
+    for path in ["/archive/unsent-issue", "/archive/private-issue"] {
+        let (status, _, _) = get_text(&test_app, path).await;
+
+        assert_eq!(status, 404, "{} was published", path);
+    }
+
+    for path in ["/archive", "/feed.xml", "/feed.atom"] {
+        let (_, _, content) = get_text(&test_app, path).await;
+
+        assert!(
+            !content.contains("Unsent issue"),
+            "{} lists an unsent issue",
+            path
+        );
+        assert!(
+            !content.contains("Private issue"),
+            "{} lists a private issue",
+            path
+        );
+    }
+}
+
+#[tokio::test]
+async fn emails_link_to_the_archived_issue() {
+    let test_app = spawn_app_with_subscriber().await;
+
+    send_newsletter(
+        &test_app,
+        serde_json::json!({
+          "title": "Newsletter title",
+          "content": { "html": "<html><body><p>Newsletter content</p></body></html>" },
+          "track_clicks": false
+        }),
+    )
+    .await;
+
+    let received_requests = test_app.email_server.received_requests().await.unwrap();
+    let body: SendEmailBody = received_requests.last().unwrap().body_json().unwrap();
+
+    assert!(body.content[0]
+        .value
+        .starts_with("View in browser: http://localhost/archive/newsletter-title\n\n"));
+    assert!(body.content[1]
+        .value
+        .contains(r#"<a href="http://localhost/archive/newsletter-title" style="color: #71717a;">View in browser</a>"#));
+}
+
+#[tokio::test]
+async fn view_in_browser_merge_tag_replaces_the_default_link() {
+    let test_app = spawn_app_with_subscriber().await;
+
+    send_newsletter(
+        &test_app,
+        serde_json::json!({
+          "title": "Newsletter title",
+          "content": {
+            "html": "<p><a href=\"{{ view_in_browser_url }}\">Read online</a></p>",
+            "text": "Read online at {{ view_in_browser_url }}"
+          },
+          "track_clicks": false
+        }),
+    )
+    .await;
+
+    let received_requests = test_app.email_server.received_requests().await.unwrap();
+    let body: SendEmailBody = received_requests.last().unwrap().body_json().unwrap();
+
+    assert_eq!(
+        body.content[0].value,
+        "Read online at http://localhost/archive/newsletter-title"
+    );
+    assert!(!body.content[1].value.contains("View in browser"));
+}
+
+#[tokio::test]
+async fn private_issues_cannot_link_to_the_archive() {
+    let test_app = TestApp::spawn_app().await;
+
+    let response = test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "content": { "html": "<a href=\"{{ view_in_browser_url }}\">Read online</a>" },
+          "is_private": true
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn issues_with_the_same_title_have_different_slugs() {
+    let test_app = TestApp::spawn_app().await;
+    let newsletter = serde_json::json!({
+      "title": "Newsletter title",
+      "content": { "html": "<p>Newsletter content</p>" }
+    });
+
+    let first_issue = send_newsletter(&test_app, newsletter.clone()).await;
+    let second_issue = send_newsletter(&test_app, newsletter).await;
+
+    assert_eq!(first_issue["slug"], "newsletter-title");
+    assert_eq!(
+        second_issue["slug"],
+        format!(
+            "newsletter-title-{}",
+            &second_issue["id"].as_str().unwrap()[..8]
+        )
+    );
+}
+
+#[tokio::test]
+async fn issues_with_the_same_title_published_at_once_are_all_created() {
+    let test_app = TestApp::spawn_app().await;
+    let newsletter = serde_json::json!({
+      "title": "Newsletter title",
+      "content": { "html": "<p>Newsletter content</p>" }
+    });
+
+    let publishes: Vec<_> = (0..5)
+        .map(|_| {
+            let url = format!("{}/newsletters", test_app.address);
+            let newsletter = newsletter.clone();
+
+            tokio::spawn(async move {
+                reqwest::Client::new()
+                    .post(url)
+                    .json(&newsletter)
+                    .send()
+                    .await
+                    .unwrap()
+            })
+        })
+        .collect();
+
+    for publish in publishes {
+        assert_eq!(publish.await.unwrap().status().as_u16(), 200);
+    }
+
+    let slugs: Vec<String> =
+        sqlx::query_scalar("SELECT slug FROM newsletter_issues WHERE slug = 'newsletter-title'")
+            .fetch_all(&test_app.db_pool)
+            .await
+            .unwrap();
+
+    assert_eq!(slugs.len(), 1);
+}
+
+#[tokio::test]
+async fn issues_whose_slugs_are_all_taken_return_409() {
+    let test_app = TestApp::spawn_app().await;
+    let newsletter = serde_json::json!({
+      "title": "Newsletter title",
+      "content": { "html": "<p>Newsletter content</p>" }
+    });
+
+    send_newsletter(&test_app, newsletter.clone()).await;
+
+    // Every slug tried by the next issues is the one already taken
+    sqlx::query(
+        r#"
+        CREATE FUNCTION take_slug() RETURNS trigger AS $$
+        BEGIN
+            NEW.slug := 'newsletter-title';
+            RETURN NEW;
+        END;
+        $$ LANGUAGE plpgsql
+        "#,
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        r#"
+        CREATE TRIGGER take_slug BEFORE INSERT ON newsletter_issues
+        FOR EACH ROW EXECUTE FUNCTION take_slug()
+        "#,
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    let response = test_app.post_newsletter(newsletter).await;
+
+    assert_eq!(response.status().as_u16(), 409);
+}
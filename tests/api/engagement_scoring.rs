@@ -0,0 +1,238 @@
+use chrono::{Duration, Utc};
+use email_newsletter::content::tracking::sign_click_token;
+use uuid::Uuid;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::TestApp;
+
+/// The default scoring adds 1 per open, 3 per click and -5 per bounce, halving every 30 days
+async fn spawn_app_with_subscribers(emails: &[&str]) -> (TestApp, Vec<Uuid>) {
+    let test_app = TestApp::spawn_app().await;
+    let mut subscriber_ids = vec![];
+
+    for email in emails {
+        subscriber_ids.push(test_app.create_confirmed_subscriber(email).await);
+    }
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    (test_app, subscriber_ids)
+}
+
+/// Publishes and delivers a newsletter, returning the delivery of the subscriber
+async fn send_newsletter(test_app: &TestApp, subscriber_id: Uuid) -> Uuid {
+    test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "content": { "html": "<html><body><p>Newsletter content</p></body></html>" }
+        }))
+        .await
+        .error_for_status()
+        .unwrap();
+    test_app.dispatch_all_pending_emails().await;
+
+    sqlx::query_scalar(
+        "SELECT id FROM issue_deliveries WHERE subscriber_id = $1 ORDER BY created_at DESC LIMIT 1",
+    )
+    .bind(subscriber_id)
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap()
+}
+
+async fn open_delivery(test_app: &TestApp, delivery_id: Uuid) {
+    reqwest::get(format!("{}/t/o/{}.gif", test_app.address, delivery_id))
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn get_engagement_score(test_app: &TestApp, subscriber_id: Uuid) -> f64 {
+    sqlx::query_scalar("SELECT engagement_score FROM subscriptions WHERE id = $1")
+        .bind(subscriber_id)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+}
+
+fn assert_score(score: f64, expected: f64) {
+    assert!(
+        (score - expected).abs() < 0.01,
+        "The engagement score was {}, expected {}.",
+        score,
+        expected
+    );
+}
+
+#[tokio::test]
+async fn first_open_and_click_of_each_delivery_raise_the_engagement_score() {
+    let (test_app, subscriber_ids) = spawn_app_with_subscribers(&["frank@test.com"]).await;
+    let delivery_id = send_newsletter(&test_app, subscriber_ids[0]).await;
+
+    for _ in 0..2 {
+        open_delivery(&test_app, delivery_id).await;
+    }
+
+    assert_score(
+        get_engagement_score(&test_app, subscriber_ids[0]).await,
+        1.0,
+    );
+
+    let url = "https://blog.test.com/post";
+    let token = sign_click_token(&test_app.hmac_secret, &delivery_id, url);
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+
+    for _ in 0..2 {
+        let response = client
+            .get(format!("{}/t/c/{}", test_app.address, token))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status().as_u16(), 302);
+    }
+
+    assert_score(
+        get_engagement_score(&test_app, subscriber_ids[0]).await,
+        4.0,
+    );
+
+    // The score is part of the subscriber returned by the API
+    let subscriber: serde_json::Value = reqwest::Client::new()
+        .put(format!(
+            "{}/admin/subscribers/{}/timezone",
+            test_app.address, subscriber_ids[0]
+        ))
+        .json(&serde_json::json!({ "timezone": "Europe/Madrid" }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_score(subscriber["engagement_score"].as_f64().unwrap(), 4.0);
+}
+
+#[tokio::test]
+async fn concurrent_first_opens_raise_the_engagement_score_once() {
+    let (test_app, subscriber_ids) = spawn_app_with_subscribers(&["frank@test.com"]).await;
+    let delivery_id = send_newsletter(&test_app, subscriber_ids[0]).await;
+    let opens: Vec<_> = (0..10)
+        .map(|_| {
+            let url = format!("{}/t/o/{}.gif", test_app.address, delivery_id);
+
+            tokio::spawn(
+                async move { reqwest::get(url).await.unwrap().error_for_status().unwrap() },
+            )
+        })
+        .collect();
+
+    for open in opens {
+        open.await.unwrap();
+    }
+
+    let opens: i64 =
+        sqlx::query_scalar("SELECT count(*) FROM issue_opens WHERE issue_delivery_id = $1")
+            .bind(delivery_id)
+            .fetch_one(&test_app.db_pool)
+            .await
+            .unwrap();
+
+    assert_eq!(opens, 10);
+    assert_score(
+        get_engagement_score(&test_app, subscriber_ids[0]).await,
+        1.0,
+    );
+}
+
+#[tokio::test]
+async fn bounces_lower_the_engagement_score_once() {
+    let (test_app, subscriber_ids) = spawn_app_with_subscribers(&["frank@test.com"]).await;
+    let delivery_id = send_newsletter(&test_app, subscriber_ids[0]).await;
+
+    let response = test_app
+        .post_sendgrid_events(serde_json::json!([
+            { "event": "bounce", "timestamp": 1760000000, "sg_event_id": "1", "delivery_id": delivery_id },
+            { "event": "bounce", "timestamp": 1760000000, "sg_event_id": "1", "delivery_id": delivery_id },
+            { "event": "delivered", "timestamp": 1760000000, "sg_event_id": "2", "delivery_id": delivery_id }
+        ]))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_score(
+        get_engagement_score(&test_app, subscriber_ids[0]).await,
+        -5.0,
+    );
+}
+
+#[tokio::test]
+async fn engagement_scores_decay_when_refreshed() {
+    let (test_app, subscriber_ids) = spawn_app_with_subscribers(&["frank@test.com"]).await;
+    let delivery_id = send_newsletter(&test_app, subscriber_ids[0]).await;
+
+    open_delivery(&test_app, delivery_id).await;
+
+    // Scores updated by an event are not due yet
+    assert_eq!(test_app.run_engagement_scoring(Utc::now()).await, 0);
+
+    let now = Utc::now() + Duration::days(30);
+
+    assert_eq!(test_app.run_engagement_scoring(now).await, 1);
+    assert_eq!(test_app.run_engagement_scoring(now).await, 0);
+
+    // Half of the open, plus half of the subscription age weight after a half-life
+    assert_score(
+        get_engagement_score(&test_app, subscriber_ids[0]).await,
+        1.5,
+    );
+}
+
+#[tokio::test]
+async fn segments_can_filter_by_engagement_score() {
+    let (test_app, subscriber_ids) =
+        spawn_app_with_subscribers(&["frank@test.com", "ursula@test.com"]).await;
+    let delivery_id = send_newsletter(&test_app, subscriber_ids[0]).await;
+
+    open_delivery(&test_app, delivery_id).await;
+
+    test_app
+        .post_segment(serde_json::json!({ "name": "Engaged", "filter": "engagement_score > 0.5" }))
+        .await
+        .error_for_status()
+        .unwrap();
+    test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "content": { "html": "<html><body><p>Newsletter content</p></body></html>" },
+          "segment": "Engaged"
+        }))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let deliveries: Vec<(Uuid, f64)> = sqlx::query_as(
+        r#"
+        SELECT subscriber_id, priority
+        FROM issue_deliveries
+        WHERE status = 'pending'
+        "#,
+    )
+    .fetch_all(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    // Deliveries keep the score of the subscriber as their sending priority
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].0, subscriber_ids[0]);
+    assert_score(deliveries[0].1, 1.0);
+}
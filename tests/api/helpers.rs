@@ -1,15 +1,27 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
 use linkify::{LinkFinder, LinkKind};
 use reqwest::Response;
 use reqwest::Url;
-use sqlx::{migrate, Connection, Executor, PgConnection, PgPool};
-use std::collections::HashMap;
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+use secrecy::Secret;
+use sqlx::{migrate, Connection, Executor, PgConnection, PgPool, Row};
+use std::time::Duration;
 use uuid::Uuid;
-use wiremock::MockServer;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
 
 use email_newsletter::{
     config::{get_configuration, DatabaseSettings, Settings},
-    email_client::SendEmailBody,
-    startup::{get_connection_db_pool, Application},
+    domain::sendgrid_webhook_key::SendgridWebhookKey,
+    email_client::{EmailClient, SendEmailBody},
+    engagement_scoring::EngagementScoringJob,
+    issue_delivery_worker::{try_execute_task, ExecutionOutcome},
+    pending_subscriptions::{PendingSubscriptionsJob, PendingSubscriptionsOutcome},
+    scheduler::{run_due_jobs, SchedulerOutcome},
+    startup::{get_connection_db_pool, get_email_client, Application},
+    sunset_policy::{SunsetPolicyJob, SunsetPolicyOutcome},
 };
 
 pub struct ConfirmationLink {
@@ -17,16 +29,33 @@ pub struct ConfirmationLink {
 }
 
 pub struct TestApp {
-    pub config: Settings,
     pub address: String,
     pub db_pool: PgPool,
     pub email_server: MockServer,
+    pub email_client: EmailClient,
+    pub base_url: String,
+    pub hmac_secret: Secret<String>,
+    /// Signs the requests to the Sendgrid event webhook
+    pub webhook_key_pair: EcdsaKeyPair,
     pub port: u16,
+    pub pending_subscriptions_job: PendingSubscriptionsJob,
+    pub sunset_policy_job: SunsetPolicyJob,
+    pub engagement_scoring_job: EngagementScoringJob,
 }
 
 impl TestApp {
     pub async fn spawn_app() -> TestApp {
+        Self::spawn_app_with(|_| {}).await
+    }
+
+    /// Spawns the application after letting the test change its configuration
+    pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
         let mut config = get_configuration().expect("Missing configuration file.");
+        let webhook_key_pair = generate_webhook_key_pair();
+
+        // Tests run in parallel against the same Redis, they would share the send budget
+        config.set_email_client_rate_limit(None);
+        configure(&mut config);
 
         let email_server = MockServer::start().await;
 
@@ -34,6 +63,9 @@ impl TestApp {
         // take into account: when port is 0, the OS will search for the first available port
         config.set_app_port(0);
         config.set_email_client_base_url(email_server.uri());
+        config.set_email_client_webhook_verification_key(SendgridWebhookKey::encode(
+            webhook_key_pair.public_key().as_ref(),
+        ));
 
         let db_pool = configure_db(&mut config.database).await;
 
@@ -48,14 +80,20 @@ impl TestApp {
 
         TestApp {
             address,
-            config: config.clone(),
-            db_pool,
+            db_pool: db_pool.clone(),
             email_server,
+            email_client: get_email_client(&config),
+            base_url: config.get_app_base_url(),
+            hmac_secret: config.get_hmac_secret(),
+            webhook_key_pair,
             port: application_port,
+            pending_subscriptions_job: PendingSubscriptionsJob::new(db_pool.clone(), &config),
+            sunset_policy_job: SunsetPolicyJob::new(db_pool.clone(), &config),
+            engagement_scoring_job: EngagementScoringJob::new(db_pool.clone(), &config),
         }
     }
 
-    pub async fn post_subscription(&self, body: HashMap<&str, &str>) -> Response {
+    pub async fn post_subscription(&self, body: impl serde::Serialize) -> Response {
         let client = reqwest::Client::new();
         let url = format!("{}/subscriptions", self.address);
 
@@ -69,6 +107,41 @@ impl TestApp {
         response
     }
 
+    /// Posts the events to the Sendgrid webhook, signed like Sendgrid does
+    pub async fn post_sendgrid_events(&self, events: serde_json::Value) -> Response {
+        self.post_sendgrid_events_signed_at(events, chrono::Utc::now())
+            .await
+    }
+
+    pub async fn post_sendgrid_events_signed_at(
+        &self,
+        events: serde_json::Value,
+        signed_at: chrono::DateTime<chrono::Utc>,
+    ) -> Response {
+        let body = serde_json::to_vec(&events).unwrap();
+        let timestamp = signed_at.timestamp().to_string();
+        let signature = self
+            .webhook_key_pair
+            .sign(
+                &SystemRandom::new(),
+                &[timestamp.as_bytes(), &body].concat(),
+            )
+            .unwrap();
+
+        reqwest::Client::new()
+            .post(format!("{}/webhooks/sendgrid", self.address))
+            .header("Content-Type", "application/json")
+            .header("X-Twilio-Email-Event-Webhook-Timestamp", timestamp)
+            .header(
+                "X-Twilio-Email-Event-Webhook-Signature",
+                STANDARD.encode(signature),
+            )
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute post Sendgrid events request.")
+    }
+
     pub async fn post_newsletter(&self, body: serde_json::Value) -> Response {
         let client = reqwest::Client::new();
         let url = format!("{}/newsletters", self.address);
@@ -83,13 +156,208 @@ impl TestApp {
         response
     }
 
+    pub async fn post_attribute_definition(&self, body: serde_json::Value) -> Response {
+        let client = reqwest::Client::new();
+        let url = format!("{}/admin/attributes", self.address);
+
+        client
+            .post(&url)
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute post attribute definition request.")
+    }
+
+    pub async fn put_subscriber_attributes(
+        &self,
+        subscriber_id: &Uuid,
+        body: serde_json::Value,
+    ) -> Response {
+        let client = reqwest::Client::new();
+        let url = format!(
+            "{}/admin/subscribers/{}/attributes",
+            self.address, subscriber_id
+        );
+
+        client
+            .put(&url)
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute put subscriber attributes request.")
+    }
+
+    pub async fn post_segment(&self, body: serde_json::Value) -> Response {
+        let client = reqwest::Client::new();
+        let url = format!("{}/admin/segments", self.address);
+
+        client
+            .post(&url)
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute post segment request.")
+    }
+
+    pub async fn post_sequence(&self, body: serde_json::Value) -> Response {
+        let client = reqwest::Client::new();
+        let url = format!("{}/admin/sequences", self.address);
+
+        client
+            .post(&url)
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute post sequence request.")
+    }
+
+    pub async fn post_layout(&self, body: serde_json::Value) -> Response {
+        let client = reqwest::Client::new();
+        let url = format!("{}/admin/layouts", self.address);
+
+        client
+            .post(&url)
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute post layout request.")
+    }
+
+    pub async fn post_recurring_newsletter(&self, body: serde_json::Value) -> Response {
+        let client = reqwest::Client::new();
+        let url = format!("{}/admin/recurring-newsletters", self.address);
+
+        client
+            .post(&url)
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute post recurring newsletter request.")
+    }
+
+    pub async fn post_subscriber_tag(&self, subscriber_id: &Uuid, tag: &str) -> Response {
+        let client = reqwest::Client::new();
+        let url = format!("{}/admin/subscribers/{}/tags", self.address, subscriber_id);
+
+        client
+            .post(&url)
+            .json(&serde_json::json!({ "tag": tag }))
+            .send()
+            .await
+            .expect("Failed to execute post subscriber tag request.")
+    }
+
+    /// Pauses, resumes or cancels the delivery of a newsletter issue
+    pub async fn post_issue_delivery_change(
+        &self,
+        newsletter_issue_id: &str,
+        change: &str,
+    ) -> Response {
+        let client = reqwest::Client::new();
+        let url = format!(
+            "{}/admin/issues/{}/{}",
+            self.address, newsletter_issue_id, change
+        );
+
+        client
+            .post(&url)
+            .send()
+            .await
+            .expect("Failed to execute post issue delivery change request.")
+    }
+
+    /// Subscribes and confirms a new subscriber, returning its id
+    pub async fn create_confirmed_subscriber(&self, email: &str) -> Uuid {
+        let _mock_guard = Mock::given(path("/mail/send"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .named("Create confirmed subscriber")
+            .expect(1)
+            .mount_as_scoped(&self.email_server)
+            .await;
+
+        self.post_subscription(serde_json::json!({ "name": "Frank", "email": email }))
+            .await
+            .error_for_status()
+            .unwrap();
+
+        let received_requests = self.email_server.received_requests().await.unwrap();
+        let confirmation_link = self
+            .get_confirmation_link(received_requests.last().unwrap())
+            .await;
+
+        reqwest::get(confirmation_link.html)
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+
+        sqlx::query("SELECT id FROM subscriptions WHERE email = $1")
+            .bind(email)
+            .fetch_one(&self.db_pool)
+            .await
+            .expect("Failed to fetch the confirmed subscriber.")
+            .get("id")
+    }
+
+    /// Runs the delivery worker until every pending newsletter delivery has been processed
+    pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.db_pool,
+                &self.email_client,
+                &self.base_url,
+                &self.hmac_secret,
+            )
+            .await
+            .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    /// Runs the scheduler as if it was `now`, waiting for the run of the application's own scheduler to finish
+    pub async fn run_scheduler(&self, now: chrono::DateTime<chrono::Utc>) -> SchedulerOutcome {
+        loop {
+            match run_due_jobs(&self.db_pool, now).await.unwrap() {
+                SchedulerOutcome::Locked => tokio::time::sleep(Duration::from_millis(50)).await,
+                outcome => return outcome,
+            }
+        }
+    }
+
+    pub async fn run_pending_subscriptions_job(
+        &self,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> PendingSubscriptionsOutcome {
+        self.pending_subscriptions_job.run(now).await.unwrap()
+    }
+
+    pub async fn run_sunset_policy(
+        &self,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> SunsetPolicyOutcome {
+        self.sunset_policy_job.run(now).await.unwrap()
+    }
+
+    /// Returns the number of subscribers whose score was refreshed
+    pub async fn run_engagement_scoring(&self, now: chrono::DateTime<chrono::Utc>) -> usize {
+        self.engagement_scoring_job.run(now).await.unwrap()
+    }
+
     pub async fn get_confirmation_link(
         &self,
         email_request: &wiremock::Request,
     ) -> ConfirmationLink {
         let body: &SendEmailBody = &email_request.body_json().unwrap();
+        let html_content = body
+            .content
+            .iter()
+            .find(|content| content.content_type == "text/html")
+            .expect("Email without HTML content.");
         let links: Vec<_> = LinkFinder::new()
-            .links(body.content[0].value.as_str())
+            .links(html_content.value.as_str())
             .filter(|l| *l.kind() == LinkKind::Url)
             .collect();
         let raw_confirmation_link = links[0].as_str();
@@ -105,6 +373,14 @@ impl TestApp {
     }
 }
 
+fn generate_webhook_key_pair() -> EcdsaKeyPair {
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &SystemRandom::new())
+        .expect("Failed to generate the webhook key pair.");
+
+    EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref())
+        .expect("Failed to parse the webhook key pair.")
+}
+
 async fn configure_db(db_config: &mut DatabaseSettings) -> PgPool {
     let db_test_name = format!("db_{}", Uuid::new_v4().to_string().replace('-', "_"));
 
@@ -1,5 +1,20 @@
+mod admin_attributes;
+mod admin_issues;
+mod admin_layouts;
+mod admin_segments;
+mod admin_sequences;
+mod archive;
+mod engagement_scoring;
 mod health_check;
 mod helpers;
 mod newsletters;
+mod pending_subscriptions;
+mod rate_limit;
+mod scheduling;
+mod subject_tests;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_preferences;
+mod sunset_policy;
+mod tracking;
+mod webhooks;
@@ -1,7 +1,11 @@
 use std::collections::HashMap;
 
 use crate::helpers::{ConfirmationLink, TestApp};
-use wiremock::matchers::{any, method, path};
+use chrono::Timelike;
+use email_newsletter::email_client::SendEmailBody;
+use sqlx::Row;
+use uuid::Uuid;
+use wiremock::matchers::{any, body_string_contains, method, path};
 use wiremock::{Mock, ResponseTemplate};
 
 #[tokio::test]
@@ -25,6 +29,8 @@ async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
     let response = test_app.post_newsletter(body).await;
 
     assert_eq!(response.status().as_u16(), 200);
+
+    test_app.dispatch_all_pending_emails().await;
 }
 
 #[tokio::test]
@@ -48,6 +54,8 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     let response = test_app.post_newsletter(body).await;
 
     assert_eq!(response.status().as_u16(), 200);
+
+    test_app.dispatch_all_pending_emails().await;
 }
 
 #[tokio::test]
@@ -68,6 +76,25 @@ async fn newsletters_returns_400_when_body_is_invalid() {
             }),
             "missing content",
         ),
+        (
+            serde_json::json!({
+                "title": "Newsletter title",
+                "content": {
+                  "text": "Newsletter content"
+                }
+            }),
+            "missing HTML and Markdown content",
+        ),
+        (
+            serde_json::json!({
+                "title": "Newsletter title",
+                "content": {
+                  "html": "<p>Newsletter content</p>",
+                  "markdown": "Newsletter content"
+                }
+            }),
+            "both HTML and Markdown content",
+        ),
     ];
 
     for (invalid_body, error_message) in test_cases {
@@ -82,6 +109,448 @@ async fn newsletters_returns_400_when_body_is_invalid() {
     }
 }
 
+#[tokio::test]
+async fn newsletters_returns_400_when_template_is_invalid() {
+    let test_app = TestApp::spawn_app().await;
+    let test_cases = vec![
+        ("<p>{% if attr.company %}Hi</p>", "unclosed block"),
+        ("<p>Hi {{ subscriber.nmae }}</p>", "unknown merge tag"),
+    ];
+
+    for (html, error_message) in test_cases {
+        let response = test_app
+            .post_newsletter(serde_json::json!({
+              "title": "Newsletter title",
+              "content": { "html": html }
+            }))
+            .await;
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 status when template had an {}",
+            error_message
+        );
+    }
+}
+
+#[tokio::test]
+async fn newsletters_are_rendered_for_each_subscriber() {
+    let test_app = TestApp::spawn_app().await;
+
+    test_app
+        .post_attribute_definition(serde_json::json!({ "key": "company", "type": "text" }))
+        .await;
+
+    let subscriber_id = test_app.create_confirmed_subscriber("acme@test.com").await;
+
+    test_app.create_confirmed_subscriber("other@test.com").await;
+    test_app
+        .put_subscriber_attributes(
+            &subscriber_id,
+            serde_json::json!({ "attributes": { "company": "ACME" } }),
+        )
+        .await;
+
+    Mock::given(body_string_contains("Hi Frank from ACME"))
+        .and(body_string_contains("acme@test.com"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    Mock::given(body_string_contains("Hi Frank from nowhere"))
+        .and(body_string_contains("other@test.com"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "content": {
+            "html": "<p>Hi {{ subscriber.name }} from {{ attr.company | default(\"nowhere\") }}</p>"
+          }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    test_app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn subscribers_are_unsubscribed_with_the_newsletter_link() {
+    let test_app = TestApp::spawn_app().await;
+
+    create_confirmed_subscriber(&test_app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "content": {
+            "html": "<a href=\"{{ unsubscribe_url }}\">Unsubscribe</a>"
+          }
+        }))
+        .await;
+    test_app.dispatch_all_pending_emails().await;
+
+    let received_requests = test_app.email_server.received_requests().await.unwrap();
+    let body: SendEmailBody = received_requests.last().unwrap().body_json().unwrap();
+    let headers = &body.personalizations[0].headers;
+    let link = headers["List-Unsubscribe"]
+        .trim_start_matches('<')
+        .trim_end_matches('>');
+
+    assert!(body.content[1]
+        .value
+        .contains(&format!(r#"<a href="{}">Unsubscribe</a>"#, link)));
+    assert_eq!(
+        headers["List-Unsubscribe-Post"],
+        "List-Unsubscribe=One-Click"
+    );
+
+    // The link has the port of the configured base URL, not the one of the test app
+    let mut unsubscribe_link = reqwest::Url::parse(link).unwrap();
+    unsubscribe_link.set_port(Some(test_app.port)).unwrap();
+
+    // Following the link only shows the confirmation page
+    let response = reqwest::get(unsubscribe_link.clone()).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains(r#"method="post""#));
+
+    let get_status = || async {
+        sqlx::query_scalar::<_, String>("SELECT status FROM subscriptions")
+            .fetch_one(&test_app.db_pool)
+            .await
+            .unwrap()
+    };
+
+    assert_eq!(get_status().await, "confirmed");
+
+    // Email clients unsubscribe with one click, posting to the link
+    let response = reqwest::Client::new()
+        .post(unsubscribe_link)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(get_status().await, "unsubscribed");
+}
+
+#[tokio::test]
+async fn unsubscribe_links_without_a_valid_signature_are_rejected() {
+    let test_app = TestApp::spawn_app().await;
+    let client = reqwest::Client::new();
+
+    for token in [Uuid::new_v4().to_string(), String::from("not-a-token")] {
+        let url = format!(
+            "{}/subscriptions/unsubscribe?token={}",
+            test_app.address, token
+        );
+
+        assert_eq!(
+            client.get(&url).send().await.unwrap().status().as_u16(),
+            400
+        );
+        assert_eq!(
+            client.post(&url).send().await.unwrap().status().as_u16(),
+            400
+        );
+    }
+}
+
+#[tokio::test]
+async fn newsletters_include_a_generated_plain_text_part() {
+    let test_app = TestApp::spawn_app().await;
+
+    create_confirmed_subscriber(&test_app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "content": {
+            "html": "<p>Hi {{ subscriber.name }}, read <a href=\"https://blog.test.com\">our blog</a></p>"
+          },
+          "track_clicks": false
+        }))
+        .await;
+    test_app.dispatch_all_pending_emails().await;
+
+    let received_requests = test_app.email_server.received_requests().await.unwrap();
+    let body: SendEmailBody = received_requests.last().unwrap().body_json().unwrap();
+
+    assert_eq!(body.content[0].content_type, "text/plain");
+    assert_eq!(
+        body.content[0].value,
+        "View in browser: http://localhost/archive/newsletter-title\n\nHi Frank, read our blog [1]\n\n[1] https://blog.test.com"
+    );
+    assert_eq!(body.content[1].content_type, "text/html");
+}
+
+#[tokio::test]
+async fn newsletters_use_the_provided_plain_text_part() {
+    let test_app = TestApp::spawn_app().await;
+
+    create_confirmed_subscriber(&test_app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "content": {
+            "html": "<p>Newsletter content</p>",
+            "text": "Plain content for {{ subscriber.name }}"
+          }
+        }))
+        .await;
+    test_app.dispatch_all_pending_emails().await;
+
+    let received_requests = test_app.email_server.received_requests().await.unwrap();
+    let body: SendEmailBody = received_requests.last().unwrap().body_json().unwrap();
+
+    assert_eq!(
+        body.content[0].value,
+        "View in browser: http://localhost/archive/newsletter-title\n\nPlain content for Frank"
+    );
+}
+
+#[tokio::test]
+async fn newsletters_written_in_markdown_are_rendered_with_the_email_layout() {
+    let test_app = TestApp::spawn_app().await;
+
+    create_confirmed_subscriber(&test_app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "content": {
+            "markdown": "# Hi {{ subscriber.name }}\n\nRead [our blog](https://blog.test.com)[^1].\n\n[^1]: Every week."
+          },
+          "track_clicks": false
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    test_app.dispatch_all_pending_emails().await;
+
+    let received_requests = test_app.email_server.received_requests().await.unwrap();
+    let body: SendEmailBody = received_requests.last().unwrap().body_json().unwrap();
+
+    assert_eq!(
+        body.content[0].value,
+        "View in browser: http://localhost/archive/newsletter-title\n\nHi Frank\n\nRead our blog [1]^1.\n\n----------\n\n1. Every week.\n\n[1] https://blog.test.com"
+    );
+    assert!(body.content[1].value.starts_with("<!DOCTYPE html>"));
+    assert!(body.content[1].value.contains(
+        r#"<h1 style="margin: 0 0 16px; font-size: 28px; line-height: 1.25;">Hi Frank</h1>"#
+    ));
+}
+
+#[tokio::test]
+async fn newsletters_returns_400_when_content_check_finds_errors() {
+    let test_app = TestApp::spawn_app().await;
+
+    let response = test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "content": { "html": "<div><h1>Newsletter title</><p>Newsletter content</p></div>" }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Closing tag </> has no element name."));
+}
+
+#[tokio::test]
+async fn newsletters_with_content_errors_are_published_when_overridden() {
+    let test_app = TestApp::spawn_app().await;
+
+    let response = test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "content": { "html": "<div><p>Newsletter content</p>" },
+          "ignore_content_errors": true
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn newsletters_are_sanitised_and_content_warnings_are_returned() {
+    let test_app = TestApp::spawn_app().await;
+
+    create_confirmed_subscriber(&test_app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "content": {
+            "html": "<p>Newsletter content</p><script>alert(1)</script><img src=\"https://test.com/a.png\">"
+          },
+          "track_opens": false
+        }))
+        .await;
+    let newsletter_issue: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(
+        newsletter_issue["content_warnings"],
+        serde_json::json!([
+            "Element <script> was removed.",
+            "Image https://test.com/a.png has no alt text.",
+            "There is no unsubscribe link, add one with {{ unsubscribe_url }}."
+        ])
+    );
+
+    test_app.dispatch_all_pending_emails().await;
+
+    let received_requests = test_app.email_server.received_requests().await.unwrap();
+    let body: SendEmailBody = received_requests.last().unwrap().body_json().unwrap();
+
+    assert_eq!(
+        body.content[1].value,
+        r#"<p style="margin: 0; padding: 8px; font-size: 12px; text-align: center;"><a href="http://localhost/archive/newsletter-title" style="color: #71717a;">View in browser</a></p><p>Newsletter content</p><img src="https://test.com/a.png">"#
+    );
+}
+
+#[tokio::test]
+async fn deliveries_to_invalid_emails_are_skipped() {
+    let test_app = TestApp::spawn_app().await;
+
+    create_confirmed_subscriber(&test_app).await;
+    // Rows written before the email validation existed, or edited by hand
+    sqlx::query(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES (gen_random_uuid(), 'not-an-email', 'Tom', now(), 'confirmed')
+        "#,
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "content": { "html": "<p>Newsletter content</p>" }
+        }))
+        .await;
+    test_app.dispatch_all_pending_emails().await;
+
+    let statuses: Vec<(String, String)> = sqlx::query(
+        r#"
+        SELECT subscriptions.email, issue_deliveries.status
+        FROM issue_deliveries
+        JOIN subscriptions ON subscriptions.id = issue_deliveries.subscriber_id
+        ORDER BY subscriptions.email
+        "#,
+    )
+    .map(|row: sqlx::postgres::PgRow| (row.get("email"), row.get("status")))
+    .fetch_all(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    assert_eq!(
+        statuses,
+        vec![
+            (String::from("not-an-email"), String::from("skipped")),
+            (String::from("test@test.com"), String::from("sent")),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn deliveries_claimed_by_a_stopped_worker_are_sent_once_the_claim_ends() {
+    let test_app = TestApp::spawn_app().await;
+
+    create_confirmed_subscriber(&test_app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "content": { "html": "<p>Newsletter content</p>" }
+        }))
+        .await;
+
+    // A worker claimed the delivery and stopped before sending it
+    sqlx::query("UPDATE issue_deliveries SET claimed_until = now() + interval '5 minutes'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+    test_app.dispatch_all_pending_emails().await;
+
+    let status: String = sqlx::query_scalar("SELECT status FROM issue_deliveries")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    assert_eq!(status, "pending");
+
+    sqlx::query("UPDATE issue_deliveries SET claimed_until = now() - interval '1 second'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+    test_app.dispatch_all_pending_emails().await;
+
+    let status: String = sqlx::query_scalar("SELECT status FROM issue_deliveries")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    assert_eq!(status, "sent");
+}
+
 async fn create_unconfirmed_subscriber(test_app: &TestApp) -> ConfirmationLink {
     let mut body: HashMap<&str, &str> = HashMap::new();
 
@@ -106,7 +575,7 @@ async fn create_unconfirmed_subscriber(test_app: &TestApp) -> ConfirmationLink {
 }
 
 async fn create_confirmed_subscriber(test_app: &TestApp) {
-    let confirmation_link = create_unconfirmed_subscriber(&test_app).await;
+    let confirmation_link = create_unconfirmed_subscriber(test_app).await;
     let client = reqwest::Client::new();
 
     client
@@ -117,3 +586,136 @@ async fn create_confirmed_subscriber(test_app: &TestApp) {
         .error_for_status()
         .unwrap();
 }
+
+async fn put_subscriber_timezone(
+    test_app: &TestApp,
+    subscriber_id: &uuid::Uuid,
+    timezone: serde_json::Value,
+) -> reqwest::Response {
+    reqwest::Client::new()
+        .put(format!(
+            "{}/admin/subscribers/{}/timezone",
+            test_app.address, subscriber_id
+        ))
+        .json(&serde_json::json!({ "timezone": timezone }))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn newsletters_sent_at_a_local_time_are_delivered_as_each_timezone_reaches_it() {
+    let test_app = TestApp::spawn_app().await;
+    let tokyo_subscriber_id = test_app.create_confirmed_subscriber("tokyo@test.com").await;
+    let new_york_subscriber_id = test_app
+        .create_confirmed_subscriber("new-york@test.com")
+        .await;
+
+    for (subscriber_id, timezone) in [
+        (tokyo_subscriber_id, "Asia/Tokyo"),
+        (new_york_subscriber_id, "America/New_York"),
+    ] {
+        let response = put_subscriber_timezone(&test_app, &subscriber_id, timezone.into()).await;
+
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // The local time already passed in Tokyo, but it is still hours away in New York
+    let local_send_at = (chrono::Utc::now().with_timezone(&chrono_tz::Asia::Tokyo)
+        - chrono::Duration::hours(1))
+    .naive_local()
+    .with_nanosecond(0)
+    .unwrap();
+    let response = test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "content": { "html": "<p>Newsletter content</p>" },
+          "local_send_at": local_send_at
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    test_app.dispatch_all_pending_emails().await;
+
+    let received_requests = test_app.email_server.received_requests().await.unwrap();
+    let body: SendEmailBody = received_requests.last().unwrap().body_json().unwrap();
+
+    assert_eq!(body.personalizations[0].to[0].email, "tokyo@test.com");
+
+    let send_at: chrono::DateTime<chrono::Utc> = sqlx::query(
+        "SELECT send_at FROM issue_deliveries WHERE subscriber_id = $1 AND status = 'pending'",
+    )
+    .bind(new_york_subscriber_id)
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap()
+    .get("send_at");
+
+    assert_eq!(
+        send_at
+            .with_timezone(&chrono_tz::America::New_York)
+            .naive_local(),
+        local_send_at
+    );
+}
+
+#[tokio::test]
+async fn newsletters_returns_400_when_local_send_time_is_invalid() {
+    let test_app = TestApp::spawn_app().await;
+    let now = chrono::Utc::now();
+    let test_cases = vec![
+        (
+            serde_json::json!({ "local_send_at": (now - chrono::Duration::days(1)).naive_utc() }),
+            "local time that passed in every timezone",
+        ),
+        (
+            serde_json::json!({
+              "local_send_at": (now + chrono::Duration::days(1)).naive_utc(),
+              "send_at": now + chrono::Duration::days(1)
+            }),
+            "local time and a send time",
+        ),
+    ];
+
+    for (send_time, error_message) in test_cases {
+        let mut body = serde_json::json!({
+          "title": "Newsletter title",
+          "content": { "html": "<p>Newsletter content</p>" }
+        });
+
+        body.as_object_mut()
+            .unwrap()
+            .extend(send_time.as_object().unwrap().clone());
+
+        let response = test_app.post_newsletter(body).await;
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 status when the newsletter had a {}",
+            error_message
+        );
+    }
+}
+
+#[tokio::test]
+async fn subscriber_timezone_returns_400_when_invalid() {
+    let test_app = TestApp::spawn_app().await;
+    let subscriber_id = test_app.create_confirmed_subscriber("frank@test.com").await;
+
+    let response =
+        put_subscriber_timezone(&test_app, &subscriber_id, "Europe/Atlantis".into()).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = put_subscriber_timezone(&test_app, &uuid::Uuid::new_v4(), "UTC".into()).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,209 @@
+use chrono::{Duration, Utc};
+use sqlx::Row;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::TestApp;
+use email_newsletter::config::PendingCleanup;
+use email_newsletter::email_client::SendEmailBody;
+use email_newsletter::pending_subscriptions::PendingSubscriptionsOutcome;
+
+async fn post_pending_subscription(test_app: &TestApp, email: &str) {
+    test_app
+        .post_subscription(serde_json::json!({ "name": "Frank", "email": email }))
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn pending_subscribers_get_a_single_reminder_that_confirms_them() {
+    let test_app = TestApp::spawn_app().await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+
+    post_pending_subscription(&test_app, "frank@test.com").await;
+
+    // Too early for a reminder, the default delay is 24 hours
+    let outcome = test_app
+        .run_pending_subscriptions_job(Utc::now() + Duration::hours(23))
+        .await;
+    assert_eq!(outcome, PendingSubscriptionsOutcome::default());
+
+    let outcome = test_app
+        .run_pending_subscriptions_job(Utc::now() + Duration::hours(25))
+        .await;
+    assert_eq!(
+        outcome,
+        PendingSubscriptionsOutcome {
+            reminders_sent: 1,
+            ..Default::default()
+        }
+    );
+
+    let outcome = test_app
+        .run_pending_subscriptions_job(Utc::now() + Duration::hours(26))
+        .await;
+    assert_eq!(outcome, PendingSubscriptionsOutcome::default());
+
+    let received_requests = test_app.email_server.received_requests().await.unwrap();
+    let reminder: SendEmailBody = received_requests[1].body_json().unwrap();
+    assert_eq!(
+        reminder.subject,
+        "Confirm your subscription to our newsletter"
+    );
+
+    let confirmation_link = test_app.get_confirmation_link(&received_requests[1]).await;
+    reqwest::get(confirmation_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let status: String = sqlx::query("SELECT status FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .get("status");
+    assert_eq!(status, "confirmed");
+}
+
+#[tokio::test]
+async fn pending_subscriptions_are_deleted_after_the_retention_period() {
+    let test_app = TestApp::spawn_app().await;
+    let confirmed_subscriber_id = test_app
+        .create_confirmed_subscriber("confirmed@test.com")
+        .await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    post_pending_subscription(&test_app, "frank@test.com").await;
+
+    // No reminder is sent to subscriptions about to be cleaned up
+    let outcome = test_app
+        .run_pending_subscriptions_job(Utc::now() + Duration::days(31))
+        .await;
+    assert_eq!(
+        outcome,
+        PendingSubscriptionsOutcome {
+            deleted: 1,
+            tokens_revoked: 1,
+            ..Default::default()
+        }
+    );
+
+    let subscriber_ids: Vec<uuid::Uuid> = sqlx::query_scalar("SELECT id FROM subscriptions")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscriber_ids, vec![confirmed_subscriber_id]);
+}
+
+#[tokio::test]
+async fn anonymised_pending_subscriptions_cannot_be_confirmed() {
+    let test_app =
+        TestApp::spawn_app_with(|config| config.set_pending_cleanup(PendingCleanup::Anonymise))
+            .await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    post_pending_subscription(&test_app, "frank@test.com").await;
+
+    let outcome = test_app
+        .run_pending_subscriptions_job(Utc::now() + Duration::days(31))
+        .await;
+    assert_eq!(
+        outcome,
+        PendingSubscriptionsOutcome {
+            anonymised: 1,
+            tokens_revoked: 1,
+            ..Default::default()
+        }
+    );
+
+    let row = sqlx::query("SELECT id, email, name, status FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        row.get::<String, _>("email"),
+        format!("{}@anonymised.invalid", row.get::<uuid::Uuid, _>("id"))
+    );
+    assert_eq!(row.get::<String, _>("name"), "Anonymised");
+    assert_eq!(row.get::<String, _>("status"), "pending_confirmation");
+
+    let received_requests = test_app.email_server.received_requests().await.unwrap();
+    let confirmation_link = test_app.get_confirmation_link(&received_requests[0]).await;
+    let response = reqwest::get(confirmation_link.html).await.unwrap();
+    assert_eq!(response.status(), 404);
+
+    // Anonymised subscriptions are not cleaned up again
+    let outcome = test_app
+        .run_pending_subscriptions_job(Utc::now() + Duration::days(32))
+        .await;
+    assert_eq!(outcome, PendingSubscriptionsOutcome::default());
+}
+
+#[tokio::test]
+async fn every_confirmation_link_of_an_anonymised_subscription_is_revoked() {
+    let test_app =
+        TestApp::spawn_app_with(|config| config.set_pending_cleanup(PendingCleanup::Anonymise))
+            .await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    post_pending_subscription(&test_app, "frank@test.com").await;
+    test_app
+        .run_pending_subscriptions_job(Utc::now() + Duration::hours(25))
+        .await;
+
+    let tokens: i64 = sqlx::query_scalar("SELECT count(*) FROM subscription_tokens")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(tokens, 2);
+
+    let outcome = test_app
+        .run_pending_subscriptions_job(Utc::now() + Duration::days(31))
+        .await;
+    assert_eq!(
+        outcome,
+        PendingSubscriptionsOutcome {
+            anonymised: 1,
+            tokens_revoked: 2,
+            ..Default::default()
+        }
+    );
+
+    let received_requests = test_app.email_server.received_requests().await.unwrap();
+    assert_eq!(received_requests.len(), 2);
+
+    for request in received_requests.iter() {
+        let confirmation_link = test_app.get_confirmation_link(request).await;
+        let response = reqwest::get(confirmation_link.html).await.unwrap();
+        assert_eq!(response.status(), 404);
+    }
+
+    let tokens: i64 = sqlx::query_scalar("SELECT count(*) FROM subscription_tokens")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(tokens, 0);
+}
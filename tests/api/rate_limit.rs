@@ -0,0 +1,52 @@
+use email_newsletter::config::RateLimitSettings;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::TestApp;
+
+// The bucket script is unit tested in `rate_limit`, this checks it with the Redis it runs on
+#[tokio::test]
+#[ignore = "needs a Redis server with scripting, run with `cargo test -- --ignored`"]
+async fn deliveries_to_a_domain_are_sent_at_its_rate() {
+    let test_app = TestApp::spawn_app_with(|config| {
+        config.set_email_client_rate_limit(Some(RateLimitSettings {
+            messages_per_second: 100,
+            messages_per_second_per_domain: 2,
+            fail_open: false,
+        }))
+    })
+    .await;
+    // A domain of its own, so the bucket is not shared with other tests
+    let domain = format!("{}.com", Uuid::new_v4());
+
+    for name in ["frank", "tom", "jerry"] {
+        test_app
+            .create_confirmed_subscriber(&format!("{}@{}", name, domain))
+            .await;
+    }
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&test_app.email_server)
+        .await;
+
+    // The confirmation emails took the burst of the domain
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "content": { "html": "<p>Newsletter content</p>" }
+        }))
+        .await;
+
+    let started_at = Instant::now();
+
+    test_app.dispatch_all_pending_emails().await;
+
+    // Two emails go out right away with the refilled bucket, the third one waits for the next token
+    assert!(started_at.elapsed() >= Duration::from_millis(400));
+}
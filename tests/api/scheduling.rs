@@ -0,0 +1,393 @@
+use chrono::{DateTime, Datelike, Duration, Timelike, Utc, Weekday};
+use email_newsletter::scheduler::{run_due_jobs, SchedulerOutcome, SCHEDULER_LOCK_ID};
+use sqlx::Row;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::TestApp;
+
+async fn count_deliveries(test_app: &TestApp) -> i64 {
+    sqlx::query("SELECT count(*) AS deliveries FROM issue_deliveries")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .get("deliveries")
+}
+
+async fn get_recurring_newsletters(test_app: &TestApp) -> serde_json::Value {
+    reqwest::get(format!("{}/admin/recurring-newsletters", test_app.address))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+fn parse_time(time: &serde_json::Value) -> DateTime<Utc> {
+    time.as_str().unwrap().parse().unwrap()
+}
+
+fn newsletter() -> serde_json::Value {
+    serde_json::json!({
+      "title": "Weekly digest",
+      "content": { "html": "<p>Newsletter content</p>" }
+    })
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_sent_to_the_subscribers_confirmed_at_send_time() {
+    let test_app = TestApp::spawn_app().await;
+    let send_at = Utc::now() + Duration::hours(1);
+    let mut body = newsletter();
+
+    body["send_at"] = serde_json::json!(send_at);
+
+    let response = test_app.post_newsletter(body).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let newsletter_issue: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(parse_time(&newsletter_issue["send_at"]), send_at);
+
+    test_app.create_confirmed_subscriber("frank@test.com").await;
+
+    assert_eq!(
+        test_app.run_scheduler(Utc::now()).await,
+        SchedulerOutcome::Completed {
+            scheduled_issues: 0,
+            recurring_newsletters: 0,
+            subject_tests: 0
+        }
+    );
+    assert_eq!(count_deliveries(&test_app).await, 0);
+
+    assert_eq!(
+        test_app.run_scheduler(send_at).await,
+        SchedulerOutcome::Completed {
+            scheduled_issues: 1,
+            recurring_newsletters: 0,
+            subject_tests: 0
+        }
+    );
+    assert_eq!(count_deliveries(&test_app).await, 1);
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app.dispatch_all_pending_emails().await;
+
+    // The issue is enqueued only once
+    test_app.run_scheduler(send_at + Duration::hours(1)).await;
+
+    assert_eq!(count_deliveries(&test_app).await, 1);
+}
+
+#[tokio::test]
+async fn recurring_newsletters_publish_an_issue_every_time_they_fire() {
+    let test_app = TestApp::spawn_app().await;
+
+    test_app.create_confirmed_subscriber("frank@test.com").await;
+
+    let response = test_app
+        .post_recurring_newsletter(serde_json::json!({
+          "cron": "0 8 * * MON",
+          "timezone": "Europe/Madrid",
+          "newsletter": newsletter()
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let mut last_run_at = None;
+
+    for _ in 0..2 {
+        let recurring_newsletter = &get_recurring_newsletters(&test_app).await[0];
+        let next_run_at = parse_time(&recurring_newsletter["next_run_at"]);
+        let local_next_run_at = next_run_at.with_timezone(&chrono_tz::Europe::Madrid);
+
+        // Local time is kept when the daylight saving time changes
+        assert_eq!(local_next_run_at.weekday(), Weekday::Mon);
+        assert_eq!(local_next_run_at.hour(), 8);
+        assert_eq!(
+            recurring_newsletter["last_run_at"]
+                .as_str()
+                .map(|time| time.parse().unwrap()),
+            last_run_at
+        );
+        assert_eq!(
+            test_app.run_scheduler(next_run_at).await,
+            SchedulerOutcome::Completed {
+                scheduled_issues: 0,
+                recurring_newsletters: 1,
+                subject_tests: 0
+            }
+        );
+        // Running again at the same time does not publish the issue twice
+        assert_eq!(
+            test_app.run_scheduler(next_run_at).await,
+            SchedulerOutcome::Completed {
+                scheduled_issues: 0,
+                recurring_newsletters: 0,
+                subject_tests: 0
+            }
+        );
+
+        last_run_at = Some(next_run_at);
+    }
+
+    assert_eq!(count_deliveries(&test_app).await, 2);
+}
+
+#[tokio::test]
+async fn recurring_newsletters_with_a_corrupt_cron_are_skipped() {
+    let test_app = TestApp::spawn_app().await;
+
+    for cron in ["*/5 * * * *", "*/10 * * * *"] {
+        test_app
+            .post_recurring_newsletter(serde_json::json!({
+              "cron": cron,
+              "timezone": "UTC",
+              "newsletter": newsletter()
+            }))
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+
+    sqlx::query(
+        "UPDATE recurring_newsletters SET cron = 'every monday' WHERE cron = '*/10 * * * *'",
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    // The valid newsletter still fires
+    assert_eq!(
+        test_app.run_scheduler(Utc::now() + Duration::days(1)).await,
+        SchedulerOutcome::Completed {
+            scheduled_issues: 0,
+            recurring_newsletters: 1,
+            subject_tests: 0
+        }
+    );
+}
+
+#[tokio::test]
+async fn subject_tests_with_a_corrupt_metric_do_not_stop_the_scheduled_issues() {
+    let test_app = TestApp::spawn_app().await;
+    let send_at = Utc::now() + Duration::hours(1);
+    let subject_test = serde_json::json!({
+      "subjects": ["Our weekly digest", "Don't miss this week's news"],
+      "sample_percent": 10,
+      "window_hours": 4
+    });
+
+    for i in 0..3 {
+        test_app
+            .create_confirmed_subscriber(&format!("subscriber{}@test.com", i))
+            .await;
+    }
+
+    // A running test and a scheduled one, whose metrics are edited by hand in the database
+    let mut running_test = newsletter();
+
+    running_test["subject_test"] = subject_test.clone();
+    test_app
+        .post_newsletter(running_test)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let mut scheduled_test = newsletter();
+
+    scheduled_test["send_at"] = serde_json::json!(send_at);
+    scheduled_test["subject_test"] = subject_test;
+    test_app
+        .post_newsletter(scheduled_test)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    sqlx::query("UPDATE subject_tests SET metric = 'replies'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    let mut scheduled_issue = newsletter();
+
+    scheduled_issue["send_at"] = serde_json::json!(send_at);
+
+    let response = test_app.post_newsletter(scheduled_issue).await;
+    let newsletter_issue: serde_json::Value = response.json().await.unwrap();
+
+    // The valid issue is still sent
+    assert_eq!(
+        test_app.run_scheduler(send_at + Duration::hours(4)).await,
+        SchedulerOutcome::Completed {
+            scheduled_issues: 1,
+            recurring_newsletters: 0,
+            subject_tests: 0
+        }
+    );
+
+    let deliveries: i64 = sqlx::query(
+        "SELECT count(*) AS deliveries FROM issue_deliveries WHERE newsletter_issue_id = $1",
+    )
+    .bind(uuid::Uuid::parse_str(newsletter_issue["id"].as_str().unwrap()).unwrap())
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap()
+    .get("deliveries");
+
+    assert_eq!(deliveries, 3);
+
+    let statuses: Vec<String> = sqlx::query("SELECT status FROM subject_tests")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| row.get("status"))
+        .collect();
+
+    assert_eq!(statuses, vec!["failed", "failed"]);
+}
+
+#[tokio::test]
+async fn deleted_recurring_newsletters_do_not_fire() {
+    let test_app = TestApp::spawn_app().await;
+    let client = reqwest::Client::new();
+    let response = test_app
+        .post_recurring_newsletter(serde_json::json!({
+          "cron": "*/5 * * * *",
+          "timezone": "UTC",
+          "newsletter": newsletter()
+        }))
+        .await;
+    let recurring_newsletter: serde_json::Value = response.json().await.unwrap();
+    let url = format!(
+        "{}/admin/recurring-newsletters/{}",
+        test_app.address,
+        recurring_newsletter["id"].as_str().unwrap()
+    );
+
+    assert_eq!(
+        client.delete(&url).send().await.unwrap().status().as_u16(),
+        200
+    );
+    assert_eq!(
+        client.delete(&url).send().await.unwrap().status().as_u16(),
+        404
+    );
+    assert_eq!(
+        test_app.run_scheduler(Utc::now() + Duration::days(1)).await,
+        SchedulerOutcome::Completed {
+            scheduled_issues: 0,
+            recurring_newsletters: 0,
+            subject_tests: 0
+        }
+    );
+}
+
+#[tokio::test]
+async fn invalid_schedules_are_rejected() {
+    let test_app = TestApp::spawn_app().await;
+    let mut body = newsletter();
+
+    body["send_at"] = serde_json::json!(Utc::now() - Duration::minutes(1));
+
+    let response = test_app.post_newsletter(body).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    let mut scheduled_newsletter = newsletter();
+
+    scheduled_newsletter["send_at"] = serde_json::json!(Utc::now() + Duration::hours(1));
+
+    let test_cases = vec![
+        (
+            "0 8 * * MON",
+            "Mars/Olympus",
+            newsletter(),
+            "unknown timezone",
+        ),
+        ("0 8 * *", "UTC", newsletter(), "missing cron field"),
+        ("0 8 * * 1", "UTC", newsletter(), "numeric day of week"),
+        (
+            "0 8 30 2 *",
+            "UTC",
+            newsletter(),
+            "schedule that never fires",
+        ),
+        (
+            "0 8 * * MON",
+            "UTC",
+            scheduled_newsletter,
+            "send time in a recurring newsletter",
+        ),
+        (
+            "0 8 * * MON",
+            "UTC",
+            serde_json::json!({ "title": "Weekly digest", "content": {} }),
+            "newsletter without content",
+        ),
+    ];
+
+    for (cron, timezone, newsletter, error_message) in test_cases {
+        let response = test_app
+            .post_recurring_newsletter(serde_json::json!({
+              "cron": cron,
+              "timezone": timezone,
+              "newsletter": newsletter
+            }))
+            .await;
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 status when the recurring newsletter had a {}",
+            error_message
+        );
+    }
+}
+
+#[tokio::test]
+async fn scheduler_does_not_run_while_another_instance_holds_the_lock() {
+    let test_app = TestApp::spawn_app().await;
+    let send_at = Utc::now() + Duration::hours(1);
+    let mut body = newsletter();
+
+    body["send_at"] = serde_json::json!(send_at);
+    test_app.create_confirmed_subscriber("frank@test.com").await;
+    test_app.post_newsletter(body).await;
+
+    // Another instance is running the scheduler
+    let mut transaction = test_app.db_pool.begin().await.unwrap();
+    let is_locked: bool = sqlx::query("SELECT pg_try_advisory_xact_lock($1)")
+        .bind(SCHEDULER_LOCK_ID)
+        .fetch_one(&mut transaction)
+        .await
+        .unwrap()
+        .get(0);
+
+    assert!(is_locked);
+    assert_eq!(
+        run_due_jobs(&test_app.db_pool, send_at).await.unwrap(),
+        SchedulerOutcome::Locked
+    );
+    assert_eq!(count_deliveries(&test_app).await, 0);
+
+    transaction.rollback().await.unwrap();
+
+    assert_eq!(
+        run_due_jobs(&test_app.db_pool, send_at).await.unwrap(),
+        SchedulerOutcome::Completed {
+            scheduled_issues: 1,
+            recurring_newsletters: 0,
+            subject_tests: 0
+        }
+    );
+}
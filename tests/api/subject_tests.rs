@@ -0,0 +1,350 @@
+use chrono::{Duration, Utc};
+use email_newsletter::email_client::SendEmailBody;
+use email_newsletter::scheduler::SchedulerOutcome;
+use sqlx::{postgres::PgRow, Row};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::TestApp;
+
+async fn create_confirmed_subscribers(test_app: &TestApp, count: usize) {
+    for i in 0..count {
+        test_app
+            .create_confirmed_subscriber(&format!("subscriber{}@test.com", i))
+            .await;
+    }
+}
+
+async fn get_subject_test(test_app: &TestApp, newsletter_issue_id: &str) -> reqwest::Response {
+    reqwest::get(format!(
+        "{}/admin/issues/{}/subject-test",
+        test_app.address, newsletter_issue_id
+    ))
+    .await
+    .unwrap()
+}
+
+/// Number of deliveries of each subject of the test, and of those that get the winner
+async fn count_deliveries_by_variant(test_app: &TestApp) -> Vec<(Option<i16>, i64)> {
+    sqlx::query(
+        r#"
+        SELECT subject_variant, count(*) AS deliveries
+        FROM issue_deliveries
+        GROUP BY subject_variant
+        ORDER BY subject_variant NULLS LAST
+        "#,
+    )
+    .map(|row: PgRow| (row.get("subject_variant"), row.get("deliveries")))
+    .fetch_all(&test_app.db_pool)
+    .await
+    .unwrap()
+}
+
+fn newsletter() -> serde_json::Value {
+    serde_json::json!({
+      "title": "Weekly digest",
+      "content": { "html": "<p>Newsletter content</p>" },
+      "subject_test": {
+        "subjects": ["Our weekly digest", "Don't miss this week's news"],
+        "sample_percent": 10,
+        "window_hours": 4
+      }
+    })
+}
+
+#[tokio::test]
+async fn subject_with_the_highest_open_rate_is_sent_to_the_rest_of_the_audience() {
+    let test_app = TestApp::spawn_app().await;
+
+    create_confirmed_subscribers(&test_app, 10).await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(10)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app.post_newsletter(newsletter()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let newsletter_issue: serde_json::Value = response.json().await.unwrap();
+    let newsletter_issue_id = newsletter_issue["id"].as_str().unwrap();
+
+    // Each subject is sent to 10% of the audience
+    assert_eq!(
+        count_deliveries_by_variant(&test_app).await,
+        vec![(Some(0), 1), (Some(1), 1)]
+    );
+
+    test_app.dispatch_all_pending_emails().await;
+
+    let received_requests = test_app.email_server.received_requests().await.unwrap();
+    let samples: Vec<SendEmailBody> = received_requests
+        .iter()
+        .rev()
+        .take(2)
+        .map(|request| request.body_json().unwrap())
+        .collect();
+    let winner = samples
+        .iter()
+        .find(|sample| sample.subject == "Don't miss this week's news")
+        .unwrap();
+
+    assert!(samples
+        .iter()
+        .any(|sample| sample.subject == "Our weekly digest"));
+
+    reqwest::get(format!(
+        "{}/t/o/{}.gif",
+        test_app.address, winner.personalizations[0].custom_args["delivery_id"]
+    ))
+    .await
+    .unwrap();
+
+    let subject_test: serde_json::Value = get_subject_test(&test_app, newsletter_issue_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(subject_test["status"], "running");
+    assert_eq!(subject_test["results"][1]["opened"], 1);
+
+    // The winner is not picked before the window ends
+    assert_eq!(
+        test_app
+            .run_scheduler(Utc::now() + Duration::hours(3))
+            .await,
+        SchedulerOutcome::Completed {
+            scheduled_issues: 0,
+            recurring_newsletters: 0,
+            subject_tests: 0
+        }
+    );
+    assert_eq!(
+        test_app
+            .run_scheduler(Utc::now() + Duration::hours(5))
+            .await,
+        SchedulerOutcome::Completed {
+            scheduled_issues: 0,
+            recurring_newsletters: 0,
+            subject_tests: 1
+        }
+    );
+
+    test_app.dispatch_all_pending_emails().await;
+
+    let received_requests = test_app.email_server.received_requests().await.unwrap();
+
+    for request in received_requests.iter().rev().take(8) {
+        let body: SendEmailBody = request.body_json().unwrap();
+
+        assert_eq!(body.subject, "Don't miss this week's news");
+    }
+
+    let subject_test: serde_json::Value = get_subject_test(&test_app, newsletter_issue_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(subject_test["status"], "completed");
+    assert_eq!(subject_test["winner"], "Don't miss this week's news");
+    assert_eq!(subject_test["results"][0]["sent"], 1);
+    assert_eq!(subject_test["results"][0]["open_rate"], 0.0);
+    assert_eq!(subject_test["results"][1]["opened"], 1);
+    assert_eq!(subject_test["results"][1]["open_rate"], 1.0);
+}
+
+#[tokio::test]
+async fn scheduled_issues_start_the_subject_test_at_send_time() {
+    let test_app = TestApp::spawn_app().await;
+    let send_at = Utc::now() + Duration::hours(1);
+    let mut body = newsletter();
+
+    body["send_at"] = serde_json::json!(send_at);
+    create_confirmed_subscribers(&test_app, 3).await;
+
+    let response = test_app.post_newsletter(body).await;
+    let newsletter_issue: serde_json::Value = response.json().await.unwrap();
+    let newsletter_issue_id = newsletter_issue["id"].as_str().unwrap();
+    let subject_test: serde_json::Value = get_subject_test(&test_app, newsletter_issue_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(subject_test["status"], "scheduled");
+    assert!(count_deliveries_by_variant(&test_app).await.is_empty());
+
+    test_app.run_scheduler(send_at).await;
+
+    assert_eq!(
+        count_deliveries_by_variant(&test_app).await,
+        vec![(Some(0), 1), (Some(1), 1)]
+    );
+
+    test_app.run_scheduler(send_at + Duration::hours(4)).await;
+
+    assert_eq!(
+        count_deliveries_by_variant(&test_app).await,
+        vec![(Some(0), 1), (Some(1), 1), (None, 1)]
+    );
+}
+
+#[tokio::test]
+async fn cancelled_subject_tests_do_not_send_the_winner() {
+    let test_app = TestApp::spawn_app().await;
+
+    create_confirmed_subscribers(&test_app, 3).await;
+
+    let response = test_app.post_newsletter(newsletter()).await;
+    let newsletter_issue: serde_json::Value = response.json().await.unwrap();
+    let newsletter_issue_id = newsletter_issue["id"].as_str().unwrap();
+
+    let response = test_app
+        .post_issue_delivery_change(newsletter_issue_id, "cancel")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    test_app
+        .run_scheduler(Utc::now() + Duration::hours(5))
+        .await;
+
+    let subject_test: serde_json::Value = get_subject_test(&test_app, newsletter_issue_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(subject_test["status"], "cancelled");
+    assert_eq!(
+        count_deliveries_by_variant(&test_app).await,
+        vec![(Some(0), 1), (Some(1), 1)]
+    );
+}
+
+#[tokio::test]
+async fn newsletters_returns_400_when_subject_test_is_invalid() {
+    let test_app = TestApp::spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({ "subjects": ["Only one"], "sample_percent": 10, "window_hours": 4 }),
+            "a single subject",
+        ),
+        (
+            serde_json::json!({ "subjects": ["A", "B"], "sample_percent": 50, "window_hours": 4 }),
+            "samples covering the whole audience",
+        ),
+        (
+            serde_json::json!({ "subjects": ["A", "B"], "sample_percent": 10, "window_hours": 0 }),
+            "an empty window",
+        ),
+        (
+            serde_json::json!({
+                "subjects": ["A", "B"], "sample_percent": 10, "window_hours": 4, "metric": "clicks"
+            }),
+            "a metric that is not tracked",
+        ),
+    ];
+
+    for (subject_test, error_message) in test_cases {
+        let mut body = newsletter();
+
+        body["subject_test"] = subject_test;
+        body["track_clicks"] = serde_json::json!(false);
+
+        let response = test_app.post_newsletter(body).await;
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 status when the subject test had {}",
+            error_message
+        );
+    }
+
+    let mut body = newsletter();
+
+    body["local_send_at"] = serde_json::json!("2100-01-01T09:00:00");
+
+    let response = test_app.post_newsletter(body).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn subject_test_returns_404_when_issue_has_no_test() {
+    let test_app = TestApp::spawn_app().await;
+    let mut body = newsletter();
+
+    body.as_object_mut().unwrap().remove("subject_test");
+
+    let response = test_app.post_newsletter(body).await;
+    let newsletter_issue: serde_json::Value = response.json().await.unwrap();
+
+    let response = get_subject_test(&test_app, newsletter_issue["id"].as_str().unwrap()).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn paused_subject_tests_do_not_send_the_winner_until_resumed() {
+    let test_app = TestApp::spawn_app().await;
+
+    create_confirmed_subscribers(&test_app, 3).await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app.post_newsletter(newsletter()).await;
+    let newsletter_issue: serde_json::Value = response.json().await.unwrap();
+    let newsletter_issue_id = newsletter_issue["id"].as_str().unwrap();
+
+    // Every sample is sent, the test only waits for its window to end
+    test_app.dispatch_all_pending_emails().await;
+
+    let response = test_app
+        .post_issue_delivery_change(newsletter_issue_id, "pause")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let subject_test: serde_json::Value = get_subject_test(&test_app, newsletter_issue_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(subject_test["status"], "paused");
+
+    test_app
+        .run_scheduler(Utc::now() + Duration::hours(5))
+        .await;
+
+    assert_eq!(
+        count_deliveries_by_variant(&test_app).await,
+        vec![(Some(0), 1), (Some(1), 1)]
+    );
+
+    let response = test_app
+        .post_issue_delivery_change(newsletter_issue_id, "resume")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    test_app
+        .run_scheduler(Utc::now() + Duration::hours(5))
+        .await;
+
+    assert_eq!(
+        count_deliveries_by_variant(&test_app).await,
+        vec![(Some(0), 1), (Some(1), 1), (None, 1)]
+    );
+}
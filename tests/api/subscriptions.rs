@@ -1,13 +1,18 @@
 use email_newsletter::domain::subscriber::Subscriber;
-use sqlx::{postgres::PgRow, Row};
+use sqlx::{postgres::PgRow, types::Json, Row};
 use std::collections::HashMap;
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
 
 use crate::helpers::TestApp;
 use email_newsletter::{
-    domain::subscriber_email::SubscriberEmail, domain::subscriber_name::SubscriberName,
+    content::check::check_html,
+    domain::subscriber_attributes::{AttributesMap, SubscriberAttributes},
+    domain::subscriber_email::SubscriberEmail,
+    domain::subscriber_locale::SubscriberLocale,
+    domain::subscriber_name::SubscriberName,
     domain::subscriber_status::SubscriberStatus,
+    domain::subscriber_timezone::SubscriberTimezone,
 };
 
 #[tokio::test]
@@ -45,22 +50,31 @@ async fn subscribe_persists_the_new_subscriber() {
 
     test_app.post_subscription(body).await;
 
-    let new_subscription: Subscriber =
-        sqlx::query("SELECT id, email, name, subscribed_at, status FROM subscriptions;")
-            .map(|row: PgRow| Subscriber {
-                id: row.get("id"),
-                email: SubscriberEmail::parse(row.get("email")).unwrap(),
-                name: SubscriberName::parse(row.get("name")).unwrap(),
-                subscribed_at: row.get("subscribed_at"),
-                status: SubscriberStatus::parse(row.get("status")).unwrap(),
-            })
-            .fetch_one(&test_app.db_pool)
-            .await
-            .expect("Query to fetch subscriptions failed.");
+    let new_subscription: Subscriber = sqlx::query(
+        "SELECT id, email, name, subscribed_at, status, attributes, locale, timezone, engagement_score, list FROM subscriptions;",
+    )
+    .map(|row: PgRow| Subscriber {
+        id: row.get("id"),
+        email: SubscriberEmail::parse(row.get("email")).unwrap(),
+        name: SubscriberName::parse(row.get("name")).unwrap(),
+        subscribed_at: row.get("subscribed_at"),
+        status: SubscriberStatus::parse(row.get("status")).unwrap(),
+        attributes: SubscriberAttributes::from(row.get::<Json<AttributesMap>, _>("attributes").0),
+        locale: SubscriberLocale::parse(row.get("locale")).unwrap(),
+        timezone: row
+            .get::<Option<String>, _>("timezone")
+            .map(|timezone| SubscriberTimezone::parse(timezone).unwrap()),
+        engagement_score: row.get("engagement_score"),
+        list: row.get("list"),
+    })
+    .fetch_one(&test_app.db_pool)
+    .await
+    .expect("Query to fetch subscriptions failed.");
 
     assert_eq!(new_subscription.email.as_ref(), "test@test.com");
     assert_eq!(new_subscription.name.as_ref(), "Test");
     assert_eq!(new_subscription.status.as_ref(), "pending_confirmation");
+    assert_eq!(new_subscription.locale.as_ref(), "en");
 }
 
 #[tokio::test]
@@ -147,3 +161,188 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
 
     assert_eq!(received_requests.len(), 1);
 }
+
+#[tokio::test]
+async fn subscribe_sends_a_confirmation_email_with_valid_html() {
+    let test_app = TestApp::spawn_app().await;
+    let mut body = HashMap::new();
+
+    body.insert("name", "Test");
+    body.insert("email", "test@test.com");
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app.post_subscription(body).await;
+
+    let received_requests = &test_app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&received_requests[0].body).unwrap();
+    let content_check = check_html(body["content"][1]["value"].as_str().unwrap());
+
+    assert!(
+        content_check.errors.is_empty(),
+        "The confirmation email has invalid HTML: {:?}",
+        content_check.errors
+    );
+}
+
+#[tokio::test]
+async fn subscribe_sends_the_confirmation_email_in_the_accepted_language() {
+    let test_app = TestApp::spawn_app().await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions", test_app.address))
+        .header("Accept-Language", "fr-FR, es-ES;q=0.9, en;q=0.8")
+        .json(&serde_json::json!({ "name": "Test", "email": "test@test.com" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let received_requests = &test_app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&received_requests[0].body).unwrap();
+    let locale: String = sqlx::query("SELECT locale FROM subscriptions")
+        .map(|row: PgRow| row.get("locale"))
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    assert_eq!(body["subject"], "Bienvenido a nuestra newsletter");
+    assert_eq!(locale, "es");
+
+    test_app.get_confirmation_link(&received_requests[0]).await;
+}
+
+#[tokio::test]
+async fn subscribe_stores_the_locale_chosen_in_the_form() {
+    let test_app = TestApp::spawn_app().await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions", test_app.address))
+        .header("Accept-Language", "es")
+        .json(&serde_json::json!({ "name": "Test", "email": "test@test.com", "locale": "pt_br" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let received_requests = &test_app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&received_requests[0].body).unwrap();
+    let locale: String = sqlx::query("SELECT locale FROM subscriptions")
+        .map(|row: PgRow| row.get("locale"))
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    // There is no Portuguese translation, so the default one is sent
+    assert_eq!(body["subject"], "Welcome to our newsletter");
+    assert_eq!(locale, "pt-BR");
+}
+
+#[tokio::test]
+async fn subscribe_returns_400_when_locale_is_invalid() {
+    let test_app = TestApp::spawn_app().await;
+
+    let response = test_app
+        .post_subscription(serde_json::json!({
+            "name": "Test",
+            "email": "test@test.com",
+            "locale": "not a locale"
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+async fn subscribe_with_timezone(
+    test_app: &TestApp,
+    accept_language: &str,
+    body: serde_json::Value,
+) -> Option<String> {
+    let _mock_guard = Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&test_app.email_server)
+        .await;
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions", test_app.address))
+        .header("Accept-Language", accept_language)
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    sqlx::query("SELECT timezone FROM subscriptions WHERE email = $1")
+        .bind(body["email"].as_str().unwrap())
+        .map(|row: PgRow| row.get("timezone"))
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn subscribe_infers_the_timezone_from_the_locale() {
+    let test_app = TestApp::spawn_app().await;
+    let test_cases = vec![
+        ("en", "pt_br", None, Some("America/Sao_Paulo")),
+        ("de, es-ES;q=0.9", "", None, Some("Europe/Madrid")),
+        ("en-US", "", Some("Asia/Tokyo"), Some("Asia/Tokyo")),
+        ("en", "", None, None),
+    ];
+
+    for (index, (accept_language, locale, timezone, expected_timezone)) in
+        test_cases.into_iter().enumerate()
+    {
+        let mut body = serde_json::json!({
+            "name": "Test",
+            "email": format!("test{}@test.com", index),
+            "timezone": timezone
+        });
+
+        if !locale.is_empty() {
+            body["locale"] = serde_json::json!(locale);
+        }
+
+        assert_eq!(
+            subscribe_with_timezone(&test_app, accept_language, body).await,
+            expected_timezone.map(String::from),
+            "Unexpected timezone for Accept-Language {:?} and locale {:?}",
+            accept_language,
+            locale
+        );
+    }
+}
+
+#[tokio::test]
+async fn subscribe_returns_400_when_timezone_is_invalid() {
+    let test_app = TestApp::spawn_app().await;
+
+    let response = test_app
+        .post_subscription(serde_json::json!({
+            "name": "Test",
+            "email": "test@test.com",
+            "timezone": "Europe/Atlantis"
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -1,13 +1,17 @@
-use sqlx::{postgres::PgRow, Row};
+use sqlx::{postgres::PgRow, types::Json, Row};
 use std::collections::HashMap;
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
 
 use crate::helpers::TestApp;
+use email_newsletter::config::ConfirmationRedirects;
 use email_newsletter::domain::subscriber::Subscriber;
+use email_newsletter::domain::subscriber_attributes::{AttributesMap, SubscriberAttributes};
 use email_newsletter::domain::subscriber_email::SubscriberEmail;
+use email_newsletter::domain::subscriber_locale::SubscriberLocale;
 use email_newsletter::domain::subscriber_name::SubscriberName;
 use email_newsletter::domain::subscriber_status::SubscriberStatus;
+use email_newsletter::domain::subscriber_timezone::SubscriberTimezone;
 
 #[tokio::test]
 async fn subcriptions_without_token_are_rejected_with_400() {
@@ -15,7 +19,7 @@ async fn subcriptions_without_token_are_rejected_with_400() {
     let client = reqwest::Client::new();
 
     let response = client
-        .get(&format!("{}/subscriptions/confirm", &app.address))
+        .get(format!("{}/subscriptions/confirm", &app.address))
         .send()
         .await
         .expect("Failed to execute request.");
@@ -78,21 +82,272 @@ async fn subscriptions_change_to_confirmed_after_clicking_confirmation_link() {
         .error_for_status()
         .unwrap();
 
-    let subscriber =
-        sqlx::query("SELECT id, email, name, subscribed_at, status FROM subscriptions;")
-            .map(|row: PgRow| Subscriber {
-                id: row.get("id"),
-                email: SubscriberEmail::parse(row.get("email")).unwrap(),
-                name: SubscriberName::parse(row.get("name")).unwrap(),
-                subscribed_at: row.get("subscribed_at"),
-                status: SubscriberStatus::parse(row.get("status")).unwrap(),
-            })
-            .fetch_one(&test_app.db_pool)
-            .await
-            .expect("Failed to fetch saved subscription.");
+    let subscriber = sqlx::query(
+        "SELECT id, email, name, subscribed_at, status, attributes, locale, timezone, engagement_score, list FROM subscriptions;",
+    )
+    .map(|row: PgRow| Subscriber {
+        id: row.get("id"),
+        email: SubscriberEmail::parse(row.get("email")).unwrap(),
+        name: SubscriberName::parse(row.get("name")).unwrap(),
+        subscribed_at: row.get("subscribed_at"),
+        status: SubscriberStatus::parse(row.get("status")).unwrap(),
+        attributes: SubscriberAttributes::from(row.get::<Json<AttributesMap>, _>("attributes").0),
+        locale: SubscriberLocale::parse(row.get("locale")).unwrap(),
+        timezone: row
+            .get::<Option<String>, _>("timezone")
+            .map(|timezone| SubscriberTimezone::parse(timezone).unwrap()),
+        engagement_score: row.get("engagement_score"),
+        list: row.get("list"),
+    })
+    .fetch_one(&test_app.db_pool)
+    .await
+    .expect("Failed to fetch saved subscription.");
 
     assert_eq!(
         subscriber.status.as_ref(),
         SubscriberStatus::Confirmed.as_ref()
     );
 }
+
+/// Subscribes a new subscriber and returns its confirmation link
+async fn subscribe(test_app: &TestApp) -> reqwest::Url {
+    subscribe_with(
+        test_app,
+        serde_json::json!({ "name": "Frank", "email": "frank@test.com" }),
+    )
+    .await
+}
+
+/// Subscribes a new subscriber with the given body and returns its confirmation link
+async fn subscribe_with(test_app: &TestApp, body: serde_json::Value) -> reqwest::Url {
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app.post_subscription(body).await;
+
+    let received_requests = &test_app.email_server.received_requests().await.unwrap();
+
+    test_app
+        .get_confirmation_link(&received_requests[0])
+        .await
+        .html
+}
+
+#[tokio::test]
+async fn confirmation_link_shows_a_landing_page() {
+    let test_app = TestApp::spawn_app().await;
+    let confirmation_link = subscribe(&test_app).await;
+
+    let response = reqwest::get(confirmation_link.clone()).await.unwrap();
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers()["content-type"],
+        "text/html; charset=utf-8"
+    );
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Subscription confirmed"));
+
+    let response = reqwest::get(confirmation_link).await.unwrap();
+
+    assert_eq!(response.status(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Subscription already confirmed"));
+}
+
+#[tokio::test]
+async fn expired_confirmation_links_are_rejected_with_410() {
+    let test_app = TestApp::spawn_app_with(|config| {
+        config.subscription_confirmation.token_validity_hours = 0;
+    })
+    .await;
+    let confirmation_link = subscribe(&test_app).await;
+
+    let response = reqwest::get(confirmation_link).await.unwrap();
+
+    assert_eq!(response.status(), 410);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Confirmation link expired"));
+
+    let status: String = sqlx::query("SELECT status FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .get("status");
+
+    assert_eq!(status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn unknown_tokens_are_rejected_with_404() {
+    let test_app = TestApp::spawn_app().await;
+
+    let response = reqwest::get(format!(
+        "{}/subscriptions/confirm?token=unknown",
+        test_app.address
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status(), 404);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Invalid confirmation link"));
+}
+
+#[tokio::test]
+async fn subscribers_with_a_corrupt_row_get_an_error_page() {
+    let test_app = TestApp::spawn_app().await;
+    let confirmation_link = subscribe(&test_app).await;
+
+    sqlx::query("UPDATE subscriptions SET status = 'archived'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    let response = reqwest::get(confirmation_link).await.unwrap();
+
+    assert_eq!(response.status(), 500);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Something went wrong"));
+}
+
+#[tokio::test]
+async fn confirmation_link_redirects_to_the_configured_urls() {
+    let test_app = TestApp::spawn_app_with(|config| {
+        config
+            .subscription_confirmation
+            .redirects
+            .success_redirect_url = Some(String::from("https://example.com/welcome"));
+        config
+            .subscription_confirmation
+            .redirects
+            .error_redirect_url = Some(String::from("https://example.com/oops"));
+    })
+    .await;
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    let confirmation_link = subscribe(&test_app).await;
+    let test_cases = vec![
+        (confirmation_link.to_string(), "https://example.com/welcome"),
+        (
+            format!("{}/subscriptions/confirm?token=unknown", test_app.address),
+            "https://example.com/oops",
+        ),
+    ];
+
+    for (link, location) in test_cases {
+        let response = client.get(link).send().await.unwrap();
+
+        assert_eq!(response.status(), 303);
+        assert_eq!(response.headers()["location"], location);
+    }
+
+    // There is no redirect for already confirmed subscribers, so the page is shown
+    let response = client.get(confirmation_link).send().await.unwrap();
+
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn confirmation_link_redirects_to_the_urls_of_the_list() {
+    let test_app = TestApp::spawn_app_with(|config| {
+        config
+            .subscription_confirmation
+            .redirects
+            .success_redirect_url = Some(String::from("https://example.com/welcome"));
+        config
+            .subscription_confirmation
+            .redirects
+            .already_confirmed_redirect_url = Some(String::from("https://example.com/already"));
+        config.subscription_confirmation.lists.insert(
+            String::from("blog"),
+            ConfirmationRedirects {
+                success_redirect_url: Some(String::from("https://blog.example.com/welcome")),
+                ..Default::default()
+            },
+        );
+    })
+    .await;
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    let confirmation_link = subscribe_with(
+        &test_app,
+        serde_json::json!({ "name": "Frank", "email": "frank@test.com", "list": "blog" }),
+    )
+    .await;
+
+    let response = client.get(confirmation_link.clone()).send().await.unwrap();
+
+    assert_eq!(response.status(), 303);
+    assert_eq!(
+        response.headers()["location"],
+        "https://blog.example.com/welcome"
+    );
+
+    // The list has no URL of its own for this outcome, so the shared one is used
+    let response = client.get(confirmation_link).send().await.unwrap();
+
+    assert_eq!(response.status(), 303);
+    assert_eq!(
+        response.headers()["location"],
+        "https://example.com/already"
+    );
+}
+
+#[tokio::test]
+async fn subscriptions_to_an_unknown_list_are_rejected_with_400() {
+    let test_app = TestApp::spawn_app().await;
+
+    let response = test_app
+        .post_subscription(
+            serde_json::json!({ "name": "Frank", "email": "frank@test.com", "list": "blog" }),
+        )
+        .await;
+
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn confirmation_links_of_unsubscribed_subscribers_are_rejected_with_404() {
+    let test_app = TestApp::spawn_app().await;
+    let confirmation_link = subscribe(&test_app).await;
+
+    sqlx::query("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    let response = reqwest::get(confirmation_link).await.unwrap();
+
+    assert_eq!(response.status(), 404);
+
+    let status: String = sqlx::query("SELECT status FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .get("status");
+
+    assert_eq!(status, "unsubscribed");
+}
@@ -0,0 +1,152 @@
+use email_newsletter::content::tracking::sign_preferences_token;
+use email_newsletter::email_client::SendEmailBody;
+use linkify::{LinkFinder, LinkKind};
+use reqwest::Url;
+use uuid::Uuid;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::TestApp;
+
+fn preferences_url(test_app: &TestApp, subscriber_id: &Uuid) -> String {
+    format!(
+        "{}/subscriptions/preferences?token={}",
+        test_app.address,
+        sign_preferences_token(&test_app.hmac_secret, subscriber_id)
+    )
+}
+
+async fn get_timezone(test_app: &TestApp) -> Option<String> {
+    sqlx::query_scalar("SELECT timezone FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn newsletters_link_to_the_preferences_of_the_subscriber() {
+    let test_app = TestApp::spawn_app().await;
+
+    test_app.create_confirmed_subscriber("frank@test.com").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "content": {
+            "html": "<a href=\"{{ preferences_url }}\">Preferences</a><a href=\"{{ unsubscribe_url }}\">Unsubscribe</a>"
+          }
+        }))
+        .await;
+    test_app.dispatch_all_pending_emails().await;
+
+    let received_requests = test_app.email_server.received_requests().await.unwrap();
+    let body: SendEmailBody = received_requests.last().unwrap().body_json().unwrap();
+    // Preferences links are not rewritten to track clicks
+    let mut preferences_link = LinkFinder::new()
+        .links(&body.content[1].value)
+        .filter(|link| *link.kind() == LinkKind::Url)
+        .map(|link| Url::parse(link.as_str()).unwrap())
+        .find(|link| link.path() == "/subscriptions/preferences")
+        .unwrap();
+
+    preferences_link.set_port(Some(test_app.port)).unwrap();
+
+    let response = reqwest::get(preferences_link).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains(r#"method="post""#));
+}
+
+#[tokio::test]
+async fn subscribers_choose_their_timezone_in_the_preferences() {
+    let test_app = TestApp::spawn_app().await;
+    let subscriber_id = test_app.create_confirmed_subscriber("frank@test.com").await;
+    let url = preferences_url(&test_app, &subscriber_id);
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(&url)
+        .form(&[("timezone", "Europe/Madrid")])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(get_timezone(&test_app).await.unwrap(), "Europe/Madrid");
+
+    // The form shows the current timezone
+    let response = client.get(&url).send().await.unwrap();
+
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains(r#"value="Europe/Madrid""#));
+
+    let response = client
+        .post(&url)
+        .form(&[("timezone", "")])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(get_timezone(&test_app).await, None);
+}
+
+#[tokio::test]
+async fn preferences_return_400_when_the_timezone_or_the_token_is_invalid() {
+    let test_app = TestApp::spawn_app().await;
+    let subscriber_id = test_app.create_confirmed_subscriber("frank@test.com").await;
+    let client = reqwest::Client::new();
+    let test_cases = vec![
+        (
+            preferences_url(&test_app, &subscriber_id),
+            "Mars/Olympus_Mons",
+            "the timezone is invalid",
+        ),
+        (
+            format!(
+                "{}/subscriptions/preferences?token={}",
+                test_app.address, subscriber_id
+            ),
+            "Europe/Madrid",
+            "the token is not signed",
+        ),
+    ];
+
+    for (url, timezone, error_message) in test_cases {
+        let response = client
+            .post(url)
+            .form(&[("timezone", timezone)])
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 status when {}",
+            error_message
+        );
+    }
+
+    assert_eq!(get_timezone(&test_app).await, None);
+}
+
+#[tokio::test]
+async fn preferences_return_404_when_the_subscriber_does_not_exist() {
+    let test_app = TestApp::spawn_app().await;
+
+    let response = reqwest::get(preferences_url(&test_app, &Uuid::new_v4()))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 404);
+}
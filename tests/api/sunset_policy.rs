@@ -0,0 +1,363 @@
+use chrono::{Duration, Utc};
+use config::{Config, File, FileFormat};
+use std::num::NonZeroU32;
+use uuid::Uuid;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::TestApp;
+use email_newsletter::config::{get_configuration, SunsetPolicySettings};
+use email_newsletter::email_client::SendEmailBody;
+use email_newsletter::sunset_policy::SunsetPolicyOutcome;
+
+/// Subscribers are inactive after two tracked sends without opens or clicks
+async fn spawn_app_with_subscriber() -> (TestApp, Uuid) {
+    let test_app = TestApp::spawn_app_with(|config| {
+        config.set_sunset_policy(SunsetPolicySettings {
+            inactive_after_sends: NonZeroU32::new(2),
+            inactive_after_months: None,
+            unsubscribe_after_days: 14,
+        })
+    })
+    .await;
+    let subscriber_id = test_app.create_confirmed_subscriber("frank@test.com").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    (test_app, subscriber_id)
+}
+
+async fn send_tracked_newsletter(test_app: &TestApp) {
+    test_app
+        .post_newsletter(serde_json::json!({
+          "title": "Newsletter title",
+          "content": { "html": "<html><body><p>Newsletter content</p></body></html>" }
+        }))
+        .await
+        .error_for_status()
+        .unwrap();
+    test_app.dispatch_all_pending_emails().await;
+}
+
+async fn open_last_delivery(test_app: &TestApp) {
+    let delivery_id: Uuid =
+        sqlx::query_scalar("SELECT id FROM issue_deliveries ORDER BY sent_at DESC LIMIT 1")
+            .fetch_one(&test_app.db_pool)
+            .await
+            .unwrap();
+
+    reqwest::get(format!("{}/t/o/{}.gif", test_app.address, delivery_id))
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn get_status(test_app: &TestApp, subscriber_id: Uuid) -> String {
+    sqlx::query_scalar("SELECT status FROM subscriptions WHERE id = $1")
+        .bind(subscriber_id)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+}
+
+async fn get_status_history(test_app: &TestApp, subscriber_id: Uuid) -> Vec<String> {
+    let history: serde_json::Value = reqwest::get(format!(
+        "{}/admin/subscribers/{}/status_history",
+        test_app.address, subscriber_id
+    ))
+    .await
+    .unwrap()
+    .error_for_status()
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+
+    history
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| String::from(entry["action"].as_str().unwrap()))
+        .collect()
+}
+
+#[tokio::test]
+async fn unresponsive_subscribers_are_unsubscribed_after_the_grace_period() {
+    let (test_app, subscriber_id) = spawn_app_with_subscriber().await;
+
+    send_tracked_newsletter(&test_app).await;
+    assert_eq!(
+        test_app.run_sunset_policy(Utc::now()).await,
+        SunsetPolicyOutcome::default()
+    );
+
+    send_tracked_newsletter(&test_app).await;
+    assert_eq!(
+        test_app.run_sunset_policy(Utc::now()).await,
+        SunsetPolicyOutcome {
+            marked_inactive: 1,
+            re_engagements_sent: 1,
+            ..Default::default()
+        }
+    );
+
+    let received_requests = test_app.email_server.received_requests().await.unwrap();
+    let re_engagement_email: SendEmailBody = received_requests.last().unwrap().body_json().unwrap();
+    assert_eq!(
+        re_engagement_email.subject,
+        "Do you still want to receive our newsletter?"
+    );
+
+    let segments: serde_json::Value = reqwest::get(format!("{}/admin/segments", test_app.address))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(segments[0]["name"], "inactive");
+    assert_eq!(segments[0]["filter"], "inactive");
+
+    // Still within the grace period
+    assert_eq!(
+        test_app
+            .run_sunset_policy(Utc::now() + Duration::days(13))
+            .await,
+        SunsetPolicyOutcome::default()
+    );
+    assert_eq!(
+        test_app
+            .run_sunset_policy(Utc::now() + Duration::days(15))
+            .await,
+        SunsetPolicyOutcome {
+            unsubscribed: 1,
+            ..Default::default()
+        }
+    );
+    assert_eq!(get_status(&test_app, subscriber_id).await, "unsubscribed");
+    assert_eq!(
+        get_status_history(&test_app, subscriber_id).await,
+        vec!["marked_inactive", "re_engagement_sent", "unsubscribed"]
+    );
+}
+
+#[tokio::test]
+async fn failed_re_engagement_emails_are_retried_before_the_grace_period_starts() {
+    let (test_app, subscriber_id) = spawn_app_with_subscriber().await;
+
+    send_tracked_newsletter(&test_app).await;
+    send_tracked_newsletter(&test_app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    assert_eq!(
+        test_app.run_sunset_policy(Utc::now()).await,
+        SunsetPolicyOutcome {
+            marked_inactive: 1,
+            re_engagements_failed: 1,
+            ..Default::default()
+        }
+    );
+
+    // The subscriber never received the email, so the grace period did not start
+    let retried_at = Utc::now() + Duration::days(15);
+    assert_eq!(
+        test_app.run_sunset_policy(retried_at).await,
+        SunsetPolicyOutcome {
+            re_engagements_sent: 1,
+            ..Default::default()
+        }
+    );
+    assert_eq!(get_status(&test_app, subscriber_id).await, "confirmed");
+
+    assert_eq!(
+        test_app
+            .run_sunset_policy(retried_at + Duration::days(15))
+            .await,
+        SunsetPolicyOutcome {
+            unsubscribed: 1,
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        get_status_history(&test_app, subscriber_id).await,
+        vec!["marked_inactive", "re_engagement_sent", "unsubscribed"]
+    );
+}
+
+#[tokio::test]
+async fn inactive_subscribers_following_the_re_engagement_link_stay_subscribed() {
+    let (test_app, subscriber_id) = spawn_app_with_subscriber().await;
+
+    send_tracked_newsletter(&test_app).await;
+    send_tracked_newsletter(&test_app).await;
+    test_app.run_sunset_policy(Utc::now()).await;
+
+    let received_requests = test_app.email_server.received_requests().await.unwrap();
+    let reactivation_link = test_app
+        .get_confirmation_link(received_requests.last().unwrap())
+        .await;
+
+    for _ in 0..2 {
+        let response = reqwest::get(reactivation_link.html.clone()).await.unwrap();
+
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // Inactivity is measured from the reactivation, the previous sends are not counted again
+    assert_eq!(
+        test_app
+            .run_sunset_policy(Utc::now() + Duration::days(15))
+            .await,
+        SunsetPolicyOutcome::default()
+    );
+    assert_eq!(get_status(&test_app, subscriber_id).await, "confirmed");
+    assert_eq!(
+        get_status_history(&test_app, subscriber_id).await,
+        vec!["marked_inactive", "re_engagement_sent", "reactivated"]
+    );
+}
+
+#[tokio::test]
+async fn inactive_subscribers_who_open_an_issue_are_reactivated() {
+    let (test_app, subscriber_id) = spawn_app_with_subscriber().await;
+
+    send_tracked_newsletter(&test_app).await;
+    send_tracked_newsletter(&test_app).await;
+    test_app.run_sunset_policy(Utc::now()).await;
+    open_last_delivery(&test_app).await;
+
+    assert_eq!(
+        test_app.run_sunset_policy(Utc::now()).await,
+        SunsetPolicyOutcome {
+            reactivated: 1,
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        get_status_history(&test_app, subscriber_id).await,
+        vec!["marked_inactive", "re_engagement_sent", "reactivated"]
+    );
+}
+
+#[tokio::test]
+async fn subscribers_who_opened_a_recent_issue_are_not_marked_inactive() {
+    let (test_app, subscriber_id) = spawn_app_with_subscriber().await;
+
+    send_tracked_newsletter(&test_app).await;
+    send_tracked_newsletter(&test_app).await;
+    open_last_delivery(&test_app).await;
+
+    assert_eq!(
+        test_app.run_sunset_policy(Utc::now()).await,
+        SunsetPolicyOutcome::default()
+    );
+    assert!(get_status_history(&test_app, subscriber_id)
+        .await
+        .is_empty());
+}
+
+#[tokio::test]
+async fn subscribers_without_engagement_in_the_last_months_are_marked_inactive() {
+    let test_app = TestApp::spawn_app_with(|config| {
+        config.set_sunset_policy(SunsetPolicySettings {
+            inactive_after_sends: None,
+            inactive_after_months: NonZeroU32::new(3),
+            unsubscribe_after_days: 14,
+        })
+    })
+    .await;
+    let subscriber_id = test_app.create_confirmed_subscriber("frank@test.com").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    send_tracked_newsletter(&test_app).await;
+
+    // Subscribers who joined recently are given time to engage
+    assert_eq!(
+        test_app.run_sunset_policy(Utc::now()).await,
+        SunsetPolicyOutcome::default()
+    );
+
+    sqlx::query("UPDATE subscriptions SET subscribed_at = $1")
+        .bind(Utc::now() - Duration::days(120))
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        test_app.run_sunset_policy(Utc::now()).await,
+        SunsetPolicyOutcome {
+            marked_inactive: 1,
+            re_engagements_sent: 1,
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        get_status_history(&test_app, subscriber_id).await,
+        vec!["marked_inactive", "re_engagement_sent"]
+    );
+}
+
+#[test]
+fn sunset_policy_is_disabled_unless_a_threshold_is_set() {
+    let config = get_configuration().expect("Failed to read configuration.");
+
+    assert!(!config.get_sunset_policy().is_enabled());
+}
+
+#[test]
+fn sunset_policy_thresholds_below_one_are_rejected() {
+    for threshold in ["inactive_after_sends", "inactive_after_months"] {
+        let settings = Config::builder()
+            .add_source(File::from_str(
+                &format!("{} = 0\nunsubscribe_after_days = 14", threshold),
+                FileFormat::Toml,
+            ))
+            .build()
+            .unwrap()
+            .try_deserialize::<SunsetPolicySettings>();
+
+        assert!(settings.is_err(), "A {} of 0 was accepted", threshold);
+    }
+}
+
+#[tokio::test]
+async fn invalid_reactivation_tokens_are_rejected_with_400() {
+    let test_app = TestApp::spawn_app().await;
+    let test_cases = vec![
+        ("", "empty token"),
+        ("not-a-token", "token without signature"),
+        (
+            "cmUtZW5nYWdlbWVudDo.c2lnbmF0dXJl",
+            "token with a forged signature",
+        ),
+    ];
+
+    for (token, description) in test_cases {
+        let response = reqwest::get(format!(
+            "{}/subscriptions/reactivate?token={}",
+            test_app.address, token
+        ))
+        .await
+        .unwrap();
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 Bad Request when the payload was {}.",
+            description
+        );
+    }
+}
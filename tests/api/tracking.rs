@@ -0,0 +1,274 @@
+use email_newsletter::content::tracking::{sign_click_token, sign_unsubscribe_token};
+use email_newsletter::email_client::SendEmailBody;
+use secrecy::Secret;
+use sqlx::Row;
+use uuid::Uuid;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::TestApp;
+
+/// Publishes and delivers a newsletter to a confirmed subscriber, returning the issue and delivery ids
+async fn send_newsletter(test_app: &TestApp, newsletter: serde_json::Value) -> (Uuid, Uuid) {
+    test_app.create_confirmed_subscriber("frank@test.com").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    let mut body = serde_json::json!({
+      "title": "Newsletter title",
+      "content": { "html": "<html><body><p>Newsletter content</p></body></html>" }
+    });
+
+    body.as_object_mut()
+        .unwrap()
+        .extend(newsletter.as_object().unwrap().clone());
+
+    let response = test_app.post_newsletter(body).await;
+    let newsletter_issue: serde_json::Value = response.json().await.unwrap();
+
+    test_app.dispatch_all_pending_emails().await;
+
+    let delivery_id = sqlx::query("SELECT id FROM issue_deliveries")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .get("id");
+
+    (
+        Uuid::parse_str(newsletter_issue["id"].as_str().unwrap()).unwrap(),
+        delivery_id,
+    )
+}
+
+async fn get_sent_email(test_app: &TestApp) -> SendEmailBody {
+    let received_requests = test_app.email_server.received_requests().await.unwrap();
+
+    received_requests.last().unwrap().body_json().unwrap()
+}
+
+async fn get_open_summary(test_app: &TestApp, newsletter_issue_id: &Uuid) -> serde_json::Value {
+    reqwest::get(format!(
+        "{}/admin/issues/{}/opens",
+        test_app.address, newsletter_issue_id
+    ))
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn opens_are_recorded_by_the_tracking_pixel() {
+    let test_app = TestApp::spawn_app().await;
+    let (newsletter_issue_id, delivery_id) =
+        send_newsletter(&test_app, serde_json::json!({})).await;
+    let body = get_sent_email(&test_app).await;
+
+    assert!(body.content[1]
+        .value
+        .contains(&format!("/t/o/{}.gif", delivery_id)));
+    assert!(!body.content[0].value.contains("/t/o/"));
+
+    let client = reqwest::Client::new();
+
+    for _ in 0..2 {
+        let response = client
+            .get(format!("{}/t/o/{}.gif", test_app.address, delivery_id))
+            .header("User-Agent", "Thunderbird")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(response.headers()["content-type"], "image/gif");
+    }
+
+    let user_agent: String = sqlx::query("SELECT user_agent FROM issue_opens LIMIT 1")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .get("user_agent");
+
+    assert_eq!(user_agent, "Thunderbird");
+
+    let summary = get_open_summary(&test_app, &newsletter_issue_id).await;
+
+    assert_eq!(summary["track_opens"], true);
+    assert_eq!(summary["sent"], 1);
+    assert_eq!(summary["opens"], 2);
+    assert_eq!(summary["unique_opens"], 1);
+    assert_eq!(summary["unique_open_rate"], 1.0);
+}
+
+#[tokio::test]
+async fn opens_are_not_tracked_when_disabled() {
+    let test_app = TestApp::spawn_app().await;
+    let (newsletter_issue_id, delivery_id) =
+        send_newsletter(&test_app, serde_json::json!({ "track_opens": false })).await;
+    let body = get_sent_email(&test_app).await;
+
+    assert!(!body.content[1].value.contains("/t/o/"));
+
+    let response = reqwest::get(format!("{}/t/o/{}.gif", test_app.address, delivery_id))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let summary = get_open_summary(&test_app, &newsletter_issue_id).await;
+
+    assert_eq!(summary["track_opens"], false);
+    assert_eq!(summary["opens"], 0);
+}
+
+#[tokio::test]
+async fn tracking_pixel_is_returned_for_unknown_tokens() {
+    let test_app = TestApp::spawn_app().await;
+    let test_cases = vec![Uuid::new_v4().to_string(), String::from("not-a-token")];
+
+    for token in test_cases {
+        let response = reqwest::get(format!("{}/t/o/{}.gif", test_app.address, token))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(response.bytes().await.unwrap().len(), 43);
+    }
+}
+
+#[tokio::test]
+async fn open_summary_returns_404_when_issue_does_not_exist() {
+    let test_app = TestApp::spawn_app().await;
+
+    let response = reqwest::get(format!(
+        "{}/admin/issues/{}/opens",
+        test_app.address,
+        Uuid::new_v4()
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+const LINKS_HTML: &str = r#"<p><a href="https://blog.test.com/post?id=1&amp;ref=email">Blog</a> <a href="mailto:frank@test.com">Write us</a> <a href="{{ unsubscribe_url }}">Unsubscribe</a></p>"#;
+
+/// Returns the tracked links of the content
+fn get_tracked_links(content: &str) -> Vec<&str> {
+    content
+        .match_indices("/t/c/")
+        .map(|(position, _)| {
+            let token = &content[position + 5..];
+
+            &token[..token
+                .find(|c: char| c == '"' || c.is_whitespace())
+                .unwrap_or(token.len())]
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn links_are_rewritten_to_tracked_redirects() {
+    let test_app = TestApp::spawn_app().await;
+    let (_, delivery_id) = send_newsletter(
+        &test_app,
+        serde_json::json!({ "content": { "html": LINKS_HTML } }),
+    )
+    .await;
+    let body = get_sent_email(&test_app).await;
+    let html = &body.content[1].value;
+
+    assert!(!html.contains("https://blog.test.com"));
+    assert!(html.contains(r#"href="mailto:frank@test.com""#));
+    assert!(html.contains(&format!(
+        "/subscriptions/unsubscribe?token={}",
+        sign_unsubscribe_token(&test_app.hmac_secret, &delivery_id)
+    )));
+
+    // The plain-text part has the same tracked link, signed for the same delivery and URL
+    let tracked_links = get_tracked_links(html);
+
+    assert_eq!(tracked_links.len(), 1);
+    assert_eq!(get_tracked_links(&body.content[0].value), tracked_links);
+
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    let response = client
+        .get(format!("{}/t/c/{}", test_app.address, tracked_links[0]))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 302);
+    assert_eq!(
+        response.headers()["location"],
+        "https://blog.test.com/post?id=1&ref=email"
+    );
+
+    let click = sqlx::query("SELECT issue_delivery_id, url FROM issue_clicks")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    assert_eq!(click.get::<Uuid, _>("issue_delivery_id"), delivery_id);
+    assert_eq!(
+        click.get::<String, _>("url"),
+        "https://blog.test.com/post?id=1&ref=email"
+    );
+}
+
+#[tokio::test]
+async fn links_are_not_tracked_when_disabled() {
+    let test_app = TestApp::spawn_app().await;
+
+    send_newsletter(
+        &test_app,
+        serde_json::json!({ "content": { "html": LINKS_HTML }, "track_clicks": false }),
+    )
+    .await;
+
+    let body = get_sent_email(&test_app).await;
+
+    assert!(body.content[1]
+        .value
+        .contains(r#"href="https://blog.test.com/post?id=1&amp;ref=email""#));
+    assert!(get_tracked_links(&body.content[1].value).is_empty());
+}
+
+#[tokio::test]
+async fn tracked_links_with_invalid_signature_are_rejected_with_404() {
+    let test_app = TestApp::spawn_app().await;
+    let forged_token = sign_click_token(
+        &Secret::new(String::from("guessed-secret")),
+        &Uuid::new_v4(),
+        "https://evil.com",
+    );
+    let test_cases = vec![
+        (forged_token, "signed with another secret"),
+        (String::from("not-a-token"), "malformed"),
+    ];
+
+    for (token, error_message) in test_cases {
+        let response = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap()
+            .get(format!("{}/t/c/{}", test_app.address, token))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            404,
+            response.status().as_u16(),
+            "The API did not fail with 404 status when the token was {}",
+            error_message
+        );
+    }
+}
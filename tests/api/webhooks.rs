@@ -0,0 +1,129 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use email_newsletter::config::get_configuration;
+use email_newsletter::startup::Application;
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+
+use crate::helpers::TestApp;
+
+async fn count_delivery_events(test_app: &TestApp) -> i64 {
+    sqlx::query_scalar("SELECT count(*) FROM issue_delivery_events")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn sendgrid_events_without_a_valid_signature_are_rejected() {
+    let test_app = TestApp::spawn_app().await;
+    let delivery_id = uuid::Uuid::new_v4();
+    let body = serde_json::json!([
+        { "event": "bounce", "timestamp": 1760000000, "sg_event_id": "1", "delivery_id": delivery_id }
+    ])
+    .to_string();
+    let client = reqwest::Client::new();
+    let url = format!("{}/webhooks/sendgrid", test_app.address);
+    let timestamp = chrono::Utc::now().timestamp().to_string();
+    // Signed with a key that is not the one in the configuration
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &SystemRandom::new())
+        .unwrap();
+    let other_key_pair =
+        EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref()).unwrap();
+    let other_signature = STANDARD.encode(
+        other_key_pair
+            .sign(
+                &SystemRandom::new(),
+                format!("{}{}", timestamp, body).as_bytes(),
+            )
+            .unwrap(),
+    );
+    let test_cases = [
+        (vec![], "no signature"),
+        (
+            vec![("X-Twilio-Email-Event-Webhook-Timestamp", timestamp.as_str())],
+            "no signature header",
+        ),
+        (
+            vec![
+                ("X-Twilio-Email-Event-Webhook-Timestamp", timestamp.as_str()),
+                ("X-Twilio-Email-Event-Webhook-Signature", "not-a-signature"),
+            ],
+            "an invalid signature",
+        ),
+        (
+            vec![
+                ("X-Twilio-Email-Event-Webhook-Timestamp", timestamp.as_str()),
+                (
+                    "X-Twilio-Email-Event-Webhook-Signature",
+                    other_signature.as_str(),
+                ),
+            ],
+            "a signature of another key",
+        ),
+    ];
+
+    for (headers, description) in test_cases {
+        let mut request = client
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .body(body.clone());
+
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+
+        let response = request.send().await.unwrap();
+
+        assert_eq!(
+            response.status().as_u16(),
+            401,
+            "The webhook did not return 401 with {}.",
+            description
+        );
+    }
+
+    assert_eq!(count_delivery_events(&test_app).await, 0);
+}
+
+#[tokio::test]
+async fn sendgrid_events_signed_long_ago_are_rejected() {
+    let test_app = TestApp::spawn_app().await;
+    let events = serde_json::json!([
+        { "event": "bounce", "timestamp": 1760000000, "delivery_id": uuid::Uuid::new_v4() }
+    ]);
+
+    // A captured request replayed later, or signed with a clock far ahead
+    for offset in [-600, 600] {
+        let signed_at = chrono::Utc::now() + chrono::Duration::seconds(offset);
+        let response = test_app
+            .post_sendgrid_events_signed_at(events.clone(), signed_at)
+            .await;
+
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    assert_eq!(count_delivery_events(&test_app).await, 0);
+}
+
+#[tokio::test]
+async fn signed_sendgrid_events_are_accepted() {
+    let test_app = TestApp::spawn_app().await;
+    let response = test_app
+        .post_sendgrid_events(serde_json::json!([
+            { "event": "delivered", "timestamp": 1760000000, "sg_event_id": "1", "delivery_id": uuid::Uuid::new_v4() }
+        ]))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn invalid_webhook_verification_keys_stop_the_application_build() {
+    let mut config = get_configuration().expect("Missing configuration file.");
+
+    config.set_app_port(0);
+    config.set_email_client_webhook_verification_key(String::from("not a key"));
+
+    assert!(Application::build(config).await.is_err());
+}